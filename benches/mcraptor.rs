@@ -1,19 +1,95 @@
 use std::hint::black_box;
 use std::iter::repeat_with;
-use criterion::{criterion_group, criterion_main, Criterion};
+use std::time::Instant;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
 
-use dev_utils::get_example_scenario;
+use dev_utils::{load_scenario, summarise_query_times, QuerySampler};
 use raptor::journey::JourneyPreferences;
 use raptor::network::PathfindingCost;
-use raptor::mc_raptor_query;
+use raptor::{mc_raptor_query, mc_raptor_query_exact, Bag, Epsilon, Label, StopIdx};
 
+// Label copying and bag churn dominate these queries, so compare changes to the inner loop against a saved baseline:
+//   cargo bench --bench mcraptor -- McRaptor --save-baseline before
+//   cargo bench --bench mcraptor -- McRaptor --baseline before
 fn mc_raptor_benchmark(c: &mut Criterion) {
-    let (network, start, start_time, end) = get_example_scenario();
+    let (network, start, start_time, end) = load_scenario().unwrap();
     fastrand::seed(7);
     let costs: Vec<_> = repeat_with(|| fastrand::f32() as PathfindingCost).take(network.stop_times.len()).collect();
     let path_preferences = JourneyPreferences::default();
-    c.bench_function("McRaptor", |b| b.iter(|| mc_raptor_query(&network, black_box(start), black_box(start_time), black_box(end), &costs, &path_preferences)));
+    c.bench_function("McRaptor", |b| b.iter(|| mc_raptor_query::<5>(&network, StopIdx(black_box(start)), black_box(start_time), black_box(&[StopIdx(end)]), &costs, &path_preferences)));
 }
 
-criterion_group!(benches, mc_raptor_benchmark);
+fn mc_raptor_exact_benchmark(c: &mut Criterion) {
+    let (network, start, start_time, end) = load_scenario().unwrap();
+    fastrand::seed(7);
+    let costs: Vec<_> = repeat_with(|| fastrand::f32() as PathfindingCost).take(network.stop_times.len()).collect();
+    let path_preferences = JourneyPreferences::default();
+    c.bench_function("McRaptor exact", |b| b.iter(|| mc_raptor_query_exact(&network, StopIdx(black_box(start)), black_box(start_time), black_box(&[StopIdx(end)]), &costs, &path_preferences)));
+}
+
+fn mc_raptor_epsilon_benchmark(c: &mut Criterion) {
+    let (network, start, start_time, end) = load_scenario().unwrap();
+    fastrand::seed(7);
+    let costs: Vec<_> = repeat_with(|| fastrand::f32() as PathfindingCost).take(network.stop_times.len()).collect();
+    let path_preferences = JourneyPreferences { epsilon: Epsilon { time: 60, cost: 0.01 }, ..Default::default() };
+    c.bench_function("McRaptor epsilon", |b| b.iter(|| mc_raptor_query::<5>(&network, StopIdx(black_box(start)), black_box(start_time), black_box(&[StopIdx(end)]), &costs, &path_preferences)));
+}
+
+// Random queries across the day, as a single query is one route pattern.
+fn mc_raptor_sampled_benchmark(c: &mut Criterion) {
+    let (network, ..) = load_scenario().unwrap();
+    fastrand::seed(7);
+    let costs: Vec<_> = repeat_with(|| fastrand::f32() as PathfindingCost).take(network.stop_times.len()).collect();
+    let path_preferences = JourneyPreferences::default();
+    let queries = QuerySampler::new(&network, 7).sample(20);
+
+    let durations = queries.iter().map(|&(start, start_time, end)| {
+        let query_start = Instant::now();
+        let _ = black_box(mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &costs, &path_preferences));
+        query_start.elapsed()
+    }).collect();
+    println!("McRaptor sampled: {}", summarise_query_times(durations));
+
+    let mut group = c.benchmark_group("Sampled queries");
+    group.throughput(Throughput::Elements(queries.len() as u64));
+    group.sample_size(10);
+    group.bench_function("McRaptor", |b| b.iter(|| {
+        for &(start, start_time, end) in queries.iter() {
+            let _ = black_box(mc_raptor_query::<5>(&network, StopIdx(black_box(start)), black_box(start_time), black_box(&[StopIdx(end)]), &costs, &path_preferences));
+        }
+    }));
+    group.finish();
+}
+
+fn bag_merge_benchmark(c: &mut Criterion) {
+    fastrand::seed(7);
+    let random_bag = || {
+        let mut bag = Bag::<5>::new();
+        for _ in 0..10 {
+            bag.add(Label::new(fastrand::u32(0..3600), fastrand::f32() as PathfindingCost));
+        }
+        bag
+    };
+    let bags: Vec<_> = repeat_with(|| (random_bag(), random_bag())).take(1000).collect();
+
+    // Compare merging whole bags against adding labels one by one, as mc_raptor_query used to.
+    c.bench_function("Bag add", |b| b.iter(|| {
+        for (bag, other) in bags.iter() {
+            let mut bag = bag.clone();
+            for label in other.iter() {
+                bag.add(*label);
+            }
+            black_box(bag);
+        }
+    }));
+    c.bench_function("Bag merge", |b| b.iter(|| {
+        for (bag, other) in bags.iter() {
+            let mut bag = bag.clone();
+            bag.merge(other);
+            black_box(bag);
+        }
+    }));
+}
+
+criterion_group!(benches, mc_raptor_benchmark, mc_raptor_exact_benchmark, mc_raptor_epsilon_benchmark, mc_raptor_sampled_benchmark, bag_merge_benchmark);
 criterion_main!(benches);
@@ -0,0 +1,68 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+use std::iter::repeat_with;
+
+use dev_utils::{get_example_date, get_example_scenario, get_example_start_stop_idx, get_example_end_stop_idx, get_example_start_time, get_example_transfer_time, load_example_gtfs};
+use gtfs_structures::RouteType;
+use raptor::journey::Connection;
+use raptor::network::NetworkPoint;
+use raptor::{raptor_query, Network, StopIdx};
+
+fn nearest_stops_benchmark(c: &mut Criterion) {
+    let (network, ..) = get_example_scenario();
+    fastrand::seed(11);
+    let points: Vec<_> = repeat_with(|| NetworkPoint { latitude: -38.2 + fastrand::f32() * 0.6, longitude: 144.7 + fastrand::f32() * 0.8 }).take(100).collect();
+
+    // Build the index before timing.
+    network.nearest_stops(points[0], 1);
+    c.bench_function("Nearest stops grid", |b| b.iter(|| {
+        for &point in points.iter() {
+            black_box(network.nearest_stops(black_box(point), 5));
+        }
+    }));
+    c.bench_function("Nearest stops scan", |b| b.iter(|| {
+        for &point in points.iter() {
+            let mut distances: Vec<_> = network.stop_points.iter().enumerate()
+                .filter(|(_, stop_point)| stop_point.is_valid())
+                .map(|(stop, &stop_point)| (stop, point.distance(stop_point)))
+                .collect();
+            distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+            distances.truncate(5);
+            black_box(distances);
+        }
+    }));
+}
+
+fn network_new_benchmark(c: &mut Criterion) {
+    let gtfs = load_example_gtfs().unwrap();
+    let mut group = c.benchmark_group("Network construction");
+    group.sample_size(10);
+    group.bench_function("Network::new", |b| b.iter(|| Network::new(black_box(&gtfs), None, get_example_date(), get_example_transfer_time())));
+    group.finish();
+}
+
+fn build_connections_benchmark(c: &mut Criterion) {
+    let (mut network, ..) = get_example_scenario();
+    c.bench_function("Build connections", |b| b.iter(|| network.build_connections()));
+    c.bench_function("Build connections serial", |b| b.iter(|| {
+        let mut connections = network.iter_connections().collect::<Vec<_>>();
+        connections.sort_by_key(Connection::sort_key);
+        black_box(connections)
+    }));
+}
+
+// Filtering by route type leaves stops without service, which every query allocates space for until they are pruned.
+fn prune_benchmark(c: &mut Criterion) {
+    let gtfs = load_example_gtfs().unwrap();
+    let mut network = Network::new(&gtfs, Some(RouteType::Rail), get_example_date(), get_example_transfer_time());
+    let start_time = get_example_start_time();
+    let (start, end) = (get_example_start_stop_idx(&network), get_example_end_stop_idx(&network));
+    c.bench_function("Raptor unpruned", |b| b.iter(|| raptor_query(black_box(&network), StopIdx(start), start_time, StopIdx(end))));
+
+    let mapping = network.prune();
+    let (start, end) = (mapping.to_new(start).unwrap(), mapping.to_new(end).unwrap());
+    c.bench_function("Raptor pruned", |b| b.iter(|| raptor_query(black_box(&network), StopIdx(start), start_time, StopIdx(end))));
+}
+
+criterion_group!(benches, nearest_stops_benchmark, network_new_benchmark, build_connections_benchmark, prune_benchmark);
+criterion_main!(benches);
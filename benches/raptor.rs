@@ -1,19 +1,184 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::collections::BTreeMap;
 use std::hint::black_box;
+use std::time::Instant;
 
-use dev_utils::get_example_scenario;
-use raptor::{csa_query, raptor_query};
+use dev_utils::{load_scenario, summarise_query_times, QuerySampler};
+use raptor::journey::{JourneyPreferences, JourneyResult};
+use raptor::network::{CountBoardings, NetworkPoint, StopIndex, StopTime, TimetableRoute, Timestamp};
+use raptor::utils::BitSet;
+use raptor::{csa_query, mc_raptor_query, raptor_one_to_all_journeys, raptor_query, Network, StopIdx};
+
+// Sampled with a fixed seed, so results are comparable across commits.
+const NUM_SAMPLED_QUERIES: usize = 200;
+const SAMPLE_SEED: u64 = 7;
 
 fn raptor_benchmark(c: &mut Criterion) {
-    let (network, start, start_time, end) = get_example_scenario();
-    c.bench_function("Raptor", |b| b.iter(|| raptor_query(&network, black_box(start), black_box(start_time), black_box(end))));
+    let (network, start, start_time, end) = load_scenario().unwrap();
+    c.bench_function("Raptor", |b| b.iter(|| raptor_query(&network, StopIdx(black_box(start)), black_box(start_time), StopIdx(black_box(end)))));
 }
 
 fn csa_benchmark(c: &mut Criterion) {
-    let (mut network, start, start_time, end) = get_example_scenario();
+    let (mut network, start, start_time, end) = load_scenario().unwrap();
+    network.build_connections();
+    c.bench_function("CSA", |b| b.iter(|| csa_query(&network, StopIdx(black_box(start)), black_box(start_time), StopIdx(black_box(end)))));
+}
+
+fn sampled_network() -> (Network, Vec<(StopIndex, Timestamp, StopIndex)>) {
+    let (mut network, ..) = load_scenario().unwrap();
     network.build_connections();
-    c.bench_function("CSA", |b| b.iter(|| csa_query(&network, black_box(start), black_box(start_time), black_box(end))));
+    let queries = QuerySampler::new(&network, SAMPLE_SEED).sample(NUM_SAMPLED_QUERIES);
+    (network, queries)
+}
+
+// A single query is one route pattern, so also benchmark a batch of random queries across the day.
+fn sampled_benchmark(c: &mut Criterion) {
+    let (mut network, queries) = sampled_network();
+
+    let mut group = c.benchmark_group("Sampled queries");
+    group.throughput(Throughput::Elements(queries.len() as u64));
+    group.bench_function("Raptor", |b| b.iter(|| {
+        for &(start, start_time, end) in queries.iter() {
+            let _ = black_box(raptor_query(&network, StopIdx(black_box(start)), black_box(start_time), StopIdx(black_box(end))));
+        }
+    }));
+    group.bench_function("CSA", |b| b.iter(|| {
+        for &(start, start_time, end) in queries.iter() {
+            let _ = black_box(csa_query(&network, StopIdx(black_box(start)), black_box(start_time), StopIdx(black_box(end))));
+        }
+    }));
+    // Without the bucket index, CSA falls back to binary searching every connection for its start.
+    network.connection_buckets.clear();
+    group.bench_function("CSA without connection buckets", |b| b.iter(|| {
+        for &(start, start_time, end) in queries.iter() {
+            let _ = black_box(csa_query(&network, StopIdx(black_box(start)), black_box(start_time), StopIdx(black_box(end))));
+        }
+    }));
+    group.finish();
+}
+
+// A grid of lines along each row and column, both ways, running from 06:00 to 22:00 every headway seconds.
+fn grid_network(columns: usize, rows: usize, headway: Timestamp) -> Network {
+    let stop_names: Vec<_> = (0..columns * rows).map(|stop| (format!("Stop {stop}"), stop.to_string())).collect();
+    let stops: Vec<_> = stop_names.iter().enumerate().map(|(stop, (name, id))| {
+        let point = NetworkPoint { latitude: -37.8 + (stop / columns) as f32 * 0.005, longitude: 144.9 + (stop % columns) as f32 * 0.005 };
+        (name.as_str(), id.as_str(), point)
+    }).collect();
+
+    let row_lines = (0..rows).map(|row| (0..columns).map(|column| (row * columns + column) as StopIndex).collect::<Vec<_>>());
+    let column_lines = (0..columns).map(|column| (0..rows).map(|row| (row * columns + column) as StopIndex).collect::<Vec<_>>());
+    let routes = row_lines.chain(column_lines)
+        .flat_map(|line_stops| [line_stops.iter().rev().copied().collect(), line_stops])
+        .enumerate()
+        .map(|(route, line_stops): (usize, Vec<StopIndex>)| {
+            // Two minutes between stops.
+            let trips = (0..16 * 3600 / headway).map(|trip| {
+                let start = 6 * 3600 + trip * headway;
+                let stop_times = (0..line_stops.len() as Timestamp).map(|i| StopTime { arrival_time: start + i * 120, departure_time: start + i * 120 + 30 }).collect();
+                (format!("{route}.{trip}"), stop_times)
+            }).collect();
+            TimetableRoute { line: format!("Line {route}"), colour: Default::default(), stops: line_stops, trips }
+        })
+        .collect();
+    Network::from_timetable(&stops, routes, dev_utils::get_example_date(), 120)
+}
+
+fn grid_benchmark(c: &mut Criterion, name: &str, network: Network) {
+    let queries = QuerySampler::new(&network, SAMPLE_SEED).sample(NUM_SAMPLED_QUERIES);
+
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(queries.len() as u64));
+    group.bench_function(format!("Raptor ({}-bit stops)", std::mem::size_of::<StopIndex>() * 8), |b| b.iter(|| {
+        for &(start, start_time, end) in queries.iter() {
+            let _ = black_box(raptor_query(&network, StopIdx(black_box(start)), black_box(start_time), StopIdx(black_box(end))));
+        }
+    }));
+    group.finish();
+}
+
+// A regional-sized grid (3000 stops, 220 routes) where 16-bit indices fit. Compare index widths with criterion baselines:
+//   cargo bench --bench raptor -- Regional --save-baseline u32
+//   cargo bench --bench raptor --features small-indices -- Regional --baseline u32
+fn regional_benchmark(c: &mut Criterion) {
+    grid_benchmark(c, "Regional sampled queries", grid_network(60, 50, 600));
+}
+
+// Trips every minute, so finding the earliest catchable trip scans long departure columns.
+fn high_frequency_benchmark(c: &mut Criterion) {
+    grid_benchmark(c, "High-frequency sampled queries", grid_network(12, 10, 60));
+}
+
+// Batch analytics reconstruct many journeys from one one-to-all query. journey collects each journey's legs in a new Vec, while
+// journey_into reuses one buffer, so only the journey's own legs are allocated.
+fn journey_reconstruction_benchmark(c: &mut Criterion) {
+    const NUM_RECONSTRUCTIONS: usize = 10_000;
+    let network = grid_network(60, 50, 600);
+    let one_to_all = raptor_one_to_all_journeys(&network, StopIdx(0), 8 * 3600);
+    let mut rng = fastrand::Rng::with_seed(SAMPLE_SEED);
+    let ends: Vec<_> = std::iter::repeat_with(|| StopIdx(rng.u32(1..network.stops.len() as u32))).take(NUM_RECONSTRUCTIONS).collect();
+
+    let mut group = c.benchmark_group("Journey reconstruction");
+    group.throughput(Throughput::Elements(NUM_RECONSTRUCTIONS as u64));
+    group.bench_function("journey", |b| b.iter(|| {
+        for &end in ends.iter() {
+            let _ = black_box(one_to_all.journey(black_box(end)));
+        }
+    }));
+    let mut legs_buf = Vec::new();
+    group.bench_function("journey_into", |b| b.iter(|| {
+        for &end in ends.iter() {
+            let _ = black_box(one_to_all.journey_into(black_box(end), &mut legs_buf));
+        }
+    }));
+    group.finish();
+}
+
+// The per-round cycle of marking the stops a round improves, iterating over them and clearing them, on a large network's worth
+// of stops with a sparse set marked.
+fn marked_stops_benchmark(c: &mut Criterion) {
+    const NUM_STOPS: usize = 25_000;
+    let mut rng = fastrand::Rng::with_seed(SAMPLE_SEED);
+    let marked: Vec<_> = std::iter::repeat_with(|| rng.usize(0..NUM_STOPS)).take(500).collect();
+    let mut marked_stops = BitSet::new(NUM_STOPS);
+    c.bench_function("Marked stops cycle", |b| b.iter(|| {
+        for &stop in marked.iter() {
+            marked_stops.insert(black_box(stop));
+        }
+        black_box(marked_stops.iter().sum::<usize>());
+        marked_stops.clear();
+        black_box(marked_stops.is_empty())
+    }));
+}
+
+// Per-query latency percentiles and the number of rounds (trips boarded) the journeys needed, which criterion's batch timings hide.
+fn latency_report(_: &mut Criterion) {
+    let (network, queries) = sampled_network();
+    let path_preferences = JourneyPreferences::default();
+
+    let report = |name: &str, query: &dyn for<'a> Fn(&'a Network, StopIndex, Timestamp, StopIndex) -> JourneyResult<'a>| {
+        let mut durations = Vec::with_capacity(queries.len());
+        let mut rounds = BTreeMap::<usize, usize>::new();
+        let mut num_not_found = 0;
+        for &(start, start_time, end) in queries.iter() {
+            let query_start = Instant::now();
+            let result = black_box(query(&network, start, start_time, end));
+            durations.push(query_start.elapsed());
+            match result {
+                Ok(journey) => *rounds.entry(journey.legs_vehicle().count()).or_default() += 1,
+                Err(_) => num_not_found += 1,
+            }
+        }
+        let rounds = rounds.iter().map(|(num_rounds, count)| format!("{num_rounds}: {count}")).collect::<Vec<_>>().join(", ");
+        println!("{name}: {}", summarise_query_times(durations));
+        println!("{name} rounds used: {rounds}, no journey: {num_not_found}");
+    };
+    report("Raptor", &|network, start, start_time, end| raptor_query(network, StopIdx(start), start_time, StopIdx(end)));
+    report("CSA", &|network, start, start_time, end| csa_query(network, StopIdx(start), start_time, StopIdx(end)));
+    report("McRaptor", &|network: &Network, start, start_time, end| {
+        mc_raptor_query::<5>(network, StopIdx(start), start_time, &[StopIdx(end)], &CountBoardings, &path_preferences).remove(0)
+    });
 }
 
-criterion_group!(benches, raptor_benchmark, csa_benchmark);
-criterion_main!(benches);
+criterion_group!(benches, raptor_benchmark, csa_benchmark, sampled_benchmark, regional_benchmark, high_frequency_benchmark, journey_reconstruction_benchmark, marked_stops_benchmark);
+criterion_group!(reports, latency_report);
+criterion_main!(benches, reports);
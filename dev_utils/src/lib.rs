@@ -1,13 +1,19 @@
-use std::ffi::OsStr;
 use chrono::NaiveDate;
 use gtfs_structures::{Error, Gtfs, GtfsReader};
-use raptor::network::{StopIndex, Timestamp};
-use raptor::{utils, Network};
+use parquet::column::reader::get_typed_column_reader;
+use parquet::data_type::{ByteArrayType, DataType, Int32Type, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::reader::{FileReader, RowGroupReader, SerializedFileReader};
+use raptor::network::{CoordType, NetworkPoint, PathfindingCost, StopIndex, StopTime, Timestamp, TimetableRoute};
+use std::collections::HashMap;
+use raptor::utils::OptionExt;
+use raptor::{utils, Journey, Leg, Network};
 use std::fs;
-use std::fs::{DirEntry, File};
+use std::fs::File;
 use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::OnceLock;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 use rayon::{ThreadPool, ThreadPoolBuildError};
 
 // Create a rayon thread pool with the given number of threads.
@@ -19,57 +25,162 @@ pub fn create_pool(num_threads: usize) -> Result<ThreadPool, ThreadPoolBuildErro
 
 // Common example data for the examples and benchmarks.
 
-// Returns if any file in the directory tree matches the condition.
-fn visit_dirs(dir: &Path, cb: &mut impl FnMut(&DirEntry) -> bool, ignore: &[&OsStr]) -> io::Result<bool> {
-    if dir.file_name().map(|s| ignore.contains(&s)).unwrap_or(false) {
-        return Ok(false);
+// Returns the folder holding the example data (gtfs/ and data/). In order, this is RAPTOR_DEV_DATA if it's set, this crate's
+// own folder, and as a last resort a dev_utils folder in the current directory or one of its ancestors (for when the crate has
+// been moved since it was built). The error lists every location tried.
+pub fn find_dev_data_folder() -> Result<PathBuf, io::Error> {
+    if let Some(dev_data) = std::env::var_os("RAPTOR_DEV_DATA") {
+        let dev_data = PathBuf::from(dev_data);
+        return if dev_data.is_dir() {
+            Ok(dev_data)
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotFound, format!("RAPTOR_DEV_DATA is set to {}, which isn't a directory.", dev_data.display())))
+        };
     }
-    if dir.is_dir() {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            if cb(&entry) {
-                return Ok(true);
-            }
-            let path = entry.path();
-            if path.is_dir() {
-                if visit_dirs(&path, cb, ignore)? {
-                    return Ok(true);
-                }
-            }
+
+    let mut tried = vec![PathBuf::from(env!("CARGO_MANIFEST_DIR"))];
+    if let Ok(current_dir) = std::env::current_dir() {
+        tried.extend(current_dir.ancestors().map(|dir| dir.join("dev_utils")));
+    }
+    match tried.iter().find(|dir| dir.join("gtfs").is_dir()) {
+        Some(dir) => Ok(dir.clone()),
+        None => {
+            let tried = tried.iter().map(|dir| format!("  {}", dir.display())).collect::<Vec<_>>().join("\n");
+            Err(io::Error::new(io::ErrorKind::NotFound, format!("Couldn't find the example data (a folder containing gtfs/). Set RAPTOR_DEV_DATA, or \
+                                                                  put it in one of:\n{tried}")))
         }
     }
-    Ok(false)
 }
 
-fn find_dev_utils_folder() -> Result<PathBuf, io::Error> {
-    static DEV_UTILS_PATH: OnceLock<PathBuf> = OnceLock::new();
+// Resolves a file in the example data folder, with the path in the error if it's missing.
+fn find_dev_data_file(relative_path: &str) -> Result<PathBuf, io::Error> {
+    let path = find_dev_data_folder()?.join(relative_path);
+    if path.is_file() {
+        Ok(path)
+    } else {
+        Err(io::Error::new(io::ErrorKind::NotFound, format!("Example data file {} doesn't exist.", path.display())))
+    }
+}
+
+pub fn load_example_gtfs() -> Result<Gtfs, Error> {
+    let gtfs_path = find_dev_data_file("gtfs/melbourne.zip")?;
+    GtfsReader::default().read_shapes(false).read_from_path(gtfs_path.to_str().unwrap_or_default())
+}
+
+pub fn find_example_patronage_data() -> Result<File, io::Error> {
+    let data_path = find_dev_data_file("data/melbourne.parquet")?;
+    File::open(&data_path).map_err(|e| io::Error::new(e.kind(), format!("Couldn't open example patronage data {}: {e}", data_path.display())))
+}
+
+// Why load_example_costs failed.
+#[derive(Debug)]
+pub enum PatronageError {
+    Io(io::Error),
+    Parquet(ParquetError),
+}
+
+impl std::fmt::Display for PatronageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatronageError::Io(error) => write!(f, "{error}"),
+            PatronageError::Parquet(error) => write!(f, "Couldn't read example patronage data: {error}"),
+        }
+    }
+}
 
-    Ok(DEV_UTILS_PATH.get_or_init(|| {
-        let current_dir = std::env::current_dir().unwrap();
-        let mut dev_utils_path = None;
+impl std::error::Error for PatronageError {}
 
-        visit_dirs(&current_dir.join("../"), &mut |entry| {
-            let is_dev_utils = entry.path().ends_with("raptor-rs/dev_utils");
-            if is_dev_utils {
-                dev_utils_path = Some(entry.path());
+impl From<io::Error> for PatronageError {
+    fn from(error: io::Error) -> Self {
+        PatronageError::Io(error)
+    }
+}
+
+impl From<ParquetError> for PatronageError {
+    fn from(error: ParquetError) -> Self {
+        PatronageError::Parquet(error)
+    }
+}
+
+// Stop time costs from the example patronage data.
+pub struct ExampleCosts {
+    // Indexed like network.stop_times.
+    pub costs: Vec<PathfindingCost>,
+    // The number of patronage rows that couldn't be matched to a trip in the network, because the station isn't in the network
+    // or has no service after the row's departure time on the network's date.
+    pub num_skipped: usize,
+}
+
+// Patronage data is origin-destination demand (Origin_Station, Destination_Station, Departure_Time, Agent_Count) rather than per-trip loads.
+// Each row's agents are assigned to the first trip departing their origin station at or after their departure time,
+// giving a boarding demand for each stop time. Stop times with no demand get a cost of zero.
+pub fn load_example_costs(network: &Network) -> Result<ExampleCosts, PatronageError> {
+    let reader = SerializedFileReader::new(find_example_patronage_data()?)?;
+
+    let mut costs = vec![0. as PathfindingCost; network.stop_times.len()];
+    let mut stop_cache = HashMap::<String, Option<StopIndex>>::new();
+    let mut num_skipped = 0;
+
+    // The row API can't convert TIME columns, so read each column of a row group in full.
+    for row_group in 0..reader.num_row_groups() {
+        let row_group = reader.get_row_group(row_group)?;
+        let origins = read_column::<ByteArrayType>(&*row_group, "Origin_Station")?;
+        // Times are stored in microseconds since midnight.
+        let departure_times = read_column::<Int64Type>(&*row_group, "Departure_Time")?;
+        let agent_counts = read_column::<Int32Type>(&*row_group, "Agent_Count")?;
+
+        for ((origin, departure_time), agent_count) in origins.into_iter().zip(departure_times).zip(agent_counts) {
+            let origin = origin.and_then(|station| {
+                let station = station.as_utf8().ok()?;
+                *stop_cache.entry(station.to_string()).or_insert_with(|| network.get_stop_idx_from_name(station))
+            });
+            let index = match (origin, departure_time) {
+                (Some(origin), Some(departure_time)) => next_departure_index(network, origin, (departure_time / 1_000_000) as Timestamp),
+                _ => None,
+            };
+            match (index, agent_count) {
+                (Some(index), Some(agent_count)) => costs[index] += agent_count as PathfindingCost,
+                _ => num_skipped += 1,
             }
-            is_dev_utils
-        }, &[".git".as_ref(), ".idea".as_ref()]).unwrap();
+        }
+    }
 
-        dev_utils_path.unwrap()
-    }).to_owned())
+    Ok(ExampleCosts { costs, num_skipped })
 }
 
-pub fn load_example_gtfs() -> Result<Gtfs, Error> {
-    let dev_utils_dir = find_dev_utils_folder()?;
-    let gtfs_dir = dev_utils_dir.join("gtfs/melbourne.zip");
-    GtfsReader::default().read_shapes(false).read_from_path(gtfs_dir.to_str().unwrap())
+// Reads a whole optional column of a row group, with None for nulls.
+fn read_column<T: DataType>(row_group: &dyn RowGroupReader, name: &str) -> Result<Vec<Option<T::T>>, ParquetError> {
+    let column = row_group.metadata().columns().iter().position(|column| column.column_path().string() == name)
+        .ok_or_else(|| ParquetError::General(format!("No {name} column in the patronage data.")))?;
+    let num_rows = row_group.metadata().num_rows() as usize;
+    let mut reader = get_typed_column_reader::<T>(row_group.get_column_reader(column)?);
+    let (mut def_levels, mut values) = (Vec::with_capacity(num_rows), Vec::with_capacity(num_rows));
+    while reader.read_records(num_rows, Some(&mut def_levels), None, &mut values)?.0 > 0 {}
+    // Values only hold the non-null entries, in order.
+    let mut values = values.into_iter();
+    Ok(def_levels.into_iter().map(|def_level| if def_level > 0 { values.next() } else { None }).collect())
 }
 
-pub fn find_example_patronage_data() -> Result<File, io::Error> {
-    let dev_utils_dir = find_dev_utils_folder()?;
-    let data_path = dev_utils_dir.join("data/melbourne.parquet");
-    File::open(data_path)
+// Returns the stop times index of the first departure from the given stop at or after the given time, over all routes serving the stop.
+fn next_departure_index(network: &Network, stop: StopIndex, time: Timestamp) -> Option<usize> {
+    let mut best: Option<(Timestamp, usize)> = None;
+    for &route_idx in network.get_stop(stop as usize).get_routes(&network.stop_routes) {
+        let route = &network.routes[route_idx as usize];
+        for (stop_order, &route_stop) in route.get_stops(&network.route_stops).iter().enumerate() {
+            if route_stop != stop {
+                continue;
+            }
+            // Trips in a route are sorted by departure time, so find the first trip departing at or after the time.
+            let departures = network.departure_column(route_idx as usize, stop_order);
+            let trip_order = departures.partition_point(|&departure_time| departure_time < time);
+            if let Some(&departure_time) = departures.get(trip_order) {
+                if OptionExt::is_none_or(best, |(best_time, _)| departure_time < best_time) {
+                    best = Some((departure_time, route.get_stop_times_index(trip_order, stop_order)));
+                }
+            }
+        }
+    }
+    best.map(|(_, index)| index)
 }
 
 pub fn get_example_date() -> NaiveDate {
@@ -107,3 +218,466 @@ pub fn get_example_scenario() -> (Network, StopIndex, Timestamp, StopIndex) {
     (network, start, start_time, end)
 }
 
+
+// A small network built in code, for tests and reproductions that don't need the example GTFS.
+// Stops are created by name as lines use them (the name is also the stop ID), and are placed about 1 km apart along a parallel
+// unless given a position with stop. Each line is one route, e.g.
+// SyntheticNetwork::new().line("Up", &[("A", "08:00", "08:00"), ("B", "08:05", "08:06")]).every(10, 6).
+pub struct SyntheticNetwork {
+    // Each stop's name, and its position if it was given one.
+    stops: Vec<(String, Option<NetworkPoint>)>,
+    lines: Vec<SyntheticLine>,
+    footpaths: Vec<(String, String, Timestamp)>,
+    date: NaiveDate,
+    transfer_time: Timestamp,
+}
+
+struct SyntheticLine {
+    name: String,
+    stops: Vec<StopIndex>,
+    // The stop times of the first trip.
+    stop_times: Vec<StopTime>,
+    headway: Timestamp,
+    num_trips: usize,
+}
+
+impl SyntheticNetwork {
+    // An empty network on the example date, with no transfer time.
+    pub fn new() -> Self {
+        Self { stops: Vec::new(), lines: Vec::new(), footpaths: Vec::new(), date: get_example_date(), transfer_time: 0 }
+    }
+
+    // Adds a stop at the given position. Stops are numbered in the order they're added or first used by a line.
+    // Panics if the stop already exists.
+    pub fn stop(mut self, name: &str, latitude: CoordType, longitude: CoordType) -> Self {
+        assert!(self.stops.iter().all(|(stop, _)| stop != name), "Stop {name} already exists.");
+        self.stops.push((name.to_string(), Some(NetworkPoint { latitude, longitude })));
+        self
+    }
+
+    // Adds a line with one trip, with the (stop name, arrival time, departure time) of each stop in order. Times are parsed with
+    // utils::parse_time.
+    // Panics if the line has fewer than two stops, or its times are malformed or go backwards.
+    pub fn line(mut self, name: &str, stop_times: &[(&str, &str, &str)]) -> Self {
+        assert!(stop_times.len() >= 2, "Line {name} needs at least two stops.");
+        let parse = |time: &str| utils::parse_time(time).unwrap_or_else(|e| panic!("Line {name}: {e}"));
+        let mut stops = Vec::with_capacity(stop_times.len());
+        let mut times = Vec::with_capacity(stop_times.len());
+        for &(stop_name, arrival_time, departure_time) in stop_times {
+            let stop_idx = match self.stops.iter().position(|(stop, _)| stop == stop_name) {
+                Some(stop_idx) => stop_idx,
+                None => {
+                    self.stops.push((stop_name.to_string(), None));
+                    self.stops.len() - 1
+                }
+            };
+            let stop_time = StopTime { arrival_time: parse(arrival_time), departure_time: parse(departure_time) };
+            assert!(stop_time.arrival_time <= stop_time.departure_time, "Line {name} departs {stop_name} before arriving.");
+            assert!(OptionExt::is_none_or(times.last(), |previous: &StopTime| previous.departure_time <= stop_time.arrival_time),
+                    "Line {name} arrives at {stop_name} before departing the previous stop.");
+            stops.push(stop_idx as StopIndex);
+            times.push(stop_time);
+        }
+        self.lines.push(SyntheticLine { name: name.to_string(), stops, stop_times: times, headway: 0, num_trips: 1 });
+        self
+    }
+
+    // Repeats the last line's trip every given number of minutes, for count trips in total.
+    // Panics if there is no line yet, or count is zero.
+    pub fn every(mut self, minutes: Timestamp, count: usize) -> Self {
+        assert!(count > 0, "A line needs at least one trip.");
+        let line = self.lines.last_mut().expect("Call line before every.");
+        line.headway = minutes * 60;
+        line.num_trips = count;
+        self
+    }
+
+    // Adds a one-way footpath from one stop to another, taking duration seconds to walk.
+    // Panics in build if Network::set_footpaths rejects it, e.g. if either stop doesn't exist.
+    pub fn footpath(mut self, from: &str, to: &str, duration: Timestamp) -> Self {
+        self.footpaths.push((from.to_string(), to.to_string(), duration));
+        self
+    }
+
+    pub fn transfer_time(mut self, transfer_time: Timestamp) -> Self {
+        self.transfer_time = transfer_time;
+        self
+    }
+
+    pub fn date(mut self, date: NaiveDate) -> Self {
+        self.date = date;
+        self
+    }
+
+    pub fn build(&self) -> Network {
+        let stops = self.stops.iter().enumerate().map(|(i, (name, point))| {
+            let point = point.unwrap_or(NetworkPoint { latitude: -37.8, longitude: 145. + 0.0114 * i as CoordType });
+            (name.as_str(), name.as_str(), point)
+        }).collect::<Vec<_>>();
+        let routes = self.lines.iter().map(|line| TimetableRoute {
+            line: line.name.clone(),
+            colour: Default::default(),
+            stops: line.stops.clone(),
+            trips: (0..line.num_trips).map(|trip| {
+                let offset = trip as Timestamp * line.headway;
+                let stop_times = line.stop_times.iter().map(|stop_time| StopTime {
+                    arrival_time: stop_time.arrival_time + offset,
+                    departure_time: stop_time.departure_time + offset,
+                }).collect();
+                (format!("{} {trip}", line.name), stop_times)
+            }).collect(),
+        }).collect();
+        let mut network = Network::from_timetable(&stops, routes, self.date, self.transfer_time);
+        if !self.footpaths.is_empty() {
+            let footpaths = self.footpaths.iter().map(|(from, to, duration)| (from.as_str(), to.as_str(), *duration)).collect::<Vec<_>>();
+            let rejected = network.set_footpaths(&footpaths);
+            assert!(rejected.is_empty(), "Footpaths were rejected: {rejected:?}");
+        }
+        network
+    }
+}
+
+impl Default for SyntheticNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A synthetic stand-in for the example scenario: Cheltenham to Greensborough at 08:30, changing at Flinders Street. The Frankston
+// line runs every 10 minutes and the Hurstbridge line every 15, with a City Circle loop and a Sandringham line for alternatives.
+pub fn build_synthetic_network() -> Network {
+    SyntheticNetwork::new()
+        .transfer_time(get_example_transfer_time())
+        .line("Frankston", &[
+            ("Cheltenham", "06:00", "06:00"),
+            ("Caulfield", "06:14", "06:15"),
+            ("Richmond", "06:24", "06:25"),
+            ("Flinders Street", "06:30", "06:30"),
+        ]).every(10, 60)
+        .line("Sandringham", &[
+            ("Richmond", "06:02", "06:02"),
+            ("Parliament", "06:06", "06:06"),
+        ]).every(20, 30)
+        .line("City Circle", &[
+            ("Flinders Street", "06:00", "06:00"),
+            ("Parliament", "06:04", "06:05"),
+            ("Melbourne Central", "06:07", "06:08"),
+            ("Flagstaff", "06:10", "06:10"),
+            ("Flinders Street", "06:14", "06:14"),
+        ]).every(5, 150)
+        .line("Hurstbridge", &[
+            ("Flinders Street", "06:03", "06:03"),
+            ("Parliament", "06:06", "06:07"),
+            ("Clifton Hill", "06:15", "06:16"),
+            ("Greensborough", "06:35", "06:35"),
+        ]).every(15, 40)
+        .build()
+}
+
+pub fn get_synthetic_scenario() -> (Network, StopIndex, Timestamp, StopIndex) {
+    let network = build_synthetic_network();
+    let start = get_example_start_stop_idx(&network);
+    let start_time = get_example_start_time();
+    let end = get_example_end_stop_idx(&network);
+    (network, start, start_time, end)
+}
+
+// Relative number of queries starting in each hour of the day, with morning and evening peaks.
+const HOURLY_QUERY_WEIGHTS: [u32; 24] = [0, 0, 0, 0, 0, 1, 3, 8, 10, 6, 4, 4, 4, 4, 4, 5, 8, 10, 7, 4, 3, 2, 2, 1];
+
+// Draws random origin-destination queries, deterministic under the seed, for benchmarks and soak tests.
+// Origins and destinations are weighted by the number of routes serving each stop (so stops with no service are never drawn), and
+// start times follow HOURLY_QUERY_WEIGHTS.
+pub struct QuerySampler {
+    rng: fastrand::Rng,
+    // Cumulative stop weights, indexed by stop index.
+    cumulative_stop_weights: Vec<usize>,
+}
+
+impl QuerySampler {
+    // Panics if fewer than two stops have service, as origins and destinations must differ.
+    pub fn new(network: &Network, seed: u64) -> Self {
+        let cumulative_stop_weights = network.stops.iter().scan(0, |total, stop| {
+            *total += stop.num_routes;
+            Some(*total)
+        }).collect::<Vec<_>>();
+        assert!(network.stops.iter().filter(|stop| stop.num_routes > 0).count() >= 2, "The network needs at least two stops with service.");
+        Self { rng: fastrand::Rng::with_seed(seed), cumulative_stop_weights }
+    }
+
+    fn sample_stop(&mut self) -> StopIndex {
+        let weight = self.rng.usize(0..*self.cumulative_stop_weights.last().unwrap());
+        self.cumulative_stop_weights.partition_point(|&cumulative_weight| cumulative_weight <= weight) as StopIndex
+    }
+
+    fn sample_time(&mut self) -> Timestamp {
+        let total_weight = HOURLY_QUERY_WEIGHTS.iter().sum::<u32>();
+        let mut weight = self.rng.u32(0..total_weight);
+        let hour = HOURLY_QUERY_WEIGHTS.iter().position(|&hour_weight| {
+            if weight < hour_weight {
+                return true;
+            }
+            weight -= hour_weight;
+            false
+        }).unwrap() as Timestamp;
+        hour * 3600 + self.rng.u32(0..3600)
+    }
+
+    // Returns n (start, start time, end) queries with distinct start and end stops.
+    pub fn sample(&mut self, n: usize) -> Vec<(StopIndex, Timestamp, StopIndex)> {
+        (0..n).map(|_| {
+            let start = self.sample_stop();
+            let end = loop {
+                let end = self.sample_stop();
+                if end != start {
+                    break end;
+                }
+            };
+            (start, self.sample_time(), end)
+        }).collect()
+    }
+}
+
+// Summarises the time each query in a batch took, e.g. "100 queries: mean 1.2ms, p50 1.0ms, p95 3.1ms, p99 3.9ms, max 4.0ms".
+pub fn summarise_query_times(mut durations: Vec<Duration>) -> String {
+    if durations.is_empty() {
+        return String::from("0 queries");
+    }
+    durations.sort_unstable();
+    let percentile = |p: usize| durations[(durations.len() - 1) * p / 100];
+    let mean = durations.iter().sum::<Duration>() / durations.len() as u32;
+    format!("{} queries: mean {mean:.1?}, p50 {:.1?}, p95 {:.1?}, p99 {:.1?}, max {:.1?}", durations.len(), percentile(50), percentile(95),
+            percentile(99), percentile(100))
+}
+
+// A tiny GTFS feed for tests of the GTFS parsing path, built declaratively and written to a temporary directory, e.g.
+// GtfsFixture::new().stop("A", "Stop A", -37.8, 145.).route("R", "Line", 2).trip("T", "R", "S").stop_times("T", &[("A", "08:00:00", "08:00:00")])
+// The required files (agency.txt, stops.txt, routes.txt, trips.txt and stop_times.txt) are always written, with a single agency.
+// Optional files are only written if they're given, so a test can target exactly one GTFS feature. Times and dates are written
+// as given, in GTFS format (HH:MM:SS and YYYYMMDD).
+#[derive(Default)]
+pub struct GtfsFixture {
+    stops: Vec<String>,
+    routes: Vec<String>,
+    trips: Vec<String>,
+    stop_times: Vec<String>,
+    // Optional files, as (file name, header, rows).
+    optional_files: Vec<(String, String, Vec<String>)>,
+}
+
+impl GtfsFixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn stop(self, id: &str, name: &str, latitude: f64, longitude: f64) -> Self {
+        self.stop_in_zone(id, name, latitude, longitude, "")
+    }
+
+    // A stop with a zone_id, for fare_rules.txt.
+    pub fn stop_in_zone(mut self, id: &str, name: &str, latitude: f64, longitude: f64, zone_id: &str) -> Self {
+        self.stops.push(format!("{id},{name},{latitude},{longitude},{zone_id}"));
+        self
+    }
+
+    // See the GTFS reference for route types, e.g. 2 is rail and 3 is bus.
+    pub fn route(mut self, id: &str, short_name: &str, route_type: u16) -> Self {
+        self.routes.push(format!("{id},agency,{short_name},,{route_type}"));
+        self
+    }
+
+    // An outbound trip.
+    pub fn trip(mut self, id: &str, route_id: &str, service_id: &str) -> Self {
+        self.trips.push(format!("{route_id},{service_id},{id},0"));
+        self
+    }
+
+    // The (stop ID, arrival time, departure time) of each stop of the trip, in order.
+    pub fn stop_times(mut self, trip_id: &str, stop_times: &[(&str, &str, &str)]) -> Self {
+        for (stop_sequence, (stop_id, arrival_time, departure_time)) in stop_times.iter().enumerate() {
+            self.stop_times.push(format!("{trip_id},{arrival_time},{departure_time},{stop_id},{}", stop_sequence + 1));
+        }
+        self
+    }
+
+    // Weekdays are seven 0s and 1s from Monday to Sunday, e.g. "1111100" for weekdays only.
+    pub fn calendar(self, service_id: &str, weekdays: &str, start_date: &str, end_date: &str) -> Self {
+        assert!(weekdays.len() == 7 && weekdays.bytes().all(|b| b == b'0' || b == b'1'), "Invalid weekdays {weekdays:?}.");
+        let weekdays = weekdays.chars().map(String::from).collect::<Vec<_>>().join(",");
+        self.row("calendar.txt", "service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date",
+                 &format!("{service_id},{weekdays},{start_date},{end_date}"))
+    }
+
+    // The exception type is 1 if service is added on the date, and 2 if it's removed.
+    pub fn calendar_date(self, service_id: &str, date: &str, exception_type: u8) -> Self {
+        self.row("calendar_dates.txt", "service_id,date,exception_type", &format!("{service_id},{date},{exception_type}"))
+    }
+
+    // Adds a row to any optional file, e.g. frequencies.txt or transfers.txt. The header must match earlier rows of the file.
+    pub fn row(mut self, file_name: &str, header: &str, row: &str) -> Self {
+        match self.optional_files.iter_mut().find(|(name, ..)| name == file_name) {
+            Some((_, existing_header, rows)) => {
+                assert_eq!(existing_header, header, "Mismatched headers for {file_name}.");
+                rows.push(row.to_string());
+            }
+            None => self.optional_files.push((file_name.to_string(), header.to_string(), vec![row.to_string()])),
+        }
+        self
+    }
+
+    // Writes the feed to a new temporary directory, which is removed when the returned value is dropped.
+    pub fn write(&self) -> io::Result<GtfsFixtureDir> {
+        static NEXT_FIXTURE: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!("raptor-gtfs-fixture-{}-{}", std::process::id(), NEXT_FIXTURE.fetch_add(1, Ordering::Relaxed)));
+        fs::create_dir_all(&path)?;
+        let fixture_dir = GtfsFixtureDir { path };
+
+        let agency = [String::from("agency,Agency,https://example.com,Australia/Melbourne")];
+        let files = [
+            ("agency.txt", "agency_id,agency_name,agency_url,agency_timezone", &agency[..]),
+            ("stops.txt", "stop_id,stop_name,stop_lat,stop_lon,zone_id", &self.stops[..]),
+            ("routes.txt", "route_id,agency_id,route_short_name,route_long_name,route_type", &self.routes[..]),
+            ("trips.txt", "route_id,service_id,trip_id,direction_id", &self.trips[..]),
+            ("stop_times.txt", "trip_id,arrival_time,departure_time,stop_id,stop_sequence", &self.stop_times[..]),
+        ];
+        let optional_files = self.optional_files.iter().map(|(name, header, rows)| (name.as_str(), header.as_str(), &rows[..]));
+        for (file_name, header, rows) in files.into_iter().chain(optional_files) {
+            let mut contents = format!("{header}\n");
+            for row in rows {
+                contents.push_str(row);
+                contents.push('\n');
+            }
+            fs::write(fixture_dir.path.join(file_name), contents)?;
+        }
+        Ok(fixture_dir)
+    }
+}
+
+// A GTFS feed written by GtfsFixture, removed on drop.
+pub struct GtfsFixtureDir {
+    path: PathBuf,
+}
+
+impl GtfsFixtureDir {
+    // The directory, which GtfsReader::read_from_path accepts.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for GtfsFixtureDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+// Why load_scenario failed.
+#[derive(Debug)]
+pub enum ScenarioError {
+    // The GTFS feed at the path couldn't be read.
+    Gtfs(PathBuf, Error),
+    MalformedVariable { variable: &'static str, value: String, reason: String },
+    UnknownStop { variable: &'static str, name: String },
+}
+
+impl std::fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScenarioError::Gtfs(path, error) => write!(f, "Couldn't read GTFS from {} (set RAPTOR_GTFS to use another feed): {error}", path.display()),
+            ScenarioError::MalformedVariable { variable, value, reason } => write!(f, "Malformed {variable}={value:?}: {reason}"),
+            ScenarioError::UnknownStop { variable, name } => write!(f, "No stop named {name:?} (from {variable}) in the network."),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+// Reads an environment variable, or None if it's unset.
+fn scenario_variable(variable: &'static str) -> Result<Option<String>, ScenarioError> {
+    match std::env::var(variable) {
+        Ok(value) => Ok(Some(value)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(std::env::VarError::NotUnicode(value)) => Err(ScenarioError::MalformedVariable {
+            variable,
+            value: value.to_string_lossy().into_owned(),
+            reason: String::from("not valid Unicode"),
+        }),
+    }
+}
+
+fn parse_scenario_variable<T, E: std::fmt::Display>(variable: &'static str, parse: impl FnOnce(&str) -> Result<T, E>) -> Result<Option<T>, ScenarioError> {
+    scenario_variable(variable)?.map(|value| parse(value.trim()).map_err(|e| ScenarioError::MalformedVariable { variable, reason: e.to_string(), value })).transpose()
+}
+
+// Loads the GTFS feed at RAPTOR_GTFS (a zip or directory), or the example feed if it's unset.
+pub fn load_scenario_gtfs() -> Result<Gtfs, ScenarioError> {
+    let path = match scenario_variable("RAPTOR_GTFS")? {
+        Some(path) => PathBuf::from(path),
+        None => find_dev_data_file("gtfs/melbourne.zip").map_err(|e| ScenarioError::Gtfs(PathBuf::from("dev_utils/gtfs/melbourne.zip"), e.into()))?,
+    };
+    GtfsReader::default().read_shapes(false).read_from_path(path.to_str().unwrap_or_default()).map_err(|e| ScenarioError::Gtfs(path, e))
+}
+
+// Like get_example_scenario, but configurable for other cities through environment variables, each defaulting to the example:
+// RAPTOR_GTFS (see load_scenario_gtfs), RAPTOR_DATE (see utils::parse_date), RAPTOR_ORIGIN and RAPTOR_DEST (stop names, see
+// Network::get_stop_idx_from_name) and RAPTOR_TIME (see utils::parse_time).
+pub fn load_scenario() -> Result<(Network, StopIndex, Timestamp, StopIndex), ScenarioError> {
+    // Check the variables before the slow GTFS load.
+    let date = parse_scenario_variable("RAPTOR_DATE", |date| utils::parse_date(date, None))?.unwrap_or_else(get_example_date);
+    let start_time = parse_scenario_variable("RAPTOR_TIME", utils::parse_time)?.unwrap_or_else(get_example_start_time);
+    let origin = scenario_variable("RAPTOR_ORIGIN")?;
+    let destination = scenario_variable("RAPTOR_DEST")?;
+
+    let gtfs = load_scenario_gtfs()?;
+    let network = Network::new(&gtfs, None, date, get_example_transfer_time());
+    let find_stop = |variable: &'static str, name: Option<String>, default: &str| {
+        let name = name.unwrap_or_else(|| default.to_string());
+        network.get_stop_idx_from_name(name.trim()).ok_or(ScenarioError::UnknownStop { variable, name })
+    };
+    let start = find_stop("RAPTOR_ORIGIN", origin, "Cheltenham")?;
+    let end = find_stop("RAPTOR_DEST", destination, "Greensborough")?;
+    Ok((network, start, start_time, end))
+}
+
+// A stable text form of a journey for snapshot tests, with one line per leg. Legs use GTFS trip and stop IDs and HH:MM:SS times,
+// so the snapshot doesn't depend on stop and route indices (which follow HashMap order when loading GTFS) or on float formatting.
+pub fn journey_snapshot(journey: &Journey) -> String {
+    let network = journey.network;
+    let stop_id = |stop: StopIndex| network.stop_id(stop as usize);
+    let mut snapshot = String::new();
+    for leg in journey.legs.iter() {
+        let line = match leg {
+            Leg::Vehicle(leg) => format!("trip {} from {} at {} to {} at {}", leg.trip_id(network), stop_id(leg.boarded_stop),
+                                         utils::get_time_str(leg.boarded_time), stop_id(leg.arrival_stop), utils::get_time_str(leg.arrival_time)),
+            Leg::Transfer { at_stop, transfer_time, wait_time } => format!("transfer at {} for {transfer_time} s and wait {wait_time} s", stop_id(*at_stop)),
+            Leg::Walk { from, to, duration, .. } => format!("walk from {} to {} for {duration} s", stop_id(*from), stop_id(*to)),
+        };
+        snapshot.push_str(&line);
+        snapshot.push('\n');
+    }
+    if journey.legs.is_empty() {
+        snapshot.push_str("no journey\n");
+    }
+    snapshot
+}
+
+// Compares actual against the snapshot dev_utils/snapshots/{name}.snap, panicking with both if they differ or if the snapshot
+// is missing, so that a snapshot can't silently go unchecked. Set RAPTOR_UPDATE_SNAPSHOTS=1 to write the snapshot instead.
+pub fn assert_snapshot(name: &str, actual: &str) {
+    // Snapshots are part of the source, not the example data, so they're always in this crate.
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("snapshots").join(format!("{name}.snap"));
+    if std::env::var("RAPTOR_UPDATE_SNAPSHOTS").is_ok_and(|value| value == "1") {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    match fs::read_to_string(&path) {
+        Ok(expected) => {
+            assert!(expected == actual, "Snapshot {name} changed (set RAPTOR_UPDATE_SNAPSHOTS=1 to accept).\nExpected:\n{expected}\nActual:\n{actual}");
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            panic!("Snapshot {name} is missing. Run the tests with RAPTOR_UPDATE_SNAPSHOTS=1 and commit {}.", path.display());
+        }
+        Err(e) => panic!("Failed to read snapshot {}: {e}", path.display()),
+    }
+}
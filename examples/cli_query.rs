@@ -1,11 +1,9 @@
 use std::io::{stdout, Write};
 
-use chrono::NaiveDate;
-
-use raptor::{csa_query, raptor_query, utils, Journey, Network};
+use raptor::{csa_query, raptor_query, utils, Journey, Network, StopIdx};
 use raptor::network::StopIndex;
 
-use dev_utils::load_example_gtfs;
+use dev_utils::load_scenario_gtfs;
 
 pub fn get_stop_from_user(network: &Network, prompt: &str) -> Result<StopIndex, std::io::Error> {
     loop {
@@ -16,27 +14,44 @@ pub fn get_stop_from_user(network: &Network, prompt: &str) -> Result<StopIndex,
         if let Some(stop) = network.get_stop_idx_from_name(stop_name.trim()) {
             return Ok(stop);
         }
-        println!("Stop not found. Please try again.");
+
+        // Offer the closest matches instead.
+        let matches = network.search_stops(stop_name.trim(), 5);
+        if matches.is_empty() {
+            println!("Stop not found. Please try again.");
+            continue;
+        }
+        println!("Stop not found. Did you mean:");
+        for (i, &(stop, _)) in matches.iter().enumerate() {
+            println!("  {}. {}", i + 1, utils::get_short_stop_name(network.stop_name(stop as usize)));
+        }
+        print!("Enter a number, or press enter to try again: ");
+        stdout().flush()?;
+        let mut choice = String::new();
+        std::io::stdin().read_line(&mut choice)?;
+        if let Some(&(stop, _)) = choice.trim().parse::<usize>().ok().and_then(|i| matches.get(i.wrapping_sub(1))) {
+            return Ok(stop);
+        }
     }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let gtfs = load_example_gtfs()?;
+    let gtfs = load_scenario_gtfs()?;
 
     gtfs.print_stats();
     println!();
 
     // Get user input for query.
     let journey_date = loop {
-        // Hardcode year to 2024.
-        let mut date_str = String::from("2024/");
-        print!("When are you travelling (in 2024)? (DD/MM): ");
+        let mut date_str = String::new();
+        print!("When are you travelling? (DD/MM, DD/MM/YYYY or YYYY-MM-DD): ");
         stdout().flush()?;
         std::io::stdin().read_line(&mut date_str)?;
-        match NaiveDate::parse_from_str(date_str.trim(), "%Y/%d/%m") {
+        // The example feed is for 2024.
+        match utils::parse_date(date_str.trim(), Some(2024)) {
             Ok(parsed_date) => break parsed_date,
             Err(e) => {
-                println!("Invalid date format: {e:?}. Please try again.");
+                println!("{e} Please try again.");
             }
         }
     };
@@ -57,13 +72,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             print!("What time are you starting? (HH:MM): ");
             stdout().flush()?;
             std::io::stdin().read_line(&mut time_str)?;
-            // Remove trailing whitespace and append seconds so it can be parsed.
-            let mut time_str = String::from(time_str.trim_end());
-            time_str += ":00";
-            match utils::parse_time(&time_str) {
+            match utils::parse_time(time_str.trim()) {
                 Ok(time) => break time,
                 Err(e) => {
-                    println!("Invalid time format: {e:?}. Please try again.");
+                    println!("{e} Please try again.");
                 }
             }
         };
@@ -72,10 +84,10 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!();
         println!(
             "Start: {} at time {}",
-            network.get_stop(start as usize).name,
-            utils::get_time_str(start_time)
+            network.stop_name(start as usize),
+            utils::get_time_str_wrapped(start_time)
         );
-        println!("End: {}", network.get_stop(end as usize).name);
+        println!("End: {}", network.stop_name(end as usize));
         println!();
 
         let num_iterations = 10;
@@ -83,14 +95,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let mut journey = Journey::empty(&network);
         let query_start = std::time::Instant::now();
         for _ in 0..num_iterations {
-            journey = raptor_query(&network, start, start_time, end).unwrap();
+            journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
         }
         println!("RAPTOR:");
         println!("Query took {:?}", query_start.elapsed() / num_iterations);
         println!("{journey}");
         let query_start = std::time::Instant::now();
         for _ in 0..num_iterations {
-            journey = csa_query(&network, start, start_time, end).unwrap();
+            journey = csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
         }
         println!("CSA:");
         println!("Query took {:?}", query_start.elapsed() / num_iterations);
@@ -0,0 +1,31 @@
+use raptor::journey::JourneyPreferences;
+use raptor::network::PathfindingCost;
+use raptor::{mc_raptor_query, Label, StopIdx};
+
+use dev_utils::{load_example_costs, load_scenario};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let (network, start, start_time, end) = load_scenario()?;
+    let costs = load_example_costs(&network)?.costs;
+
+    // Trade off travel time against crowding, valuing each unit of crowding cost at 10 seconds.
+    // Labels that didn't board a trip (only the start label) are never preferred.
+    let preferences = JourneyPreferences {
+        utility_function: Box::new(|label: &Label, start_time| {
+            if !label.has_boarded() {
+                return PathfindingCost::INFINITY;
+            }
+            (label.arrival_time() - start_time) as PathfindingCost + 10. * label.cost()
+        }),
+        ..Default::default()
+    };
+
+    for journey in mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &costs, &preferences) {
+        match journey {
+            Ok(journey) => println!("{journey}"),
+            Err(e) => println!("{e}"),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use raptor::isochrone::DEFAULT_ISOCHRONE_BANDS;
+use raptor::{utils, Isochrone};
+
+use dev_utils::load_scenario;
+
+// Usage: cargo run --example isochrone -- [origin stop name] [time] [output path] [--hulls]
+// Missing arguments fall back to the dev_utils scenario (see load_scenario) and isochrone.geojson.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+    let with_hulls = args.iter().any(|arg| arg == "--hulls");
+    args.retain(|arg| arg != "--hulls");
+
+    let (network, scenario_start, scenario_start_time, _) = load_scenario()?;
+    network.print_stats();
+
+    let start = match args.first() {
+        Some(stop_name) => network.get_stop_idx_from_name(stop_name).ok_or_else(|| format!("Stop {stop_name:?} not found."))?,
+        None => scenario_start,
+    };
+    let start_time = match args.get(1) {
+        Some(time) => utils::parse_time(time)?,
+        None => scenario_start_time,
+    };
+    let output_path = args.get(2).map_or("isochrone.geojson", String::as_str);
+
+    let isochrone = Isochrone::new(&network, start, start_time, &DEFAULT_ISOCHRONE_BANDS);
+    println!("Stops reachable from {} at {}:", network.stop_name(start as usize), utils::get_time_str(start_time));
+    for (limit, band) in isochrone.band_limits.iter().zip(&isochrone.bands) {
+        println!("  Within {} minutes: {}", limit / 60, band.len());
+    }
+
+    std::fs::write(output_path, isochrone.to_geojson(&network, with_hulls))?;
+    println!("Wrote {output_path}.");
+
+    Ok(())
+}
@@ -1,24 +1,20 @@
-use std::iter::repeat_with;
-use raptor::network::PathfindingCost;
-use raptor::mc_raptor_query;
+use raptor::{mc_raptor_query, StopIdx};
 
-use dev_utils::get_example_scenario;
+use dev_utils::{load_example_costs, load_scenario};
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let (network, start, start_time, end) = get_example_scenario();
+    let (network, start, start_time, end) = load_scenario()?;
     network.print_stats();
 
-    // Random pathfinding costs.
-    fastrand::seed(7);
-    let costs: Vec<_> = repeat_with(|| fastrand::f32() as PathfindingCost).take(network.stop_times.len()).collect();
+    // Crowding costs from patronage data.
+    let costs = load_example_costs(&network)?.costs;
     let preferences = raptor::journey::JourneyPreferences::default();
-    let journey = mc_raptor_query::<5>(&network, start, start_time, &[end], &costs, &preferences);
+    let journey = mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &costs, &preferences);
 
     for journey in journey {
-        if let Ok(journey) = journey {
-            println!("{journey}");
-        } else {
-            println!("No journey found.");
+        match journey {
+            Ok(journey) => println!("{journey}"),
+            Err(e) => println!("{e}"),
         }
     }
 
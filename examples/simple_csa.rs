@@ -1,18 +1,17 @@
-use raptor::csa_query;
+use raptor::{csa_query, StopIdx};
 
-use dev_utils::get_example_scenario;
+use dev_utils::load_scenario;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let (mut network, start, start_time, end) = get_example_scenario();
+    let (mut network, start, start_time, end) = load_scenario()?;
     network.build_connections();
     network.print_stats();
 
-    let journey = csa_query(&network, start, start_time, end);
+    let journey = csa_query(&network, StopIdx(start), start_time, StopIdx(end));
 
-    if let Ok(journey) = journey {
-        println!("{journey}");
-    } else {
-        println!("No journey found.");
+    match journey {
+        Ok(journey) => println!("{journey}"),
+        Err(e) => println!("{e}"),
     }
 
     Ok(())
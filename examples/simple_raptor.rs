@@ -1,17 +1,16 @@
-use raptor::raptor_query;
+use raptor::{raptor_query, StopIdx};
 
-use dev_utils::get_example_scenario;
+use dev_utils::load_scenario;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let (network, start, start_time, end) = get_example_scenario();
+    let (network, start, start_time, end) = load_scenario()?;
     network.print_stats();
 
-    let journey = raptor_query(&network, start, start_time, end);
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end));
 
-    if let Ok(journey) = journey {
-        println!("{journey}");
-    } else {
-        println!("No journey found.");
+    match journey {
+        Ok(journey) => println!("{journey}"),
+        Err(e) => println!("{e}"),
     }
 
     Ok(())
@@ -0,0 +1,37 @@
+use chrono::NaiveDate;
+use raptor::network::{NetworkPoint, StopTime, Timestamp, TimetableRoute};
+use raptor::{raptor_query, Network, StopIdx};
+
+// The flow for running queries in the browser: a server builds the network (from GTFS, or here from a timetable) and serializes
+// it, and the query-only build deserializes it and runs queries. The library part of this compiles for wasm32-unknown-unknown:
+//   cargo check --lib --target wasm32-unknown-unknown --no-default-features --features query
+// This example runs natively, since examples also build the dev-dependencies, which need a filesystem.
+// Usage: cargo run --example wasm_query --features query
+
+// Builds a two-line network: an express from Flinders Street to Caulfield, and a stopping service via Richmond.
+fn build_network() -> Network {
+    let trip = |trip_id: &str, offsets: &[Timestamp]| (trip_id.to_string(), offsets.iter().map(|&offset| StopTime { arrival_time: 8 * 3600 + offset, departure_time: 8 * 3600 + offset }).collect());
+    let point = |latitude, longitude| NetworkPoint { latitude, longitude };
+    Network::from_timetable(&[
+        ("Flinders Street", "fss", point(-37.8183, 144.9671)),
+        ("Richmond", "rmd", point(-37.8240, 144.9901)),
+        ("Caulfield", "cfd", point(-37.8774, 145.0424)),
+    ], vec![
+        TimetableRoute { line: "Express".to_string(), colour: Default::default(), stops: vec![0, 2], trips: vec![trip("express", &[600, 1500])] },
+        TimetableRoute { line: "Stopping".to_string(), colour: Default::default(), stops: vec![0, 1, 2], trips: vec![trip("stopping", &[0, 300, 1200])] },
+    ], NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(), 120)
+}
+
+// What the browser would run on the bytes fetched from the server.
+fn query(serialized_network: &str, start_time: Timestamp) -> Result<String, Box<dyn std::error::Error>> {
+    let network: Network = serde_json::from_str(serialized_network)?;
+    let journey = raptor_query(&network, StopIdx(network.get_stop_idx("fss")), start_time, StopIdx(network.get_stop_idx("cfd")))?;
+    Ok(journey.to_string())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let serialized_network = serde_json::to_string(&build_network())?;
+    println!("Serialized network is {} bytes.", serialized_network.len());
+    println!("{}", query(&serialized_network, 8 * 3600)?);
+    Ok(())
+}
@@ -0,0 +1,92 @@
+#ifndef RAPTOR_H
+#define RAPTOR_H
+
+/* Generated by cbindgen from src/ffi.rs. Don't edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum RaptorLegKind {
+  RAPTOR_LEG_KIND_VEHICLE = 0,
+  RAPTOR_LEG_KIND_TRANSFER = 1,
+  RAPTOR_LEG_KIND_WALK = 2,
+} RaptorLegKind;
+
+typedef enum RaptorStatus {
+  RAPTOR_STATUS_OK = 0,
+  RAPTOR_STATUS_NULL_ARGUMENT = 1,
+  RAPTOR_STATUS_INVALID_UTF8 = 2,
+  RAPTOR_STATUS_IO = 3,
+  RAPTOR_STATUS_INVALID_NETWORK = 4,
+  RAPTOR_STATUS_UNKNOWN_STOP = 5,
+  RAPTOR_STATUS_NO_JOURNEY_FOUND = 6,
+  RAPTOR_STATUS_PANIC = 7,
+} RaptorStatus;
+
+typedef struct Network Network;
+
+#if !defined(RAPTOR_SMALL_STOP_INDICES)
+typedef uint32_t StopIndex;
+#endif
+
+#if defined(RAPTOR_SMALL_STOP_INDICES)
+typedef uint16_t StopIndex;
+#endif
+
+typedef uint32_t Timestamp;
+
+#if !defined(RAPTOR_SMALL_ROUTE_INDICES)
+typedef uint32_t RouteIndex;
+#endif
+
+#if defined(RAPTOR_SMALL_ROUTE_INDICES)
+typedef uint16_t RouteIndex;
+#endif
+
+typedef uint32_t TripOrder;
+
+typedef struct RaptorLeg {
+  enum RaptorLegKind kind;
+  StopIndex from_stop;
+  StopIndex to_stop;
+  Timestamp departure_time;
+  Timestamp arrival_time;
+  RouteIndex route_idx;
+  TripOrder trip_order;
+} RaptorLeg;
+
+typedef struct RaptorJourney {
+  struct RaptorLeg *legs;
+  size_t num_legs;
+  Timestamp departure_time;
+  Timestamp arrival_time;
+} RaptorJourney;
+
+typedef float CoordType;
+
+enum RaptorStatus raptor_network_load(const char *path, struct Network **out_network);
+
+void raptor_network_free(struct Network *network);
+
+enum RaptorStatus raptor_stop_index(const struct Network *network,
+                                    const char *stop_id,
+                                    StopIndex *out_stop);
+
+enum RaptorStatus raptor_stop_id(const struct Network *network,
+                                 StopIndex stop,
+                                 char *buffer,
+                                 size_t buffer_len,
+                                 size_t *out_len);
+
+enum RaptorStatus raptor_query_c(const struct Network *network,
+                                 const char *start_stop_id,
+                                 Timestamp start_time,
+                                 const char *end_stop_id,
+                                 struct RaptorJourney *out_journey);
+
+void raptor_journey_free(struct RaptorJourney *journey);
+
+#endif  /* RAPTOR_H */
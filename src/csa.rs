@@ -1,31 +1,43 @@
 use crate::{Journey, Network};
 use crate::journey::{Boarding, JourneyPreferences, JourneyResult, TauEntry};
-use crate::network::{PathfindingCost, StopIndex, Timestamp};
+use crate::index::StopIdx;
+use crate::network::{CostProvider, GlobalTripIndex, StopIndex, Timestamp};
+use crate::time::{Seconds, Time};
 
 // Run a connection scanning algorithm (CSA) query on the network.
-pub fn csa_query(network: &Network, start: StopIndex, start_time: Timestamp, end: StopIndex) -> JourneyResult {
+pub fn csa_query(network: &Network, start: StopIdx, start_time: Timestamp, end: StopIdx) -> JourneyResult {
+    csa_query_with_capacity(network, start, start_time, end, |_, _| true)
+}
+
+// Run a CSA query that refuses boardings where can_board(trip, stop_times_index) is false (e.g. because the trip is full at that stop).
+// Staying on a trip that has already been boarded is always allowed.
+pub fn csa_query_with_capacity(network: &Network, start: StopIdx, start_time: Timestamp, end: StopIdx, can_board: impl Fn(GlobalTripIndex, usize) -> bool) -> JourneyResult {
     if start == end {
-        return Ok(Journey::empty(network));
+        return Ok(Journey::empty_at(network, start.0, start_time));
     }
 
     // Require connections be built
     debug_assert!(network.connections.len() > 0, "Connections must be built before running CSA.");
 
-    let start = start as usize;
-    let end = end as usize;
+    let start = start.idx();
+    let end = end.idx();
 
     //  τ[i] records the earliest arrival time at stop i.
     let mut tau = vec![TauEntry::default(); network.stops.len()];
-    tau[start] = TauEntry { time: start_time, boarding: None };
-    let mut end_time = Timestamp::MAX;
+    tau[start] = TauEntry { time: Time(start_time), boarding: None, walked_from: None };
+    let mut end_time = Time::MAX;
+    relax_footpaths(network, start, end, &mut tau, &mut end_time);
 
     let mut trip_reachable = vec![false; network.num_trips as usize];
+    // Where each reachable trip was first boarded, for riding through stops it skips.
+    let mut trip_boardings: Vec<Option<Boarding>> = vec![None; network.num_trips as usize];
 
-    // Start Criterion Optimisation: Binary search start connection (first connection where departure time >= start time).
-    let start_connection = network.connections.partition_point(|connection| connection.departure_time < start_time);
+    // Start Criterion Optimisation: Look up the start connection (first connection where departure time >= start time) in the bucket index.
+    let start_connection = network.first_connection_at_or_after(start_time);
 
     for connection in &network.connections[start_connection..] {
-        if connection.departure_time >= end_time {
+        let (departure_time, arrival_time) = (Time(connection.departure_time), Time(connection.arrival_time));
+        if departure_time >= end_time {
             break;
         }
 
@@ -33,49 +45,89 @@ pub fn csa_query(network: &Network, start: StopIndex, start_time: Timestamp, end
         let departure_idx = connection.departure_idx as usize;
         let arrival_idx = connection.arrival_idx as usize;
 
-        let transfer_time = if arrival_idx == start {
+        // The transfer is at the departure stop, and there is none at the start, or after walking as the footpath's duration includes it.
+        let transfer_time = if departure_idx == start || tau[departure_idx].walked_from.is_some() {
             0
         } else {
-            network.transfer_times[arrival_idx]
+            let from_route = tau[departure_idx].boarding.as_ref().map(|boarding| boarding.trip.route_idx);
+            network.get_route_transfer_time(connection.departure_idx, from_route, connection.trip.route_idx)
         };
         
         if !trip_reachable[sequential_trip_idx] {
-            if tau[departure_idx].time.saturating_add(transfer_time) > connection.departure_time {
+            if tau[departure_idx].time.saturating_add(Seconds(transfer_time)) > departure_time {
                 // Unreachable.
                 continue;
             }
 
+            // Full trips, and trips that skip this stop, can't be boarded.
+            let route = &network.routes[connection.trip.route().idx()];
+            let index = route.get_stop_times_index(connection.trip.trip().idx(), connection.departure_stop_order as usize);
+            if !can_board(connection.trip, index) || route.is_skipped(connection.trip.trip().idx(), connection.departure_stop_order as usize) {
+                continue;
+            }
+
             // Reachable.
             trip_reachable[sequential_trip_idx] = true;
+            trip_boardings[sequential_trip_idx] = Some(Boarding::from(connection));
         }
 
-        if connection.arrival_time < tau[arrival_idx].time {
-            tau[arrival_idx].time = connection.arrival_time;
+        // Passengers can't alight where the trip skips the stop.
+        let route = &network.routes[connection.trip.route().idx()];
+        let trip_order = connection.trip.trip().idx();
+        let departure_stop_order = connection.departure_stop_order as usize;
+        if arrival_time < tau[arrival_idx].time && !route.is_skipped(trip_order, departure_stop_order + 1) {
+            tau[arrival_idx].time = arrival_time;
+            tau[arrival_idx].walked_from = None;
 
-            if let Some(boarding) = tau[departure_idx].boarding.clone() {
+            match tau[departure_idx].boarding.clone() {
                 // If travelling along the same trip, use the same boarding.
-                if boarding.trip == connection.trip {
-                    tau[arrival_idx].boarding = Some(boarding);
-                } else {
-                    tau[arrival_idx].boarding = Some(Boarding::from(connection));
+                Some(boarding) if boarding.trip == connection.trip => tau[arrival_idx].boarding = Some(boarding),
+                // Changing onto the trip here needs the transfer time, and the trip must stop here and have room. Otherwise it was
+                // boarded earlier, e.g. where it passed through this stop without stopping. No boarding is ever recorded at a stop
+                // the trip skips.
+                _ if route.is_skipped(trip_order, departure_stop_order)
+                    || tau[departure_idx].time.saturating_add(Seconds(transfer_time)) > departure_time
+                    || !can_board(connection.trip, route.get_stop_times_index(trip_order, departure_stop_order)) => {
+                    tau[arrival_idx].boarding = trip_boardings[sequential_trip_idx].clone();
+                }
+                Some(_) => tau[arrival_idx].boarding = Some(Boarding::from(connection)),
+                // Boarding where the passenger walked to.
+                None if departure_idx != start => tau[arrival_idx].boarding = Some(Boarding::from(connection)),
+                None => {
+                    // This should only happen to the start stop.
+                    debug_assert!(departure_idx == start);
+                    tau[departure_idx].boarding = Some(Boarding::from(connection));
+                    tau[arrival_idx].boarding = tau[departure_idx].boarding.clone();
                 }
-            } else {
-                // This should only happen to the start stop.
-                debug_assert!(departure_idx == start);
-                tau[departure_idx].boarding = Some(Boarding::from(connection));
-                tau[arrival_idx].boarding = tau[departure_idx].boarding.clone();
             }
 
             if arrival_idx == end {
-                end_time = connection.arrival_time;
+                end_time = arrival_time;
             }
+            relax_footpaths(network, arrival_idx, end, &mut tau, &mut end_time);
         }
     }
 
     Journey::from_tau(&tau, network, start, end)
 }
 
-pub fn mc_csa_query<'a>(_network: &'a Network, _start: StopIndex, _start_time: Timestamp, _end: StopIndex, _costs: &[PathfindingCost], _path_preferences: &JourneyPreferences) -> JourneyResult<'a> {
+// Walks the footpaths from stop, which was just reached by a vehicle (or is the start), updating the stops they reach earlier.
+// Walks aren't chained, as in RAPTOR. A walk arrives no earlier than the connection that reached stop, so the connections
+// departing where it arrives haven't been scanned yet.
+fn relax_footpaths(network: &Network, stop: usize, end: usize, tau: &mut [TauEntry], end_time: &mut Time) {
+    for footpath in network.footpaths_from(stop as StopIndex) {
+        let to = footpath.to as usize;
+        let arrival_time = tau[stop].time.saturating_add(Seconds(network.get_footpath_time(footpath)));
+        if arrival_time < tau[to].time.min(*end_time) {
+            tau[to] = TauEntry { time: arrival_time, boarding: None, walked_from: Some(stop as StopIndex) };
+            if to == end {
+                *end_time = arrival_time;
+            }
+        }
+    }
+}
+
+pub fn mc_csa_query<'a>(_network: &'a Network, _start: StopIdx, _start_time: Timestamp, _end: StopIdx, _costs: &(impl CostProvider + ?Sized), _path_preferences: &JourneyPreferences) -> JourneyResult<'a> {
     /*
     if start == end {
         return Journey::empty(network);
@@ -89,7 +141,7 @@ pub fn mc_csa_query<'a>(_network: &'a Network, _start: StopIndex, _start_time: T
 
     //  τ[i] records the earliest arrival time at stop i.
     let mut tau = vec![TauEntry::default(); network.stops.len()];
-    tau[start] = TauEntry { time: start_time, boarding: None };
+    tau[start] = TauEntry { time: start_time, boarding: None, walked_from: None };
     let mut end_time = Timestamp::MAX;
 
     let mut trip_reachable = vec![false; network.num_trips as usize];
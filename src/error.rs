@@ -0,0 +1,24 @@
+use crate::journey::JourneyError;
+use crate::network::{AddTripError, CancelError, DelayError, NetworkError, UnknownStop};
+use crate::utils::{DateParseError, TimeParseError};
+
+// Any error returned by the crate, so callers can use ? across queries, network edits and parsing.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Journey(#[from] JourneyError),
+    #[error(transparent)]
+    Network(#[from] NetworkError),
+    #[error(transparent)]
+    UnknownStop(#[from] UnknownStop),
+    #[error(transparent)]
+    CancelTrip(#[from] CancelError),
+    #[error(transparent)]
+    AddTrip(#[from] AddTripError),
+    #[error(transparent)]
+    Delay(#[from] DelayError),
+    #[error(transparent)]
+    TimeParse(#[from] TimeParseError),
+    #[error(transparent)]
+    DateParse(#[from] DateParseError),
+}
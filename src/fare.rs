@@ -0,0 +1,151 @@
+// Fares of completed journeys, in the spirit of GTFS fare_attributes.txt and fare_rules.txt. Stops belong to numbered zones, and
+// a leg costs the fare of the highest zone it touches. As with Melbourne's 2-hour fares, a zone fare also covers the legs boarded
+// within a transfer window, so the highest zone touched in the window caps what they cost together. Lines can instead charge a
+// flat fare per boarding.
+use crate::journey::VehicleLeg;
+use crate::network::Timestamp;
+use crate::{Journey, Network};
+#[cfg(feature = "gtfs")]
+use gtfs_structures::Gtfs;
+use std::collections::HashMap;
+
+// An amount of money in the smallest unit of its currency, e.g. cents.
+pub type FareAmount = u32;
+
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FareTable {
+    // The zone of each stop (indexed by stop index), numbered from 1. Zone 0 means the stop has no zone.
+    pub stop_zones: Vec<u8>,
+    // The fare of travel touching zones up to zone z is zone_fares[z - 1], so fares should increase with the zone.
+    pub zone_fares: Vec<FareAmount>,
+    // Flat fares per boarding, keyed by line, for lines that don't charge zone fares.
+    pub line_fares: HashMap<String, FareAmount>,
+    // How long after boarding a zone fare covers further boardings, None if every leg is paid for separately.
+    pub transfer_window: Option<Timestamp>,
+}
+
+impl FareTable {
+    // Reads the fares of a GTFS feed from fare_attributes.txt and fare_rules.txt, with stops indexed as in the network. Returns
+    // None if the feed has no fares.
+    // Zone IDs must be zone numbers: stops whose zone_id isn't one have no zone. A rule for a route charges a line fare, and any
+    // other rule charges a zone fare for the highest zone it names. Zones without a rule of their own cost the same as the zone
+    // below (or the lowest zone with one), and the transfer window is the longest transfer_duration of the zone fares. Prices
+    // are read in cents.
+    #[cfg(feature = "gtfs")]
+    pub fn from_gtfs(gtfs: &Gtfs, network: &Network) -> Option<Self> {
+        if gtfs.fare_attributes.is_empty() {
+            return None;
+        }
+        let parse_zone = |zone_id: Option<&String>| zone_id.and_then(|zone_id| zone_id.trim().parse::<u8>().ok()).unwrap_or(0);
+
+        let mut stop_zones = vec![0; network.stops.len()];
+        for (stop_id, stop) in &gtfs.stops {
+            if let Some(stop_idx) = network.try_get_stop_idx(stop_id) {
+                stop_zones[stop_idx as usize] = parse_zone(stop.zone_id.as_ref());
+            }
+        }
+
+        let mut zone_fares = Vec::<Option<FareAmount>>::new();
+        let mut line_fares = HashMap::new();
+        let mut transfer_window = None;
+        for rule in gtfs.fare_rules.values().flatten() {
+            let Some(attribute) = gtfs.fare_attributes.get(&rule.fare_id) else {
+                log::warn!("Fare rule for unknown fare {}.", rule.fare_id);
+                continue;
+            };
+            let Ok(price) = attribute.price.trim().parse::<f64>() else {
+                log::warn!("Fare {} has malformed price {:?}.", attribute.id, attribute.price);
+                continue;
+            };
+            let price = (price * 100.).round() as FareAmount;
+
+            if let Some(route_id) = &rule.route_id {
+                if let Some(route) = gtfs.routes.get(route_id) {
+                    line_fares.insert(crate::network::gtfs_line_name(route).to_string(), price);
+                }
+                continue;
+            }
+            let zone = [&rule.origin_id, &rule.destination_id, &rule.contains_id].into_iter().map(|zone_id| parse_zone(zone_id.as_ref())).max().unwrap_or(0);
+            if zone == 0 {
+                continue;
+            }
+            if zone_fares.len() < zone as usize {
+                zone_fares.resize(zone as usize, None);
+            }
+            // The cheapest fare that covers the zone.
+            let zone_fare = &mut zone_fares[zone as usize - 1];
+            *zone_fare = Some(zone_fare.map_or(price, |fare| fare.min(price)));
+            if let Some(duration) = attribute.transfer_duration {
+                transfer_window = Some(transfer_window.unwrap_or(0).max(duration as Timestamp));
+            }
+        }
+
+        let mut below = zone_fares.iter().flatten().next().copied().unwrap_or(0);
+        let zone_fares = zone_fares.into_iter().map(|fare| {
+            below = fare.unwrap_or(below);
+            below
+        }).collect();
+        Some(Self { stop_zones, zone_fares, line_fares, transfer_window })
+    }
+
+    // The fare of travel touching zones up to the given zone.
+    fn zone_fare(&self, zone: u8) -> Option<FareAmount> {
+        self.zone_fares.get((zone as usize).checked_sub(1)?).copied()
+    }
+
+    // The highest zone touched by a leg, including the stops passed through. None if any of the stops has no zone.
+    fn leg_zone(&self, leg: &VehicleLeg, network: &Network) -> Option<u8> {
+        let route = &network.routes[leg.trip.route().idx()];
+        let stops = &route.get_stops(&network.route_stops)[leg.boarded_stop_order as usize..=leg.arrival_stop_order as usize];
+        stops.iter().try_fold(0, |max_zone, &stop| {
+            let zone = self.stop_zones.get(stop as usize).copied().filter(|&zone| zone > 0)?;
+            Some(max_zone.max(zone))
+        })
+    }
+}
+
+// The fare of a journey, itemised by vehicle leg.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fare {
+    // What each vehicle leg would cost on its own, in journey order.
+    pub leg_fares: Vec<FareAmount>,
+    // What the journey costs: the line fares, plus for each transfer window the zone fare of the highest zone touched in it.
+    pub total: FareAmount,
+}
+
+impl Journey<'_> {
+    // Prices the journey with the network's fare table. Returns None if the network has no fares, or a zone fare leg touches a
+    // stop without a zone or a zone without a fare.
+    pub fn fare(&self) -> Option<Fare> {
+        let fares = self.network.fares.as_ref()?;
+        let mut leg_fares = Vec::with_capacity(self.legs_vehicle().count());
+        let mut total = 0;
+        // When the current transfer window opened, and the highest zone touched since.
+        let mut window: Option<(Timestamp, u8)> = None;
+        for leg in self.legs_vehicle() {
+            if let Some(&line_fare) = fares.line_fares.get(leg.line_name(self.network)) {
+                leg_fares.push(line_fare);
+                total += line_fare;
+                continue;
+            }
+
+            let zone = fares.leg_zone(leg, self.network)?;
+            leg_fares.push(fares.zone_fare(zone)?);
+            match window {
+                Some((opened, window_zone)) if fares.transfer_window.is_some_and(|duration| leg.boarded_time < opened + duration) => {
+                    // Covered by the current fare, apart from the step up to a higher zone.
+                    let new_zone = window_zone.max(zone);
+                    total += fares.zone_fare(new_zone)?.saturating_sub(fares.zone_fare(window_zone)?);
+                    window = Some((opened, new_zone));
+                }
+                _ => {
+                    total += fares.zone_fare(zone)?;
+                    window = Some((leg.boarded_time, zone));
+                }
+            }
+        }
+        Some(Fare { leg_fares, total })
+    }
+}
@@ -0,0 +1,183 @@
+// A C API for embedding the router in non-Rust hosts. Build a C library with
+//   cargo rustc --release --lib --features ffi --crate-type staticlib
+// and include include/raptor.h (generated by cbindgen, see tests/ffi.rs).
+// Every function returns a RaptorStatus, and panics are caught and returned as RAPTOR_STATUS_PANIC rather than unwinding into C.
+// Safety: pointer arguments must be null or valid for the access described on each function, and networks and journeys must
+// only be freed once, by the matching free function.
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, CStr};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::network::{RouteIndex, StopIndex, Timestamp, TripOrder};
+use crate::{raptor_query, Leg, Network, StopIdx};
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RaptorStatus {
+    Ok = 0,
+    NullArgument = 1,
+    InvalidUtf8 = 2,
+    Io = 3,
+    InvalidNetwork = 4,
+    UnknownStop = 5,
+    NoJourneyFound = 6,
+    Panic = 7,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RaptorLegKind {
+    Vehicle = 0,
+    Transfer = 1,
+    Walk = 2,
+}
+
+// A journey leg with absolute times. Stops are stop indices (see raptor_stop_index and raptor_stop_id). For transfers, from_stop
+// and to_stop are both the transfer stop. route_idx and trip_order are only meaningful for vehicle legs.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RaptorLeg {
+    pub kind: RaptorLegKind,
+    pub from_stop: StopIndex,
+    pub to_stop: StopIndex,
+    pub departure_time: Timestamp,
+    pub arrival_time: Timestamp,
+    pub route_idx: RouteIndex,
+    pub trip_order: TripOrder,
+}
+
+// A journey returned by raptor_query_c. The legs are owned by the library, so free them with raptor_journey_free.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RaptorJourney {
+    pub legs: *mut RaptorLeg,
+    pub num_legs: usize,
+    pub departure_time: Timestamp,
+    pub arrival_time: Timestamp,
+}
+
+// Runs f, turning a panic into RaptorStatus::Panic.
+fn catch_panics(f: impl FnOnce() -> Result<(), RaptorStatus>) -> RaptorStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => RaptorStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => RaptorStatus::Panic,
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, RaptorStatus> {
+    if s.is_null() {
+        return Err(RaptorStatus::NullArgument);
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| RaptorStatus::InvalidUtf8)
+}
+
+unsafe fn network_arg<'a>(network: *const Network) -> Result<&'a Network, RaptorStatus> {
+    network.as_ref().ok_or(RaptorStatus::NullArgument)
+}
+
+// Loads a network serialized as JSON (e.g. with serde_json::to_writer) from the file at path, and writes a pointer to it to
+// out_network. Free it with raptor_network_free.
+#[no_mangle]
+pub unsafe extern "C" fn raptor_network_load(path: *const c_char, out_network: *mut *mut Network) -> RaptorStatus {
+    catch_panics(|| {
+        let path = str_arg(path)?;
+        if out_network.is_null() {
+            return Err(RaptorStatus::NullArgument);
+        }
+        let file = std::fs::File::open(path).map_err(|_| RaptorStatus::Io)?;
+        let network: Network = serde_json::from_reader(std::io::BufReader::new(file)).map_err(|_| RaptorStatus::InvalidNetwork)?;
+        *out_network = Box::into_raw(Box::new(network));
+        Ok(())
+    })
+}
+
+// Frees a network from raptor_network_load. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn raptor_network_free(network: *mut Network) {
+    if !network.is_null() {
+        drop(Box::from_raw(network));
+    }
+}
+
+// Writes the stop index of a GTFS stop ID to out_stop.
+#[no_mangle]
+pub unsafe extern "C" fn raptor_stop_index(network: *const Network, stop_id: *const c_char, out_stop: *mut StopIndex) -> RaptorStatus {
+    catch_panics(|| {
+        let network = network_arg(network)?;
+        let stop_id = str_arg(stop_id)?;
+        let out_stop = out_stop.as_mut().ok_or(RaptorStatus::NullArgument)?;
+        *out_stop = network.try_get_stop_idx(stop_id).ok_or(RaptorStatus::UnknownStop)?;
+        Ok(())
+    })
+}
+
+// Copies the GTFS ID of a stop index into buffer, truncated to buffer_len - 1 bytes and null-terminated, like snprintf. The
+// untruncated length (excluding the null) is written to out_len, so a buffer of out_len + 1 bytes always fits.
+#[no_mangle]
+pub unsafe extern "C" fn raptor_stop_id(network: *const Network, stop: StopIndex, buffer: *mut c_char, buffer_len: usize, out_len: *mut usize) -> RaptorStatus {
+    catch_panics(|| {
+        let network = network_arg(network)?;
+        let stop_id = network.stop_ids.get(stop as usize).ok_or(RaptorStatus::UnknownStop)?.as_bytes();
+        if !out_len.is_null() {
+            *out_len = stop_id.len();
+        }
+        if buffer_len > 0 {
+            if buffer.is_null() {
+                return Err(RaptorStatus::NullArgument);
+            }
+            let copied = stop_id.len().min(buffer_len - 1);
+            std::ptr::copy_nonoverlapping(stop_id.as_ptr(), buffer.cast::<u8>(), copied);
+            *buffer.add(copied) = 0;
+        }
+        Ok(())
+    })
+}
+
+// Runs a RAPTOR query between two GTFS stop IDs and writes the journey to out_journey, which must be freed with
+// raptor_journey_free. On failure, out_journey is left empty.
+#[no_mangle]
+pub unsafe extern "C" fn raptor_query_c(network: *const Network, start_stop_id: *const c_char, start_time: Timestamp, end_stop_id: *const c_char, out_journey: *mut RaptorJourney) -> RaptorStatus {
+    let out_journey = match out_journey.as_mut() {
+        Some(out_journey) => out_journey,
+        None => return RaptorStatus::NullArgument,
+    };
+    *out_journey = RaptorJourney { legs: std::ptr::null_mut(), num_legs: 0, departure_time: 0, arrival_time: 0 };
+    catch_panics(|| {
+        let network = network_arg(network)?;
+        let start = network.try_get_stop_idx(str_arg(start_stop_id)?).ok_or(RaptorStatus::UnknownStop)?;
+        let end = network.try_get_stop_idx(str_arg(end_stop_id)?).ok_or(RaptorStatus::UnknownStop)?;
+        let journey = raptor_query(network, StopIdx(start), start_time, StopIdx(end)).map_err(|_| RaptorStatus::NoJourneyFound)?;
+
+        // Transfers and walks only have durations, so their times follow on from the previous leg. Walks before the first trip
+        // end just in time to board it.
+        let mut time = Timestamp::from(journey.setting_off_time());
+        let departure_time = time;
+        let legs = journey.legs.iter().map(|leg| {
+            let (kind, from_stop, to_stop, departure_time, route_idx, trip_order) = match leg {
+                Leg::Vehicle(leg) => (RaptorLegKind::Vehicle, leg.boarded_stop, leg.arrival_stop, leg.boarded_time, leg.trip.route_idx, leg.trip.trip_order),
+                Leg::Transfer { at_stop, .. } => (RaptorLegKind::Transfer, *at_stop, *at_stop, time, 0, 0),
+                Leg::Walk { from, to, .. } => (RaptorLegKind::Walk, *from, *to, time, 0, 0),
+            };
+            time = departure_time + leg.duration();
+            RaptorLeg { kind, from_stop, to_stop, departure_time, arrival_time: time, route_idx, trip_order }
+        }).collect::<Box<[RaptorLeg]>>();
+
+        *out_journey = RaptorJourney { num_legs: legs.len(), departure_time, arrival_time: time, legs: Box::into_raw(legs).cast::<RaptorLeg>() };
+        Ok(())
+    })
+}
+
+// Frees the legs of a journey from raptor_query_c and leaves it empty. Null and empty journeys are ignored.
+#[no_mangle]
+pub unsafe extern "C" fn raptor_journey_free(journey: *mut RaptorJourney) {
+    let Some(journey) = journey.as_mut() else {
+        return;
+    };
+    if !journey.legs.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(journey.legs, journey.num_legs)));
+    }
+    journey.legs = std::ptr::null_mut();
+    journey.num_legs = 0;
+}
@@ -0,0 +1,88 @@
+use crate::network::{RouteIndex, StopIndex, TripOrder};
+use std::fmt::{Display, Formatter};
+
+// Typed indices, so a stop index can't be used to index routes (or a trip order to index stops) by accident.
+// Each wraps a raw alias and converts to and from it. The queries and the typed accessors on Network (stop, route, trip, and
+// so on) take them, and GlobalTripIndex hands them out through route() and trip(); the network's arrays, legs and
+// GlobalTripIndex's fields still use the raw aliases, so convert at the boundary.
+// The queries' raw-index signatures are kept for one release in crate::raw.
+macro_rules! index_type {
+    ($(#[$meta:meta])* $name:ident($raw:ty)) => {
+        $(#[$meta])*
+        #[repr(transparent)]
+        #[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(transparent))]
+        pub struct $name(pub $raw);
+
+        impl $name {
+            // Panics if the index doesn't fit, which would mean a network with more elements than the raw alias can count.
+            pub fn new(idx: usize) -> Self {
+                Self(<$raw>::try_from(idx).expect(concat!(stringify!($name), " out of range")))
+            }
+
+            pub const fn idx(self) -> usize {
+                self.0 as usize
+            }
+        }
+
+        impl From<$raw> for $name {
+            fn from(idx: $raw) -> Self {
+                Self(idx)
+            }
+        }
+
+        impl From<$name> for $raw {
+            fn from(idx: $name) -> Self {
+                idx.0
+            }
+        }
+
+        impl From<$name> for usize {
+            fn from(idx: $name) -> Self {
+                idx.idx()
+            }
+        }
+
+        impl Display for $name {
+            fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+index_type!(
+    // Index into Network::stops, stop_points and transfer_times.
+    StopIdx(StopIndex)
+);
+index_type!(
+    // Index into Network::routes.
+    RouteIdx(RouteIndex)
+);
+index_type!(
+    // The order of a trip within its route, which only means something together with a RouteIdx (see GlobalTripIndex).
+    TripIdx(TripOrder)
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let stop = StopIdx::new(12);
+        assert_eq!(stop.idx(), 12);
+        assert_eq!(usize::from(stop), 12);
+        assert_eq!(StopIndex::from(stop), 12);
+        assert_eq!(StopIdx::from(12), stop);
+        assert_eq!(stop.to_string(), "12");
+        assert!(RouteIdx(1) < RouteIdx(2));
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(target_pointer_width = "64")]
+    fn new_panics_out_of_range() {
+        TripIdx::new(u32::MAX as usize + 1);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::index::StopIdx;
+use crate::network::{NetworkPoint, StopIndex, Timestamp};
+use crate::raptor::raptor_one_to_all;
+use crate::{utils, Network};
+
+// Upper bounds of the default isochrone bands in seconds of travel time: 15, 30, 45 and 60 minutes.
+pub const DEFAULT_ISOCHRONE_BANDS: [Timestamp; 4] = [15 * 60, 30 * 60, 45 * 60, 60 * 60];
+
+// Stops reachable from a start stop, bucketed by travel time.
+#[derive(Clone, Debug)]
+pub struct Isochrone {
+    pub start: StopIndex,
+    pub start_time: Timestamp,
+    // Inclusive upper bound of each band's travel time, in increasing order.
+    pub band_limits: Vec<Timestamp>,
+    // Stops and their travel times in each band. A stop is in the first band whose limit is at least its travel time,
+    // and stops slower than the last limit are left out.
+    pub bands: Vec<Vec<(StopIndex, Timestamp)>>,
+}
+
+impl Isochrone {
+    pub fn new(network: &Network, start: StopIndex, start_time: Timestamp, band_limits: &[Timestamp]) -> Self {
+        assert!(band_limits.windows(2).all(|limits| limits[0] < limits[1]), "Band limits must be increasing.");
+
+        let mut bands = vec![Vec::new(); band_limits.len()];
+        for (stop_idx, arrival_time) in raptor_one_to_all(network, StopIdx(start), start_time).into_iter().enumerate() {
+            let Some(arrival_time) = arrival_time else {
+                continue;
+            };
+            let travel_time = arrival_time - start_time;
+            if let Some(band) = band_limits.iter().position(|&limit| travel_time <= limit) {
+                bands[band].push((stop_idx as StopIndex, travel_time));
+            }
+        }
+
+        Self {
+            start,
+            start_time,
+            band_limits: band_limits.to_vec(),
+            bands,
+        }
+    }
+
+    // Exports the isochrone as a GeoJSON FeatureCollection of stop Points with "band" (the band's limit in minutes) and "travel_time" properties.
+    // If with_hulls is set, a convex hull Polygon of everything reachable within each band is added too.
+    // Stops without valid coordinates are left out.
+    pub fn to_geojson(&self, network: &Network, with_hulls: bool) -> String {
+        let point_json = |point: NetworkPoint| format!("[{},{}]", point.longitude, point.latitude);
+
+        let mut features = Vec::new();
+        let mut reachable_points = Vec::new();
+        for (&limit, band) in self.band_limits.iter().zip(&self.bands) {
+            let band_points = band.iter()
+                .map(|&(stop_idx, travel_time)| (stop_idx, travel_time, network.stop_points[stop_idx as usize]))
+                .filter(|(_, _, point)| point.is_valid())
+                .collect::<Vec<_>>();
+            reachable_points.extend(band_points.iter().map(|&(_, _, point)| point));
+
+            if with_hulls && reachable_points.len() >= 3 {
+                let mut hull = convex_hull(&reachable_points);
+                // GeoJSON rings are closed.
+                hull.push(hull[0]);
+                let coordinates = hull.into_iter().map(point_json).collect::<Vec<_>>().join(",");
+                features.push(format!(
+                    r#"{{"type":"Feature","geometry":{{"type":"Polygon","coordinates":[[{coordinates}]]}},"properties":{{"kind":"hull","band":{}}}}}"#,
+                    limit / 60,
+                ));
+            }
+
+            for (stop_idx, travel_time, point) in band_points {
+                features.push(format!(
+                    r#"{{"type":"Feature","geometry":{{"type":"Point","coordinates":{}}},"properties":{{"kind":"stop","stop":"{}","band":{},"travel_time":{travel_time}}}}}"#,
+                    point_json(point),
+                    utils::escape_json_string(network.stop_name(stop_idx as usize)),
+                    limit / 60,
+                ));
+            }
+        }
+
+        format!(r#"{{"type":"FeatureCollection","features":[{}]}}"#, features.join(","))
+    }
+}
+
+// Returns the convex hull of the points in counter-clockwise order (Andrew's monotone chain), treating coordinates as planar.
+fn convex_hull(points: &[NetworkPoint]) -> Vec<NetworkPoint> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a.longitude.total_cmp(&b.longitude).then(a.latitude.total_cmp(&b.latitude)));
+    points.dedup_by(|a, b| a.longitude == b.longitude && a.latitude == b.latitude);
+    if points.len() < 3 {
+        return points;
+    }
+
+    let cross = |o: NetworkPoint, a: NetworkPoint, b: NetworkPoint| {
+        (a.longitude - o.longitude) * (b.latitude - o.latitude) - (a.latitude - o.latitude) * (b.longitude - o.longitude)
+    };
+    let mut hull: Vec<NetworkPoint> = Vec::with_capacity(points.len() * 2);
+    // Lower hull, then upper hull.
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let lower_len = hull.len();
+        for point in pass {
+            while hull.len() >= lower_len + 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0. {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point of each pass is the first point of the next.
+        hull.pop();
+    }
+    hull
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hull_of_square() {
+        let point = |longitude, latitude| NetworkPoint { latitude, longitude };
+        let hull = convex_hull(&[point(0., 0.), point(1., 0.), point(0.5, 0.5), point(1., 1.), point(0., 1.), point(1., 0.)]);
+        let hull = hull.into_iter().map(|point| (point.longitude, point.latitude)).collect::<Vec<_>>();
+        assert_eq!(hull, vec![(0., 0.), (1., 0.), (1., 1.), (0., 1.)]);
+    }
+}
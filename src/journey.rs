@@ -1,8 +1,14 @@
-use crate::multicriteria::{Bag, Label};
-use crate::network::{GlobalTripIndex, PathfindingCost, Route, StopIndex, Timestamp, TripOrder};
+use crate::multicriteria::{Epsilon, GenericBag, Label, LabelStorage, OverflowPolicy, TieBreak};
+use crate::network::{CostProvider, CoordType, GlobalTripIndex, NetworkPoint, PathfindingCost, RouteIndex, StopIndex, Timestamp, TripOrder};
+use crate::time::{Seconds, Time};
+use crate::utils::{DurationStyle, OptionExt, TimeFormat};
 use crate::{utils, Network};
-use std::fmt::Display;
+use rgb::RGB8;
+use std::fmt::{Debug, Display};
+use std::io::Write;
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Connection {
     pub sequential_trip_idx: TripOrder, // Used to index a global trip array (for csa).
     pub trip: GlobalTripIndex, // Used to lookup trip data in the network.
@@ -13,50 +19,214 @@ pub struct Connection {
     pub arrival_time: Timestamp,
 }
 
+impl Connection {
+    // Connections are sorted by departure time, with ties broken by trip and stop order so the order is deterministic.
+    pub fn sort_key(&self) -> (Timestamp, TripOrder, StopIndex) {
+        (self.departure_time, self.sequential_trip_idx, self.departure_stop_order)
+    }
+}
+
+// Where and when the trip that reached a stop was boarded.
 #[derive(Clone)]
 pub(crate) struct Boarding {
-    pub boarded_stop: StopIndex,
-    pub boarded_stop_order: StopIndex,
-    pub boarded_time: Timestamp,
-    pub trip: GlobalTripIndex,
+    pub(crate) boarded_stop: StopIndex,
+    pub(crate) boarded_stop_order: StopIndex,
+    pub(crate) boarded_time: Time,
+    pub(crate) trip: GlobalTripIndex,
 }
 
 impl Boarding {
-    pub fn from(connection: &Connection) -> Self {
+    pub(crate) fn from(connection: &Connection) -> Self {
         Self {
             boarded_stop: connection.departure_idx,
             boarded_stop_order: connection.departure_stop_order,
-            boarded_time: connection.departure_time,
+            boarded_time: connection.departure_time.into(),
             trip: connection.trip,
         }
     }
 }
 
+// The earliest arrival at a stop and how it was reached, from which journeys are reconstructed (see Journey::from_tau_into).
 #[derive(Clone)]
 pub(crate) struct TauEntry {
-    pub time: Timestamp,
-    pub boarding: Option<Boarding>,
+    pub(crate) time: Time,
+    pub(crate) boarding: Option<Boarding>,
+    // The stop walked from, if the stop was reached by a footpath.
+    pub(crate) walked_from: Option<StopIndex>,
 }
 
 impl Default for TauEntry {
     fn default() -> Self {
         Self {
-            time: Timestamp::MAX,
+            time: Time::MAX,
             boarding: None,
+            walked_from: None,
         }
     }
 }
 
-pub struct Leg {
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VehicleLeg {
     pub boarded_stop: StopIndex,
     pub boarded_stop_order: StopIndex,
     pub boarded_time: Timestamp,
     pub arrival_stop: StopIndex,
     pub arrival_stop_order: StopIndex,
     pub arrival_time: Timestamp,
-    // The time to transfer from this leg to the next one (None for the last leg).
-    pub transfer_time: Option<Timestamp>,
     pub trip: GlobalTripIndex,
+    // The contribution of this leg to the journey cost (zero for single-criteria queries).
+    pub cost: PathfindingCost,
+}
+
+/// Accessors for displaying a leg.
+///
+/// ```
+/// let (network, start, start_time, end) = dev_utils::get_example_scenario();
+/// let journey = raptor::raptor_query(&network, raptor::StopIdx(start), start_time, raptor::StopIdx(end)).unwrap();
+/// let leg = journey.legs_vehicle().next().unwrap();
+///
+/// assert_eq!(leg.boarded_stop_name(&network), network.stop_name(start as usize));
+/// assert!(!leg.arrival_stop_name(&network).is_empty());
+/// assert!(!leg.line_name(&network).is_empty());
+/// assert_eq!(leg.route_colour(&network), network.routes[leg.trip.route().idx()].colour);
+/// assert_eq!(leg.trip_id(&network), network.get_trip_id(leg.trip));
+/// assert_eq!(leg.duration(), leg.arrival_time - leg.boarded_time);
+/// ```
+impl VehicleLeg {
+    pub fn duration(&self) -> Timestamp {
+        Time(self.arrival_time).saturating_duration_since(Time(self.boarded_time)).into()
+    }
+
+    pub fn line_name<'a>(&self, network: &'a Network) -> &'a str {
+        &network.routes[self.trip.route().idx()].line
+    }
+
+    pub fn route_colour(&self, network: &Network) -> RGB8 {
+        network.routes[self.trip.route().idx()].colour
+    }
+
+    pub fn trip_id<'a>(&self, network: &'a Network) -> &'a str {
+        network.get_trip_id(self.trip)
+    }
+
+    pub fn boarded_stop_name<'a>(&self, network: &'a Network) -> &'a str {
+        network.stop_name(self.boarded_stop as usize)
+    }
+
+    pub fn arrival_stop_name<'a>(&self, network: &'a Network) -> &'a str {
+        network.stop_name(self.arrival_stop as usize)
+    }
+
+    // The number of stops travelled on this leg, counting the arrival stop but not the boarded stop.
+    pub fn num_stops(&self) -> usize {
+        self.arrival_stop_order.saturating_sub(self.boarded_stop_order) as usize
+    }
+
+    // The distance travelled in km, along the route's shape, or in straight lines between the stops where the route has no shape.
+    pub fn distance_km(&self, network: &Network) -> CoordType {
+        if self.boarded_stop_order == self.arrival_stop_order {
+            log::warn!("Leg on trip {} alights where it boarded.", self.trip_id(network));
+            return 0.;
+        }
+        let route = &network.routes[self.trip.route().idx()];
+        let points = route.shape_between(self.boarded_stop_order as usize, self.arrival_stop_order as usize, network);
+        points.windows(2).map(|pair| pair[0].distance(pair[1])).sum()
+    }
+
+    // Iterates over the stops passed between boarding and arrival (exclusive), with the arrival time at each.
+    // Stop orders are used rather than stop indices, so loop routes that visit a stop twice are handled.
+    pub fn intermediate_stops<'a>(&self, network: &'a Network) -> impl Iterator<Item=(StopIndex, Timestamp)> + 'a {
+        let route = &network.routes[self.trip.route().idx()];
+        let stops = route.get_stops(&network.route_stops);
+        let trip = route.get_trip(self.trip.trip().idx(), &network.stop_times);
+        let range = (self.boarded_stop_order as usize + 1)..(self.arrival_stop_order as usize);
+        range.map(move |stop_order| (stops[stop_order], trip.arrival_times[stop_order]))
+    }
+}
+
+// A part of a journey. Consecutive vehicle legs are separated by a transfer or walk leg.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Leg {
+    // Travelling on a trip.
+    Vehicle(VehicleLeg),
+    // Changing trips at a stop, between alighting one trip and boarding the next.
+    // transfer_time is the stop's configured interchange time (e.g. walking between platforms), and wait_time is the
+    // remaining time spent waiting for the next departure. Together they make up the time between the two trips.
+    Transfer { at_stop: StopIndex, transfer_time: Timestamp, wait_time: Timestamp },
+    // Walking between two stops along a footpath (see Network::set_footpaths). Distance is the straight-line distance in km.
+    Walk { from: StopIndex, to: StopIndex, duration: Timestamp, distance: CoordType },
+}
+
+impl Leg {
+    pub fn as_vehicle(&self) -> Option<&VehicleLeg> {
+        match self {
+            Leg::Vehicle(leg) => Some(leg),
+            _ => None,
+        }
+    }
+
+    pub fn duration(&self) -> Timestamp {
+        match self {
+            Leg::Vehicle(leg) => leg.duration(),
+            Leg::Transfer { transfer_time, wait_time, .. } => Seconds(*transfer_time).saturating_add(Seconds(*wait_time)).into(),
+            Leg::Walk { duration, .. } => *duration,
+        }
+    }
+
+    // The distance covered in km. Transfers cover none.
+    pub fn distance_km(&self, network: &Network) -> CoordType {
+        match self {
+            Leg::Vehicle(leg) => leg.distance_km(network),
+            Leg::Transfer { .. } => 0.,
+            Leg::Walk { distance, .. } => *distance,
+        }
+    }
+}
+
+// The total duration of the legs, e.g. the walks before the first trip.
+fn total_duration<'l>(legs: impl Iterator<Item = &'l Leg>) -> Seconds {
+    legs.fold(Seconds::ZERO, |total, leg| total.saturating_add(Seconds(leg.duration())))
+}
+
+// A stop visited during a journey. Boarding events have no arrival time and alighting events have no departure time.
+// Transfer and walk events have no trip.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct JourneyEvent {
+    pub stop: StopIndex,
+    pub arrival: Option<Timestamp>,
+    pub departure: Option<Timestamp>,
+    pub trip: Option<GlobalTripIndex>,
+}
+
+// A journey event as a row of a stop-times table, with the stop, trip and line by ID and name. See Journey::to_rows.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct JourneyRow<'a> {
+    pub stop_id: &'a str,
+    pub stop_name: &'a str,
+    pub arrival_time: Option<Timestamp>,
+    pub departure_time: Option<Timestamp>,
+    // The trip and line ridden to or from the stop. None for transfers and walks.
+    pub trip_id: Option<&'a str>,
+    pub line: Option<&'a str>,
+    // The time spent at the stop between trips, for transfers.
+    pub wait: Option<Timestamp>,
+}
+
+// Where the time of a journey goes, from the requested start time to the final arrival. The three durations add up to
+// Journey::total_duration.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JourneyStats {
+    pub in_vehicle_secs: Timestamp,
+    // Waiting for the first service and between trips, including the interchange time of transfers.
+    pub waiting_secs: Timestamp,
+    pub walking_secs: Timestamp,
+    // Changes from one trip to another, whether by a transfer or a walk.
+    pub num_transfers: usize,
+    // Stops passed through on board, not counting where trips are boarded or alighted.
+    pub num_stops_passed: usize,
 }
 
 // Journey preferences for a multi-criteria journey query.
@@ -64,20 +234,83 @@ type JourneyUtilityFn = dyn Fn(&Label, Timestamp) -> PathfindingCost + Send + Sy
 pub struct JourneyPreferences {
     // Function to determine the utility of a label, given a journey start time.
     pub utility_function: Box<JourneyUtilityFn>,
+    // How to choose between labels with exactly the same arrival time and cost.
+    pub tie_break: TieBreak,
+    // Epsilon-dominance tolerances for pruning near-duplicate labels. Trades optimality for speed; zero by default.
+    pub epsilon: Epsilon,
+    // Which label to discard when a bounded bag is full.
+    pub overflow: OverflowPolicy,
 }
 
 impl Default for JourneyPreferences {
     fn default() -> Self {
         // By default, ignore cost and only consider travel time.
-        JourneyPreferences { utility_function: Box::new(|label, _| label.arrival_time as PathfindingCost) }
+        JourneyPreferences {
+            utility_function: Box::new(|label, _| label.arrival_time as PathfindingCost),
+            tie_break: TieBreak::default(),
+            epsilon: Epsilon::default(),
+            overflow: OverflowPolicy::default(),
+        }
     }
 }
 
 impl JourneyPreferences {
-    // Finds the label that arrives before the next boarding time and with the best utility.
-    pub(crate) fn best_label<'a>(&self, next_boarding_time: Timestamp, labels: &'a [Label], start_time: Timestamp) -> Option<&'a Label> {
+    // Preset preferences. Utilities are minimised, and labels with equal utility are chosen by earliest arrival time.
+
+    // Earliest arrival time, ignoring cost. Utility: arrival_time. This is the default.
+    pub fn fastest() -> Self {
+        Self::default()
+    }
+
+    // Lowest cost, ignoring travel time. Utility: cost.
+    pub fn least_cost() -> Self {
+        JourneyPreferences {
+            utility_function: Box::new(|label, _| label.cost),
+            ..Default::default()
+        }
+    }
+
+    // Weighted sum of travel time and cost. Utility: time_weight * (arrival_time - start_time) in seconds + cost_weight * cost.
+    pub fn balanced(time_weight: PathfindingCost, cost_weight: PathfindingCost) -> Self {
+        JourneyPreferences {
+            utility_function: Box::new(move |label, start_time| {
+                time_weight * label.arrival_time.saturating_sub(start_time) as PathfindingCost + cost_weight * label.cost
+            }),
+            ..Default::default()
+        }
+    }
+
+    // Fewest trips, then earliest arrival. Labels don't count trips, so this must be used with CountBoardings as the cost
+    // provider, which makes cost the number of boardings. Utility: cost * 86400 + (arrival_time - start_time) in seconds.
+    pub fn least_transfers() -> Self {
+        JourneyPreferences {
+            utility_function: Box::new(|label, start_time| {
+                label.cost * 86400. + label.arrival_time.saturating_sub(start_time) as PathfindingCost
+            }),
+            ..Default::default()
+        }
+    }
+
+    // Any arrival at or before latest_acceptable is equally good, so the cheapest of those labels is chosen regardless of when it
+    // arrives. Late labels are only chosen if none are on time, in which case the earliest is chosen.
+    // Utility: cost if arrival_time <= latest_acceptable, otherwise infinity.
+    pub fn arrive_relaxed(latest_acceptable: Timestamp) -> Self {
+        JourneyPreferences {
+            utility_function: Box::new(move |label, _| {
+                if label.arrival_time <= latest_acceptable {
+                    label.cost
+                } else {
+                    PathfindingCost::INFINITY
+                }
+            }),
+            ..Default::default()
+        }
+    }
+
+    // Finds the label with the best utility of those can_take allows, e.g. those that arrive in time for the next boarding.
+    pub(crate) fn best_label<'a>(&self, can_take: impl Fn(&Label) -> bool, labels: &'a [Label], start_time: Timestamp) -> Option<&'a Label> {
         labels.iter()
-            .filter(|label| label.arrival_time < next_boarding_time)
+            .filter(|label| can_take(label))
             .min_by(|a, b| f32::total_cmp(&(self.utility_function)(a, start_time), &(self.utility_function)(b, start_time)))
     }
 }
@@ -86,107 +319,420 @@ impl JourneyPreferences {
 pub enum JourneyError {
     #[error("Journey not calculated for zero agents.")]
     ZeroAgents,
-    #[error("No journey found.")]
-    NoJourneyFound,
+    // The fields describe how far the search got, for working out why end wasn't reached. {:#?} prints them.
+    #[error("No journey found from {start_name} to {end_name}.")]
+    NoJourneyFound {
+        start: StopIndex,
+        end: StopIndex,
+        start_name: String,
+        end_name: String,
+        start_time: Timestamp,
+        // The latest of the earliest arrival times at the stops reached, None if no stop other than start was reached.
+        last_reachable_time: Option<Timestamp>,
+        // The number of stops reached, not counting start.
+        stops_reached: usize,
+    },
     #[error("Infinite loop in journey reconstruction.")]
-    InfiniteLoop,
+    InfiniteLoop {
+        // The stops visited while following the parent pointers back from end, trimmed to the cycle if a stop repeats.
+        stops_visited: Vec<StopIndex>,
+    },
+}
+
+impl JourneyError {
+    // Summarises a search from start that didn't reach end, given the earliest arrival time at each stop (Timestamp::MAX
+    // where unreached).
+    pub(crate) fn no_journey_found(network: &Network, start: usize, end: usize, start_time: Timestamp, arrival_times: impl Iterator<Item=Timestamp>) -> Self {
+        Self::no_journey_found_between(start, end, network.stop_name(start), network.stop_name(end), start_time, arrival_times)
+    }
+
+    // The same as no_journey_found, for searches that have the stop names but not the network.
+    pub(crate) fn no_journey_found_between(start: usize, end: usize, start_name: &str, end_name: &str, start_time: Timestamp, arrival_times: impl Iterator<Item=Timestamp>) -> Self {
+        let (stops_reached, last_reachable_time) = arrival_times.enumerate()
+            .filter(|&(stop, time)| stop != start && time != Timestamp::MAX)
+            .fold((0, None), |(count, last), (_, time)| (count + 1, last.max(Some(time))));
+        JourneyError::NoJourneyFound {
+            start: start as StopIndex,
+            end: end as StopIndex,
+            start_name: start_name.to_string(),
+            end_name: end_name.to_string(),
+            start_time,
+            last_reachable_time,
+            stops_reached,
+        }
+    }
+
+    // Reports a reconstruction that gave up at current_stop. The legs were pushed from end backwards, so the stops they end
+    // at are the stops visited before current_stop.
+    fn infinite_loop(legs: &[Leg], current_stop: usize) -> Self {
+        let mut stops_visited: Vec<StopIndex> = legs.iter().map(|leg| match leg {
+            Leg::Vehicle(leg) => leg.arrival_stop,
+            Leg::Transfer { at_stop, .. } => *at_stop,
+            Leg::Walk { to, .. } => *to,
+        }).collect();
+        stops_visited.push(current_stop as StopIndex);
+        // Keep only the cycle, from the previous visit to the last stop onwards.
+        if let Some(cycle_start) = stops_visited[..stops_visited.len() - 1].iter().rposition(|&stop| stop == current_stop as StopIndex) {
+            stops_visited.drain(..cycle_start);
+        }
+        JourneyError::InfiniteLoop { stops_visited }
+    }
 }
 
 pub type JourneyResult<'a> = Result<Journey<'a>, JourneyError>;
 
+// An inconsistency between a journey and the network it was found in, reported by Journey::validate. leg is an index into
+// Journey::legs.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum JourneyValidationError {
+    #[error("Leg {leg} rides trip {trip:?}, which isn't in the network.")]
+    UnknownTrip { leg: usize, trip: GlobalTripIndex },
+    #[error("Leg {leg} claims stop {stop} is at stop order {stop_order} of its route, but it isn't.")]
+    WrongStopOrder { leg: usize, stop: StopIndex, stop_order: StopIndex },
+    #[error("Leg {leg} doesn't arrive at a later stop order than it boarded at.")]
+    NotForward { leg: usize },
+    #[error("Leg {leg} has time {actual} at stop order {stop_order}, but its trip's stop times have {expected}.")]
+    WrongTime { leg: usize, stop_order: StopIndex, expected: Timestamp, actual: Timestamp },
+    #[error("Leg {leg} doesn't start at the stop where the previous leg ended.")]
+    Disconnected { leg: usize },
+    #[error("Leg {leg} starts before the previous leg ends.")]
+    NotTimeOrdered { leg: usize },
+    #[error("Leg {leg} leaves {slack}s to change trips, but the transfer time is {transfer_time}s.")]
+    TransferTooShort { leg: usize, slack: Timestamp, transfer_time: Timestamp },
+    #[error("Transfer leg {leg} doesn't last from the previous trip's arrival to the next trip's departure.")]
+    WrongTransferDuration { leg: usize },
+}
+
 pub struct Journey<'a> {
     pub legs: Vec<Leg>,
+    // The time from the first boarding to the final arrival.
     pub duration: Timestamp,
+    // The requested start time of the query, which may be well before the first boarding.
+    pub start_time: Timestamp,
     pub cost: PathfindingCost,
+    // The stops the query was from and to, if known. Empty journeys have no legs to take them from.
+    pub origin: Option<StopIndex>,
+    pub destination: Option<StopIndex>,
     pub network: &'a Network,
 }
 
 impl<'a> Journey<'a> {
     pub fn empty(network: &'a Network) -> Self {
-        Self { legs: Vec::new(), duration: 0, cost: 0., network }
+        Self { legs: Vec::new(), duration: 0, start_time: 0, cost: 0., origin: None, destination: None, network }
     }
 
-    fn from(legs: Vec<Leg>, cost: PathfindingCost, network: &'a Network) -> Self {
-        let duration = match (legs.first(), legs.last()) {
-            (Some(first), Some(last)) => last.arrival_time.checked_sub(first.boarded_time).unwrap_or_else(|| {
-                log::warn!("Error: Journey duration underflow.");
-                0
-            }),
-            _ => 0,
-        };
-        Self { legs, duration, cost, network }
+    // The journey from a stop to itself, which needs no travel.
+    pub fn empty_at(network: &'a Network, stop: StopIndex, start_time: Timestamp) -> Self {
+        Self { start_time, origin: Some(stop), destination: Some(stop), ..Self::empty(network) }
     }
 
-    fn calculate_arrival_stop_order(route: &Route, network: &Network, boarded_leg: &Boarding, current_stop: usize) -> StopIndex {
-         route.get_stops(&network.route_stops).iter().enumerate().skip(boarded_leg.boarded_stop_order as usize).find_map(|(i, &stop)| {
-            if stop as usize == current_stop {
-                Some(i as StopIndex)
-            } else {
-                None
+    // The time from the requested start time to the final arrival, including the wait for the first service.
+    pub fn total_duration(&self) -> Timestamp {
+        self.arrival_time().map(|arrival_time| arrival_time.saturating_sub(self.start_time)).unwrap_or(0)
+    }
+
+    // The time from the first boarding to the final arrival.
+    pub fn in_motion_duration(&self) -> Timestamp {
+        self.duration
+    }
+
+    // The time spent waiting at the start for the first service.
+    pub fn initial_wait(&self) -> Timestamp {
+        self.legs_vehicle().next().map(|leg| leg.boarded_time.saturating_sub(self.start_time)).unwrap_or(0)
+    }
+
+    // Constructs a journey from its vehicle and walk legs (in order), inserting transfers between consecutive vehicle legs.
+    fn from_legs(legs: impl ExactSizeIterator<Item = Leg>, origin: usize, destination: usize, start_time: Timestamp, cost: PathfindingCost, network: &'a Network) -> Self {
+        let mut journey_legs = Vec::with_capacity((2 * legs.len()).saturating_sub(1));
+        let mut previous_arrival: Option<(StopIndex, Timestamp, RouteIndex)> = None;
+        for leg in legs {
+            let leg = match leg {
+                Leg::Vehicle(leg) => leg,
+                // Walking replaces the transfer.
+                other => {
+                    previous_arrival = None;
+                    journey_legs.push(other);
+                    continue;
+                }
+            };
+            if let Some((at_stop, arrival_time, from_route)) = previous_arrival {
+                let gap = Time(leg.boarded_time).checked_duration_since(Time(arrival_time)).map_or_else(|| {
+                    log::warn!("Error: Next trip boarded at {} before arriving at {}.", utils::get_time_str(leg.boarded_time), utils::get_time_str(arrival_time));
+                    0
+                }, Timestamp::from);
+                let transfer_time = network.get_route_transfer_time(at_stop, Some(from_route), leg.trip.route_idx);
+                journey_legs.push(Leg::Transfer { at_stop, transfer_time, wait_time: gap.saturating_sub(transfer_time) });
+            }
+            previous_arrival = Some((leg.arrival_stop, leg.arrival_time, leg.trip.route_idx));
+            journey_legs.push(Leg::Vehicle(leg));
+        }
+
+        let mut journey = Self { legs: journey_legs, duration: 0, start_time, cost, origin: Some(origin as StopIndex), destination: Some(destination as StopIndex), network };
+        let setting_off_time = journey.setting_off_time();
+        journey.duration = journey.arrival_time().map_or(0, |arrival_time| Time(arrival_time).checked_duration_since(setting_off_time).map_or_else(|| {
+            log::warn!("Error: Journey duration underflow.");
+            0
+        }, Timestamp::from));
+        journey
+    }
+
+    // The time the first trip is boarded.
+    pub fn departure_time(&self) -> Option<Timestamp> {
+        self.legs_vehicle().next().map(|leg| leg.boarded_time)
+    }
+
+    // The time the journey's first leg begins. Walks before the first boarding are taken just in time to board, and a journey
+    // that only walks sets off at the start time.
+    pub(crate) fn setting_off_time(&self) -> Time {
+        let leading_walks = total_duration(self.legs.iter().take_while(|leg| leg.as_vehicle().is_none()));
+        self.departure_time().map_or(Time(self.start_time), |departure_time| Time(departure_time).checked_sub(leading_walks).unwrap_or(Time::MIDNIGHT))
+    }
+
+    // The time the destination is reached, including any walks after the last trip.
+    pub fn arrival_time(&self) -> Option<Timestamp> {
+        let trailing_walks = total_duration(self.legs.iter().rev().take_while(|leg| leg.as_vehicle().is_none()));
+        match self.legs_vehicle().last() {
+            Some(leg) => Some(Time(leg.arrival_time).saturating_add(trailing_walks).into()),
+            // A journey that only walks.
+            None if !self.legs.is_empty() => Some(Time(self.start_time).saturating_add(trailing_walks).into()),
+            None => None,
+        }
+    }
+
+    // Returns true if both journeys ride the same trips between the same stops, ignoring costs.
+    pub fn same_path(&self, other: &Journey) -> bool {
+        self.legs_vehicle().count() == other.legs_vehicle().count()
+            && self.legs_vehicle().zip(other.legs_vehicle()).all(|(a, b)| {
+                a.trip == b.trip && a.boarded_stop == b.boarded_stop && a.arrival_stop == b.arrival_stop
+            })
+    }
+
+    // Iterates over every stop visited in the journey in time order, including intermediate stops, transfers and walks. Walks
+    // before the first boarding end just in time for it.
+    pub fn events(&self) -> impl Iterator<Item = JourneyEvent> + '_ {
+        let mut events = Vec::new();
+        let mut time = self.setting_off_time();
+        for leg in self.legs.iter() {
+            match leg {
+                Leg::Vehicle(leg) => {
+                    let trip = Some(leg.trip);
+                    events.push(JourneyEvent { stop: leg.boarded_stop, arrival: None, departure: Some(leg.boarded_time), trip });
+                    let route = &self.network.routes[leg.trip.route().idx()];
+                    let stops = route.get_stops(&self.network.route_stops);
+                    let stop_times = route.get_trip(leg.trip.trip().idx(), &self.network.stop_times);
+                    let passed_stops = stops.iter().enumerate().take(leg.arrival_stop_order as usize).skip(leg.boarded_stop_order as usize + 1);
+                    for (stop_order, &stop) in passed_stops {
+                        let stop_time = stop_times.get(stop_order);
+                        events.push(JourneyEvent { stop, arrival: Some(stop_time.arrival_time), departure: Some(stop_time.departure_time), trip });
+                    }
+                    events.push(JourneyEvent { stop: leg.arrival_stop, arrival: Some(leg.arrival_time), departure: None, trip });
+                    time = Time(leg.arrival_time);
+                }
+                Leg::Transfer { at_stop, .. } => {
+                    let departure = time.saturating_add(Seconds(leg.duration()));
+                    events.push(JourneyEvent { stop: *at_stop, arrival: Some(time.into()), departure: Some(departure.into()), trip: None });
+                    time = departure;
+                }
+                Leg::Walk { from, to, duration, .. } => {
+                    let arrival = time.saturating_add(Seconds(*duration));
+                    events.push(JourneyEvent { stop: *from, arrival: None, departure: Some(time.into()), trip: None });
+                    events.push(JourneyEvent { stop: *to, arrival: Some(arrival.into()), departure: None, trip: None });
+                    time = arrival;
+                }
             }
-        }).expect("Arrival stop not found in route.")
+        }
+        events.into_iter()
+    }
+
+    // Returns the journey's events as rows of a stop-times table, e.g. for joining against patronage data.
+    pub fn to_rows(&self) -> Vec<JourneyRow<'_>> {
+        let network = self.network;
+        self.events().map(|event| {
+            let stop = event.stop as usize;
+            let wait = match (event.trip, event.arrival, event.departure) {
+                (None, Some(arrival), Some(departure)) => Some(departure.saturating_sub(arrival)),
+                _ => None,
+            };
+            JourneyRow {
+                stop_id: network.stop_id(stop),
+                stop_name: network.stop_name(stop),
+                arrival_time: event.arrival,
+                departure_time: event.departure,
+                trip_id: event.trip.map(|trip| network.get_trip_id(trip)),
+                line: event.trip.map(|trip| &*network.routes[trip.route().idx()].line),
+                wait,
+            }
+        }).collect()
+    }
+
+    // Writes the rows of to_rows as CSV, with times as HH:MM:SS and empty values where a row has none.
+    pub fn write_csv(&self, mut writer: impl Write) -> std::io::Result<()> {
+        let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+        let time = |time: Option<Timestamp>| time.map(utils::get_time_str).unwrap_or_default();
+        writeln!(writer, "stop_id,stop_name,arrival_time,departure_time,trip_id,line,wait_secs")?;
+        for row in self.to_rows() {
+            writeln!(writer, "{},{},{},{},{},{},{}", quote(row.stop_id), quote(row.stop_name), time(row.arrival_time), time(row.departure_time),
+                     row.trip_id.map(quote).unwrap_or_default(), row.line.map(quote).unwrap_or_default(),
+                     row.wait.map(|wait| wait.to_string()).unwrap_or_default())?;
+        }
+        Ok(())
+    }
+
+    // The distance covered in km, on board and walking. See VehicleLeg::distance_km.
+    pub fn total_distance_km(&self) -> CoordType {
+        self.legs.iter().map(|leg| leg.distance_km(self.network)).sum()
     }
 
+    // Totals the time spent on board, waiting and walking, and counts transfers and stops passed.
+    pub fn stats(&self) -> JourneyStats {
+        let mut stats = JourneyStats::default();
+        // Waits are the gaps between legs, so that waiting after a walk (which has no transfer leg) is counted too. Walks before
+        // the first boarding end just in time for it, so the wait for the first service comes before them.
+        let mut time = self.setting_off_time();
+        stats.waiting_secs = Timestamp::from(time.saturating_duration_since(Time(self.start_time)));
+        for leg in self.legs.iter() {
+            match leg {
+                Leg::Vehicle(leg) => {
+                    stats.waiting_secs += Timestamp::from(Time(leg.boarded_time).saturating_duration_since(time));
+                    stats.in_vehicle_secs += leg.duration();
+                    stats.num_stops_passed += leg.num_stops().saturating_sub(1);
+                    time = Time(leg.arrival_time);
+                }
+                Leg::Transfer { .. } => {
+                    stats.waiting_secs += leg.duration();
+                    time = time.saturating_add(Seconds(leg.duration()));
+                }
+                Leg::Walk { duration, .. } => {
+                    stats.walking_secs += duration;
+                    time = time.saturating_add(Seconds(*duration));
+                }
+            }
+        }
+        stats.num_transfers = self.legs_vehicle().count().saturating_sub(1);
+        stats
+    }
+
+    // Iterates over the legs travelled on a vehicle, skipping transfers and walks.
+    pub fn legs_vehicle(&self) -> impl Iterator<Item = &VehicleLeg> {
+        self.legs.iter().filter_map(Leg::as_vehicle)
+    }
+
+    fn calculate_arrival_stop_order(network: &Network, boarded_leg: &Boarding, current_stop: usize) -> StopIndex {
+        // Skip the boarded stop itself, in case the route is a loop that returns to it.
+        network.stop_order_in_route(boarded_leg.trip.route().idx(), current_stop as StopIndex, boarded_leg.boarded_stop_order as usize + 1)
+            .expect("Arrival stop not found in route.") as StopIndex
+    }
+
+    // Reconstructs the journey to end by following τ*'s boardings and walks back to start.
     pub(crate) fn from_tau(tau: &[TauEntry], network: &'a Network, start: usize, end: usize) -> JourneyResult<'a> {
+        Self::from_tau_into(tau, network, start, end, &mut Vec::new())
+    }
+
+    // The same as from_tau, but the legs are collected in legs_buf (which is cleared first) rather than a new Vec, so that
+    // reconstructing many journeys only allocates each journey's own legs.
+    pub(crate) fn from_tau_into(tau: &[TauEntry], network: &'a Network, start: usize, end: usize, legs_buf: &mut Vec<Leg>) -> JourneyResult<'a> {
         // No journey found.
-        if tau[end].boarding.is_none() {
-            return Err(JourneyError::NoJourneyFound);
+        if tau[end].boarding.is_none() && tau[end].walked_from.is_none() {
+            return Err(JourneyError::no_journey_found(network, start, end, tau[start].time.into(), tau.iter().map(|entry| entry.time.into())));
         }
 
+        // τ* is only changed by arrivals before the trips boarded from it depart, so there is a single round.
+        Self::from_rounds_into(|stop, _| &tau[stop], 0, network, start, end, legs_buf)
+    }
+
+    // Reconstructs the journey to end, which must have been reached, by following the boardings and walks back to start. entry(stop,
+    // round) is how the stop was reached in the round, and end was reached in end_round. Each boarding steps back a round, so
+    // each trip is boarded from the arrival it was boarded from in the search.
+    pub(crate) fn from_rounds_into<'t>(entry: impl Fn(usize, usize) -> &'t TauEntry,
+                                       end_round: usize,
+                                       network: &'a Network,
+                                       start: usize,
+                                       end: usize,
+                                       legs_buf: &mut Vec<Leg>) -> JourneyResult<'a> {
         // Reconstruct trip from parent pointers
-        let mut legs = Vec::new();
-        let mut current_stop_opt = Some(end);
+        let legs = legs_buf;
+        legs.clear();
+        let mut current_opt = Some((end, end_round));
         const MAX_LEGS: usize = 100; // Prevent infinite loop (TODO: which is a bug).
         let mut num_legs = 0;
-        let mut last_boarding: Option<&Boarding> = None;
-        while let Some(current_stop) = current_stop_opt {
+        while let Some((current_stop, round)) = current_opt {
             if current_stop == start {
                 break;
             }
             num_legs += 1;
             if num_legs > MAX_LEGS {
-                return Err(JourneyError::InfiniteLoop);
+                return Err(JourneyError::infinite_loop(legs, current_stop));
             }
-            let current_tau = &tau[current_stop];
+            let current_tau = entry(current_stop, round);
 
             if let Some(boarded_leg) = &current_tau.boarding {
                 // Find arrival stop order.
-                let route = &network.routes[boarded_leg.trip.route_idx as usize];
-                let arrival_stop_order = Self::calculate_arrival_stop_order(route, network, boarded_leg, current_stop);
+                let arrival_stop_order = Self::calculate_arrival_stop_order(network, boarded_leg, current_stop);
 
-                legs.push(Leg {
+                legs.push(Leg::Vehicle(VehicleLeg {
                     boarded_stop: boarded_leg.boarded_stop,
                     boarded_stop_order: boarded_leg.boarded_stop_order,
-                    boarded_time: boarded_leg.boarded_time,
+                    boarded_time: boarded_leg.boarded_time.into(),
                     arrival_stop: current_stop as StopIndex,
                     arrival_stop_order,
-                    arrival_time: current_tau.time,
-                    transfer_time: last_boarding.map(|last_boarding| last_boarding.boarded_time - current_tau.time),
+                    arrival_time: current_tau.time.into(),
                     trip: boarded_leg.trip,
+                    cost: 0.,
+                }));
+                current_opt = Some((boarded_leg.boarded_stop as usize, round.saturating_sub(1)));
+            } else if let Some(walked_from) = current_tau.walked_from {
+                legs.push(Leg::Walk {
+                    from: walked_from,
+                    to: current_stop as StopIndex,
+                    duration: network.footpath_duration(walked_from, current_stop as StopIndex).expect("Walked without a footpath."),
+                    distance: network.stop_points[walked_from as usize].distance(network.stop_points[current_stop]),
                 });
-
-                last_boarding = Some(boarded_leg);
+                current_opt = Some((walked_from as usize, round));
+            } else {
+                current_opt = None;
             }
-            current_stop_opt = current_tau.boarding.as_ref().map(|leg| leg.boarded_stop as usize);
         }
 
-        legs.reverse();
+        // The start stop's entry holds the query start time.
+        let journey = Journey::from_legs(legs.drain(..).rev(), start, end, entry(start, 0).time.into(), 0., network);
+        journey.debug_validate();
+        Ok(journey)
+    }
 
-        Ok(Journey::from(legs, 0., network))
+    // Recomputes the cost of riding a trip between two stop orders, the same way mc_raptor_query accumulates it.
+    fn calculate_leg_cost(network: &Network, costs: &(impl CostProvider + ?Sized), trip: GlobalTripIndex, boarded_stop_order: usize, arrival_stop_order: usize) -> PathfindingCost {
+        let route = &network.routes[trip.route().idx()];
+        let trip_order = trip.trip().idx();
+        let boarding_cost = costs.boarding_cost(network, trip, route.get_stop_times_index(trip_order, boarded_stop_order));
+        ((boarded_stop_order + 1)..=arrival_stop_order).fold(boarding_cost, |cost, stop_order| {
+            cost + costs.cost(network, trip, route.get_stop_times_index(trip_order, stop_order))
+        })
     }
 
-    pub(crate) fn from_tau_bag<const N: usize>(tau: &[Bag<N>], network: &'a Network, start: usize, end: usize, path_preferences: &JourneyPreferences) -> JourneyResult<'a> {
+    // Reconstructs the journey to end preferred by path_preferences from multicriteria bags, whose labels refer to boardings.
+    // Like from_tau_into, the legs are collected in legs_buf so that the journeys to several ends can share it.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_tau_bag_into<S: LabelStorage>(tau: &[GenericBag<S>],
+                                                     boardings: &[Boarding],
+                                                     network: &'a Network,
+                                                     start: usize,
+                                                     end: usize,
+                                                     costs: &(impl CostProvider + ?Sized),
+                                                     path_preferences: &JourneyPreferences,
+                                                     legs_buf: &mut Vec<Leg>) -> JourneyResult<'a> {
         // No journey found.
-        if tau[end].is_empty() {
-            return Err(JourneyError::NoJourneyFound);
-        }
-
         debug_assert!(tau[start].as_slice().len() == 1);
         let start_time = tau[start].as_slice()[0].arrival_time;
 
-        let mut legs = Vec::new();
+        if tau[end].is_empty() {
+            let arrival_times = tau.iter().map(|bag| bag.as_slice().iter().map(|label| label.arrival_time).min().unwrap_or(Timestamp::MAX));
+            return Err(JourneyError::no_journey_found(network, start, end, start_time, arrival_times));
+        }
+
+        let legs = legs_buf;
+        legs.clear();
         let mut current_stop_opt = Some(end);
-        let journey_cost = path_preferences.best_label(Timestamp::MAX, tau[end].as_slice(), start_time).unwrap().cost;
+        let journey_cost = path_preferences.best_label(|_| true, tau[end].as_slice(), start_time).unwrap().cost;
         const MAX_LEGS: usize = 100; // Prevent infinite loop (TODO: which is a bug).
         let mut num_legs = 0;
         // Because we push legs in reverse, the previously iterated leg here is the next leg in the journey.
@@ -195,66 +741,388 @@ impl<'a> Journey<'a> {
             if current_stop == start {
                 break;
             }
-            let next_boarding_time = next_boarding.map(|l| l.boarded_time).unwrap_or(Timestamp::MAX);
-            if let Some(current_tau) = path_preferences.best_label(next_boarding_time, tau[current_stop].as_slice(), start_time) {
-                if let Some(boarded_leg) = &current_tau.boarding {
+            // The label must leave the transfer time before the next trip departs, as the label it was boarded from in the search did.
+            // There is none after walking, as the footpath's duration includes it.
+            let arrives_in_time = |label: &Label| OptionExt::is_none_or(next_boarding, |next_boarding: &Boarding| {
+                let transfer_time = match label.walked_from {
+                    Some(_) => 0,
+                    None => {
+                        let from_route = label.boarding(boardings).map(|boarding| boarding.trip.route_idx);
+                        network.get_route_transfer_time(current_stop as StopIndex, from_route, next_boarding.trip.route_idx)
+                    }
+                };
+                Time(label.arrival_time).saturating_add(Seconds(transfer_time)) <= next_boarding.boarded_time
+            });
+            if let Some(current_tau) = path_preferences.best_label(arrives_in_time, tau[current_stop].as_slice(), start_time) {
+                // A walked label keeps the boarding of the label it walked from, so its trip is ridden to the stop walked from.
+                let (mut arrival_stop, mut arrival_time) = (current_stop, current_tau.arrival_time);
+                if let Some(walked_from) = current_tau.walked_from {
+                    let duration = network.footpath_duration(walked_from, current_stop as StopIndex).expect("Walked without a footpath.");
+                    legs.push(Leg::Walk {
+                        from: walked_from,
+                        to: current_stop as StopIndex,
+                        duration,
+                        distance: network.stop_points[walked_from as usize].distance(network.stop_points[current_stop]),
+                    });
+                    (arrival_stop, arrival_time) = (walked_from as usize, arrival_time.saturating_sub(duration));
+                }
+                if let Some(boarded_leg) = current_tau.boarding(boardings) {
                     // Find arrival stop order.
-                    let route = &network.routes[boarded_leg.trip.route_idx as usize];
-                    let arrival_stop_order = Self::calculate_arrival_stop_order(route, network, boarded_leg, current_stop);
+                    let arrival_stop_order = Self::calculate_arrival_stop_order(network, boarded_leg, arrival_stop);
 
-                    legs.push(Leg {
+                    legs.push(Leg::Vehicle(VehicleLeg {
                         boarded_stop: boarded_leg.boarded_stop,
                         boarded_stop_order: boarded_leg.boarded_stop_order,
-                        boarded_time: boarded_leg.boarded_time,
-                        arrival_stop: current_stop as StopIndex,
+                        boarded_time: boarded_leg.boarded_time.into(),
+                        arrival_stop: arrival_stop as StopIndex,
                         arrival_stop_order,
-                        arrival_time: current_tau.arrival_time,
-                        transfer_time: next_boarding.map(|last_boarding| last_boarding.boarded_time - current_tau.arrival_time),
+                        arrival_time,
                         trip: boarded_leg.trip,
-                    });
+                        cost: Self::calculate_leg_cost(network, costs, boarded_leg.trip, boarded_leg.boarded_stop_order as usize, arrival_stop_order as usize),
+                    }));
                     next_boarding = Some(boarded_leg);
                 }
-                current_stop_opt = current_tau.boarding.as_ref().map(|leg| leg.boarded_stop as usize);
+                current_stop_opt = current_tau.boarding(boardings).map(|leg| leg.boarded_stop as usize).or(current_tau.walked_from.map(|stop| stop as usize));
             }
             num_legs += 1;
             if num_legs > MAX_LEGS {
-                return Err(JourneyError::InfiniteLoop);
+                return Err(JourneyError::infinite_loop(legs, current_stop));
             }
         }
 
-        legs.reverse();
-        Ok(Journey::from(legs, journey_cost, network))
+        let journey = Journey::from_legs(legs.drain(..).rev(), start, end, start_time, journey_cost, network);
+        journey.debug_validate();
+        Ok(journey)
     }
 }
 
-impl Display for Journey<'_> {
+impl Journey<'_> {
+    // Checks the journey against its network: each vehicle leg's stops and times must be those of its trip at the claimed stop
+    // orders, consecutive legs must meet at the same stop without going back in time, and changing trips must leave at least
+    // the transfer time between the two routes. Returns the first problem found.
+    pub fn validate(&self) -> Result<(), JourneyValidationError> {
+        let network = self.network;
+        // Where and when the previous leg ended. Leading walks are taken just in time, so the time is only known from the first trip.
+        let mut previous_stop: Option<StopIndex> = None;
+        let mut previous_time = matches!(self.legs.first(), Some(Leg::Vehicle(_))).then_some(Time(self.start_time));
+        // The route of the previous trip, unless a walk replaced the transfer since, and the transfer leg after it, if any.
+        let mut from_route: Option<RouteIndex> = None;
+        let mut transfer: Option<(usize, Timestamp)> = None;
+        for (i, leg) in self.legs.iter().enumerate() {
+            match leg {
+                Leg::Vehicle(leg) => {
+                    self.validate_vehicle_leg(i, leg)?;
+                    if previous_stop.is_some_and(|stop| stop != leg.boarded_stop) {
+                        return Err(JourneyValidationError::Disconnected { leg: i });
+                    }
+                    if let Some(time) = previous_time {
+                        let slack = Time(leg.boarded_time).checked_duration_since(time).ok_or(JourneyValidationError::NotTimeOrdered { leg: i })?.into();
+                        if let Some(from_route) = from_route {
+                            let transfer_time = network.get_route_transfer_time(leg.boarded_stop, Some(from_route), leg.trip.route_idx);
+                            if slack < transfer_time {
+                                return Err(JourneyValidationError::TransferTooShort { leg: i, slack, transfer_time });
+                            }
+                        }
+                        if let Some((transfer_leg, duration)) = transfer.take() {
+                            if duration != slack {
+                                return Err(JourneyValidationError::WrongTransferDuration { leg: transfer_leg });
+                            }
+                        }
+                    }
+                    previous_stop = Some(leg.arrival_stop);
+                    previous_time = Some(Time(leg.arrival_time));
+                    from_route = Some(leg.trip.route_idx);
+                }
+                Leg::Transfer { at_stop, .. } => {
+                    if previous_stop != Some(*at_stop) {
+                        return Err(JourneyValidationError::Disconnected { leg: i });
+                    }
+                    transfer = Some((i, leg.duration()));
+                }
+                Leg::Walk { from, to, duration, .. } => {
+                    if previous_stop.is_some_and(|stop| stop != *from) {
+                        return Err(JourneyValidationError::Disconnected { leg: i });
+                    }
+                    previous_stop = Some(*to);
+                    previous_time = previous_time.map(|time| time.saturating_add(Seconds(*duration)));
+                    from_route = None;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn validate_vehicle_leg(&self, i: usize, leg: &VehicleLeg) -> Result<(), JourneyValidationError> {
+        let network = self.network;
+        let route = network.routes.get(leg.trip.route().idx())
+            .filter(|route| leg.trip.trip().idx() < route.num_trips as usize)
+            .ok_or(JourneyValidationError::UnknownTrip { leg: i, trip: leg.trip })?;
+        let stops = route.get_stops(&network.route_stops);
+        for (stop, stop_order) in [(leg.boarded_stop, leg.boarded_stop_order), (leg.arrival_stop, leg.arrival_stop_order)] {
+            if stops.get(stop_order as usize) != Some(&stop) {
+                return Err(JourneyValidationError::WrongStopOrder { leg: i, stop, stop_order });
+            }
+        }
+        if leg.arrival_stop_order <= leg.boarded_stop_order {
+            return Err(JourneyValidationError::NotForward { leg: i });
+        }
+        let trip = route.get_trip(leg.trip.trip().idx(), &network.stop_times);
+        for (stop_order, expected, actual) in [
+            (leg.boarded_stop_order, trip.departure_times[leg.boarded_stop_order as usize], leg.boarded_time),
+            (leg.arrival_stop_order, trip.arrival_times[leg.arrival_stop_order as usize], leg.arrival_time),
+        ] {
+            if expected != actual {
+                return Err(JourneyValidationError::WrongTime { leg: i, stop_order, expected, actual });
+            }
+        }
+        Ok(())
+    }
+
+    // Validates a reconstructed journey in debug builds.
+    fn debug_validate(&self) {
+        if cfg!(debug_assertions) {
+            if let Err(error) = self.validate() {
+                panic!("Reconstructed an invalid journey: {error}\n{self:?}");
+            }
+        }
+    }
+}
+
+impl Journey<'_> {
+    // Returns the geometry of a vehicle leg, following the route shape between the boarded and arrival stops if available,
+    // and otherwise straight lines between the stops.
+    fn leg_geometry(&self, leg: &VehicleLeg) -> Vec<NetworkPoint> {
+        let route = &self.network.routes[leg.trip.route().idx()];
+        route.shape_between(leg.boarded_stop_order as usize, leg.arrival_stop_order as usize, self.network)
+    }
+
+    // Exports the journey as a GeoJSON FeatureCollection, with a LineString for each vehicle leg and Points for each boarding and alighting.
+    pub fn to_geojson(&self) -> String {
+        let network = self.network;
+        let point_json = |point: NetworkPoint| format!("[{},{}]", point.longitude, point.latitude);
+
+        let mut features = Vec::new();
+        for leg in self.legs_vehicle() {
+            let line = utils::escape_json_string(leg.line_name(network));
+            let colour = utils::get_colour_hex(leg.route_colour(network));
+
+            let coordinates = self.leg_geometry(leg).into_iter().map(point_json).collect::<Vec<_>>().join(",");
+            features.push(format!(
+                r#"{{"type":"Feature","geometry":{{"type":"LineString","coordinates":[{coordinates}]}},"properties":{{"kind":"leg","line":"{line}","colour":"{colour}","trip_id":"{}","departure_time":"{}","arrival_time":"{}"}}}}"#,
+                utils::escape_json_string(leg.trip_id(network)),
+                utils::get_time_str(leg.boarded_time),
+                utils::get_time_str(leg.arrival_time),
+            ));
+
+            for (kind, stop, time) in [("board", leg.boarded_stop, leg.boarded_time), ("alight", leg.arrival_stop, leg.arrival_time)] {
+                features.push(format!(
+                    r#"{{"type":"Feature","geometry":{{"type":"Point","coordinates":{}}},"properties":{{"kind":"{kind}","stop":"{}","time":"{}","line":"{line}","colour":"{colour}"}}}}"#,
+                    point_json(network.stop_points[stop as usize]),
+                    utils::escape_json_string(network.stop_name(stop as usize)),
+                    utils::get_time_str(time),
+                ));
+            }
+        }
+
+        format!(r#"{{"type":"FeatureCollection","features":[{}]}}"#, features.join(","))
+    }
+}
+
+// Resolves stop names and lines so assertion failures are readable.
+impl Debug for Journey<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let stop_name = |stop: StopIndex| self.network.stop_name(stop as usize);
+        let legs = self.legs.iter().map(|leg| match leg {
+            Leg::Vehicle(leg) => format!(
+                "{} {} -> {} {} ({} line, trip {})",
+                leg.boarded_stop_name(self.network),
+                utils::get_time_str(leg.boarded_time),
+                leg.arrival_stop_name(self.network),
+                utils::get_time_str(leg.arrival_time),
+                leg.line_name(self.network),
+                leg.trip_id(self.network),
+            ),
+            Leg::Transfer { at_stop, transfer_time, wait_time } => format!("Transfer at {} ({}s transfer, {}s wait)", stop_name(*at_stop), transfer_time, wait_time),
+            Leg::Walk { from, to, duration, .. } => format!("Walk {} -> {} ({}s)", stop_name(*from), stop_name(*to), duration),
+        }).collect::<Vec<_>>();
+
+        f.debug_struct("Journey")
+         .field("legs", &legs)
+         .field("duration", &self.duration)
+         .field("start_time", &self.start_time)
+         .field("cost", &self.cost)
+         .finish()
+    }
+}
+
+// Text layouts for rendering a journey as an itinerary.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ItineraryStyle {
+    // One line per vehicle leg, e.g. "08:32 Cheltenham → 09:14 Richmond (Frankston)".
+    Compact,
+    // Boardings, arrivals, waits and intermediate stop counts. This is what Display uses.
+    Detailed,
+    // A Markdown table of vehicle legs, for pasting into issues and reports.
+    Markdown,
+}
+
+impl Journey<'_> {
+    // Renders the journey as an itinerary in the given style and time format. Times past midnight are shown with a day offset.
+    pub fn format(&self, style: ItineraryStyle, time_format: TimeFormat) -> String {
+        let mut output = String::new();
+        let result = match style {
+            ItineraryStyle::Compact => self.write_compact(&mut output, time_format),
+            ItineraryStyle::Detailed => self.write_detailed(&mut output, time_format, true),
+            ItineraryStyle::Markdown => self.write_markdown(&mut output, time_format),
+        };
+        // Writing to a String can't fail.
+        result.unwrap();
+        output
+    }
+
+    fn write_compact(&self, f: &mut impl std::fmt::Write, time_format: TimeFormat) -> std::fmt::Result {
+        if self.legs.is_empty() {
+            return self.write_no_legs(f);
+        }
+        for leg in self.legs_vehicle() {
+            writeln!(f,
+                     "{} {} → {} {} ({})",
+                     utils::format_time(leg.boarded_time, time_format),
+                     utils::get_short_stop_name(leg.boarded_stop_name(self.network)),
+                     utils::format_time(leg.arrival_time, time_format),
+                     utils::get_short_stop_name(leg.arrival_stop_name(self.network)),
+                     leg.line_name(self.network),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_markdown(&self, f: &mut impl std::fmt::Write, time_format: TimeFormat) -> std::fmt::Result {
+        if self.legs.is_empty() {
+            return self.write_no_legs(f);
+        }
+        writeln!(f, "| Departs | From | Arrives | To | Line | Stops |")?;
+        writeln!(f, "|---|---|---|---|---|---|")?;
+        for leg in self.legs_vehicle() {
+            writeln!(f,
+                     "| {} | {} | {} | {} | {} | {} |",
+                     utils::format_time(leg.boarded_time, time_format),
+                     leg.boarded_stop_name(self.network),
+                     utils::format_time(leg.arrival_time, time_format),
+                     leg.arrival_stop_name(self.network),
+                     leg.line_name(self.network),
+                     leg.num_stops(),
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Total journey time: {}.", utils::format_duration(self.total_duration(), DurationStyle::Short))
+    }
+
+    // Explains a journey without legs. Queries that find no journey return JourneyError::NoJourneyFound instead, so this is
+    // either a journey from a stop to itself or Journey::empty.
+    fn write_no_legs(&self, f: &mut impl std::fmt::Write) -> std::fmt::Result {
+        match (self.origin, self.destination) {
+            (Some(origin), Some(destination)) if origin == destination => {
+                writeln!(f, "Already at {}, no travel needed.", self.network.stop_name(origin as usize))
+            }
+            _ => writeln!(f, "No journey found."),
+        }
+    }
+
+    fn write_detailed(&self, f: &mut impl std::fmt::Write, time_format: TimeFormat, show_stop_counts: bool) -> std::fmt::Result {
+        let duration_str = |duration: Timestamp| utils::format_duration(duration, DurationStyle::Short);
         write!(f, "-----------------------------------------------")?;
         if self.legs.len() > 0 {
+            // Tracks the time as in stats, so that waits for the first service and after walks are shown as well as transfers.
+            let mut time = Time(self.start_time);
             for leg in self.legs.iter() {
                 writeln!(f)?;
-                writeln!(f,
-                         "Board at {} at {} ({} line).",
-                         //leg.boarded_stop_name,
-                         utils::get_short_stop_name(&self.network.get_stop(leg.boarded_stop as usize).name),
-                         utils::get_time_str(leg.boarded_time),
-                         self.network.routes[leg.trip.route_idx as usize].line,
-                )?;
-                writeln!(f,
-                         "Arrive at {} at {}.",
-                         //leg.arrival_stop_name,
-                         &self.network.get_stop(leg.arrival_stop as usize).name,
-                         utils::get_time_str(leg.arrival_time)
-                )?;
+                match leg {
+                    Leg::Vehicle(leg) => {
+                        let wait = Timestamp::from(Time(leg.boarded_time).saturating_duration_since(time));
+                        if wait > 0 {
+                            writeln!(f, "Wait {} at {}.", duration_str(wait), leg.boarded_stop_name(self.network))?;
+                        }
+                        writeln!(f,
+                                 "Board at {} at {} ({} line).",
+                                 utils::get_short_stop_name(leg.boarded_stop_name(self.network)),
+                                 utils::format_time(leg.boarded_time, time_format),
+                                 leg.line_name(self.network),
+                        )?;
+                        if show_stop_counts {
+                            writeln!(f, "Pass {} stops.", leg.num_stops().saturating_sub(1))?;
+                        }
+                        write!(f,
+                               "Arrive at {} at {}",
+                               leg.arrival_stop_name(self.network),
+                               utils::format_time(leg.arrival_time, time_format)
+                        )?;
+                        if leg.cost != 0. {
+                            write!(f, " (cost {:.1})", leg.cost)?;
+                        }
+                        writeln!(f, ".")?;
+                        time = Time(leg.arrival_time);
+                    }
+                    Leg::Transfer { at_stop, transfer_time, wait_time } => {
+                        write!(f, "Transfer at {} ({} interchange)", self.network.stop_name(*at_stop as usize), duration_str(*transfer_time))?;
+                        if *wait_time > 0 {
+                            write!(f, ", then wait {}", duration_str(*wait_time))?;
+                        }
+                        writeln!(f, ".")?;
+                        time = time.saturating_add(Seconds(leg.duration()));
+                    }
+                    Leg::Walk { to, duration, .. } => {
+                        writeln!(f, "Walk {} to {}.", duration_str(*duration), self.network.stop_name(*to as usize))?;
+                        time = time.saturating_add(Seconds(*duration));
+                    }
+                }
+            }
+            writeln!(f)?;
+            let num_transfers = self.legs_vehicle().count().saturating_sub(1);
+            writeln!(f, "{num_transfers} {}.", if num_transfers == 1 { "transfer" } else { "transfers" })?;
+            writeln!(f, "Travel time: {}.", duration_str(self.in_motion_duration()))?;
+            write!(f, "Door-to-door time from {}: {}", utils::format_time(self.start_time, time_format), duration_str(self.total_duration()))?;
+            let initial_wait = self.initial_wait();
+            if initial_wait > 0 {
+                write!(f, " (including {} waiting for the first service)", duration_str(initial_wait))?;
             }
-            writeln!(f, )?;
-            writeln!(f, "Total journey time: {} minutes.", (self.legs.last().unwrap().arrival_time - self.legs[0].boarded_time) / 60)?;
+            writeln!(f, ".")?;
         } else {
             writeln!(f)?;
-            writeln!(f, "No journey found.")?;
+            self.write_no_legs(f)?;
         }
         writeln!(f, "-----------------------------------------------")?;
         Ok(())
     }
 }
 
+impl Display for Journey<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The alternate format ({:#}) also prints the number of intermediate stops.
+        let show_stop_counts = f.alternate();
+        self.write_detailed(f, TimeFormat::H24, show_stop_counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preset_preferences() {
+        let start_time = 1000;
+        // A fast but expensive label, a medium label, and a slow but cheap label.
+        let labels = [Label::new(1100, 5.), Label::new(1200, 3.), Label::new(1500, 1.)];
+        let best = |preferences: JourneyPreferences| preferences.best_label(|_| true, &labels, start_time).unwrap().arrival_time;
+
+        assert_eq!(best(JourneyPreferences::fastest()), 1100);
+        assert_eq!(best(JourneyPreferences::least_cost()), 1500);
+        // 100 + 500 = 600, 200 + 300 = 500, 500 + 100 = 600.
+        assert_eq!(best(JourneyPreferences::balanced(1., 100.)), 1200);
+        // Here cost is the number of boardings, so the last label takes one trip.
+        assert_eq!(best(JourneyPreferences::least_transfers()), 1500);
+        assert_eq!(best(JourneyPreferences::arrive_relaxed(1300)), 1200);
+        assert_eq!(best(JourneyPreferences::arrive_relaxed(1050)), 1100);
+    }
+}
@@ -4,15 +4,55 @@ pub use network::Network;
 
 pub mod journey;
 
-pub use journey::{Journey, Leg};
+pub use journey::{ItineraryStyle, Journey, JourneyEvent, JourneyRow, JourneyStats, Leg, VehicleLeg};
 
 pub mod raptor;
 
-pub use raptor::{raptor_query, mc_raptor_query};
+pub use raptor::{raptor_query, raptor_one_to_all, raptor_one_to_all_journeys, OneToAll, raptor_query_alternatives, raptor_query_with_capacity, mc_raptor_query, mc_raptor_query_exact, mc_raptor_query_with_stats};
 
 pub mod csa;
 
-pub use csa::{csa_query, mc_csa_query};
+pub use csa::{csa_query, csa_query_with_capacity, mc_csa_query};
+
+pub mod fare;
+
+pub use fare::{Fare, FareTable};
+
+pub mod reference;
+
+pub mod isochrone;
+
+pub use isochrone::Isochrone;
+
+#[cfg(feature = "gtfs-rt")]
+pub mod realtime;
+
+#[cfg(feature = "serde")]
+pub mod otp;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 pub mod utils;
-pub(crate) mod multicriteria;
+
+pub use utils::{DurationStyle, TimeFormat};
+
+pub mod time;
+
+pub mod index;
+
+pub use index::{RouteIdx, StopIdx, TripIdx};
+
+pub mod raw;
+
+mod error;
+
+pub use error::Error;
+
+mod spatial;
+pub mod multicriteria;
+
+pub use multicriteria::{Bag, DynBag, Epsilon, Label, McRaptorStats, OverflowPolicy, TieBreak};
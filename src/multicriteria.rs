@@ -1,39 +1,297 @@
 use crate::journey::Boarding;
-use crate::network::{PathfindingCost, Timestamp};
+use crate::network::{GlobalTripIndex, PathfindingCost, StopIndex, Timestamp};
 use arrayvec::ArrayVec;
-use std::iter::repeat;
+use std::ops::{Deref, DerefMut};
 
-#[derive(Clone)]
+// The index of a boarding in the boardings of a multicriteria query.
+pub(crate) type BoardingIdx = u32;
+
+// A label is a candidate arrival at a stop, with the trip that was boarded to get there (none for the start stop), and the stop
+// walked from if the label's stop was then reached on foot.
+// The boarding is an index into the query's boardings, so labels stay small and are copied rather than cloned. The boarded
+// trip and stop are also kept in the label, so that they can be read without the boardings (e.g. by a utility function).
+#[derive(Clone, Copy)]
 pub struct Label {
     pub arrival_time: Timestamp,
     pub cost: PathfindingCost,
-    pub(crate) boarding: Option<Boarding>,
+    pub(crate) boarding: Option<BoardingIdx>,
+    pub(crate) boarded: Option<(GlobalTripIndex, StopIndex)>,
+    pub(crate) walked_from: Option<StopIndex>,
 }
 
 impl Label {
-    pub(crate) fn new(arrival_time: Timestamp, cost: PathfindingCost) -> Self {
-        Label { arrival_time, cost, boarding: None }
+    pub fn new(arrival_time: Timestamp, cost: PathfindingCost) -> Self {
+        Label { arrival_time, cost, boarding: None, boarded: None, walked_from: None }
+    }
+
+    // A label that boarded boardings[boarding_idx], which is boarding.
+    pub(crate) fn boarded(arrival_time: Timestamp, cost: PathfindingCost, boarding_idx: BoardingIdx, boarding: &Boarding) -> Self {
+        Label { arrival_time, cost, boarding: Some(boarding_idx), boarded: Some((boarding.trip, boarding.boarded_stop)), walked_from: None }
+    }
+
+    // This label after walking on from its stop, which takes duration. The boarding is kept, so the trip ridden before the walk
+    // can be found without looking up a label at the stop walked from.
+    pub(crate) fn walked(&self, from: StopIndex, duration: Timestamp) -> Self {
+        Label { arrival_time: self.arrival_time.saturating_add(duration), walked_from: Some(from), ..*self }
+    }
+
+    pub fn arrival_time(&self) -> Timestamp {
+        self.arrival_time
     }
+
+    pub fn cost(&self) -> PathfindingCost {
+        self.cost
+    }
+
+    // Whether a trip was boarded to reach this label's stop, which is false only for the start label and walks from the start.
+    pub fn has_boarded(&self) -> bool {
+        self.boarded.is_some()
+    }
+
+    // The stop walked from, if this label's stop was reached on foot.
+    pub fn walked_from(&self) -> Option<StopIndex> {
+        self.walked_from
+    }
+
+    // Where and when the trip was boarded, looked up in the boardings of the query that made the label. None if the label has no
+    // boarding, or if it isn't in boardings (e.g. when bags are used outside a query, with no boardings).
+    pub(crate) fn boarding<'b>(&self, boardings: &'b [Boarding]) -> Option<&'b Boarding> {
+        self.boarding.and_then(|boarding| boardings.get(boarding as usize))
+    }
+
+    // The trip that was boarded to reach this label's stop.
+    pub fn boarded_trip(&self) -> Option<GlobalTripIndex> {
+        self.boarded.map(|(trip, _)| trip)
+    }
+
+    // The stop the trip was boarded at.
+    pub fn boarded_stop(&self) -> Option<StopIndex> {
+        self.boarded.map(|(_, stop)| stop)
+    }
+
     fn dominates(&self, other_label: &Label) -> bool {
         self.arrival_time <= other_label.arrival_time && self.cost <= other_label.cost
     }
+
+    // Like dominates, but also dominates labels that are better on one axis by no more than epsilon.
+    fn dominates_with_epsilon(&self, other_label: &Label, epsilon: Epsilon) -> bool {
+        (self.arrival_time <= other_label.arrival_time.saturating_add(epsilon.time) && self.cost <= other_label.cost)
+            || (self.arrival_time <= other_label.arrival_time && self.cost <= other_label.cost + epsilon.cost)
+    }
+}
+
+// Tolerances for epsilon-dominance: a label is pruned if an existing label is no more than time later (or no more than cost
+// more expensive) and no worse on the other criterion. Zero epsilons are ordinary Pareto dominance.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Epsilon {
+    pub time: Timestamp,
+    pub cost: PathfindingCost,
+}
+
+// How to choose between two labels with exactly the same arrival time and cost.
+// Ties never panic: exactly one of the two labels is kept, chosen deterministically by the policy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TieBreak {
+    // Keep the label that was added first.
+    #[default]
+    KeepFirst,
+    // Keep the label that boarded its trip earliest (the one with more slack).
+    EarlierBoarding,
+    // Keep the label whose boarded trip is on the lowest route index.
+    LowerRouteIndex,
+}
+
+impl TieBreak {
+    // Returns true if the new label should replace the existing label it ties with.
+    fn prefers(self, new_label: &Label, existing_label: &Label, boardings: &[Boarding]) -> bool {
+        match self {
+            TieBreak::KeepFirst => false,
+            TieBreak::EarlierBoarding => {
+                let boarded_time = |label: &Label| label.boarding(boardings).map(|boarding| boarding.boarded_time);
+                boarded_time(new_label) < boarded_time(existing_label)
+            }
+            TieBreak::LowerRouteIndex => new_label.boarded_trip().map(|trip| trip.route_idx) < existing_label.boarded_trip().map(|trip| trip.route_idx),
+        }
+    }
+}
+
+// A utility function over labels, where a larger value is worse.
+pub type LabelUtilityFn = dyn Fn(&Label) -> PathfindingCost + Send + Sync;
+
+// Which label to discard when a non-dominated label is added to a full bag.
+// Only bounded bags ever overflow; a DynBag keeps every non-dominated label regardless of the policy.
+#[derive(Default)]
+pub enum OverflowPolicy {
+    // Discard the label with the latest arrival time (which may be the new label).
+    #[default]
+    DropWorstArrival,
+    // Discard the label with the highest cost (which may be the new label).
+    DropWorstCost,
+    // Discard the label with the largest value of the given function (which may be the new label).
+    DropByUtility(Box<LabelUtilityFn>),
+    // Keep the existing labels and discard the new label.
+    RejectNew,
+}
+
+impl OverflowPolicy {
+    // Returns the index of the existing label to evict to make room for the new label, or None if the new label should be discarded.
+    // Ties between the new label and an existing label discard the new label.
+    fn evict(&self, labels: &[Label], new_label: &Label) -> Option<usize> {
+        let evict_worst_by = |f: &dyn Fn(&Label) -> PathfindingCost| {
+            let (index, worst_label) = labels.iter().enumerate().max_by(|(_, a), (_, b)| f(a).total_cmp(&f(b)))?;
+            (f(new_label) < f(worst_label)).then_some(index)
+        };
+        match self {
+            // Labels are sorted by arrival time, so the last label arrives latest.
+            OverflowPolicy::DropWorstArrival => (new_label.arrival_time < labels.last()?.arrival_time).then(|| labels.len() - 1),
+            OverflowPolicy::DropWorstCost => evict_worst_by(&|label| label.cost),
+            OverflowPolicy::DropByUtility(f) => evict_worst_by(f.as_ref()),
+            OverflowPolicy::RejectNew => None,
+        }
+    }
 }
 
-#[derive(Clone)]
-pub(crate) struct Bag<const N: usize = 4> {
-    // Labels are sorted by increasing arrival time.
-    // Only non-dominated labels are stored, so labels end up also sorted in decreasing cost.
-    // Labels are stored in a fixed-size array to avoid heap allocation. Worst arrival time labels are discarded.
-    labels: ArrayVec<Label, N>,
+// The result of adding a label to a bag.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum AddOutcome {
+    // The label was added.
+    Added,
+    // The label was added, and an existing label was evicted to make room for it.
+    Displaced,
+    // The label was dominated by (or lost a tie to) an existing label.
+    Dominated,
+    // The label was discarded because the bag was full.
+    Overflowed,
 }
 
+impl AddOutcome {
+    pub(crate) fn is_added(self) -> bool {
+        matches!(self, AddOutcome::Added | AddOutcome::Displaced)
+    }
+}
+
+// Counters for tuning multicriteria queries, e.g. the bag size and epsilon values.
+// Every label created by the query is either added to a bag, rejected by dominance or evicted by overflow, so
+// labels_created == labels_added + labels_rejected + labels_evicted. labels_displaced counts existing labels evicted to make
+// room for an added label.
+#[derive(Clone, Default, Debug)]
+pub struct McRaptorStats {
+    pub labels_created: usize,
+    pub labels_added: usize,
+    pub labels_rejected: usize,
+    pub labels_evicted: usize,
+    pub labels_displaced: usize,
+    pub bag_merges: usize,
+    // Largest number of labels in any bag, for each round.
+    pub max_bag_occupancy: Vec<usize>,
+}
+
+// Records query statistics. The implementation for () does nothing, so queries without statistics compile to the same code.
+pub(crate) trait StatsRecorder {
+    fn record_label(&mut self, outcome: AddOutcome);
+    fn record_merge(&mut self);
+    fn record_occupancy(&mut self, round: usize, occupancy: usize);
+}
+
+impl StatsRecorder for () {
+    #[inline(always)]
+    fn record_label(&mut self, _outcome: AddOutcome) {}
+    #[inline(always)]
+    fn record_merge(&mut self) {}
+    #[inline(always)]
+    fn record_occupancy(&mut self, _round: usize, _occupancy: usize) {}
+}
+
+impl StatsRecorder for McRaptorStats {
+    fn record_label(&mut self, outcome: AddOutcome) {
+        self.labels_created += 1;
+        match outcome {
+            AddOutcome::Added => self.labels_added += 1,
+            AddOutcome::Displaced => {
+                self.labels_added += 1;
+                self.labels_displaced += 1;
+            }
+            AddOutcome::Dominated => self.labels_rejected += 1,
+            AddOutcome::Overflowed => self.labels_evicted += 1,
+        }
+    }
+
+    fn record_merge(&mut self) {
+        self.bag_merges += 1;
+    }
+
+    fn record_occupancy(&mut self, round: usize, occupancy: usize) {
+        if self.max_bag_occupancy.len() <= round {
+            self.max_bag_occupancy.resize(round + 1, 0);
+        }
+        self.max_bag_occupancy[round] = self.max_bag_occupancy[round].max(occupancy);
+    }
+}
+
+// Storage for the labels in a bag.
+// Bounded storage discards labels when full, while unbounded storage keeps every non-dominated label.
+pub trait LabelStorage: Default + Clone + Deref<Target = [Label]> + DerefMut + IntoIterator<Item = Label> {
+    fn is_full(&self) -> bool;
+    fn push(&mut self, label: Label);
+    fn pop(&mut self) -> Option<Label>;
+    fn insert(&mut self, index: usize, label: Label);
+    fn remove(&mut self, index: usize) -> Label;
+    fn retain(&mut self, f: impl FnMut(&mut Label) -> bool);
+    fn clear(&mut self);
+}
+
+impl<const N: usize> LabelStorage for ArrayVec<Label, N> {
+    fn is_full(&self) -> bool { ArrayVec::is_full(self) }
+    fn push(&mut self, label: Label) { ArrayVec::push(self, label) }
+    fn pop(&mut self) -> Option<Label> { ArrayVec::pop(self) }
+    fn insert(&mut self, index: usize, label: Label) { ArrayVec::insert(self, index, label) }
+    fn remove(&mut self, index: usize) -> Label { ArrayVec::remove(self, index) }
+    fn retain(&mut self, f: impl FnMut(&mut Label) -> bool) { ArrayVec::retain(self, f) }
+    fn clear(&mut self) { ArrayVec::clear(self) }
+}
+
+impl LabelStorage for Vec<Label> {
+    fn is_full(&self) -> bool { false }
+    fn push(&mut self, label: Label) { Vec::push(self, label) }
+    fn pop(&mut self) -> Option<Label> { Vec::pop(self) }
+    fn insert(&mut self, index: usize, label: Label) { Vec::insert(self, index, label) }
+    fn remove(&mut self, index: usize) -> Label { Vec::remove(self, index) }
+    fn retain(&mut self, mut f: impl FnMut(&mut Label) -> bool) { Vec::retain_mut(self, |label| f(label)) }
+    fn clear(&mut self) { Vec::clear(self) }
+}
+
+// A Pareto set of labels over arrival time and cost.
+// Invariants: labels are sorted by strictly increasing arrival time, and are mutually non-dominated, so they are also
+// sorted by strictly decreasing cost.
+#[derive(Clone, Default)]
+pub struct GenericBag<S: LabelStorage> {
+    labels: S,
+}
+
+// A bag holding at most N labels in a fixed-size array to avoid heap allocation. Worst arrival time labels are discarded,
+// so queries using it are only heuristically Pareto-optimal.
+pub type Bag<const N: usize = 4> = GenericBag<ArrayVec<Label, N>>;
+
+// A heap-backed bag that never discards non-dominated labels, for exact Pareto sets.
+pub type DynBag = GenericBag<Vec<Label>>;
+
 impl<const N: usize> Bag<N> {
     pub const fn new() -> Self {
-        Bag { labels: ArrayVec::new_const() }
+        GenericBag { labels: ArrayVec::new_const() }
     }
+}
 
+impl DynBag {
+    pub const fn new() -> Self {
+        GenericBag { labels: Vec::new() }
+    }
+}
+
+impl<S: LabelStorage> GenericBag<S> {
     pub fn dominates(&self, other_label: &Label) -> bool {
-        for label in &self.labels {
+        for label in self.labels.iter() {
             if label.dominates(other_label) {
                 return true;
             }
@@ -41,12 +299,35 @@ impl<const N: usize> Bag<N> {
         false
     }
 
+    // Panics if the bag's labels are not sorted by strictly increasing arrival time and strictly decreasing cost.
+    // This is checked after every add and merge in debug builds.
+    pub fn check_invariants(&self) {
+        for pair in self.labels.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            assert!(a.arrival_time < b.arrival_time, "Bag labels not sorted by unique arrival time: {} then {}.", a.arrival_time, b.arrival_time);
+            assert!(a.cost > b.cost, "Bag labels not sorted by decreasing cost: {} then {}.", a.cost, b.cost);
+        }
+    }
+
+    pub fn dominates_with_epsilon(&self, other_label: &Label, epsilon: Epsilon) -> bool {
+        self.labels.iter().any(|label| label.dominates_with_epsilon(other_label, epsilon))
+    }
+
     pub fn is_empty(&self) -> bool {
         self.labels.is_empty()
     }
 
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    // Returns the label with the lowest value of the given function, e.g. a utility function.
+    pub fn best_by(&self, f: impl Fn(&Label) -> PathfindingCost) -> Option<&Label> {
+        self.labels.iter().min_by(|a, b| f(a).total_cmp(&f(b)))
+    }
+
     pub fn as_slice(&self) -> &[Label] {
-        self.labels.as_slice()
+        &self.labels
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &Label> {
@@ -57,16 +338,57 @@ impl<const N: usize> Bag<N> {
         std::mem::take(&mut self.labels).into_iter()
     }
 
-    pub fn set(&mut self, bag: Bag<N>) {
+    pub fn set(&mut self, bag: GenericBag<S>) {
         self.labels = bag.labels;
     }
 
-    // Adds a label to the bag, discarding non-dominated labels.
+    // Removes every label, keeping any heap storage for reuse.
+    pub fn clear(&mut self) {
+        self.labels.clear();
+    }
+
+    // Replaces the labels with the labels of other for which f is true. Any subset of a Pareto set is also one, so unlike
+    // adding the labels one by one this needs no dominance checks.
+    pub(crate) fn set_filtered(&mut self, other: &GenericBag<S>, mut f: impl FnMut(&Label) -> bool) {
+        self.labels.clear();
+        for label in other.labels.iter().filter(|label| f(label)) {
+            self.labels.push(*label);
+        }
+    }
+
+    // Adds a label to the bag, discarding non-dominated labels. Exact ties keep the existing label.
     // Returns true if the label was added <=> the bag was modified.
     pub fn add(&mut self, new_label: Label) -> bool {
+        self.add_with_tie_break(new_label, TieBreak::KeepFirst)
+    }
+
+    // Adds a label to the bag, discarding non-dominated labels, and resolving exact ties with the given policy.
+    // Returns true if the label was added <=> the bag was modified.
+    pub fn add_with_tie_break(&mut self, new_label: Label, tie_break: TieBreak) -> bool {
+        self.add_with_policies(new_label, tie_break, &OverflowPolicy::DropWorstArrival)
+    }
+
+    // Adds a label to the bag, resolving exact ties and overflow of a full bag with the given policies.
+    // Returns true if the label was added <=> the bag was modified.
+    pub fn add_with_policies(&mut self, new_label: Label, tie_break: TieBreak, overflow: &OverflowPolicy) -> bool {
+        // The boardings of labels made by a query aren't available here, so ties between them are broken as if neither had
+        // boarded.
+        self.insert(new_label, tie_break, overflow, &[]).is_added()
+    }
+
+    // As add_with_policies, but reports why a label was or wasn't added, and breaks ties using the boardings the labels refer to.
+    pub(crate) fn insert(&mut self, new_label: Label, tie_break: TieBreak, overflow: &OverflowPolicy, boardings: &[Boarding]) -> AddOutcome {
+        let outcome = self.insert_unchecked(new_label, tie_break, overflow, boardings);
+        if cfg!(debug_assertions) {
+            self.check_invariants();
+        }
+        outcome
+    }
+
+    fn insert_unchecked(&mut self, new_label: Label, tie_break: TieBreak, overflow: &OverflowPolicy, boardings: &[Boarding]) -> AddOutcome {
         if self.labels.is_empty() {
             self.labels.push(new_label);
-            return true;
+            return AddOutcome::Added;
         }
         // At least one label is present.
 
@@ -75,19 +397,36 @@ impl<const N: usize> Bag<N> {
         let is_last_label = partition.is_none();
         let partition = partition.unwrap_or(self.labels.len());
 
+        // Arrival times are unique, so a label with the same arrival time as the new label must be the label before the partition.
+        if partition > 0 {
+            let previous_label = &mut self.labels[partition - 1];
+            if previous_label.arrival_time == new_label.arrival_time && previous_label.cost == new_label.cost {
+                // Exact tie: nothing else in the bag changes, so only decide which of the two labels to keep.
+                return if tie_break.prefers(&new_label, previous_label, boardings) {
+                    *previous_label = new_label;
+                    AddOutcome::Added
+                } else {
+                    AddOutcome::Dominated
+                };
+            }
+        }
+
         // All the labels before the partition have an earlier arrival time than the new label, and may dominate it.
         if self.labels[..partition].iter().any(|label| label.cost <= new_label.cost) {
             // The new label is dominated by at least one existing label.
-            false
+            AddOutcome::Dominated
         } else {
             // The new label is not dominated. Remove existing labels that are dominated by the new label.
 
             if !is_last_label {
                 // All the labels after the partition have a larger arrival time than the new label, so only keep ones with a smaller cost.
-                let keep = self.labels.iter().skip(partition).map(|label| label.cost < new_label.cost).collect::<ArrayVec<_, N>>();
-                let mut keep_iter = repeat(true).take(partition).chain(keep.into_iter());
-                debug_assert!(keep_iter.size_hint().0 == self.labels.len());
-                self.labels.retain(|_| keep_iter.next().unwrap());
+                // Labels are visited in order, so count them to find the partition.
+                let mut i = 0;
+                self.labels.retain(|label| {
+                    let keep = i < partition || label.cost < new_label.cost;
+                    i += 1;
+                    keep
+                });
             }
 
             // Arrival times are unique, so if a label exists with the same arrival time as the new label, it must be the label before the partition.
@@ -98,39 +437,135 @@ impl<const N: usize> Bag<N> {
                     // If the new label has a smaller cost, replace the previous label.
                     if new_label.cost < previous_label.cost {
                         *previous_label = new_label;
-                        return true;
+                        return AddOutcome::Added;
                     } else {
-                        // The new label is dominated by the previous label.
-                        unreachable!("The new label should have been dominated in the previous check.");
+                        // The new label is dominated by the previous label. This should have been caught by the previous check,
+                        // unless a cost is NaN.
+                        return AddOutcome::Dominated;
                     };
                 }
             }
 
-            // Add the new label.
+            // Add the new label, evicting a label to make space if the bag is full.
+            let mut partition = partition;
+            let mut outcome = AddOutcome::Added;
             if self.labels.is_full() {
-                if is_last_label {
-                    if new_label.arrival_time < self.labels.last().unwrap().arrival_time {
-                        // Prioritise arrival time over cost. Add the new label if it has a smaller arrival time than the last label.
-                        self.labels.pop();
-                    } else {
-                        // Don't add the last label.
-                        return false;
+                match overflow.evict(&self.labels, &new_label) {
+                    Some(index) => {
+                        self.labels.remove(index);
+                        if index < partition {
+                            partition -= 1;
+                        }
+                        outcome = AddOutcome::Displaced;
                     }
-                } else {
-                    // Pop off last label to make space for the new label.
-                    self.labels.pop();
+                    None => return AddOutcome::Overflowed,
                 }
             }
 
             self.labels.insert(partition, new_label);
-            true
+            outcome
+        }
+    }
+
+    // Merges another bag into this one in a single linear pass, exploiting that both bags are sorted by arrival time.
+    // Exact ties keep the existing label. Returns true if any label from the other bag was added <=> the bag was modified.
+    pub fn merge(&mut self, other: &GenericBag<S>) -> bool {
+        self.merge_with_tie_break(other, TieBreak::KeepFirst)
+    }
+
+    // Merges another bag into this one, resolving exact ties with the given policy.
+    // If the combined Pareto set does not fit, the labels with the latest arrival times are discarded. Unlike adding labels
+    // one by one, this never keeps a label that a discarded label would have dominated.
+    pub fn merge_with_tie_break(&mut self, other: &GenericBag<S>, tie_break: TieBreak) -> bool {
+        self.merge_with_policies(other, tie_break, &OverflowPolicy::DropWorstArrival)
+    }
+
+    // Merges another bag into this one, resolving exact ties and overflow with the given policies.
+    // Only DropWorstArrival can be done in a single pass; other overflow policies add the labels one by one.
+    pub fn merge_with_policies(&mut self, other: &GenericBag<S>, tie_break: TieBreak, overflow: &OverflowPolicy) -> bool {
+        self.merge_with_boardings(other, tie_break, overflow, &[])
+    }
+
+    // As merge_with_policies, but breaks ties using the boardings the labels refer to.
+    pub(crate) fn merge_with_boardings(&mut self, other: &GenericBag<S>, tie_break: TieBreak, overflow: &OverflowPolicy, boardings: &[Boarding]) -> bool {
+        let changed = self.merge_unchecked(other, tie_break, overflow, boardings);
+        if cfg!(debug_assertions) {
+            self.check_invariants();
         }
+        changed
+    }
+
+    fn merge_unchecked(&mut self, other: &GenericBag<S>, tie_break: TieBreak, overflow: &OverflowPolicy, boardings: &[Boarding]) -> bool {
+        if other.labels.is_empty() {
+            return false;
+        }
+        if !matches!(overflow, OverflowPolicy::DropWorstArrival) {
+            let mut changed = false;
+            for label in other.iter() {
+                changed |= self.insert(*label, tie_break, overflow, boardings).is_added();
+            }
+            return changed;
+        }
+
+        let mut merged = S::default();
+        let mut changed = false;
+        let mut best_cost = PathfindingCost::INFINITY;
+        let mut i = 0;
+        let mut j = 0;
+        while !merged.is_full() {
+            let (label, is_new) = match (self.labels.get(i), other.labels.get(j)) {
+                (None, None) => break,
+                (Some(existing), None) => {
+                    i += 1;
+                    (existing, false)
+                }
+                (None, Some(new)) => {
+                    j += 1;
+                    (new, true)
+                }
+                (Some(existing), Some(new)) => {
+                    if existing.arrival_time < new.arrival_time {
+                        i += 1;
+                        (existing, false)
+                    } else if new.arrival_time < existing.arrival_time {
+                        j += 1;
+                        (new, true)
+                    } else {
+                        // Same arrival time, so only the cheaper label (or the tie-break winner) can survive.
+                        i += 1;
+                        j += 1;
+                        if new.cost < existing.cost || (new.cost == existing.cost && tie_break.prefers(new, existing, boardings)) {
+                            (new, true)
+                        } else {
+                            (existing, false)
+                        }
+                    }
+                }
+            };
+
+            // Labels are visited in order of arrival time, so a label is non-dominated iff it is cheaper than every label before it.
+            if label.cost < best_cost {
+                best_cost = label.cost;
+                merged.push(*label);
+                changed |= is_new;
+            }
+        }
+
+        // If no label from the other bag survived, the existing labels are all kept, so the bag is unchanged.
+        if changed {
+            self.labels = merged;
+        }
+        changed
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::index::{RouteIdx, TripIdx};
+    use crate::network::GlobalTripIndex;
+    use crate::time::Time;
+    use proptest::prelude::*;
 
     #[test]
     fn test_bag_add() {
@@ -176,4 +611,312 @@ mod tests {
         assert_eq!(bag.add(Label::new(6, 4.)), true);    // 18 discards 17.
         assert_eq!(bag.labels.len(), 5);
     }
+
+    fn tied_label(boardings: &mut Vec<Boarding>, boarded_time: Timestamp, route_idx: u32) -> Label {
+        let boarding = Boarding {
+            boarded_stop: 0,
+            boarded_stop_order: 0,
+            boarded_time: Time(boarded_time),
+            trip: GlobalTripIndex::new(RouteIdx(route_idx), TripIdx(0)),
+        };
+        let label = Label::boarded(100, 1., boardings.len() as BoardingIdx, &boarding);
+        boardings.push(boarding);
+        label
+    }
+
+    #[test]
+    fn test_bag_tie_break() {
+        fastrand::seed(3);
+        for _ in 0..1000 {
+            let mut boardings = Vec::new();
+            let mut labels = (0..8).map(|i| tied_label(&mut boardings, fastrand::u32(0..50), i)).collect::<Vec<_>>();
+            fastrand::shuffle(&mut labels);
+            let boarding = |label: &Label| label.boarding(&boardings).unwrap().clone();
+
+            for tie_break in [TieBreak::KeepFirst, TieBreak::EarlierBoarding, TieBreak::LowerRouteIndex] {
+                let mut bag = Bag::<4>::new();
+                for label in labels.iter() {
+                    bag.insert(*label, tie_break, &OverflowPolicy::DropWorstArrival, &boardings);
+                }
+                assert_eq!(bag.len(), 1);
+                let survivor = boarding(&bag.as_slice()[0]);
+                match tie_break {
+                    TieBreak::KeepFirst => assert_eq!(survivor.trip, boarding(&labels[0]).trip),
+                    TieBreak::EarlierBoarding => assert_eq!(survivor.boarded_time, labels.iter().map(|label| boarding(label).boarded_time).min().unwrap()),
+                    TieBreak::LowerRouteIndex => assert_eq!(survivor.trip.route(), RouteIdx(0)),
+                }
+            }
+        }
+    }
+
+    fn random_bag<S: LabelStorage>(num_labels: usize, boardings: &mut Vec<Boarding>) -> GenericBag<S> {
+        // Small ranges so that exact ties are common.
+        let mut bag = GenericBag::<S>::default();
+        for _ in 0..num_labels {
+            bag.add(Label {
+                arrival_time: fastrand::u32(0..20),
+                cost: fastrand::u8(0..10) as PathfindingCost,
+                ..tied_label(boardings, fastrand::u32(0..20), fastrand::u32(0..4))
+            });
+        }
+        bag
+    }
+
+    fn label_values<S: LabelStorage>(bag: &GenericBag<S>, boardings: &[Boarding]) -> Vec<(Timestamp, PathfindingCost, Option<Time>, Option<GlobalTripIndex>)> {
+        bag.iter().map(|label| {
+            let boarding = label.boarding(boardings);
+            (label.arrival_time, label.cost, boarding.map(|boarding| boarding.boarded_time), boarding.map(|boarding| boarding.trip))
+        }).collect()
+    }
+
+    fn add_all<S: LabelStorage>(bag: &mut GenericBag<S>, other: &GenericBag<S>, tie_break: TieBreak, boardings: &[Boarding]) -> bool {
+        let mut changed = false;
+        for label in other.iter() {
+            changed |= bag.insert(*label, tie_break, &OverflowPolicy::DropWorstArrival, boardings).is_added();
+        }
+        changed
+    }
+
+    #[test]
+    fn test_merge_matches_add() {
+        fastrand::seed(5);
+        for _ in 0..1000 {
+            for tie_break in [TieBreak::KeepFirst, TieBreak::EarlierBoarding, TieBreak::LowerRouteIndex] {
+                let mut boardings = Vec::new();
+                let a = random_bag::<Vec<Label>>(fastrand::usize(0..10), &mut boardings);
+                let b = random_bag::<Vec<Label>>(fastrand::usize(0..10), &mut boardings);
+                let mut merged = a.clone();
+                let mut added = a.clone();
+                assert_eq!(merged.merge_with_boardings(&b, tie_break, &OverflowPolicy::DropWorstArrival, &boardings), add_all(&mut added, &b, tie_break, &boardings));
+                assert_eq!(label_values(&merged, &boardings), label_values(&added, &boardings));
+
+                // Bounded bags large enough to never discard labels.
+                let a = random_bag::<ArrayVec<Label, 8>>(fastrand::usize(0..4), &mut boardings);
+                let b = random_bag::<ArrayVec<Label, 8>>(fastrand::usize(0..4), &mut boardings);
+                let mut merged = a.clone();
+                let mut added = a.clone();
+                assert_eq!(merged.merge_with_boardings(&b, tie_break, &OverflowPolicy::DropWorstArrival, &boardings), add_all(&mut added, &b, tie_break, &boardings));
+                assert_eq!(label_values(&merged, &boardings), label_values(&added, &boardings));
+            }
+        }
+    }
+
+    #[test]
+    fn test_merge_discards_latest_arrivals() {
+        fastrand::seed(6);
+        for _ in 0..1000 {
+            let mut boardings = Vec::new();
+            let a = random_bag::<ArrayVec<Label, 3>>(fastrand::usize(0..10), &mut boardings);
+            let b = random_bag::<ArrayVec<Label, 3>>(fastrand::usize(0..10), &mut boardings);
+            let mut exact = DynBag { labels: a.iter().copied().collect() };
+            exact.merge(&DynBag { labels: b.iter().copied().collect() });
+
+            // The bounded merge keeps the earliest arriving labels of the exact Pareto set.
+            let mut bounded = a.clone();
+            bounded.merge(&b);
+            assert_eq!(bounded.len(), exact.len().min(3));
+            assert_eq!(label_values(&bounded, &boardings)[..], label_values(&exact, &boardings)[..bounded.len()]);
+        }
+    }
+
+    fn full_bag() -> Bag<3> {
+        let mut bag = Bag::<3>::new();
+        for label in [Label::new(10, 3.), Label::new(20, 2.), Label::new(30, 1.)] {
+            assert!(bag.add(label));
+        }
+        bag
+    }
+
+    fn arrival_times(bag: &Bag<3>) -> Vec<Timestamp> {
+        bag.iter().map(|label| label.arrival_time).collect()
+    }
+
+    // The labels of full_bag that are no longer in the bag.
+    fn evicted(bag: &Bag<3>) -> Vec<(Timestamp, PathfindingCost)> {
+        let kept = bag.iter().map(|label| (label.arrival_time, label.cost)).collect::<Vec<_>>();
+        full_bag().iter().map(|label| (label.arrival_time, label.cost)).filter(|label| !kept.contains(label)).collect()
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let new_label = Label::new(15, 2.5);
+
+        let mut bag = full_bag();
+        assert!(bag.add_with_policies(new_label, TieBreak::KeepFirst, &OverflowPolicy::DropWorstArrival));
+        assert_eq!(evicted(&bag), [(30, 1.)]);
+        assert_eq!(arrival_times(&bag), [10, 15, 20]);
+
+        let mut bag = full_bag();
+        assert!(bag.add_with_policies(new_label, TieBreak::KeepFirst, &OverflowPolicy::DropWorstCost));
+        assert_eq!(evicted(&bag), [(10, 3.)]);
+        assert_eq!(arrival_times(&bag), [15, 20, 30]);
+
+        // Utilities are 30, 40, 30 and 37.5 for the new label.
+        let mut bag = full_bag();
+        let utility = OverflowPolicy::DropByUtility(Box::new(|label| label.cost * label.arrival_time as PathfindingCost));
+        assert!(bag.add_with_policies(new_label, TieBreak::KeepFirst, &utility));
+        assert_eq!(evicted(&bag), [(20, 2.)]);
+        assert_eq!(arrival_times(&bag), [10, 15, 30]);
+
+        let mut bag = full_bag();
+        assert!(!bag.add_with_policies(new_label, TieBreak::KeepFirst, &OverflowPolicy::RejectNew));
+        assert!(evicted(&bag).is_empty());
+        assert_eq!(arrival_times(&bag), [10, 20, 30]);
+
+        // The new label is the worst by the policy, so is discarded.
+        let mut bag = full_bag();
+        assert!(!bag.add_with_policies(Label::new(35, 0.5), TieBreak::KeepFirst, &OverflowPolicy::DropWorstArrival));
+        assert!(!bag.add_with_policies(Label::new(5, 4.), TieBreak::KeepFirst, &OverflowPolicy::DropWorstCost));
+        assert!(evicted(&bag).is_empty());
+        assert_eq!(arrival_times(&bag), [10, 20, 30]);
+
+        // An unbounded bag never overflows.
+        let mut bag = DynBag::new();
+        for label in [Label::new(10, 3.), Label::new(20, 2.), Label::new(30, 1.), new_label] {
+            assert!(bag.add_with_policies(label, TieBreak::KeepFirst, &OverflowPolicy::RejectNew));
+        }
+        assert_eq!(bag.len(), 4);
+    }
+
+    #[test]
+    fn labels_from_a_query_can_be_added_without_its_boardings() {
+        let boarding = Boarding { boarded_stop: 3, boarded_stop_order: 1, boarded_time: Time(500), trip: GlobalTripIndex::new(RouteIdx(2), TripIdx(4)) };
+        let boarded = Label::boarded(10, 1., 0, &boarding);
+        assert_eq!((boarded.boarded_trip(), boarded.boarded_stop()), (Some(GlobalTripIndex::new(RouteIdx(2), TripIdx(4))), Some(3)));
+        assert_eq!(boarded.boarding(std::slice::from_ref(&boarding)).map(|boarding| boarding.boarded_time), Some(Time(500)));
+        assert!(boarded.boarding(&[]).is_none());
+
+        // Tie breaks that look at boardings find none, rather than indexing out of bounds.
+        let mut bag = Bag::<3>::new();
+        assert!(bag.add_with_tie_break(boarded, TieBreak::EarlierBoarding));
+        assert!(!bag.add_with_tie_break(Label::boarded(10, 1., 7, &boarding), TieBreak::LowerRouteIndex));
+        let mut other = Bag::<3>::new();
+        assert!(other.add(Label::boarded(5, 2., 9, &boarding)));
+        assert!(bag.merge_with_policies(&other, TieBreak::EarlierBoarding, &OverflowPolicy::DropWorstArrival));
+        assert_eq!(bag.len(), 2);
+    }
+
+    // The labels of a set that are not dominated by any other label, sorted by arrival time. Exact duplicates keep the first.
+    fn brute_force_pareto(labels: &[Label]) -> Vec<(Timestamp, PathfindingCost)> {
+        let mut pareto = Vec::new();
+        for (i, label) in labels.iter().enumerate() {
+            let dominated = labels.iter().enumerate().any(|(j, other)| {
+                let is_duplicate = other.arrival_time == label.arrival_time && other.cost == label.cost;
+                other.dominates(label) && (!is_duplicate || j < i)
+            });
+            if !dominated {
+                pareto.push((label.arrival_time, label.cost));
+            }
+        }
+        pareto.sort_by_key(|&(arrival_time, _)| arrival_time);
+        pareto
+    }
+
+    // The (arrival time, cost, boarded time, route index) of each label, in small ranges so that exact ties are common.
+    fn arbitrary_labels() -> impl Strategy<Value=Vec<(Timestamp, u8, Timestamp, u32)>> {
+        proptest::collection::vec((0..100u32, 0..30u8, 0..100u32, 0..4u32), 0..200)
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(200))]
+        #[test]
+        fn test_bag_invariants(raw_labels in arbitrary_labels()) {
+            check_bag_invariants(&raw_labels);
+        }
+    }
+
+    fn check_bag_invariants(raw_labels: &[(Timestamp, u8, Timestamp, u32)]) {
+        let policies = [
+            OverflowPolicy::DropWorstArrival,
+            OverflowPolicy::DropWorstCost,
+            OverflowPolicy::DropByUtility(Box::new(|label| label.arrival_time as PathfindingCost + label.cost * 10.)),
+            OverflowPolicy::RejectNew,
+        ];
+        let mut boardings = Vec::new();
+        let labels = raw_labels.iter()
+            .map(|&(arrival_time, cost, boarded_time, route_idx)| Label { arrival_time, cost: cost as PathfindingCost, ..tied_label(&mut boardings, boarded_time, route_idx) })
+            .collect::<Vec<_>>();
+
+        // Bounded bags must keep their invariants under every policy, even when they overflow.
+        for overflow in policies.iter() {
+            let mut bag = Bag::<4>::new();
+            for label in labels.iter() {
+                bag.insert(*label, TieBreak::EarlierBoarding, overflow, &boardings);
+                bag.check_invariants();
+            }
+            assert!(bag.len() <= 4);
+        }
+
+        // An unbounded bag is exactly the Pareto set, and dominates every label it rejected.
+        let mut bag = DynBag::new();
+        let mut rejected = Vec::new();
+        for label in labels.iter() {
+            if bag.insert(*label, TieBreak::KeepFirst, &OverflowPolicy::RejectNew, &boardings) == AddOutcome::Dominated {
+                rejected.push(*label);
+            }
+        }
+        bag.check_invariants();
+        assert_eq!(bag.iter().map(|label| (label.arrival_time, label.cost)).collect::<Vec<_>>(), brute_force_pareto(&labels));
+        for label in rejected.iter() {
+            assert!(bag.dominates(label));
+            assert!(bag.iter().all(|kept| !label.dominates(kept) || (label.arrival_time == kept.arrival_time && label.cost == kept.cost)));
+        }
+
+        // A bounded bag large enough to never overflow behaves like an unbounded bag.
+        let mut large_bag = Bag::<256>::new();
+        for label in labels.iter() {
+            large_bag.add(*label);
+        }
+        assert_eq!(label_values(&large_bag, &boardings), label_values(&bag, &boardings));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_check_invariants_detects_unsorted_labels() {
+        let bag = DynBag { labels: vec![Label::new(20, 1.), Label::new(10, 2.)] };
+        bag.check_invariants();
+    }
+
+    #[test]
+    fn test_epsilon_dominance() {
+        fastrand::seed(8);
+        for _ in 0..1000 {
+            let mut bag = DynBag::new();
+            for _ in 0..5 {
+                bag.add(Label::new(fastrand::u32(0..600), fastrand::u8(0..10) as PathfindingCost));
+            }
+            let label = Label::new(fastrand::u32(0..600), fastrand::u8(0..10) as PathfindingCost);
+            assert_eq!(bag.dominates_with_epsilon(&label, Epsilon::default()), bag.dominates(&label));
+        }
+
+        let mut bag = Bag::<4>::new();
+        bag.add(Label::new(100, 2.));
+        let epsilon = Epsilon { time: 60, cost: 0.5 };
+        // Arrives up to a minute earlier for the same cost.
+        assert!(bag.dominates_with_epsilon(&Label::new(40, 2.), epsilon));
+        assert!(!bag.dominates_with_epsilon(&Label::new(39, 2.), epsilon));
+        // Slightly cheaper for the same arrival time.
+        assert!(bag.dominates_with_epsilon(&Label::new(100, 1.5), epsilon));
+        assert!(!bag.dominates_with_epsilon(&Label::new(100, 1.4), epsilon));
+        // Better on both criteria.
+        assert!(!bag.dominates_with_epsilon(&Label::new(90, 1.9), epsilon));
+    }
+
+    #[test]
+    fn test_dyn_bag_keeps_all_labels() {
+        let mut bounded_bag = Bag::<4>::new();
+        let mut dyn_bag = DynBag::new();
+
+        // Each label arrives later but is cheaper than the last, so none are dominated.
+        for i in 0..10 {
+            let label = Label::new(i, (10 - i) as PathfindingCost);
+            bounded_bag.add(label);
+            assert!(dyn_bag.add(label));
+        }
+        assert_eq!(bounded_bag.len(), 4);
+        assert_eq!(dyn_bag.len(), 10);
+
+        // The bounded bag keeps the earliest arrivals.
+        assert!(bounded_bag.iter().map(|label| label.arrival_time).eq(0..4));
+    }
 }
\ No newline at end of file
@@ -1,41 +1,153 @@
+use crate::fare::FareTable;
+use crate::index::{RouteIdx, StopIdx, TripIdx};
 use crate::journey::Connection;
+use crate::spatial::StopGrid;
 use crate::utils;
-use chrono::NaiveDate;
+use crate::utils::{DurationStyle, OptionExt, StringArena, TimeFormat};
+use chrono::{Days, NaiveDate, NaiveDateTime};
+#[cfg(feature = "gtfs")]
 use gtfs_structures::{DirectionType, Gtfs, RouteType, Trip};
 use rgb::RGB8;
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::mem::size_of;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::sync::{Arc, OnceLock};
 
 // Timestamp is seconds since midnight.
 pub type Timestamp = u32;
+// Index widths are chosen at compile time. The small-stop-indices and small-route-indices features halve the per-stop query
+// state (tau, marked stops) for feeds with fewer than 65535 stops or routes; trip orders stay 32-bit.
+#[cfg(not(feature = "small-stop-indices"))]
 pub type StopIndex = u32;
+#[cfg(feature = "small-stop-indices")]
+pub type StopIndex = u16;
 pub type StopBitfield = bnum::BUint<7>; // Maximum 64*7 = 448 stops per route. This is required for the 901 bus route in Melbourne?
 
+#[cfg(feature = "gtfs")]
 const STOP_BITFIELD_SIZE_BITS: usize = utils::get_size_bits::<StopBitfield>();
 
+#[cfg(not(feature = "small-route-indices"))]
 pub type RouteIndex = u32;
+#[cfg(feature = "small-route-indices")]
+pub type RouteIndex = u16;
 pub type TripOrder = u32;
 pub type PathfindingCost = f32;
 
+// The width of the buckets Network::connection_buckets indexes connections by.
+const CONNECTION_BUCKET_SECONDS: Timestamp = 60;
+
 pub type CoordType = f32;
 
-// Used to globally identify a trip in the network.
-#[derive(Default, Clone, Copy, PartialEq)]
+// Used to globally identify a trip in the network. The fields are the raw indices; route() and trip() give the typed ones.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GlobalTripIndex {
     pub route_idx: RouteIndex,
     pub trip_order: TripOrder,
 }
 
+impl GlobalTripIndex {
+    pub fn new(route: RouteIdx, trip: TripIdx) -> Self {
+        Self { route_idx: route.0, trip_order: trip.0 }
+    }
+
+    pub fn route(&self) -> RouteIdx {
+        RouteIdx(self.route_idx)
+    }
+
+    pub fn trip(&self) -> TripIdx {
+        TripIdx(self.trip_order)
+    }
+}
+
+// Provides the cost of travelling to a stop time on a trip, for multicriteria queries.
+// Implemented for cost slices indexed by the global stop_times index, and for closures so costs can be computed lazily.
+pub trait CostProvider {
+    fn cost(&self, network: &Network, trip: GlobalTripIndex, stop_times_index: usize) -> PathfindingCost;
+
+    // The cost of boarding a trip at the given stop time (e.g. a flat fare), added once per boarding. Defaults to zero.
+    fn boarding_cost(&self, _network: &Network, _trip: GlobalTripIndex, _stop_times_index: usize) -> PathfindingCost {
+        0.
+    }
+}
+
+impl CostProvider for [PathfindingCost] {
+    fn cost(&self, _network: &Network, _trip: GlobalTripIndex, stop_times_index: usize) -> PathfindingCost {
+        self[stop_times_index]
+    }
+}
+
+impl CostProvider for Vec<PathfindingCost> {
+    fn cost(&self, network: &Network, trip: GlobalTripIndex, stop_times_index: usize) -> PathfindingCost {
+        self.as_slice().cost(network, trip, stop_times_index)
+    }
+}
+
+impl<F: Fn(&Network, GlobalTripIndex, usize) -> PathfindingCost> CostProvider for F {
+    fn cost(&self, network: &Network, trip: GlobalTripIndex, stop_times_index: usize) -> PathfindingCost {
+        self(network, trip, stop_times_index)
+    }
+}
+
+// Costs each boarding at 1, so a label's cost is the number of trips taken to reach it. Used with JourneyPreferences::least_transfers.
+pub struct CountBoardings;
+
+impl CostProvider for CountBoardings {
+    fn cost(&self, _network: &Network, _trip: GlobalTripIndex, _stop_times_index: usize) -> PathfindingCost {
+        0.
+    }
+
+    fn boarding_cost(&self, _network: &Network, _trip: GlobalTripIndex, _stop_times_index: usize) -> PathfindingCost {
+        1.
+    }
+}
+
+// Error for a GTFS stop ID that isn't in the network.
+#[derive(thiserror::Error, Debug, PartialEq)]
+#[error("Unknown stop ID {0:?}.")]
+pub struct UnknownStop(pub String);
+
+// Error for a GTFS feed that can't be read or built into a network. See Network::try_new and Network::load.
+#[derive(thiserror::Error, Debug)]
+pub enum NetworkError {
+    #[cfg(feature = "gtfs")]
+    #[error("Failed to read GTFS: {0}")]
+    Gtfs(#[from] gtfs_structures::Error),
+    #[error("Too many stops ({0}, max {1}) in GTFS for {2}-bit stop indices (is the small-stop-indices feature enabled?).")]
+    TooManyStops(usize, usize, usize),
+    #[error("Too many routes ({0}, max {1}) in GTFS for {2}-bit route indices (is the small-route-indices feature enabled?).")]
+    TooManyRoutes(usize, usize, usize),
+    #[error("Too many trips ({0}, max {1}) in GTFS for {2}-bit trip orders.")]
+    TooManyTrips(usize, usize, usize),
+    #[error("Too many stops in route {route_id} ({num_stops}, max {max}).")]
+    TooManyStopsInRoute { route_id: String, num_stops: usize, max: usize },
+    #[error("Stop {0} has no name.")]
+    MissingStopName(String),
+    #[error("Trip {trip_id} has no arrival or departure time at stop {stop_id}.")]
+    MissingStopTime { trip_id: String, stop_id: String },
+}
+
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NetworkPoint {
     pub latitude: CoordType,
     pub longitude: CoordType,
 }
 
 impl NetworkPoint {
-    const EARTH_RADIUS: CoordType = 6371.; // km
+    pub(crate) const EARTH_RADIUS: CoordType = 6371.; // km
     const CLOSE_THRESHOLD: CoordType = 0.1; // 0.1 km = 100 m, because shaped points sometimes aren't exactly on station points. Closest stations are 504 m apart (West and North Richmond).
 
+    // Points with non-finite or out of range coordinates are invalid, as is (0, 0), which is used for stops missing coordinates.
+    pub fn is_valid(self) -> bool {
+        self.latitude.is_finite() && self.longitude.is_finite()
+            && self.latitude.abs() <= 90. && self.longitude.abs() <= 180.
+            && !(self.latitude == 0. && self.longitude == 0.)
+    }
+
     // Equirectangular projection (on a unit sphere).
     pub fn equirectangular_delta(self, other: NetworkPoint) -> (CoordType, CoordType) {
         let x = (other.longitude - self.longitude).to_radians() * ((other.latitude + self.latitude) * 0.5).to_radians().cos();
@@ -60,7 +172,6 @@ impl NetworkPoint {
         //Self::EARTH_RADIUS * c
     }
 
-    #[allow(dead_code)]
     pub fn very_close(self, other: NetworkPoint) -> bool {
         self.distance(other) < Self::CLOSE_THRESHOLD
     }
@@ -111,6 +222,7 @@ impl NetworkPoint {
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Route {
     pub line: Arc<str>,
     pub num_stops: StopIndex,
@@ -118,9 +230,21 @@ pub struct Route {
     pub route_stops_idx: usize,
     pub stop_times_idx: usize,
     // Visual properties
-    pub trip_ids: Vec<Box<str>>,
+    pub trip_ids: StringArena,
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::serde_colour"))]
     pub colour: RGB8,
+    // The GTFS route_type code (including extended route types), or None if it isn't known, e.g. for Network::from_timetable.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub route_type: Option<i16>,
     pub shape: Box<[NetworkPoint]>,
+    // The GTFS shape the route's shape was taken from, and how many of its trips have a different (or no) shape.
+    pub shape_id: Option<Box<str>>,
+    pub num_shape_mismatches: usize,
+    // Trips cancelled after the network was built (Indexed by trip order). See Network::cancel_trip.
+    pub cancelled_trips: Vec<bool>,
+    // Stops a trip no longer serves, so passengers can't board or alight there (Indexed by trip order * num_stops + stop order).
+    // See Network::skip_stop.
+    pub skipped_stops: Vec<bool>,
     pub shape_height: CoordType,
 }
 
@@ -146,32 +270,586 @@ impl Route {
         debug_assert!(trip_range.contains(&index));
         index
     }
-    pub fn get_trip<'a>(&self, trip_order: usize, stop_times: &'a [StopTime]) -> &'a [StopTime] {
-        &stop_times[self.get_trip_range(trip_order)]
+    pub fn get_trip<'a>(&self, trip_order: usize, stop_times: &'a StopTimes) -> TripStopTimes<'a> {
+        stop_times.slice(self.get_trip_range(trip_order))
+    }
+    pub fn is_cancelled(&self, trip_order: usize) -> bool {
+        self.cancelled_trips[trip_order]
+    }
+    pub fn is_skipped(&self, trip_order: usize, stop_order: usize) -> bool {
+        self.skipped_stops[trip_order * self.num_stops as usize + stop_order]
+    }
+    // Returns the part of the route's shape between two of its stops, including both ends. Each stop is snapped to its nearest
+    // shape point, and the shape is traversed backwards if it runs in the opposite direction to the stops.
+    // Falls back to straight lines between the stops if the route has no shape or a stop isn't very close to it.
+    pub fn shape_between(&self, from_stop_order: usize, to_stop_order: usize, network: &Network) -> Vec<NetworkPoint> {
+        let stops = self.get_stops(&network.route_stops);
+        let from_point = network.stop_points[stops[from_stop_order] as usize];
+        let to_point = network.stop_points[stops[to_stop_order] as usize];
+
+        let snap = |point: NetworkPoint| {
+            self.shape.iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.distance(point).total_cmp(&b.distance(point)))
+                .filter(|(_, shape_point)| shape_point.very_close(point))
+                .map(|(i, _)| i)
+        };
+        if let (Some(from), Some(to)) = (snap(from_point), snap(to_point)) {
+            if from < to {
+                return self.shape[from..=to].to_vec();
+            } else if from > to {
+                return self.shape[to..=from].iter().rev().copied().collect();
+            }
+        }
+
+        let stop_point = |stop_order: usize| network.stop_points[stops[stop_order] as usize];
+        if from_stop_order <= to_stop_order {
+            (from_stop_order..=to_stop_order).map(stop_point).collect()
+        } else {
+            (to_stop_order..=from_stop_order).rev().map(stop_point).collect()
+        }
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StopTime {
     pub arrival_time: Timestamp,
     pub departure_time: Timestamp,
 }
 
-#[derive(Debug)]
-pub struct Stop {
+// Every stop time in the network, stored as separate arrays of arrival and departure times, because the routers' hot loops only
+// read one of them at a time. Indexed by route.get_stop_times_index, and read a trip at a time with route.get_trip.
+#[derive(Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StopTimes {
+    pub arrival_times: Vec<Timestamp>,
+    pub departure_times: Vec<Timestamp>,
+}
+
+impl StopTimes {
+    pub fn len(&self) -> usize {
+        self.arrival_times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arrival_times.is_empty()
+    }
+
+    // Panics if the index is out of bounds.
+    pub fn get(&self, index: usize) -> StopTime {
+        StopTime { arrival_time: self.arrival_times[index], departure_time: self.departure_times[index] }
+    }
+
+    pub fn slice(&self, range: std::ops::Range<usize>) -> TripStopTimes<'_> {
+        TripStopTimes { arrival_times: &self.arrival_times[range.clone()], departure_times: &self.departure_times[range] }
+    }
+
+    pub fn push(&mut self, stop_time: StopTime) {
+        self.arrival_times.push(stop_time.arrival_time);
+        self.departure_times.push(stop_time.departure_time);
+    }
+
+    // Inserts stop times before index.
+    pub fn insert(&mut self, index: usize, stop_times: &[StopTime]) {
+        self.arrival_times.splice(index..index, stop_times.iter().map(|stop_time| stop_time.arrival_time));
+        self.departure_times.splice(index..index, stop_times.iter().map(|stop_time| stop_time.departure_time));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=StopTime> + '_ {
+        self.arrival_times.iter().zip(self.departure_times.iter()).map(|(&arrival_time, &departure_time)| StopTime { arrival_time, departure_time })
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.arrival_times.shrink_to_fit();
+        self.departure_times.shrink_to_fit();
+    }
+}
+
+impl Extend<StopTime> for StopTimes {
+    fn extend<I: IntoIterator<Item=StopTime>>(&mut self, iter: I) {
+        for stop_time in iter {
+            self.push(stop_time);
+        }
+    }
+}
+
+impl FromIterator<StopTime> for StopTimes {
+    fn from_iter<I: IntoIterator<Item=StopTime>>(iter: I) -> Self {
+        let mut stop_times = StopTimes::default();
+        stop_times.extend(iter);
+        stop_times
+    }
+}
+
+// A view of consecutive stop times (usually one trip's) in StopTimes, indexed by stop order.
+#[derive(Clone, Copy, Debug)]
+pub struct TripStopTimes<'a> {
+    pub arrival_times: &'a [Timestamp],
+    pub departure_times: &'a [Timestamp],
+}
+
+impl<'a> TripStopTimes<'a> {
+    pub fn len(&self) -> usize {
+        self.arrival_times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arrival_times.is_empty()
+    }
+
+    // Panics if the stop order is out of bounds.
+    pub fn get(&self, stop_order: usize) -> StopTime {
+        StopTime { arrival_time: self.arrival_times[stop_order], departure_time: self.departure_times[stop_order] }
+    }
+
+    pub fn first(&self) -> Option<StopTime> {
+        (!self.is_empty()).then(|| self.get(0))
+    }
+
+    pub fn last(&self) -> Option<StopTime> {
+        (!self.is_empty()).then(|| self.get(self.len() - 1))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=StopTime> + 'a {
+        self.arrival_times.iter().zip(self.departure_times.iter()).map(|(&arrival_time, &departure_time)| StopTime { arrival_time, departure_time })
+    }
+
+    pub fn to_vec(&self) -> Vec<StopTime> {
+        self.iter().collect()
+    }
+}
+
+// Summary of the size of a network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetworkStats {
+    pub num_stops: usize,
+    pub num_routes: usize,
+    pub num_trips: usize,
+    pub num_stop_times: usize,
+    // Zero until build_connections is called.
+    pub num_connections: usize,
+    // Stops that no route in the network serves on its date.
+    pub num_stops_with_no_service: usize,
+    pub date: NaiveDate,
+    // The earliest departure and latest arrival of any trip, or None if there are no trips.
+    pub service_span: Option<(Timestamp, Timestamp)>,
+    pub has_shapes: bool,
+    // Approximate heap memory used by the largest arrays (see memory_report).
+    pub memory_estimate_bytes: usize,
+}
+
+impl Display for NetworkStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Network for {} has {} stops ({} with no service), {} routes, {} trips, {} stop times and {} connections",
+               self.date, self.num_stops, self.num_stops_with_no_service, self.num_routes, self.num_trips, self.num_stop_times, self.num_connections)?;
+        if self.has_shapes {
+            write!(f, " with shapes")?;
+        }
+        if let Some((first_departure, last_arrival)) = self.service_span {
+            write!(f, ", running from {} to {} ({} of service)", utils::get_time_str(first_departure), utils::get_time_str(last_arrival),
+                   utils::format_duration(last_arrival.saturating_sub(first_departure), DurationStyle::Short))?;
+        }
+        write!(f, ", using about {:.1} MB.", self.memory_estimate_bytes as f64 / (1024. * 1024.))
+    }
+}
+
+// Service on one line, over all of its routes (directions and stopping patterns).
+#[derive(Clone, Debug)]
+pub struct LineSummary {
+    pub line: Arc<str>,
+    pub num_routes: usize,
+    pub num_trips: usize,
+    // The earliest and latest departures of a trip from its first stop.
+    pub first_departure: Timestamp,
+    pub last_departure: Timestamp,
+    pub num_stops: usize,
+    // The total length of the line's route shapes in km, or None if none of them have shapes.
+    pub route_length_km: Option<CoordType>,
+}
+
+impl LineSummary {
+    // Writes the summaries as CSV, with times as HH:MM:SS and an empty length for lines without shapes.
+    pub fn write_csv(summaries: &[LineSummary], mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "line,num_routes,num_trips,first_departure,last_departure,num_stops,route_length_km")?;
+        for summary in summaries {
+            let route_length_km = summary.route_length_km.map(|length| format!("{length:.3}")).unwrap_or_default();
+            writeln!(writer, "\"{}\",{},{},{},{},{},{}", summary.line.replace('"', "\"\""), summary.num_routes, summary.num_trips,
+                     utils::get_time_str(summary.first_departure), utils::get_time_str(summary.last_departure), summary.num_stops, route_length_km)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for LineSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} routes, {} trips from {} to {} ({} of service), serving {} stops", self.line, self.num_routes, self.num_trips,
+               utils::get_time_str(self.first_departure), utils::get_time_str(self.last_departure),
+               utils::format_duration(self.last_departure.saturating_sub(self.first_departure), DurationStyle::Short), self.num_stops)?;
+        if let Some(route_length_km) = self.route_length_km {
+            write!(f, " over {route_length_km:.1} km of route")?;
+        }
+        Ok(())
+    }
+}
+
+// Memory used by one component of a network.
+#[derive(Clone, Debug)]
+pub struct MemoryUsage {
+    pub name: &'static str,
+    // Number of elements.
+    pub len: usize,
+    // Bytes used by the elements.
+    pub used_bytes: usize,
+    // Bytes allocated, including unused capacity.
+    pub capacity_bytes: usize,
+}
+
+impl MemoryUsage {
+    fn of_vec<T>(name: &'static str, vec: &Vec<T>) -> Self {
+        MemoryUsage { name, len: vec.len(), used_bytes: vec.len() * size_of::<T>(), capacity_bytes: vec.capacity() * size_of::<T>() }
+    }
+
+    fn of_stop_times(stop_times: &StopTimes) -> Self {
+        let (arrivals, departures) = (Self::of_vec("", &stop_times.arrival_times), Self::of_vec("", &stop_times.departure_times));
+        MemoryUsage { name: "stop_times", len: stop_times.len(), used_bytes: arrivals.used_bytes + departures.used_bytes, capacity_bytes: arrivals.capacity_bytes + departures.capacity_bytes }
+    }
+
+    fn of_strings<'a>(name: &'static str, arenas: impl IntoIterator<Item = &'a StringArena>) -> Self {
+        arenas.into_iter().fold(MemoryUsage { name, len: 0, used_bytes: 0, capacity_bytes: 0 }, |usage, arena| MemoryUsage {
+            name,
+            len: usage.len + arena.len(),
+            used_bytes: usage.used_bytes + arena.used_bytes(),
+            capacity_bytes: usage.capacity_bytes + arena.capacity_bytes(),
+        })
+    }
+}
+
+// Heap memory used by the major components of a network.
+#[derive(Clone, Debug)]
+pub struct MemoryReport {
+    pub components: Vec<MemoryUsage>,
+}
+
+impl MemoryReport {
+    pub fn total_used_bytes(&self) -> usize {
+        self.components.iter().map(|component| component.used_bytes).sum()
+    }
+
+    pub fn total_capacity_bytes(&self) -> usize {
+        self.components.iter().map(|component| component.capacity_bytes).sum()
+    }
+}
+
+impl Display for MemoryReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kib = |bytes: usize| bytes as f64 / 1024.;
+        writeln!(f, "{:<16} {:>12} {:>14} {:>14}", "Component", "Length", "Used (KiB)", "Alloc. (KiB)")?;
+        for component in self.components.iter() {
+            writeln!(f, "{:<16} {:>12} {:>14.1} {:>14.1}", component.name, component.len, kib(component.used_bytes), kib(component.capacity_bytes))?;
+        }
+        write!(f, "{:<16} {:>12} {:>14.1} {:>14.1}", "Total", "", kib(self.total_used_bytes()), kib(self.total_capacity_bytes()))
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum CancelError {
+    #[error("No trip {} on route {}.", .0.trip_order, .0.route_idx)]
+    UnknownTrip(GlobalTripIndex),
+    #[error("Trip {} on route {} is already cancelled.", .0.trip_order, .0.route_idx)]
+    AlreadyCancelled(GlobalTripIndex),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum AddTripError {
+    #[error("No route {0}.")]
+    UnknownRoute(RouteIndex),
+    #[error("Trip has {actual} stop times, but the route has {expected} stops.")]
+    WrongLength { expected: usize, actual: usize },
+    #[error("Stop times go backwards in time at stop order {0}.")]
+    NotMonotonic(usize),
+}
+
+// A walk between two stops, e.g. from an external walking time matrix. See Network::set_footpaths.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Footpath {
+    pub from: StopIndex,
+    pub to: StopIndex,
+    pub duration: Timestamp,
+}
+
+// Why a footpath entry was not imported.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum FootpathError {
+    #[error(transparent)]
+    UnknownStop(#[from] UnknownStop),
+    #[error("Footpath from {0:?} to itself.")]
+    SameStop(String),
+    #[error("Footpaths must take at least one second.")]
+    ZeroDuration,
+    #[error("Expected from_stop_id, to_stop_id and duration_secs, got {0:?}.")]
+    Malformed(String),
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum DelayError {
+    #[error("No trip {} on route {}.", .0.trip_order, .0.route_idx)]
+    UnknownTrip(GlobalTripIndex),
+    #[error("Stop order {0} is past the end of the route.")]
+    UnknownStopOrder(usize),
+}
+
+// Maps stop indices from before Network::prune to after it.
+#[derive(Clone, Debug)]
+pub struct StopIndexMapping {
+    // Indexed by old stop index. None if the stop was removed.
+    old_to_new: Vec<Option<StopIndex>>,
+    // Indexed by new stop index.
+    new_to_old: Vec<StopIndex>,
+}
+
+impl StopIndexMapping {
+    // Returns None if the stop was removed.
+    pub fn to_new(&self, old_stop: StopIndex) -> Option<StopIndex> {
+        self.old_to_new[old_stop as usize]
+    }
+
+    pub fn to_old(&self, new_stop: StopIndex) -> StopIndex {
+        self.new_to_old[new_stop as usize]
+    }
+
+    pub fn num_removed(&self) -> usize {
+        self.old_to_new.len() - self.new_to_old.len()
+    }
+}
+
+// A transfer time that differs from the network's default, from Network::non_default_transfer_times.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransferTimeEntry {
+    pub from: StopIndex,
+    pub to: StopIndex,
+    pub transfer_time: Timestamp,
+    // Whether this is a pairwise override rather than a per-stop transfer time.
+    pub pairwise: bool,
+}
+
+// A directed edge between two stops that some trip travels between directly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StopEdge {
+    pub from: StopIndex,
+    pub to: StopIndex,
+    // The shortest time from departing from to arriving at to, over all trips.
+    pub min_travel_time: Timestamp,
+    pub num_trips: usize,
+    pub routes: Vec<RouteIndex>,
+}
+
+// The network as a directed graph, where nodes are stops and edges join consecutive stops of a route.
+pub struct StopGraph {
+    pub num_stops: usize,
+    // Sorted by (from, to).
+    pub edges: Vec<StopEdge>,
+}
+
+impl StopGraph {
+    pub fn iter_edges(&self) -> impl Iterator<Item = &StopEdge> {
+        self.edges.iter()
+    }
+
+    // The edges leaving a stop.
+    pub fn out_edges(&self, stop: StopIndex) -> &[StopEdge] {
+        let start = self.edges.partition_point(|edge| edge.from < stop);
+        let end = self.edges.partition_point(|edge| edge.from <= stop);
+        &self.edges[start..end]
+    }
+
+    // Writes the edges as CSV, with stop IDs and names, and routes separated by semicolons.
+    pub fn write_csv(&self, network: &Network, mut writer: impl Write) -> std::io::Result<()> {
+        let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+        writeln!(writer, "from_id,from_name,to_id,to_name,min_travel_time,num_trips,routes")?;
+        for edge in self.edges.iter() {
+            let (from, to) = (edge.from as usize, edge.to as usize);
+            let routes = edge.routes.iter().map(|&route_idx| &*network.routes[route_idx as usize].line).collect::<Vec<_>>().join(";");
+            writeln!(writer, "{},{},{},{},{},{},{}", quote(network.stop_id(from)), quote(network.stop_name(from)), quote(network.stop_id(to)), quote(network.stop_name(to)), edge.min_travel_time, edge.num_trips, quote(&routes))?;
+        }
+        Ok(())
+    }
+
+    // Writes the graph in Graphviz DOT format, with nodes labelled by stop name and edges by minimum travel time in minutes.
+    pub fn write_dot(&self, network: &Network, writer: impl Write) -> std::io::Result<()> {
+        let mut used_stops = self.edges.iter().flat_map(|edge| [edge.from, edge.to]).collect::<Vec<_>>();
+        used_stops.sort_unstable();
+        used_stops.dedup();
+        let edges = self.edges.iter().map(|edge| format!("{} -> {} [label=\"{}\", weight={}];", edge.from, edge.to, edge.min_travel_time / 60, edge.num_trips));
+        write_dot_graph(network, writer, "stops", used_stops, edges)
+    }
+}
+
+// Writes a Graphviz digraph with the given stops as nodes labelled by name, followed by the given edge statements. Shared by
+// StopGraph::write_dot and Network::write_dot.
+fn write_dot_graph(network: &Network, mut writer: impl Write, name: &str, stops: impl IntoIterator<Item = StopIndex>, edges: impl IntoIterator<Item = String>) -> std::io::Result<()> {
+    writeln!(writer, "digraph {name} {{")?;
+    for stop in stops {
+        writeln!(writer, "    {stop} [label=\"{}\"];", escape_dot(network.stop_name(stop as usize)))?;
+    }
+    for edge in edges {
+        writeln!(writer, "    {edge}")?;
+    }
+    writeln!(writer, "}}")
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Summary of a list of headways (gaps between departures), in seconds.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct HeadwaySummary {
+    pub min: Timestamp,
+    // The lower median for an even number of headways.
+    pub median: Timestamp,
+    pub max: Timestamp,
+}
+
+impl HeadwaySummary {
+    // Returns None if there are no headways, i.e. fewer than two departures.
+    pub fn new(headways: &[Timestamp]) -> Option<Self> {
+        let mut sorted = headways.to_vec();
+        sorted.sort_unstable();
+        Some(HeadwaySummary {
+            min: *sorted.first()?,
+            median: sorted[(sorted.len() - 1) / 2],
+            max: *sorted.last()?,
+        })
+    }
+}
+
+// A route for Network::from_timetable. Every trip visits the route's stops in order.
+#[derive(Clone, Debug)]
+pub struct TimetableRoute {
+    pub line: String,
+    pub colour: RGB8,
+    pub stops: Vec<StopIndex>,
+    // The ID and stop times (one per stop) of each trip, in any order.
+    pub trips: Vec<(String, Vec<StopTime>)>,
+}
+
+// A departure from a stop, for departure boards.
+#[derive(Clone, Debug)]
+pub struct Departure {
+    pub line: Arc<str>,
+    // The final stop of the trip, in place of a headsign.
+    pub destination: StopIndex,
+    pub departure_time: Timestamp,
+    pub trip: GlobalTripIndex,
+    // The position of the departure stop in the trip's route.
+    pub stop_order: StopIndex,
+    pub colour: RGB8,
+}
+
+// The timetable of every trip on a route, in trip order (sorted by first arrival).
+pub struct RouteTimetable<'a> {
+    pub route_idx: RouteIndex,
+    pub line: &'a str,
+    pub trips: Vec<TripTimetable<'a>>,
+}
+
+pub struct TripTimetable<'a> {
+    pub trip: GlobalTripIndex,
+    pub trip_id: &'a str,
+    // The (stop, arrival time, departure time) of each stop on the trip, in stop order.
+    pub stop_times: Vec<(StopIndex, Timestamp, Timestamp)>,
+}
+
+// A route serving a stop, from the stop's point of view.
+#[derive(Clone, Debug)]
+pub struct StopRouteInfo {
+    pub route_idx: RouteIndex,
+    pub line: Arc<str>,
+    // The position of the stop in the route. For routes that visit the stop more than once, the first visit.
+    pub stop_order: StopIndex,
+    // None at the terminus.
+    pub next_stop: Option<StopIndex>,
+    // The final stop of the route, which gives its direction.
+    pub destination: StopIndex,
+    pub is_first: bool,
+    pub is_last: bool,
+}
+
+// Stops that are effectively the same station, e.g. separate stop IDs for each platform.
+#[derive(Clone, Debug)]
+pub struct StationGroup {
+    // The shared name, without any platform suffix.
     pub name: Box<str>,
-    pub id: Box<str>,
+    // Sorted by stop index.
+    pub stops: Vec<StopIndex>,
+}
+
+// Arrivals at one stop followed shortly by departures from a nearby stop, where a transfer between them could be provided.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransferOpportunity {
+    pub from_stop: StopIndex,
+    pub to_stop: StopIndex,
+    pub distance_km: CoordType,
+    // The number of (arrival, departure) pairs within the gap.
+    pub num_near_misses: usize,
+    // The shortest time from an arrival to a departure.
+    pub tightest_gap: Timestamp,
+}
+
+// A trip passing through a stop.
+#[derive(Clone, Debug)]
+pub struct StopVisit {
+    pub trip: GlobalTripIndex,
+    pub stop_order: StopIndex,
+    pub arrival_time: Timestamp,
+    pub departure_time: Timestamp,
+}
+
+// What Network::write_geojson exports.
+type RouteFilterFn = dyn Fn(RouteIndex, &Route) -> bool;
+pub struct GeoJsonOptions {
+    // Whether to add a Point for each stop served by an included route.
+    pub include_stops: bool,
+    // Whether to draw routes along their shapes, or as straight segments between stops.
+    pub use_shapes: bool,
+    // Which routes to include.
+    pub route_filter: Box<RouteFilterFn>,
+}
+
+impl Default for GeoJsonOptions {
+    fn default() -> Self {
+        GeoJsonOptions {
+            include_stops: true,
+            use_shapes: true,
+            route_filter: Box::new(|_, _| true),
+        }
+    }
+}
+
+// Which routes Network::write_dot draws. Routes must pass both restrictions.
+#[derive(Clone, Debug, Default)]
+pub struct DotOptions {
+    // Only routes on these lines, if set.
+    pub lines: Option<Vec<String>>,
+    // Only routes through this stop, if set.
+    pub around_stop: Option<StopIndex>,
+}
+
+// The routes through a stop. Its name and ID are in Network::stop_names and stop_ids.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stop {
     pub routes_idx: usize,
     pub num_routes: usize,
 }
 
 impl Stop {
-    pub fn new(name: &str, id: &str) -> Self {
-        Self {
-            name: name.to_owned().into_boxed_str(),
-            id: id.to_owned().into_boxed_str(),
-            routes_idx: 0,
-            num_routes: 0,
-        }
+    // Stops no longer hold their name and ID, so these are ignored.
+    #[deprecated(note = "Stop names and IDs are stored in the network. Use Stop::default, and Network::stop_name and stop_id.")]
+    pub fn new(_name: &str, _id: &str) -> Self {
+        Self::default()
     }
 
     pub fn get_routes<'a>(&self, stop_routes: &'a [RouteIndex]) -> &'a [RouteIndex] {
@@ -179,17 +857,59 @@ impl Stop {
     }
 }
 
+// The height each route's shape is drawn at, so lines that share a corridor are layered consistently.
+#[derive(Default)]
+pub enum RouteHeights {
+    // Distinct route colours are sorted by value and given heights 10, 20, 30, and so on. This doesn't depend on the order routes
+    // are built in, but does change if colours are added to or removed from the feed.
+    #[default]
+    Automatic,
+    // Heights by route colour. Colours not in the map fall back to Automatic.
+    ByColour(HashMap<RGB8, CoordType>),
+    // Heights by line name.
+    ByLine(Box<dyn Fn(&str) -> CoordType + Send + Sync>),
+}
+
+impl RouteHeights {
+    pub const AUTOMATIC_SPACING: CoordType = 10.;
+
+    fn assign(&self, routes: &mut [Route]) {
+        let mut colours = routes.iter().map(|route| route.colour).collect::<Vec<_>>();
+        colours.sort_unstable_by_key(|colour| (colour.r, colour.g, colour.b));
+        colours.dedup();
+        let automatic = |colour: RGB8| {
+            let position = colours.binary_search_by_key(&(colour.r, colour.g, colour.b), |colour| (colour.r, colour.g, colour.b)).unwrap();
+            (position + 1) as CoordType * RouteHeights::AUTOMATIC_SPACING
+        };
+
+        for route in routes.iter_mut() {
+            route.shape_height = match self {
+                RouteHeights::Automatic => automatic(route.colour),
+                RouteHeights::ByColour(heights) => heights.get(&route.colour).copied().unwrap_or_else(|| automatic(route.colour)),
+                RouteHeights::ByLine(height) => height(&route.line),
+            };
+        }
+    }
+}
+
+// Network::departure_columns: a column for each route, each built the first time a query scans the route.
+type DepartureColumns = OnceLock<Box<[OnceLock<Box<[Timestamp]>>]>>;
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Network {
     // Metadata for routes in the network.
     pub routes: Vec<Route>,
     // Metadata for stops in the network.
     pub stops: Vec<Stop>,
+    // The short name and GTFS ID of each stop (Indexed by stop index). Use stop_name and stop_id.
+    pub stop_names: StringArena,
+    pub stop_ids: StringArena,
     // Number of trips. Not encoded anywhere else, like stops.len().
     pub num_trips: TripOrder,
     // The stop index for a given stop ID.
     pub stop_index: HashMap<String, StopIndex>,
     // The stop times for each trip (Indexed by [route.stop_times_idx..(route.stop_times_idx + route.num_trips * route.num_stops)]).
-    pub stop_times: Vec<StopTime>,
+    pub stop_times: StopTimes,
     // The routes for each route (Indexed by [stop.routes_idx..(self.routes_idx + self.num_routes)]).
     pub stop_routes: Vec<RouteIndex>,
     // The stops in each route (Indexed by [route.route_stops_idx..(route.route_stops_idx + route.num_stops)]).
@@ -198,30 +918,130 @@ pub struct Network {
     pub stop_points: Vec<NetworkPoint>,
     // A linear list of all connections in the network.
     pub connections: Vec<Connection>,
+    // The index of the first connection departing in each minute, so queries can find where to start (or stop) scanning without
+    // searching every connection. Kept up to date with connections. See connections_departing_between.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub connection_buckets: Vec<usize>,
     // Transfer time between stops in seconds (Indexed by stop index).
     pub transfer_times: Vec<Timestamp>,
+    // Transfer times between specific pairs of stops, which override transfer_times. Use get_effective_transfer_time to resolve.
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::serde_pairs"))]
+    pub pairwise_transfer_times: HashMap<(StopIndex, StopIndex), Timestamp>,
+    // Transfer times at a stop between specific routes, keyed by (stop, from route, to route). These override the pairwise and
+    // per-stop transfer times. Use get_route_transfer_time to resolve.
+    #[cfg_attr(feature = "serde", serde(with = "crate::utils::serde_pairs"))]
+    pub route_transfer_times: HashMap<(StopIndex, RouteIndex, RouteIndex), Timestamp>,
+    // Walks between stops, sorted by from stop and then to stop. Use footpaths_from to find the walks leaving a stop.
+    // Only the single-criteria RAPTOR queries walk.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub footpaths: Vec<Footpath>,
+    // Zone and line fares, for pricing journeys with Journey::fare. None if the network's fares aren't known.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fares: Option<FareTable>,
+    // The transfer time that transfer_times is initialised with.
+    pub default_transfer_time: Timestamp,
     // The date for which the network is valid.
     pub date: NaiveDate,
+    // The IANA timezone stop times are local to (the GTFS agency_timezone, e.g. "Australia/Melbourne"), or None if it isn't known.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub timezone: Option<String>,
     pub has_shapes: bool,
+    // Spatial index over stop_points, built on the first nearest_stops query.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    stop_grid: OnceLock<StopGrid>,
+    // Each route's departure times transposed so that a stop's departures are contiguous (indexed by route, then by stop order
+    // * num_trips + trip order), built the first time a query scans the route. See departure_column.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    departure_columns: DepartureColumns,
+    // stop_names lowercased, with runs of whitespace collapsed to one space, for name lookups and search. Built on first use.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    normalised_stop_names: OnceLock<StringArena>,
+    // Where each stop is visited by each route, for looking up stop orders without scanning route_stops. Built on first use.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    stop_visits: OnceLock<StopVisits>,
+}
+
+// Every (route, stop order) visit of each stop, grouped by stop and sorted by route and then stop order, so the visits of a
+// stop by one route are contiguous. Loop routes visit some stops more than once.
+struct StopVisits {
+    // The visits of stop i are visits[offsets[i]..offsets[i + 1]].
+    offsets: Vec<usize>,
+    visits: Vec<(RouteIndex, StopIndex)>,
+}
+
+impl StopVisits {
+    fn new(num_stops: usize, routes: &[Route], route_stops: &[StopIndex]) -> Self {
+        let mut offsets = vec![0; num_stops + 1];
+        for &stop in route_stops {
+            offsets[stop as usize + 1] += 1;
+        }
+        for i in 0..num_stops {
+            offsets[i + 1] += offsets[i];
+        }
+        // Filling in route order, then stop order, leaves each stop's visits sorted.
+        let mut next = offsets[..num_stops].to_vec();
+        let mut visits = vec![(0, 0); offsets[num_stops]];
+        for (route_idx, route) in routes.iter().enumerate() {
+            for (stop_order, &stop) in route.get_stops(route_stops).iter().enumerate() {
+                visits[next[stop as usize]] = (route_idx as RouteIndex, stop_order as StopIndex);
+                next[stop as usize] += 1;
+            }
+        }
+        Self { offsets, visits }
+    }
+
+    fn stop_order(&self, route_idx: RouteIndex, stop_idx: StopIndex, min_order: usize) -> Option<usize> {
+        let visits = &self.visits[self.offsets[stop_idx as usize]..self.offsets[stop_idx as usize + 1]];
+        visits[visits.partition_point(|&(route, _)| route < route_idx)..]
+            .iter()
+            .take_while(|&&(route, _)| route == route_idx)
+            .map(|&(_, stop_order)| stop_order as usize)
+            .find(|&stop_order| stop_order >= min_order)
+    }
 }
 
 impl Network {
+    #[cfg(feature = "gtfs")]
     pub fn new(gtfs: &Gtfs, route_type: Option<RouteType>, journey_date: NaiveDate, default_transfer_time: Timestamp) -> Self {
-        // GTFS optional fields that are unwrapped: stop.name, stop_time.arrival_time, stop_time.departure_time.
+        Self::new_with_route_heights(gtfs, route_type, journey_date, default_transfer_time, RouteHeights::default())
+    }
 
+    // Like new, but with the heights routes are drawn at.
+    // Panics if the GTFS can't be built into a network. See try_new_with_route_heights.
+    #[cfg(feature = "gtfs")]
+    pub fn new_with_route_heights(gtfs: &Gtfs, route_type: Option<RouteType>, journey_date: NaiveDate, default_transfer_time: Timestamp, route_heights: RouteHeights) -> Self {
+        Self::try_new_with_route_heights(gtfs, route_type, journey_date, default_transfer_time, route_heights).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    // Like new, but returns an error instead of panicking.
+    #[cfg(feature = "gtfs")]
+    pub fn try_new(gtfs: &Gtfs, route_type: Option<RouteType>, journey_date: NaiveDate, default_transfer_time: Timestamp) -> Result<Self, NetworkError> {
+        Self::try_new_with_route_heights(gtfs, route_type, journey_date, default_transfer_time, RouteHeights::default())
+    }
+
+    // Reads the GTFS feed at path (a directory or zip, without shapes) and builds the network from it.
+    #[cfg(feature = "gtfs")]
+    pub fn load(path: &str, route_type: Option<RouteType>, journey_date: NaiveDate, default_transfer_time: Timestamp) -> Result<Self, NetworkError> {
+        let gtfs = gtfs_structures::GtfsReader::default().read_shapes(false).read_from_path(path)?;
+        Self::try_new(&gtfs, route_type, journey_date, default_transfer_time)
+    }
+
+    #[cfg(feature = "gtfs")]
+    pub fn try_new_with_route_heights(gtfs: &Gtfs, route_type: Option<RouteType>, journey_date: NaiveDate, default_transfer_time: Timestamp, route_heights: RouteHeights) -> Result<Self, NetworkError> {
         // We use one stop index as the direction of the trip when grouping as routes.
-        assert!(
-            gtfs.stops.len() < (StopIndex::MAX - 1) as usize,
-            "Too many stops ({}, max {}) in GTFS.",
-            gtfs.stops.len(),
-            StopIndex::MAX
-        );
+        if gtfs.stops.len() >= (StopIndex::MAX - 1) as usize {
+            return Err(NetworkError::TooManyStops(gtfs.stops.len(), (StopIndex::MAX - 2) as usize, utils::get_size_bits::<StopIndex>()));
+        }
 
         let mut stop_index = HashMap::with_capacity(gtfs.stops.capacity());
         let mut stops = Vec::with_capacity(gtfs.stops.len());
+        let (mut stop_names, mut stop_ids) = (StringArena::new(), StringArena::new());
         for (i, (id, value)) in gtfs.stops.iter().enumerate() {
             stop_index.insert(id.clone(), i as StopIndex);
-            stops.push(Stop::new(utils::get_short_stop_name(value.name.as_ref().unwrap()), id));
+            stops.push(Stop::default());
+            let name = value.name.as_ref().ok_or_else(|| NetworkError::MissingStopName(id.clone()))?;
+            stop_names.push(utils::get_short_stop_name(name));
+            stop_ids.push(id);
         }
 
         // Construct route-local stop indices.
@@ -272,11 +1092,10 @@ impl Network {
                 log::error!("Too many stops in route {route_id} ({}, max {}).", num_stops, STOP_BITFIELD_SIZE_BITS - 1);
                 for (stop_idx, mapped_stop) in mapping.iter().enumerate() {
                     if mapped_stop.is_some() {
-                        log::error!("Stop: {}", stops[stop_idx].name);
+                        log::error!("Stop: {}", &stop_names[stop_idx]);
                     }
                 }
-                assert!(false, "Too many stops in route {route_id} ({}, max {}).", num_stops, STOP_BITFIELD_SIZE_BITS - 1);
-                continue;
+                return Err(NetworkError::TooManyStopsInRoute { route_id: route_id.to_string(), num_stops, max: STOP_BITFIELD_SIZE_BITS - 1 });
             }
 
             let mut route_map = HashMap::new();
@@ -304,27 +1123,19 @@ impl Network {
             route_maps.push(route_map);
         }
 
-        assert!(
-            num_routes < RouteIndex::MAX as usize,
-            "Too many routes in GTFS (we currently use a {}-bit index for routes).",
-            utils::get_size_bits::<RouteIndex>()
-        );
-        assert!(
-            gtfs.trips.len() < TripOrder::MAX as usize,
-            "Too many trips in GTFS (we currently use a {}-bit index for trips).",
-            utils::get_size_bits::<TripOrder>()
-        );
+        if num_routes >= RouteIndex::MAX as usize {
+            return Err(NetworkError::TooManyRoutes(num_routes, (RouteIndex::MAX - 1) as usize, utils::get_size_bits::<RouteIndex>()));
+        }
+        if gtfs.trips.len() >= TripOrder::MAX as usize {
+            return Err(NetworkError::TooManyTrips(gtfs.trips.len(), (TripOrder::MAX - 1) as usize, utils::get_size_bits::<TripOrder>()));
+        }
 
         // Construct routes, which point to a series of stops and stop times.
         let mut routes = Vec::new();
         let mut route_stops = Vec::new();
-        let mut stop_times = Vec::new();
+        let mut stop_times = StopTimes::default();
         let mut num_trips = 0 as TripOrder;
 
-        // Keep track of the height of each colour.
-        let mut colour_to_height_map = HashMap::new();
-        let mut last_height = 0. as CoordType;
-
         for route_map in route_maps.iter_mut() {
             for route_trips in route_map.values_mut() {
                 let first_trip = match route_trips.get(0) {
@@ -336,22 +1147,18 @@ impl Network {
                 route_trips.sort_unstable_by_key(|x| { x.stop_times[0].arrival_time });
 
                 let first_route = &gtfs.routes[first_trip.route_id.as_str()];
-                let line_name = first_route.short_name.as_ref().unwrap_or(first_route.long_name.as_ref().unwrap_or(&first_trip.route_id));
+                let line_name = gtfs_line_name(first_route);
 
-                // Determine height based on colour. TODO: Hardcode heights for colours for consistency.
                 let colour = first_route.color;
-                let height = if let Some(&height) = colour_to_height_map.get(&colour) {
-                    height
-                } else {
-                    last_height += 10.;
-                    colour_to_height_map.insert(colour, last_height);
-                    last_height
-                };
 
-                // Extract shape.
+                // Extract shape. Trips in a route may have different shapes, so use the most common.
+                let (shape_id, num_shape_mismatches) = most_common_shape_id(route_trips.iter().map(|trip| trip.shape_id.as_deref()));
+                if num_shape_mismatches > 0 {
+                    log::debug!("{num_shape_mismatches} of {} trips in a route of {line_name} don't have shape {shape_id:?}.", route_trips.len());
+                }
                 let shape = if gtfs.shapes.len() > 0 {
-                    if let Some(shape_id) = first_trip.shape_id.as_ref() {
-                        let shapes = &gtfs.shapes[shape_id.as_str()];
+                    if let Some(shape_id) = shape_id {
+                        let shapes = &gtfs.shapes[shape_id];
                         let mut shape = Vec::with_capacity(shapes.len());
                         for shape_point in shapes.iter() {
                             shape.push(NetworkPoint {
@@ -367,15 +1174,20 @@ impl Network {
                     Vec::new()
                 };
                 routes.push(Route {
-                    line: Arc::from(line_name.as_str()),
+                    line: Arc::from(line_name),
                     num_stops: first_trip.stop_times.len() as StopIndex,
                     num_trips: route_trips.len() as TripOrder,
                     route_stops_idx: route_stops.len(),
                     stop_times_idx: stop_times.len(),
-                    trip_ids: route_trips.iter().map(|trip| trip.id.clone().into_boxed_str()).collect(),
+                    trip_ids: route_trips.iter().map(|trip| &trip.id).collect(),
                     colour,
+                    route_type: Some(gtfs_route_type_code(first_route.route_type)),
                     shape: shape.into_boxed_slice(),
-                    shape_height: height,
+                    shape_id: shape_id.map(Box::from),
+                    num_shape_mismatches,
+                    cancelled_trips: vec![false; route_trips.len()],
+                    skipped_stops: vec![false; route_trips.len() * first_trip.stop_times.len()],
+                    shape_height: 0., // Assigned once all routes are built.
                 });
 
                 // Because of how routes are constructed, all trips in a route have the same stops.
@@ -388,31 +1200,18 @@ impl Network {
 
                 for trip in route_trips {
                     for stop_time in trip.stop_times.iter() {
-                        stop_times.push(StopTime {
-                            arrival_time: stop_time.arrival_time.unwrap(),
-                            departure_time: stop_time.departure_time.unwrap(),
-                        });
+                        let (Some(arrival_time), Some(departure_time)) = (stop_time.arrival_time, stop_time.departure_time) else {
+                            return Err(NetworkError::MissingStopTime { trip_id: trip.id.clone(), stop_id: stop_time.stop.id.clone() });
+                        };
+                        stop_times.push(StopTime { arrival_time, departure_time });
                     }
                 }
             }
         }
 
-        // Index the routes for a given stop.
-        let mut stop_routes_map = vec![Vec::new(); stops.len()];
-        for (route_idx, route) in routes.iter().enumerate() {
-            for &stop in route.get_stops(&route_stops) {
-                stop_routes_map[stop as usize].push(route_idx as RouteIndex);
-            }
-        }
+        route_heights.assign(&mut routes);
 
-        let mut stop_routes = Vec::new();
-        for (stop_idx, stop) in stops.iter_mut().enumerate() {
-            stop.routes_idx = stop_routes.len();
-            for &route_idx in stop_routes_map[stop_idx].iter() {
-                stop_routes.push(route_idx);
-            }
-            stop.num_routes = stop_routes.len() - stop.routes_idx;
-        }
+        let stop_routes = index_stop_routes(&mut stops, &routes, &route_stops);
 
         // Precalculate stop points.
         let mut stop_points = Vec::with_capacity(stops.len());
@@ -423,9 +1222,11 @@ impl Network {
 
         let transfer_times = vec![default_transfer_time; stops.len()];
 
-        Self {
+        let mut network = Self {
             routes,
             stops,
+            stop_names,
+            stop_ids,
             num_trips,
             stop_index,
             stop_times,
@@ -433,64 +1234,1136 @@ impl Network {
             route_stops,
             stop_points,
             connections: Vec::new(), // These will be built later if required.
+            connection_buckets: Vec::new(),
             transfer_times,
+            pairwise_transfer_times: HashMap::new(),
+            route_transfer_times: HashMap::new(),
+            footpaths: Vec::new(),
+            fares: None,
+            default_transfer_time,
             date: journey_date,
+            timezone: gtfs.agencies.first().map(|agency| agency.timezone.clone()),
             has_shapes: gtfs.shapes.len() > 0,
+            stop_grid: OnceLock::new(),
+            departure_columns: OnceLock::new(),
+            normalised_stop_names: OnceLock::new(),
+            stop_visits: OnceLock::new(),
+        };
+        network.fares = FareTable::from_gtfs(gtfs, &network);
+        Ok(network)
+    }
+
+    // Builds a network directly from routes, without GTFS, e.g. for small test networks. Stop IDs must be unique, and stop_points
+    // is indexed by stop index like stops. Trips are sorted by first arrival within their route.
+    // Panics if a route has no stops, uses an unknown stop, or has a trip with the wrong number of stop times.
+    pub fn from_timetable(stops: &[(&str, &str, NetworkPoint)], timetable_routes: Vec<TimetableRoute>, journey_date: NaiveDate, default_transfer_time: Timestamp) -> Self {
+        assert!(stops.len() < StopIndex::MAX as usize, "Too many stops ({}, max {}) for {}-bit stop indices.",
+                stops.len(), StopIndex::MAX - 1, utils::get_size_bits::<StopIndex>());
+        assert!(timetable_routes.len() < RouteIndex::MAX as usize, "Too many routes ({}, max {}) for {}-bit route indices.",
+                timetable_routes.len(), RouteIndex::MAX - 1, utils::get_size_bits::<RouteIndex>());
+        let mut stop_index = HashMap::with_capacity(stops.len());
+        for (stop_idx, &(_, id, _)) in stops.iter().enumerate() {
+            let previous = stop_index.insert(id.to_string(), stop_idx as StopIndex);
+            assert!(previous.is_none(), "Duplicate stop ID {id}.");
+        }
+
+        let mut routes = Vec::with_capacity(timetable_routes.len());
+        let mut route_stops = Vec::new();
+        let mut stop_times = StopTimes::default();
+        let mut num_trips = 0;
+        for TimetableRoute { line, colour, stops: timetable_stops, mut trips } in timetable_routes {
+            assert!(!timetable_stops.is_empty(), "Route of {line} has no stops.");
+            assert!(timetable_stops.iter().all(|&stop| (stop as usize) < stops.len()), "Route of {line} has an unknown stop.");
+            assert!(trips.iter().all(|(_, trip_stop_times)| trip_stop_times.len() == timetable_stops.len()),
+                    "A trip of {line} doesn't have one stop time per stop.");
+
+            // Sort trips in route based on earliest arrival time.
+            trips.sort_by_key(|(_, trip_stop_times)| trip_stop_times[0].arrival_time);
+            routes.push(Route {
+                line: Arc::from(line.as_str()),
+                num_stops: timetable_stops.len() as StopIndex,
+                num_trips: trips.len() as TripOrder,
+                route_stops_idx: route_stops.len(),
+                stop_times_idx: stop_times.len(),
+                trip_ids: trips.iter().map(|(trip_id, _)| trip_id).collect(),
+                colour,
+                route_type: None,
+                shape: Box::new([]),
+                shape_id: None,
+                num_shape_mismatches: 0,
+                cancelled_trips: vec![false; trips.len()],
+                skipped_stops: vec![false; trips.len() * timetable_stops.len()],
+                shape_height: 0.,
+            });
+            route_stops.extend(timetable_stops);
+            num_trips += trips.len() as TripOrder;
+            stop_times.extend(trips.into_iter().flat_map(|(_, trip_stop_times)| trip_stop_times));
+        }
+        RouteHeights::default().assign(&mut routes);
+
+        let mut network_stops = stops.iter().map(|_| Stop::default()).collect::<Vec<_>>();
+        let stop_routes = index_stop_routes(&mut network_stops, &routes, &route_stops);
+
+        Self {
+            routes,
+            stops: network_stops,
+            stop_names: stops.iter().map(|&(name, _, _)| name).collect(),
+            stop_ids: stops.iter().map(|&(_, id, _)| id).collect(),
+            num_trips,
+            stop_index,
+            stop_times,
+            stop_routes,
+            route_stops,
+            stop_points: stops.iter().map(|&(_, _, point)| point).collect(),
+            connections: Vec::new(),
+            connection_buckets: Vec::new(),
+            transfer_times: vec![default_transfer_time; stops.len()],
+            pairwise_transfer_times: HashMap::new(),
+            route_transfer_times: HashMap::new(),
+            footpaths: Vec::new(),
+            fares: None,
+            default_transfer_time,
+            date: journey_date,
+            timezone: None,
+            has_shapes: false,
+            stop_grid: OnceLock::new(),
+            departure_columns: OnceLock::new(),
+            normalised_stop_names: OnceLock::new(),
+            stop_visits: OnceLock::new(),
         }
     }
 
+    // Panics if there is no stop with the given GTFS stop ID. See try_set_transfer_time_for_stop.
     pub fn set_transfer_time_for_stop(&mut self, stop_id: &str, transfer_time: Timestamp) {
-        let stop_idx = self.get_stop_idx(stop_id) as usize;
+        self.try_set_transfer_time_for_stop(stop_id, transfer_time).unwrap()
+    }
+
+    pub fn try_set_transfer_time_for_stop(&mut self, stop_id: &str, transfer_time: Timestamp) -> Result<(), UnknownStop> {
+        let stop_idx = self.try_get_stop_idx(stop_id).ok_or_else(|| UnknownStop(stop_id.to_string()))? as usize;
         self.transfer_times[stop_idx] = transfer_time;
+        Ok(())
+    }
+
+    // Panics if any stop is unknown. See try_set_transfer_times.
+    pub fn set_transfer_times(&mut self, transfers: &[(&str, &str, Timestamp)]) {
+        self.try_set_transfer_times(transfers).unwrap()
+    }
+
+    // Sets the transfer time between each (from_stop_id, to_stop_id) pair, overriding the per-stop transfer time.
+    // If any stop is unknown, no transfer times are changed.
+    pub fn try_set_transfer_times(&mut self, transfers: &[(&str, &str, Timestamp)]) -> Result<(), UnknownStop> {
+        let stop_idx = |stop_id: &str| self.try_get_stop_idx(stop_id).ok_or_else(|| UnknownStop(stop_id.to_string()));
+        let transfers = transfers
+            .iter()
+            .map(|&(from_stop_id, to_stop_id, transfer_time)| Ok(((stop_idx(from_stop_id)?, stop_idx(to_stop_id)?), transfer_time)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.pairwise_transfer_times.extend(transfers);
+        Ok(())
+    }
+
+    // The time needed to transfer from a vehicle arriving at from to one departing from to. Resolves the pairwise override,
+    // then the per-stop transfer time of to (which is the default unless set). All queries use this, so they agree.
+    // Between different stops, the pairwise override also sets the time to walk their footpath (see get_footpath_time).
+    pub fn get_effective_transfer_time(&self, from: StopIndex, to: StopIndex) -> Timestamp {
+        match self.pairwise_transfer_times.get(&(from, to)) {
+            Some(&transfer_time) => transfer_time,
+            None => self.transfer_times[to as usize],
+        }
+    }
+
+    // Panics if the stop is unknown. See try_set_route_transfer_time.
+    pub fn set_route_transfer_time(&mut self, stop_id: &str, from_line: &str, to_line: &str, transfer_time: Timestamp) -> usize {
+        self.try_set_route_transfer_time(stop_id, from_line, to_line, transfer_time).unwrap()
+    }
+
+    // Sets the transfer time at the stop from any route of from_line to any route of to_line (compared with line_name_cmp),
+    // overriding the pairwise and per-stop transfer times. Returns the number of route pairs set, which is zero if either line
+    // doesn't serve the stop.
+    pub fn try_set_route_transfer_time(&mut self, stop_id: &str, from_line: &str, to_line: &str, transfer_time: Timestamp) -> Result<usize, UnknownStop> {
+        let stop = self.try_get_stop_idx(stop_id).ok_or_else(|| UnknownStop(stop_id.to_string()))?;
+        let stop_routes = self.stops[stop as usize].get_routes(&self.stop_routes);
+        let line_routes = |line: &str| stop_routes.iter().copied().filter(|&route_idx| Network::line_name_cmp(&self.routes[route_idx as usize].line, line)).collect::<Vec<_>>();
+        let (from_routes, to_routes) = (line_routes(from_line), line_routes(to_line));
+        for &from_route in from_routes.iter() {
+            for &to_route in to_routes.iter() {
+                self.route_transfer_times.insert((stop, from_route, to_route), transfer_time);
+            }
+        }
+        Ok(from_routes.len() * to_routes.len())
+    }
+
+    // The time needed at the stop to transfer from a vehicle of from_route to one of to_route. Resolves the route-to-route
+    // override, then falls back to get_effective_transfer_time. from_route is None if the incoming route isn't known.
+    pub fn get_route_transfer_time(&self, stop: StopIndex, from_route: Option<RouteIndex>, to_route: RouteIndex) -> Timestamp {
+        from_route
+            .and_then(|from_route| self.route_transfer_times.get(&(stop, from_route, to_route)).copied())
+            .unwrap_or_else(|| self.get_effective_transfer_time(stop, stop))
+    }
+
+    // Lists every transfer time that differs from the default, for auditing. Per-stop entries have from == to and aren't pairwise.
+    // Sorted by (from, to), with per-stop entries before a pairwise override of the same stop.
+    pub fn non_default_transfer_times(&self) -> Vec<TransferTimeEntry> {
+        let per_stop = self.transfer_times.iter().enumerate().filter(|&(_, &transfer_time)| transfer_time != self.default_transfer_time).map(|(stop, &transfer_time)| {
+            TransferTimeEntry { from: stop as StopIndex, to: stop as StopIndex, transfer_time, pairwise: false }
+        });
+        let pairwise = self.pairwise_transfer_times.iter().map(|(&(from, to), &transfer_time)| TransferTimeEntry { from, to, transfer_time, pairwise: true });
+        let mut entries = per_stop.chain(pairwise).collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|entry| (entry.from, entry.to, entry.pairwise));
+        entries
+    }
+
+    // Cancels a trip, so that no query boards it. Its stop times are kept, so all indices remain valid, and its connections are
+    // removed (which doesn't change the order of the rest).
+    pub fn cancel_trip(&mut self, trip: GlobalTripIndex) -> Result<(), CancelError> {
+        let route = self.routes.get_mut(trip.route().idx()).filter(|route| trip.trip().idx() < route.num_trips as usize).ok_or(CancelError::UnknownTrip(trip))?;
+        let cancelled = &mut route.cancelled_trips[trip.trip().idx()];
+        if *cancelled {
+            return Err(CancelError::AlreadyCancelled(trip));
+        }
+        *cancelled = true;
+        if !self.connections.is_empty() {
+            self.connections.retain(|connection| connection.trip != trip);
+            self.index_connections();
+        }
+        Ok(())
+    }
+
+    // Marks a stop as skipped by a trip, as if it had no pickup or drop off there. The trip still passes through the stop, so its
+    // times and connections are kept, but no query boards or alights it there.
+    pub fn skip_stop(&mut self, trip: GlobalTripIndex, stop_order: usize) -> Result<(), DelayError> {
+        let route = self.routes.get_mut(trip.route().idx()).filter(|route| trip.trip().idx() < route.num_trips as usize).ok_or(DelayError::UnknownTrip(trip))?;
+        let num_stops = route.num_stops as usize;
+        if stop_order >= num_stops {
+            return Err(DelayError::UnknownStopOrder(stop_order));
+        }
+        route.skipped_stops[trip.trip().idx() * num_stops + stop_order] = true;
+        Ok(())
+    }
+
+    // Adds a trip to a route, given its (arrival, departure) time at each of the route's stops. The trip is inserted in order of
+    // first arrival, so the trip orders of later trips on the route (and the stop times indices of later routes) increase by one.
+    // Connections, if built, are updated in place. Returns the index of the new trip.
+    pub fn add_trip(&mut self, route_idx: RouteIndex, stop_times: &[(Timestamp, Timestamp)], trip_id: &str) -> Result<GlobalTripIndex, AddTripError> {
+        let route = self.routes.get(route_idx as usize).ok_or(AddTripError::UnknownRoute(route_idx))?;
+        if stop_times.len() != route.num_stops as usize {
+            return Err(AddTripError::WrongLength { expected: route.num_stops as usize, actual: stop_times.len() });
+        }
+        let mut previous_departure = 0;
+        for (stop_order, &(arrival_time, departure_time)) in stop_times.iter().enumerate() {
+            if arrival_time < previous_departure || departure_time < arrival_time {
+                return Err(AddTripError::NotMonotonic(stop_order));
+            }
+            previous_departure = departure_time;
+        }
+
+        // Insert the stop times after every trip that arrives at the first stop no later.
+        let trip_order = (0..route.num_trips as usize).map(|trip_order| self.stop_times.arrival_times[route.get_stop_times_index(trip_order, 0)]).collect::<Vec<_>>().partition_point(|&arrival_time| arrival_time <= stop_times[0].0);
+        let index = route.get_stop_times_index(trip_order, 0);
+        let num_stops = route.num_stops as usize;
+        self.stop_times.insert(index, &stop_times.iter().map(|&(arrival_time, departure_time)| StopTime { arrival_time, departure_time }).collect::<Vec<_>>());
+        for other_route in self.routes.iter_mut().skip(route_idx as usize + 1) {
+            other_route.stop_times_idx += num_stops;
+        }
+        self.clear_departure_column(route_idx as usize);
+
+        let route = &mut self.routes[route_idx as usize];
+        route.num_trips += 1;
+        route.trip_ids.insert(trip_order, trip_id);
+        route.cancelled_trips.insert(trip_order, false);
+        let skipped_idx = trip_order * num_stops;
+        route.skipped_stops.splice(skipped_idx..skipped_idx, std::iter::repeat_n(false, num_stops));
+        self.num_trips += 1;
+        let trip = GlobalTripIndex::new(RouteIdx(route_idx), TripIdx::new(trip_order));
+
+        if !self.connections.is_empty() {
+            // Renumber the trips after the new one, which keeps the existing connections in order, then insert the new connections.
+            let sequential_trip_idx = self.first_sequential_trip_idxs().nth(route_idx as usize).unwrap() + trip.trip_order;
+            for connection in self.connections.iter_mut() {
+                if connection.sequential_trip_idx >= sequential_trip_idx {
+                    connection.sequential_trip_idx += 1;
+                }
+                if connection.trip.route_idx == trip.route_idx && connection.trip.trip_order >= trip.trip_order {
+                    connection.trip.trip_order += 1;
+                }
+            }
+            let first_sequential_trip_idx = sequential_trip_idx - trip.trip_order;
+            let new_connections = self.iter_route_connections(route_idx as usize, first_sequential_trip_idx).filter(|connection| connection.trip == trip).collect::<Vec<_>>();
+            for connection in new_connections {
+                let position = self.connections.partition_point(|other| other.sort_key() < connection.sort_key());
+                self.connections.insert(position, connection);
+            }
+            self.index_connections();
+        }
+
+        Ok(trip)
+    }
+
+    // The departure times of every trip of the route at stop_order, in trip order, as one contiguous slice (stop_times strides
+    // over each trip's stops instead). The route's transposed departures are built from stop_times on first use and cached, so
+    // code that changes stop_times directly rather than through add_trip or apply_delay must not rely on this afterwards.
+    pub fn departure_column(&self, route_idx: usize, stop_order: usize) -> &[Timestamp] {
+        let columns = self.departure_columns.get_or_init(|| self.routes.iter().map(|_| OnceLock::new()).collect());
+        let route = &self.routes[route_idx];
+        let (num_trips, num_stops) = (route.num_trips as usize, route.num_stops as usize);
+        let transposed = columns[route_idx].get_or_init(|| {
+            let departure_times = &self.stop_times.departure_times[route.stop_times_idx..route.stop_times_idx + num_trips * num_stops];
+            (0..num_stops).flat_map(|stop_order| departure_times.iter().skip(stop_order).step_by(num_stops).copied()).collect()
+        });
+        &transposed[stop_order * num_trips..(stop_order + 1) * num_trips]
+    }
+
+    fn clear_departure_column(&mut self, route_idx: usize) {
+        if let Some(columns) = self.departure_columns.get_mut() {
+            columns[route_idx] = OnceLock::new();
+        }
+    }
+
+    // Delays a trip by delay_secs at from_stop_order and every later stop. Negative delays are limited so the trip doesn't leave
+    // before its departure from the previous stop (or before midnight), so the delay actually applied is returned. If the trip's
+    // first arrival changes, it is moved to keep the route's trips sorted, so the trip's new index is returned with it.
+    // Connections, if built, are kept sorted.
+    // NOTE: Queries assume trips on a route don't overtake each other, which a delay from a later stop can break.
+    pub fn apply_delay(&mut self, trip: GlobalTripIndex, from_stop_order: usize, delay_secs: i32) -> Result<(GlobalTripIndex, i32), DelayError> {
+        let route = self.routes.get(trip.route().idx()).filter(|route| trip.trip().idx() < route.num_trips as usize).ok_or(DelayError::UnknownTrip(trip))?;
+        let num_stops = route.num_stops as usize;
+        if from_stop_order >= num_stops {
+            return Err(DelayError::UnknownStopOrder(from_stop_order));
+        }
+
+        let trip_range = route.get_trip_range(trip.trip().idx());
+        let from_index = trip_range.start + from_stop_order;
+        let earliest = if from_stop_order > 0 { self.stop_times.departure_times[from_index - 1] } else { 0 };
+        let delay_secs = delay_secs.max(earliest as i32 - self.stop_times.arrival_times[from_index] as i32);
+        let shift = |time: &mut Timestamp| *time = time.saturating_add_signed(delay_secs);
+        self.stop_times.arrival_times[from_index..trip_range.end].iter_mut().for_each(shift);
+        self.stop_times.departure_times[from_index..trip_range.end].iter_mut().for_each(shift);
+        self.clear_departure_column(trip.route().idx());
+
+        // Move the trip to keep trips sorted by first arrival. Other trips keep their relative order.
+        let route = &mut self.routes[trip.route().idx()];
+        let old_order = trip.trip().idx();
+        let first_arrival = self.stop_times.arrival_times[route.get_stop_times_index(old_order, 0)];
+        let new_order = (0..route.num_trips as usize)
+            .filter(|&trip_order| trip_order != old_order)
+            .map(|trip_order| self.stop_times.arrival_times[route.get_stop_times_index(trip_order, 0)])
+            .collect::<Vec<_>>()
+            .partition_point(|&arrival_time| arrival_time <= first_arrival);
+        let route_range = route.get_trip_range(0).start..route.get_trip_range(route.num_trips as usize - 1).end;
+        let (arrival_times, departure_times) = (&mut self.stop_times.arrival_times[route_range.clone()], &mut self.stop_times.departure_times[route_range]);
+        if new_order > old_order {
+            arrival_times[old_order * num_stops..(new_order + 1) * num_stops].rotate_left(num_stops);
+            departure_times[old_order * num_stops..(new_order + 1) * num_stops].rotate_left(num_stops);
+            route.trip_ids.move_to(old_order, new_order);
+            route.cancelled_trips[old_order..=new_order].rotate_left(1);
+            route.skipped_stops[old_order * num_stops..(new_order + 1) * num_stops].rotate_left(num_stops);
+        } else if new_order < old_order {
+            arrival_times[new_order * num_stops..(old_order + 1) * num_stops].rotate_right(num_stops);
+            departure_times[new_order * num_stops..(old_order + 1) * num_stops].rotate_right(num_stops);
+            route.trip_ids.move_to(old_order, new_order);
+            route.cancelled_trips[new_order..=old_order].rotate_right(1);
+            route.skipped_stops[new_order * num_stops..(old_order + 1) * num_stops].rotate_right(num_stops);
+        }
+
+        if !self.connections.is_empty() {
+            if new_order == old_order {
+                // Only this trip's times changed, so patch them and restore the order.
+                for connection in self.connections.iter_mut().filter(|connection| connection.trip == trip) {
+                    let departure_stop_order = connection.departure_stop_order as usize;
+                    if departure_stop_order >= from_stop_order {
+                        shift(&mut connection.departure_time);
+                    }
+                    if departure_stop_order + 1 >= from_stop_order {
+                        shift(&mut connection.arrival_time);
+                    }
+                }
+                self.sort_connections();
+            } else {
+                // Trip orders changed, so rebuild.
+                self.build_connections();
+            }
+        }
+
+        Ok((GlobalTripIndex::new(trip.route(), TripIdx::new(new_order)), delay_secs))
     }
 
     // Call build connections if running a CSA query. 
+    // Connections are generated per route and sorted, in parallel with the "parallel" feature. Ties in departure time are broken
+    // by trip and then stop order, so the order is deterministic.
     pub fn build_connections(&mut self) {
-        // Construct list of connections from trips in network.
-        let mut connections = Vec::new();
-        let mut sequential_trip_idx = 0 as TripOrder;
-        for (route_idx, route) in self.routes.iter().enumerate() {
-            let route_idx = route_idx as RouteIndex;
-            let num_stops = route.num_stops as usize;
-            let stops = route.get_stops(&self.route_stops);
-            for trip_order in 0..route.num_trips as usize {
-                let trip = route.get_trip(trip_order, &self.stop_times);
-                let trip_order = trip_order as TripOrder;
-                for arrival_stop_order in 1..num_stops {
-                    let departure_stop_order = arrival_stop_order - 1;
-                    connections.push(Connection {
-                        sequential_trip_idx,
-                        trip: GlobalTripIndex {
-                            route_idx,
-                            trip_order,
-                        },
-                        departure_idx: stops[departure_stop_order],
-                        departure_stop_order: departure_stop_order as StopIndex,
-                        departure_time: trip[departure_stop_order].departure_time,
-                        arrival_idx: stops[arrival_stop_order],
-                        arrival_time: trip[arrival_stop_order].arrival_time,
-                    });
+        let first_sequential_trip_idxs = self.first_sequential_trip_idxs().collect::<Vec<_>>();
+        #[cfg(feature = "parallel")]
+        let connections = (0..self.routes.len())
+            .into_par_iter()
+            .flat_map_iter(|route_idx| self.iter_route_connections(route_idx, first_sequential_trip_idxs[route_idx]))
+            .collect::<Vec<_>>();
+        #[cfg(not(feature = "parallel"))]
+        let connections = (0..self.routes.len())
+            .flat_map(|route_idx| self.iter_route_connections(route_idx, first_sequential_trip_idxs[route_idx]))
+            .collect::<Vec<_>>();
+
+        self.connections = connections;
+        self.sort_connections();
+    }
+
+    // Sorts connections by departure time. The key is unique, so an unstable sort is deterministic.
+    fn sort_connections(&mut self) {
+        #[cfg(feature = "parallel")]
+        self.connections.par_sort_unstable_by_key(Connection::sort_key);
+        #[cfg(not(feature = "parallel"))]
+        self.connections.sort_unstable_by_key(Connection::sort_key);
+        self.index_connections();
+    }
+
+    // Rebuilds connection_buckets from the sorted connections. The last bucket starts after the last connection.
+    fn index_connections(&mut self) {
+        let num_buckets = self.connections.last().map_or(0, |connection| (connection.departure_time / CONNECTION_BUCKET_SECONDS) as usize + 2);
+        self.connection_buckets = (0..num_buckets)
+            .map(|bucket| self.connections.partition_point(|connection| connection.departure_time < bucket as Timestamp * CONNECTION_BUCKET_SECONDS))
+            .collect();
+    }
+
+    // Returns the index of the first connection departing at or after time. Only the connections in time's minute are searched,
+    // unless connection_buckets is missing (e.g. for a network serialized without it).
+    pub fn first_connection_at_or_after(&self, time: Timestamp) -> usize {
+        let bucket = (time / CONNECTION_BUCKET_SECONDS) as usize;
+        if self.connection_buckets.is_empty() {
+            return self.connections.partition_point(|connection| connection.departure_time < time);
+        }
+        match self.connection_buckets.get(bucket..=bucket + 1) {
+            Some(&[start, end]) => start + self.connections[start..end].partition_point(|connection| connection.departure_time < time),
+            _ => self.connections.len(),
+        }
+    }
+
+    // Returns the connections departing in times, in departure order. Requires build_connections.
+    pub fn connections_departing_between(&self, times: std::ops::Range<Timestamp>) -> &[Connection] {
+        let start = self.first_connection_at_or_after(times.start);
+        let end = self.first_connection_at_or_after(times.end).max(start);
+        &self.connections[start..end]
+    }
+
+    // The sequential index of the first trip of each route. Trips are numbered sequentially across routes.
+    fn first_sequential_trip_idxs(&self) -> impl Iterator<Item = TripOrder> + '_ {
+        self.routes.iter().scan(0 as TripOrder, |next, route| {
+            let first = *next;
+            *next += route.num_trips;
+            Some(first)
+        })
+    }
+
+    fn iter_route_connections(&self, route_idx: usize, first_sequential_trip_idx: TripOrder) -> impl Iterator<Item = Connection> + '_ {
+        let route = &self.routes[route_idx];
+        let route_idx = RouteIdx::new(route_idx);
+        let stops = route.get_stops(&self.route_stops);
+        (0..route.num_trips as usize).filter(|&trip_order| !route.is_cancelled(trip_order)).flat_map(move |trip_order| {
+            let trip = route.get_trip(trip_order, &self.stop_times);
+            let trip_order = TripIdx::new(trip_order);
+            (1..stops.len()).map(move |arrival_stop_order| {
+                let departure_stop_order = arrival_stop_order - 1;
+                Connection {
+                    sequential_trip_idx: first_sequential_trip_idx + trip_order.0,
+                    trip: GlobalTripIndex::new(route_idx, trip_order),
+                    departure_idx: stops[departure_stop_order],
+                    departure_stop_order: departure_stop_order as StopIndex,
+                    departure_time: trip.departure_times[departure_stop_order],
+                    arrival_idx: stops[arrival_stop_order],
+                    arrival_time: trip.arrival_times[arrival_stop_order],
                 }
-                sequential_trip_idx += 1;
+            })
+        })
+    }
+
+    // Generates the connections of every trip on the fly, in route and trip order rather than by departure time.
+    // Use this instead of build_connections when the connections only need to be visited once, e.g. for exports.
+    pub fn iter_connections(&self) -> impl Iterator<Item = Connection> + '_ {
+        self.first_sequential_trip_idxs()
+            .enumerate()
+            .flat_map(move |(route_idx, first_sequential_trip_idx)| self.iter_route_connections(route_idx, first_sequential_trip_idx))
+    }
+
+    // Replaces the network's footpaths with walks of the given durations in seconds between pairs of stop IDs, e.g. from an
+    // external walking time matrix. If a pair is given more than once, the shortest duration is kept. Entries with unknown stops,
+    // between a stop and itself, or with a duration of zero are skipped, and returned with their index and why.
+    pub fn set_footpaths(&mut self, footpaths: &[(&str, &str, Timestamp)]) -> Vec<(usize, FootpathError)> {
+        let mut rejected = Vec::new();
+        let mut new_footpaths = Vec::with_capacity(footpaths.len());
+        for (i, &(from_id, to_id, duration)) in footpaths.iter().enumerate() {
+            match self.parse_footpath(from_id, to_id, duration) {
+                Ok(footpath) => new_footpaths.push(footpath),
+                Err(error) => rejected.push((i, error)),
             }
         }
+        new_footpaths.sort_unstable_by_key(|footpath| (footpath.from, footpath.to, footpath.duration));
+        new_footpaths.dedup_by_key(|footpath| (footpath.from, footpath.to));
+        self.footpaths = new_footpaths;
+        rejected
+    }
 
-        // Sort connections by departure time.
-        connections.sort_unstable_by_key(|x| x.departure_time);
+    fn parse_footpath(&self, from_id: &str, to_id: &str, duration: Timestamp) -> Result<Footpath, FootpathError> {
+        let from = self.try_get_stop_idx(from_id).ok_or_else(|| UnknownStop(from_id.to_string()))?;
+        let to = self.try_get_stop_idx(to_id).ok_or_else(|| UnknownStop(to_id.to_string()))?;
+        if from == to {
+            return Err(FootpathError::SameStop(from_id.to_string()));
+        }
+        if duration == 0 {
+            return Err(FootpathError::ZeroDuration);
+        }
+        Ok(Footpath { from, to, duration })
+    }
 
-        self.connections = connections;
+    // Replaces the network's footpaths with those in a CSV with the columns from_stop_id, to_stop_id and duration_secs (in any order,
+    // with a header row). Values may be quoted, but can't contain commas. Rejected rows are returned with their line number
+    // (counting the header as line 1) and why, as in set_footpaths.
+    pub fn load_footpaths_csv(&mut self, reader: impl Read) -> std::io::Result<Vec<(usize, FootpathError)>> {
+        let mut lines = BufReader::new(reader).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let split = |line: &str| line.split(',').map(|value| value.trim().trim_matches('"').to_string()).collect::<Vec<_>>();
+        let header = split(&header);
+        let column = |name: &str| header.iter().position(|column| column == name)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Footpaths CSV has no {name} column.")));
+        let (from_column, to_column, duration_column) = (column("from_stop_id")?, column("to_stop_id")?, column("duration_secs")?);
+
+        let mut footpaths = Vec::new();
+        let mut rejected = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let values = split(&line);
+            let line_number = i + 2;
+            let (Some(from_id), Some(to_id), Some(duration)) = (values.get(from_column), values.get(to_column), values.get(duration_column).and_then(|duration| duration.parse().ok())) else {
+                rejected.push((line_number, FootpathError::Malformed(line)));
+                continue;
+            };
+            footpaths.push((line_number, from_id.clone(), to_id.clone(), duration));
+        }
+
+        let entries = footpaths.iter().map(|(_, from_id, to_id, duration)| (from_id.as_str(), to_id.as_str(), *duration)).collect::<Vec<_>>();
+        rejected.extend(self.set_footpaths(&entries).into_iter().map(|(i, error)| (footpaths[i].0, error)));
+        rejected.sort_by_key(|&(line_number, _)| line_number);
+        Ok(rejected)
+    }
+
+    // The time to walk the footpath between two stops, if there is one. See get_footpath_time.
+    pub fn footpath_duration(&self, from: StopIndex, to: StopIndex) -> Option<Timestamp> {
+        let footpaths = self.footpaths_from(from);
+        footpaths.binary_search_by_key(&to, |footpath| footpath.to).ok().map(|i| self.get_footpath_time(&footpaths[i]))
+    }
+
+    // The time to walk a footpath. A pairwise transfer time between its stops overrides its duration, as a transfers.txt
+    // min_transfer_time between two stops would, so queries and journeys resolve walks the same way.
+    pub fn get_footpath_time(&self, footpath: &Footpath) -> Timestamp {
+        self.pairwise_transfer_times.get(&(footpath.from, footpath.to)).copied().unwrap_or(footpath.duration)
     }
 
+    // The footpaths leaving a stop, sorted by destination.
+    pub fn footpaths_from(&self, stop: StopIndex) -> &[Footpath] {
+        let start = self.footpaths.partition_point(|footpath| footpath.from < stop);
+        let end = self.footpaths.partition_point(|footpath| footpath.from <= stop);
+        &self.footpaths[start..end]
+    }
+
+    // Writes every connection as CSV, in route and trip order, with times as HH:MM:SS (past 24:00:00 for trips after midnight).
+    // Connections are generated on the fly, so build_connections isn't needed.
+    pub fn write_connections_csv(&self, mut writer: impl Write) -> std::io::Result<()> {
+        let quote = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+        writeln!(writer, "trip_id,line,departure_stop_id,departure_stop_name,departure_time,arrival_stop_id,arrival_stop_name,arrival_time")?;
+        for connection in self.iter_connections() {
+            let (departure_stop, arrival_stop) = (connection.departure_idx as usize, connection.arrival_idx as usize);
+            writeln!(writer, "{},{},{},{},{},{},{},{}", quote(self.get_trip_id(connection.trip)), quote(&self.routes[connection.trip.route().idx()].line),
+                     quote(self.stop_id(departure_stop)), quote(self.stop_name(departure_stop)), utils::get_time_str(connection.departure_time),
+                     quote(self.stop_id(arrival_stop)), quote(self.stop_name(arrival_stop)), utils::get_time_str(connection.arrival_time))?;
+        }
+        Ok(())
+    }
+
+    // Writes the network as a GeoJSON FeatureCollection, with a LineString for each route (properties line, colour, direction and
+    // num_trips, where direction is the name of the route's final stop) and a Point for each stop (properties id, name and
+    // num_routes). Features are written as they are generated, as the whole network is too large to build as one string.
+    // Stops without valid coordinates are left out.
+    pub fn write_geojson(&self, options: &GeoJsonOptions, mut writer: impl Write) -> std::io::Result<()> {
+        let point_json = |point: NetworkPoint| format!("[{},{}]", point.longitude, point.latitude);
+
+        write!(writer, r#"{{"type":"FeatureCollection","features":["#)?;
+        // Written before each feature.
+        let mut separator = "";
+
+        let mut included_stops = vec![false; self.stops.len()];
+        for (route_idx, route) in self.routes.iter().enumerate().filter(|&(route_idx, route)| (options.route_filter)(route_idx as RouteIndex, route)) {
+            let stops = route.get_stops(&self.route_stops);
+            for &stop in stops {
+                included_stops[stop as usize] = true;
+            }
+            let points = if options.use_shapes {
+                route.shape_between(0, stops.len() - 1, self)
+            } else {
+                stops.iter().map(|&stop| self.stop_points[stop as usize]).collect()
+            };
+            let coordinates = points.into_iter().filter(|point| point.is_valid()).map(point_json).collect::<Vec<_>>().join(",");
+
+            write!(
+                writer,
+                r#"{separator}{{"type":"Feature","geometry":{{"type":"LineString","coordinates":[{coordinates}]}},"properties":{{"kind":"route","route_idx":{route_idx},"line":"{}","colour":"{}","direction":"{}","num_trips":{}}}}}"#,
+                utils::escape_json_string(&route.line),
+                utils::get_colour_hex(route.colour),
+                utils::escape_json_string(self.stop_name(*stops.last().unwrap() as usize)),
+                route.num_trips,
+            )?;
+            separator = ",";
+        }
+
+        if options.include_stops {
+            for (stop_idx, stop) in self.stops.iter().enumerate().filter(|&(stop_idx, _)| included_stops[stop_idx] && self.stop_points[stop_idx].is_valid()) {
+                write!(
+                    writer,
+                    r#"{separator}{{"type":"Feature","geometry":{{"type":"Point","coordinates":{}}},"properties":{{"kind":"stop","id":"{}","name":"{}","num_routes":{}}}}}"#,
+                    point_json(self.stop_points[stop_idx]),
+                    utils::escape_json_string(self.stop_id(stop_idx)),
+                    utils::escape_json_string(self.stop_name(stop_idx)),
+                    stop.num_routes,
+                )?;
+                separator = ",";
+            }
+        }
+
+        write!(writer, "]}}")
+    }
+
+    // Writes the route graph in Graphviz DOT format, with stops as nodes labelled by name and each route as a chain of edges
+    // labelled with its line and number of trips, and coloured with its colour. Only stops on drawn routes are included.
+    // Render it with e.g. `dot -Tsvg routes.dot -o routes.svg`.
+    pub fn write_dot<W: Write>(&self, writer: W, options: &DotOptions) -> std::io::Result<()> {
+        let mut edges = Vec::new();
+        let mut included_stops = vec![false; self.stops.len()];
+        for route in self.routes.iter() {
+            let stops = route.get_stops(&self.route_stops);
+            let on_line = OptionExt::is_none_or(options.lines.as_ref(), |lines| lines.iter().any(|line| **line == *route.line));
+            let through_stop = OptionExt::is_none_or(options.around_stop, |stop| stops.contains(&stop));
+            if !on_line || !through_stop {
+                continue;
+            }
+            for &stop in stops {
+                included_stops[stop as usize] = true;
+            }
+            for pair in stops.windows(2) {
+                edges.push(format!(
+                    "{} -> {} [label=\"{} ({} trips)\", color=\"{}\"];",
+                    pair[0], pair[1], escape_dot(&route.line), route.num_trips, utils::get_colour_hex(route.colour),
+                ));
+            }
+        }
+
+        let stops = (0..self.stops.len()).filter(|&stop_idx| included_stops[stop_idx]).map(|stop_idx| stop_idx as StopIndex);
+        write_dot_graph(self, writer, "routes", stops, edges)
+    }
+
+    // Generates connections sorted by departure time, only materialising an hour of departures at a time.
+    // Every hour rescans the network, so this trades time for memory. CSA queries need build_connections instead.
+    pub fn iter_connections_sorted(&self) -> impl Iterator<Item = Connection> + '_ {
+        const BUCKET_SECONDS: Timestamp = 60 * 60;
+        let num_buckets = self.iter_connections().map(|connection| connection.departure_time / BUCKET_SECONDS + 1).max().unwrap_or(0);
+        (0..num_buckets).flat_map(move |bucket| {
+            let mut connections = self.iter_connections()
+                .filter(|connection| connection.departure_time / BUCKET_SECONDS == bucket)
+                .collect::<Vec<_>>();
+            connections.sort_unstable_by_key(Connection::sort_key);
+            connections
+        })
+    }
+
+    // Panics if the stop index is out of bounds. See try_get_stop.
     pub fn get_stop(&self, stop: usize) -> &Stop { &self.stops[stop] }
 
+    pub fn try_get_stop(&self, stop: usize) -> Option<&Stop> { self.stops.get(stop) }
+
+    // The short name and GTFS ID of a stop. These panic if the stop index is out of bounds.
+    pub fn stop_name(&self, stop: usize) -> &str { &self.stop_names[stop] }
+
+    pub fn stop_id(&self, stop: usize) -> &str { &self.stop_ids[stop] }
+
+    // Typed accessors. These panic if the index is out of bounds, like indexing the Vecs directly.
+    pub fn stop(&self, stop: StopIdx) -> &Stop { &self.stops[stop.idx()] }
+
+    pub fn stop_point(&self, stop: StopIdx) -> NetworkPoint { self.stop_points[stop.idx()] }
+
+    pub fn transfer_time(&self, stop: StopIdx) -> Timestamp { self.transfer_times[stop.idx()] }
+
+    pub fn route(&self, route: RouteIdx) -> &Route { &self.routes[route.idx()] }
+
+    // The routes that serve a stop.
+    pub fn routes_at(&self, stop: StopIdx) -> impl Iterator<Item=RouteIdx> + '_ {
+        self.stop(stop).get_routes(&self.stop_routes).iter().map(|&route| RouteIdx(route))
+    }
+
+    // The stops of a route, in stop order.
+    pub fn stops_of(&self, route: RouteIdx) -> impl Iterator<Item=StopIdx> + '_ {
+        self.route(route).get_stops(&self.route_stops).iter().map(|&stop| StopIdx(stop))
+    }
+
+    // The stop times of a trip, in stop order.
+    pub fn trip(&self, trip: GlobalTripIndex) -> TripStopTimes<'_> {
+        self.route(trip.route()).get_trip(trip.trip().idx(), &self.stop_times)
+    }
+
+    // Panics if there is no stop with the given GTFS stop ID. See try_get_stop_idx.
     pub fn get_stop_idx(&self, stop_id: &str) -> StopIndex { self.stop_index[stop_id] }
 
+    pub fn try_get_stop_idx(&self, stop_id: &str) -> Option<StopIndex> { self.stop_index.get(stop_id).copied() }
+
     pub fn stop_name_cmp(a: &str, b: &str) -> bool {
         utils::get_short_stop_name(a).to_lowercase().replace(" ", "") == b.to_lowercase().replace(" ", "")
     }
 
+    // Exact (case and whitespace insensitive) lookup of a stop by its short name. When this fails, search_stops can suggest
+    // close matches, and an exact match is always its top result.
     pub fn get_stop_idx_from_name(&self, stop_name: &str) -> Option<StopIndex> {
-        self.stops.iter().position(|stop| Network::stop_name_cmp(&stop.name, stop_name)).map(|stop_idx| stop_idx as StopIndex)
+        let stop_name = stop_name.to_lowercase().replace(" ", "");
+        self.normalised_stop_names()
+            .iter()
+            .position(|name| name.chars().filter(|&c| c != ' ').eq(stop_name.chars()))
+            .map(|stop_idx| stop_idx as StopIndex)
+    }
+
+    // Short names lowercased, with runs of whitespace collapsed to one space.
+    fn normalise_stop_name(name: &str) -> String {
+        utils::get_short_stop_name(name).split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+    }
+
+    fn normalised_stop_names(&self) -> &StringArena {
+        self.normalised_stop_names.get_or_init(|| self.stop_names.iter().map(Network::normalise_stop_name).collect())
+    }
+
+    // Case and whitespace insensitive comparison of line names, like stop_name_cmp.
+    pub fn line_name_cmp(a: &str, b: &str) -> bool {
+        a.to_lowercase().replace(" ", "") == b.to_lowercase().replace(" ", "")
+    }
+
+    // Returns every route (i.e. direction and stopping pattern) of the named line, in route order.
+    pub fn get_routes_by_line(&self, line: &str) -> Vec<RouteIndex> {
+        self.routes.iter().enumerate().filter(|(_, route)| Network::line_name_cmp(&route.line, line)).map(|(route_idx, _)| route_idx as RouteIndex).collect()
+    }
+
+    // Returns every route whose line name contains the query (case and whitespace insensitive), in route order.
+    pub fn get_routes_by_partial_line(&self, query: &str) -> Vec<RouteIndex> {
+        let query = query.to_lowercase().replace(" ", "");
+        self.routes
+            .iter()
+            .enumerate()
+            .filter(|(_, route)| route.line.to_lowercase().replace(" ", "").contains(&query))
+            .map(|(route_idx, _)| route_idx as RouteIndex)
+            .collect()
+    }
+
+    // Iterates over the distinct line names, in order of their first route.
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        let mut seen = HashSet::new();
+        self.routes.iter().map(|route| &*route.line).filter(move |&line| seen.insert(line))
+    }
+
+    // Stops within this distance (in km) of another stop with the same station name are in the same station.
+    pub const STATION_RADIUS_KM: CoordType = 0.5;
+
+    // The station name of a stop, e.g. "Richmond" for "Richmond Railway Station (Platform 1/2)".
+    fn station_name(stop_name: &str) -> &str {
+        let name = utils::get_short_stop_name(stop_name);
+        name.split(" (").next().unwrap().trim()
+    }
+
+    // Groups stops into stations. Stops are in the same station if their names match (ignoring case, whitespace and any
+    // platform suffix) and they are linked by a chain of stops within STATION_RADIUS_KM of each other. Stops without valid
+    // coordinates are only grouped with themselves. Groups are sorted by their first stop.
+    pub fn station_groups(&self) -> Vec<StationGroup> {
+        let mut stops_by_name = HashMap::<String, Vec<StopIndex>>::new();
+        for (stop_idx, stop_name) in self.stop_names.iter().enumerate() {
+            let name = Network::station_name(stop_name).to_lowercase().replace(" ", "");
+            stops_by_name.entry(name).or_default().push(stop_idx as StopIndex);
+        }
+
+        let mut groups = Vec::new();
+        for stops in stops_by_name.into_values() {
+            // Single-linkage clustering, growing each group from its first unassigned stop.
+            let mut assigned = vec![false; stops.len()];
+            for first in 0..stops.len() {
+                if assigned[first] {
+                    continue;
+                }
+                assigned[first] = true;
+                let mut group = vec![stops[first]];
+                let mut i = 0;
+                while i < group.len() {
+                    let point = self.stop_points[group[i] as usize];
+                    for (other, &other_stop) in stops.iter().enumerate() {
+                        let other_point = self.stop_points[other_stop as usize];
+                        if !assigned[other] && point.is_valid() && other_point.is_valid() && point.distance(other_point) <= Network::STATION_RADIUS_KM {
+                            assigned[other] = true;
+                            group.push(other_stop);
+                        }
+                    }
+                    i += 1;
+                }
+                group.sort_unstable();
+                let name = Network::station_name(self.stop_name(group[0] as usize)).into();
+                groups.push(StationGroup { name, stops: group });
+            }
+        }
+        groups.sort_unstable_by_key(|group| group.stops[0]);
+        groups
+    }
+
+    // Returns every stop in the station of the stop found by get_stop_idx_from_name, e.g. all platforms of "Richmond". This can
+    // be used as the destination set of a multi-target query. Same-named stations elsewhere aren't included.
+    pub fn stops_in_station(&self, name: &str) -> Vec<StopIndex> {
+        let name = Network::station_name(name);
+        let Some(stop) = self.stop_names.iter().position(|stop_name| Network::stop_name_cmp(Network::station_name(stop_name), name)) else {
+            return Vec::new();
+        };
+        let stop = stop as StopIndex;
+        self.station_groups().into_iter().find(|group| group.stops.contains(&stop)).unwrap().stops
+    }
+
+    // Searches for stops by name, tolerating abbreviations and typos. Returns up to limit matches with a score between 0 and 1,
+    // ranked by score (best first) then by stop name.
+    // Exact matches score 1. Queries where every word is a prefix of a word in the stop name, in order ("flinders st"), score
+    // between 0.8 and 0.9. Otherwise the score is 0.8 times the edit-distance similarity of the query and the stop name (or its
+    // prefix of the same length), and matches scoring below 0.5 are dropped.
+    pub fn search_stops(&self, query: &str, limit: usize) -> Vec<(StopIndex, f32)> {
+        fn is_word_prefix_match(query: &str, name: &str) -> bool {
+            let mut name_words = name.split(' ');
+            query.split(' ').all(|query_word| name_words.any(|name_word| name_word.starts_with(query_word)))
+        }
+        fn similarity(a: &str, b: &str) -> f32 {
+            let max_len = a.chars().count().max(b.chars().count()).max(1);
+            1. - utils::edit_distance(a, b) as f32 / max_len as f32
+        }
+
+        let query = Network::normalise_stop_name(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let query_len = query.chars().count();
+
+        let mut matches = Vec::new();
+        for (stop_idx, name) in self.normalised_stop_names().iter().enumerate() {
+            let name_len = name.chars().count();
+            let score = if name == query {
+                1.
+            } else if is_word_prefix_match(&query, name) {
+                0.8 + 0.1 * query_len as f32 / name_len as f32
+            } else {
+                let name_prefix = &name[..name.char_indices().nth(query_len).map_or(name.len(), |(i, _)| i)];
+                0.8 * similarity(&query, name).max(similarity(&query, name_prefix))
+            };
+            if score >= 0.5 {
+                matches.push((stop_idx as StopIndex, score));
+            }
+        }
+
+        matches.sort_by(|&(a_idx, a_score), &(b_idx, b_score)| {
+            b_score.total_cmp(&a_score).then_with(|| self.stop_name(a_idx as usize).cmp(self.stop_name(b_idx as usize)))
+        });
+        matches.truncate(limit);
+        matches
+    }
+
+    // Returns the k stops closest to the point, with their distances in km, closest first. Stops without valid coordinates are
+    // never returned. The spatial index is built from stop_points on first use, so later changes to stop_points aren't seen.
+    pub fn nearest_stops(&self, point: NetworkPoint, k: usize) -> Vec<(StopIndex, CoordType)> {
+        self.stop_grid.get_or_init(|| StopGrid::new(&self.stop_points)).nearest(&self.stop_points, point, k)
+    }
+
+    // Returns all stops within radius_km of the point, closest first, using the same spatial index as nearest_stops.
+    pub fn stops_within(&self, point: NetworkPoint, radius_km: CoordType) -> Vec<StopIndex> {
+        let grid = self.stop_grid.get_or_init(|| StopGrid::new(&self.stop_points));
+        grid.within(&self.stop_points, point, radius_km).into_iter().map(|(stop, _)| stop).collect()
+    }
+
+    // Returns the next departures from the stop at or after from_time, across all routes serving it, earliest first.
+    // Arrivals at the final stop of a trip aren't departures, so are excluded, as are cancelled trips.
+    pub fn departures(&self, stop: StopIndex, from_time: Timestamp, limit: usize) -> Vec<Departure> {
+        let mut departures = Vec::new();
+        for &route_idx in self.stops[stop as usize].get_routes(&self.stop_routes) {
+            let route = &self.routes[route_idx as usize];
+            let stops = route.get_stops(&self.route_stops);
+            let destination = *stops.last().unwrap();
+            // A route may visit the stop more than once, e.g. a loop.
+            for stop_order in (0..stops.len() - 1).filter(|&stop_order| stops[stop_order] == stop) {
+                // Trips that skip the stop don't pick up there.
+                for trip_order in (0..route.num_trips as usize).filter(|&trip_order| !route.is_cancelled(trip_order) && !route.is_skipped(trip_order, stop_order)) {
+                    let departure_time = self.stop_times.departure_times[route.get_stop_times_index(trip_order, stop_order)];
+                    if departure_time >= from_time {
+                        departures.push(Departure {
+                            line: route.line.clone(),
+                            destination,
+                            departure_time,
+                            trip: GlobalTripIndex::new(RouteIdx(route_idx), TripIdx::new(trip_order)),
+                            stop_order: stop_order as StopIndex,
+                            colour: route.colour,
+                        });
+                    }
+                }
+            }
+        }
+
+        departures.sort_by_key(|departure| (departure.departure_time, departure.trip.route_idx, departure.trip.trip_order));
+        departures.truncate(limit);
+        departures
+    }
+
+    // Renders the next departures from the stop as a departure board, one line per departure with the wait from from_time, e.g.
+    // "8:42 am  Frankston to Flinders Street (in 5m)".
+    pub fn format_departures(&self, stop: StopIndex, from_time: Timestamp, limit: usize, time_format: TimeFormat) -> String {
+        let departures = self.departures(stop, from_time, limit);
+        if departures.is_empty() {
+            return String::from("No departures.\n");
+        }
+        let times = departures.iter().map(|departure| utils::format_time(departure.departure_time, time_format)).collect::<Vec<_>>();
+        let time_width = times.iter().map(|time| time.chars().count()).max().unwrap_or(0);
+        departures.iter().zip(times.iter()).map(|(departure, time)| {
+            format!("{time:<time_width$}  {} to {} (in {})\n", departure.line, utils::get_short_stop_name(self.stop_name(departure.destination as usize)),
+                    utils::format_duration(departure.departure_time - from_time, DurationStyle::Compact))
+        }).collect()
+    }
+
+    pub fn route_timetable(&self, route_idx: usize) -> RouteTimetable<'_> {
+        let route = &self.routes[route_idx];
+        let stops = route.get_stops(&self.route_stops);
+        let trips = (0..route.num_trips as usize).map(|trip_order| {
+            let stop_times = route.get_trip(trip_order, &self.stop_times);
+            TripTimetable {
+                trip: GlobalTripIndex::new(RouteIdx::new(route_idx), TripIdx::new(trip_order)),
+                trip_id: &route.trip_ids[trip_order],
+                stop_times: stops.iter().zip(stop_times.iter()).map(|(&stop, stop_time)| (stop, stop_time.arrival_time, stop_time.departure_time)).collect(),
+            }
+        }).collect();
+        RouteTimetable { route_idx: route_idx as RouteIndex, line: &route.line, trips }
+    }
+
+    // Returns every trip that arrives at the stop within the time range, sorted by arrival time.
+    pub fn trips_through_stop(&self, stop: StopIndex, time_range: std::ops::Range<Timestamp>) -> Vec<StopVisit> {
+        let mut visits = Vec::new();
+        for &route_idx in self.stops[stop as usize].get_routes(&self.stop_routes) {
+            let route = &self.routes[route_idx as usize];
+            let stops = route.get_stops(&self.route_stops);
+            for stop_order in (0..stops.len()).filter(|&stop_order| stops[stop_order] == stop) {
+                for trip_order in 0..route.num_trips as usize {
+                    let stop_time = self.stop_times.get(route.get_stop_times_index(trip_order, stop_order));
+                    if time_range.contains(&stop_time.arrival_time) {
+                        visits.push(StopVisit {
+                            trip: GlobalTripIndex::new(RouteIdx(route_idx), TripIdx::new(trip_order)),
+                            stop_order: stop_order as StopIndex,
+                            arrival_time: stop_time.arrival_time,
+                            departure_time: stop_time.departure_time,
+                        });
+                    }
+                }
+            }
+        }
+        visits.sort_by_key(|visit| (visit.arrival_time, visit.trip.route_idx, visit.trip.trip_order));
+        visits
+    }
+
+    // Builds the stop adjacency graph from the routes and stop times. Doesn't require build_connections.
+    pub fn adjacency_graph(&self) -> StopGraph {
+        let mut edges = HashMap::<(StopIndex, StopIndex), StopEdge>::new();
+        for (route_idx, route) in self.routes.iter().enumerate() {
+            let route_idx = route_idx as RouteIndex;
+            let stops = route.get_stops(&self.route_stops);
+            for departure_stop_order in 0..stops.len().saturating_sub(1) {
+                let (from, to) = (stops[departure_stop_order], stops[departure_stop_order + 1]);
+                let min_travel_time = (0..route.num_trips as usize)
+                    .map(|trip_order| {
+                        let trip = route.get_trip(trip_order, &self.stop_times);
+                        trip.arrival_times[departure_stop_order + 1].saturating_sub(trip.departure_times[departure_stop_order])
+                    })
+                    .min();
+                let Some(min_travel_time) = min_travel_time else {
+                    continue;
+                };
+
+                let edge = edges.entry((from, to)).or_insert_with(|| StopEdge {
+                    from,
+                    to,
+                    min_travel_time,
+                    num_trips: 0,
+                    routes: Vec::new(),
+                });
+                edge.min_travel_time = edge.min_travel_time.min(min_travel_time);
+                edge.num_trips += route.num_trips as usize;
+                // A route may travel between the same stops more than once, e.g. a loop.
+                if edge.routes.last() != Some(&route_idx) {
+                    edge.routes.push(route_idx);
+                }
+            }
+        }
+
+        let mut edges = edges.into_values().collect::<Vec<_>>();
+        edges.sort_unstable_by_key(|edge| (edge.from, edge.to));
+        StopGraph { num_stops: self.stops.len(), edges }
+    }
+
+    // Returns each route serving the stop once, in the order of stop.get_routes, with the next stop and direction.
+    pub fn stop_route_info(&self, stop: StopIndex) -> Vec<StopRouteInfo> {
+        self.stops[stop as usize]
+            .get_routes(&self.stop_routes)
+            .iter()
+            .map(|&route_idx| {
+                let route = &self.routes[route_idx as usize];
+                let stops = route.get_stops(&self.route_stops);
+                let stop_order = self.stop_order_in_route(route_idx as usize, stop, 0).unwrap();
+                StopRouteInfo {
+                    route_idx,
+                    line: route.line.clone(),
+                    stop_order: stop_order as StopIndex,
+                    next_stop: stops.get(stop_order + 1).copied(),
+                    destination: *stops.last().unwrap(),
+                    is_first: stop_order == 0,
+                    is_last: stop_order == stops.len() - 1,
+                }
+            })
+            .collect()
+    }
+
+    // Calls f with each stop time at the stop, over every route and uncancelled trip serving it, until f returns false.
+    // Returns whether every stop time was visited.
+    fn for_each_stop_time_at(&self, stop: StopIndex, mut f: impl FnMut(StopTime) -> bool) -> bool {
+        for &route_idx in self.stops[stop as usize].get_routes(&self.stop_routes) {
+            let route = &self.routes[route_idx as usize];
+            let stops = route.get_stops(&self.route_stops);
+            for stop_order in (0..stops.len()).filter(|&stop_order| stops[stop_order] == stop) {
+                for trip_order in (0..route.num_trips as usize).filter(|&trip_order| !route.is_cancelled(trip_order)) {
+                    if !f(self.stop_times.get(route.get_stop_times_index(trip_order, stop_order))) {
+                        return false;
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    // Returns the earliest departure from and latest arrival at the stop on the network's date, or None if it has no service.
+    pub fn service_span(&self, stop: StopIndex) -> Option<(Timestamp, Timestamp)> {
+        let mut span: Option<(Timestamp, Timestamp)> = None;
+        self.for_each_stop_time_at(stop, |stop_time| {
+            let (first, last) = span.unwrap_or((Timestamp::MAX, 0));
+            span = Some((first.min(stop_time.departure_time), last.max(stop_time.arrival_time)));
+            true
+        });
+        span
+    }
+
+    // Returns whether any trip arrives at or departs from the stop between from (inclusive) and to (exclusive).
+    pub fn has_service_between(&self, stop: StopIndex, from: Timestamp, to: Timestamp) -> bool {
+        let times = from..to;
+        // Stops iterating (returning false) at the first match.
+        !self.for_each_stop_time_at(stop, |stop_time| !times.contains(&stop_time.arrival_time) && !times.contains(&stop_time.departure_time))
+    }
+
+    // Finds pairs of different stops within max_distance_km of each other without a pairwise transfer time, where trips arrive at
+    // the first stop between 0 and max_gap_secs before other trips depart from the second. Sorted by the number of near misses
+    // (most first), then by stops.
+    pub fn transfer_opportunity_report(&self, max_distance_km: CoordType, max_gap_secs: Timestamp) -> Vec<TransferOpportunity> {
+        let mut opportunities = Vec::new();
+        for from_stop in 0..self.stops.len() as StopIndex {
+            // Arrivals at the first stop of a trip aren't really arrivals.
+            let arrivals = self.trips_through_stop(from_stop, 0..Timestamp::MAX).into_iter().filter(|visit| visit.stop_order > 0 && !self.routes[visit.trip.route().idx()].is_cancelled(visit.trip.trip().idx())).collect::<Vec<_>>();
+            if arrivals.is_empty() {
+                continue;
+            }
+
+            for to_stop in self.stops_within(self.stop_points[from_stop as usize], max_distance_km) {
+                if to_stop == from_stop || self.pairwise_transfer_times.contains_key(&(from_stop, to_stop)) {
+                    continue;
+                }
+                let departures = self.departures(to_stop, 0, usize::MAX);
+                let mut num_near_misses = 0;
+                let mut tightest_gap = Timestamp::MAX;
+                for arrival in arrivals.iter() {
+                    let first = departures.partition_point(|departure| departure.departure_time < arrival.arrival_time);
+                    for departure in departures[first..].iter().take_while(|departure| departure.departure_time - arrival.arrival_time <= max_gap_secs) {
+                        if departure.trip != arrival.trip {
+                            num_near_misses += 1;
+                            tightest_gap = tightest_gap.min(departure.departure_time - arrival.arrival_time);
+                        }
+                    }
+                }
+                if num_near_misses > 0 {
+                    let distance_km = self.stop_points[from_stop as usize].distance(self.stop_points[to_stop as usize]);
+                    opportunities.push(TransferOpportunity { from_stop, to_stop, distance_km, num_near_misses, tightest_gap });
+                }
+            }
+        }
+        opportunities.sort_by(|a, b| b.num_near_misses.cmp(&a.num_near_misses).then((a.from_stop, a.to_stop).cmp(&(b.from_stop, b.to_stop))));
+        opportunities
+    }
+
+    // Summarises the service on each line, in the order of lines().
+    pub fn line_summaries(&self) -> Vec<LineSummary> {
+        self.lines()
+            .map(|line| {
+                let routes = self.routes.iter().filter(|route| &*route.line == line).collect::<Vec<_>>();
+                let first_departures = routes.iter().flat_map(|route| {
+                    (0..route.num_trips as usize).map(|trip_order| self.stop_times.departure_times[route.get_stop_times_index(trip_order, 0)])
+                });
+                let (first_departure, last_departure) = first_departures.fold((Timestamp::MAX, 0), |(first, last), departure_time| (first.min(departure_time), last.max(departure_time)));
+                let mut stops = routes.iter().flat_map(|route| route.get_stops(&self.route_stops)).copied().collect::<Vec<_>>();
+                stops.sort_unstable();
+                stops.dedup();
+                let shape_lengths = routes
+                    .iter()
+                    .filter(|route| route.shape.len() > 1)
+                    .map(|route| route.shape.windows(2).map(|pair| pair[0].distance(pair[1])).sum::<CoordType>())
+                    .collect::<Vec<_>>();
+
+                LineSummary {
+                    line: routes[0].line.clone(),
+                    num_routes: routes.len(),
+                    num_trips: routes.iter().map(|route| route.num_trips as usize).sum(),
+                    first_departure,
+                    last_departure,
+                    num_stops: stops.len(),
+                    route_length_km: (!shape_lengths.is_empty()).then(|| shape_lengths.iter().sum()),
+                }
+            })
+            .collect()
+    }
+
+    // Returns the gaps between consecutive departures from the route's stop within the time window, in chronological order.
+    // Departures are taken from the expanded trips, so every trip running on the network's date is included.
+    pub fn route_headways(&self, route_idx: usize, stop_order: usize, time_window: std::ops::Range<Timestamp>) -> Vec<Timestamp> {
+        let route = &self.routes[route_idx];
+        let mut departure_times = (0..route.num_trips as usize)
+            .map(|trip_order| self.stop_times.departure_times[route.get_stop_times_index(trip_order, stop_order)])
+            .filter(|departure_time| time_window.contains(departure_time))
+            .collect::<Vec<_>>();
+        departure_times.sort_unstable();
+        departure_times.windows(2).map(|pair| pair[1] - pair[0]).collect()
+    }
+
+    // Counts the departures from the stop within the time window, across all routes serving it.
+    pub fn stop_service_frequency(&self, stop: StopIndex, time_window: std::ops::Range<Timestamp>) -> usize {
+        self.departures(stop, time_window.start, usize::MAX)
+            .iter()
+            .take_while(|departure| departure.departure_time < time_window.end)
+            .count()
+    }
+
+    // Returns the route's stops from from_stop to to_stop (inclusive), or None if either isn't on the route or to_stop comes
+    // before from_stop in the route's direction. On loops, to_stop is its first visit at or after from_stop.
+    pub fn stops_between(&self, route_idx: usize, from_stop: StopIndex, to_stop: StopIndex) -> Option<&[StopIndex]> {
+        let from_order = self.stop_order_in_route(route_idx, from_stop, 0)?;
+        let to_order = self.stop_order_in_route(route_idx, to_stop, from_order)?;
+        Some(&self.routes[route_idx].get_stops(&self.route_stops)[from_order..=to_order])
+    }
+
+    // Returns the position of the stop in the route at or after min_order, or None if the route doesn't visit it there. Loop
+    // routes visit some stops more than once, so e.g. the alighting stop of a trip is searched for after its boarding stop.
+    // Looked up in a table of every stop's visits that is built on first use, so code that changes routes or route_stops
+    // directly must reset stop_visits.
+    pub fn stop_order_in_route(&self, route_idx: usize, stop_idx: StopIndex, min_order: usize) -> Option<usize> {
+        self.stop_visits
+            .get_or_init(|| StopVisits::new(self.stops.len(), &self.routes, &self.route_stops))
+            .stop_order(route_idx as RouteIndex, stop_idx, min_order)
     }
 
     pub fn get_stop_in_route(&self, route_idx: usize, stop_order: usize) -> StopIndex {
@@ -498,13 +2371,22 @@ impl Network {
     }
 
     pub fn get_departure_time(&self, route_idx: usize, trip_idx: usize, stop_idx: usize) -> Timestamp {
-        self.get_trip(route_idx, trip_idx)[stop_idx].departure_time
+        self.get_trip(route_idx, trip_idx).departure_times[stop_idx]
     }
 
     pub fn get_arrival_time(&self, route_idx: usize, trip_idx: usize, stop_idx: usize) -> Timestamp {
-        self.get_trip(route_idx, trip_idx)[stop_idx].arrival_time
+        self.get_trip(route_idx, trip_idx).arrival_times[stop_idx]
+    }
+
+    // The date and time of a timestamp on the network's date. Timestamps past midnight fall on later days.
+    pub fn datetime_for(&self, time: Timestamp) -> NaiveDateTime {
+        let (naive_time, days) = utils::timestamp_to_naive_time(time);
+        (self.date + Days::new(days as u64)).and_time(naive_time)
     }
 
+    // The height the route's shape is drawn at. See RouteHeights.
+    pub fn route_height(&self, route_idx: usize) -> CoordType { self.routes[route_idx].shape_height }
+
     pub fn num_stops(&self) -> usize { self.stops.len() }
 
     pub fn num_routes(&self) -> usize { self.routes.len() }
@@ -513,18 +2395,341 @@ impl Network {
 
     pub fn num_stops_in_route(&self, route_idx: usize) -> usize { self.routes[route_idx].num_stops as usize }
 
-    pub fn get_trip(&self, route_idx: usize, trip_idx: usize) -> &[StopTime] {
+    pub fn get_trip(&self, route_idx: usize, trip_idx: usize) -> TripStopTimes<'_> {
         let route = &self.routes[route_idx];
         route.get_trip(trip_idx, &self.stop_times)
     }
 
     pub fn get_trip_id(&self, trip_idx: GlobalTripIndex) -> &str {
-        let route = &self.routes[trip_idx.route_idx as usize];
-        route.trip_ids[trip_idx.trip_order as usize].as_ref()
+        let route = &self.routes[trip_idx.route().idx()];
+        &route.trip_ids[trip_idx.trip().idx()]
+    }
+
+    // Removes routes without trips and stops without routes (e.g. after filtering by date or route type), so that they don't
+    // take up space in every query or appear in stop search. Returns the mapping from old to new stop indices, which callers
+    // holding stop indices must use to remap them. Route indices only change if routes without trips are removed.
+    pub fn prune(&mut self) -> StopIndexMapping {
+        // Map the routes that have trips to their new indices.
+        let mut route_mapping = vec![None; self.routes.len()];
+        let mut num_kept_routes = 0;
+        for (route_idx, route) in self.routes.iter().enumerate() {
+            if route.num_trips > 0 {
+                route_mapping[route_idx] = Some(num_kept_routes as RouteIndex);
+                num_kept_routes += 1;
+            }
+        }
+
+        // Map the stops served by any remaining route to their new indices.
+        let mut is_served = vec![false; self.stops.len()];
+        for route in self.routes.iter().filter(|route| route.num_trips > 0) {
+            for &stop in route.get_stops(&self.route_stops) {
+                is_served[stop as usize] = true;
+            }
+        }
+        let new_to_old = (0..self.stops.len() as StopIndex).filter(|&stop| is_served[stop as usize]).collect::<Vec<_>>();
+        let mut old_to_new = vec![None; self.stops.len()];
+        for (new_stop, &old_stop) in new_to_old.iter().enumerate() {
+            old_to_new[old_stop as usize] = Some(new_stop as StopIndex);
+        }
+        let mapping = StopIndexMapping { old_to_new, new_to_old };
+        let remap_stop = |stop: StopIndex| mapping.to_new(stop).expect("Stop on a route with trips was pruned.");
+        let remap_route = |route_idx: RouteIndex| route_mapping[route_idx as usize].expect("Route with trips was pruned.");
+
+        // Compact the routes and the stops they visit. Routes without trips have no stop times, so stop times are unchanged.
+        let mut route_stops = Vec::with_capacity(self.route_stops.len());
+        let old_routes = std::mem::take(&mut self.routes);
+        for mut route in old_routes.into_iter().filter(|route| route.num_trips > 0) {
+            let stops = route.get_stops(&self.route_stops);
+            route.route_stops_idx = route_stops.len();
+            route_stops.extend(stops.iter().map(|&stop| remap_stop(stop)));
+            self.routes.push(route);
+        }
+        self.route_stops = route_stops;
+
+        // Compact the stops and their routes.
+        let mut stop_routes = Vec::with_capacity(self.stop_routes.len());
+        let old_stops = std::mem::take(&mut self.stops);
+        self.stop_names = self.stop_names.iter().enumerate().filter(|&(old_stop, _)| is_served[old_stop]).map(|(_, name)| name).collect();
+        self.stop_ids = self.stop_ids.iter().enumerate().filter(|&(old_stop, _)| is_served[old_stop]).map(|(_, id)| id).collect();
+        for (old_stop, mut stop) in old_stops.into_iter().enumerate() {
+            if !is_served[old_stop] {
+                continue;
+            }
+            let routes = stop.get_routes(&self.stop_routes);
+            stop.routes_idx = stop_routes.len();
+            stop_routes.extend(routes.iter().filter_map(|&route_idx| route_mapping[route_idx as usize]));
+            stop.num_routes = stop_routes.len() - stop.routes_idx;
+            self.stops.push(stop);
+        }
+        self.stop_routes = stop_routes;
+
+        // Compact everything else indexed by stop.
+        self.stop_index.retain(|_, stop| match mapping.to_new(*stop) {
+            Some(new_stop) => {
+                *stop = new_stop;
+                true
+            }
+            None => false,
+        });
+        self.stop_points = mapping.new_to_old.iter().map(|&stop| self.stop_points[stop as usize]).collect();
+        self.transfer_times = mapping.new_to_old.iter().map(|&stop| self.transfer_times[stop as usize]).collect();
+        if let Some(fares) = self.fares.as_mut() {
+            let stop_zones = mapping.new_to_old.iter().map(|&stop| fares.stop_zones.get(stop as usize).copied().unwrap_or(0)).collect();
+            fares.stop_zones = stop_zones;
+        }
+        self.pairwise_transfer_times = self
+            .pairwise_transfer_times
+            .iter()
+            .filter_map(|(&(from, to), &transfer_time)| Some(((mapping.to_new(from)?, mapping.to_new(to)?), transfer_time)))
+            .collect();
+
+        self.route_transfer_times = self
+            .route_transfer_times
+            .iter()
+            .filter_map(|(&(stop, from_route, to_route), &transfer_time)| {
+                Some(((mapping.to_new(stop)?, route_mapping[from_route as usize]?, route_mapping[to_route as usize]?), transfer_time))
+            })
+            .collect();
+
+        // Remapping preserves the stop order, so the footpaths stay sorted.
+        self.footpaths = self
+            .footpaths
+            .iter()
+            .filter_map(|footpath| Some(Footpath { from: mapping.to_new(footpath.from)?, to: mapping.to_new(footpath.to)?, duration: footpath.duration }))
+            .collect();
+
+        // The connection order doesn't depend on stop or route indices, so they can be remapped in place.
+        for connection in self.connections.iter_mut() {
+            connection.departure_idx = remap_stop(connection.departure_idx);
+            connection.arrival_idx = remap_stop(connection.arrival_idx);
+            connection.trip.route_idx = remap_route(connection.trip.route_idx);
+        }
+        self.stop_grid = OnceLock::new();
+        self.departure_columns = OnceLock::new();
+        self.normalised_stop_names = OnceLock::new();
+        self.stop_visits = OnceLock::new();
+
+        mapping
+    }
+
+    // Returns a copy of the network with only the trips whose first departure is in from..to, and routes left without trips
+    // removed. If include_in_progress, trips that depart earlier but are still running at from are also kept. Stop indices are
+    // unchanged (prune can remove stops left without service), but route indices may change. Connections are rebuilt if they
+    // were built on this network.
+    pub fn restrict_to_window(&self, from: Timestamp, to: Timestamp, include_in_progress: bool) -> Network {
+        let mut routes = Vec::new();
+        let mut route_mapping = vec![None; self.routes.len()];
+        let mut route_stops = Vec::new();
+        let mut stop_times = StopTimes::default();
+        for (route_idx, route) in self.routes.iter().enumerate() {
+            let trip_orders = (0..route.num_trips as usize)
+                .filter(|&trip_order| {
+                    let trip = route.get_trip(trip_order, &self.stop_times);
+                    let first_departure = trip.departure_times[0];
+                    (from..to).contains(&first_departure) || (include_in_progress && first_departure < from && trip.last().unwrap().arrival_time > from)
+                })
+                .collect::<Vec<_>>();
+            if trip_orders.is_empty() {
+                continue;
+            }
+
+            route_mapping[route_idx] = Some(routes.len() as RouteIndex);
+            routes.push(Route {
+                line: route.line.clone(),
+                num_stops: route.num_stops,
+                num_trips: trip_orders.len() as TripOrder,
+                route_stops_idx: route_stops.len(),
+                stop_times_idx: stop_times.len(),
+                trip_ids: trip_orders.iter().map(|&trip_order| &route.trip_ids[trip_order]).collect(),
+                colour: route.colour,
+                route_type: route.route_type,
+                shape: route.shape.clone(),
+                shape_id: route.shape_id.clone(),
+                num_shape_mismatches: route.num_shape_mismatches,
+                cancelled_trips: trip_orders.iter().map(|&trip_order| route.cancelled_trips[trip_order]).collect(),
+                skipped_stops: trip_orders.iter().flat_map(|&trip_order| (0..route.num_stops as usize).map(move |stop_order| route.is_skipped(trip_order, stop_order))).collect(),
+                shape_height: route.shape_height,
+            });
+            route_stops.extend_from_slice(route.get_stops(&self.route_stops));
+            for &trip_order in trip_orders.iter() {
+                stop_times.extend(route.get_trip(trip_order, &self.stop_times).iter());
+            }
+        }
+
+        let mut stop_routes = Vec::new();
+        let stops = self
+            .stops
+            .iter()
+            .map(|stop| {
+                let routes_idx = stop_routes.len();
+                stop_routes.extend(stop.get_routes(&self.stop_routes).iter().filter_map(|&route_idx| route_mapping[route_idx as usize]));
+                Stop { routes_idx, num_routes: stop_routes.len() - routes_idx }
+            })
+            .collect();
+        let route_transfer_times = self
+            .route_transfer_times
+            .iter()
+            .filter_map(|(&(stop, from_route, to_route), &transfer_time)| {
+                Some(((stop, route_mapping[from_route as usize]?, route_mapping[to_route as usize]?), transfer_time))
+            })
+            .collect();
+
+        let mut network = Network {
+            num_trips: routes.iter().map(|route| route.num_trips).sum(),
+            routes,
+            stops,
+            stop_names: self.stop_names.clone(),
+            stop_ids: self.stop_ids.clone(),
+            stop_index: self.stop_index.clone(),
+            stop_times,
+            stop_routes,
+            route_stops,
+            stop_points: self.stop_points.clone(),
+            connections: Vec::new(),
+            connection_buckets: Vec::new(),
+            transfer_times: self.transfer_times.clone(),
+            pairwise_transfer_times: self.pairwise_transfer_times.clone(),
+            route_transfer_times,
+            footpaths: self.footpaths.clone(),
+            fares: self.fares.clone(),
+            default_transfer_time: self.default_transfer_time,
+            date: self.date,
+            timezone: self.timezone.clone(),
+            has_shapes: self.has_shapes,
+            stop_grid: OnceLock::new(),
+            departure_columns: OnceLock::new(),
+            normalised_stop_names: OnceLock::new(),
+            stop_visits: OnceLock::new(),
+        };
+        if !self.connections.is_empty() {
+            network.build_connections();
+        }
+        network
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        let shape_points = self.routes.iter().map(|route| route.shape.len()).sum::<usize>();
+        let shapes_bytes = shape_points * size_of::<NetworkPoint>();
+        MemoryReport {
+            components: vec![
+                MemoryUsage::of_stop_times(&self.stop_times),
+                MemoryUsage::of_vec("connections", &self.connections),
+                MemoryUsage::of_vec("connection_buckets", &self.connection_buckets),
+                MemoryUsage::of_vec("route_stops", &self.route_stops),
+                MemoryUsage::of_vec("stop_routes", &self.stop_routes),
+                MemoryUsage::of_vec("stop_points", &self.stop_points),
+                MemoryUsage::of_vec("transfer_times", &self.transfer_times),
+                // Shapes are boxed slices, so have no spare capacity.
+                MemoryUsage { name: "shapes", len: shape_points, used_bytes: shapes_bytes, capacity_bytes: shapes_bytes },
+                MemoryUsage::of_strings("trip_ids", self.routes.iter().map(|route| &route.trip_ids)),
+                MemoryUsage::of_strings("stop_names", [&self.stop_names]),
+                MemoryUsage::of_strings("stop_ids", [&self.stop_ids]),
+            ],
+        }
+    }
+
+    // Frees unused capacity in the network's arrays, many of which are built by pushing.
+    pub fn shrink_to_fit(&mut self) {
+        self.stop_times.shrink_to_fit();
+        self.connections.shrink_to_fit();
+        self.connection_buckets.shrink_to_fit();
+        self.route_stops.shrink_to_fit();
+        self.stop_routes.shrink_to_fit();
+        self.stop_points.shrink_to_fit();
+        self.transfer_times.shrink_to_fit();
+        self.pairwise_transfer_times.shrink_to_fit();
+        self.route_transfer_times.shrink_to_fit();
+        self.footpaths.shrink_to_fit();
+        self.stop_index.shrink_to_fit();
+        for route in self.routes.iter_mut() {
+            route.trip_ids.shrink_to_fit();
+        }
+        self.routes.shrink_to_fit();
+        self.stops.shrink_to_fit();
+    }
+
+    pub fn stats(&self) -> NetworkStats {
+        let memory_estimate_bytes = self.memory_report().total_capacity_bytes();
+        NetworkStats {
+            num_stops: self.stops.len(),
+            num_routes: self.routes.len(),
+            num_trips: self.num_trips as usize,
+            num_stop_times: self.stop_times.len(),
+            num_connections: self.connections.len(),
+            num_stops_with_no_service: self.stops.iter().filter(|stop| stop.num_routes == 0).count(),
+            date: self.date,
+            service_span: self.stop_times.departure_times.iter().min().copied().zip(self.stop_times.arrival_times.iter().max().copied()),
+            has_shapes: self.has_shapes,
+            memory_estimate_bytes,
+        }
     }
 
     pub fn print_stats(&self) {
-        log::info!("Network has {} stops, {} routes, {} trips and {} connections.", self.stops.len(), self.routes.len(), self.num_trips, self.connections.len());
+        log::info!("{}", self.stats());
+    }
+}
+
+// Indexes the routes serving each stop, setting each stop's routes_idx and num_routes, and returns the stop routes array.
+fn index_stop_routes(stops: &mut [Stop], routes: &[Route], route_stops: &[StopIndex]) -> Vec<RouteIndex> {
+    let mut stop_routes_map = vec![Vec::new(); stops.len()];
+    for (route_idx, route) in routes.iter().enumerate() {
+        for &stop in route.get_stops(route_stops) {
+            stop_routes_map[stop as usize].push(route_idx as RouteIndex);
+        }
+    }
+
+    let mut stop_routes = Vec::new();
+    for (stop_idx, stop) in stops.iter_mut().enumerate() {
+        stop.routes_idx = stop_routes.len();
+        for &route_idx in stop_routes_map[stop_idx].iter() {
+            stop_routes.push(route_idx);
+        }
+        stop.num_routes = stop_routes.len() - stop.routes_idx;
+    }
+    stop_routes
+}
+
+// The line name of a GTFS route: its short name, or else its long name or ID.
+#[cfg(feature = "gtfs")]
+pub(crate) fn gtfs_line_name(route: &gtfs_structures::Route) -> &str {
+    route.short_name.as_deref().or(route.long_name.as_deref()).unwrap_or(&route.id)
+}
+
+// The GTFS route_type code of a route type, using the extended codes for the types gtfs_structures names beyond the basic ones.
+#[cfg(feature = "gtfs")]
+fn gtfs_route_type_code(route_type: RouteType) -> i16 {
+    match route_type {
+        RouteType::Tramway => 0,
+        RouteType::Subway => 1,
+        RouteType::Rail => 2,
+        RouteType::Bus => 3,
+        RouteType::Ferry => 4,
+        RouteType::CableCar => 5,
+        RouteType::Gondola => 6,
+        RouteType::Funicular => 7,
+        RouteType::Coach => 200,
+        RouteType::Air => 1100,
+        RouteType::Taxi => 1500,
+        RouteType::Other(code) => code,
+    }
+}
+
+// Returns the most common shape ID (ties broken by the smallest ID), and the number of trips without it. None if no trip has a shape.
+#[cfg(feature = "gtfs")]
+fn most_common_shape_id<'a>(shape_ids: impl Iterator<Item = Option<&'a str>>) -> (Option<&'a str>, usize) {
+    let mut counts = HashMap::<Option<&str>, usize>::new();
+    let mut num_trips = 0;
+    for shape_id in shape_ids {
+        *counts.entry(shape_id).or_default() += 1;
+        num_trips += 1;
+    }
+    let most_common = counts
+        .iter()
+        .filter_map(|(&shape_id, &count)| Some((shape_id?, count)))
+        .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)));
+    match most_common {
+        Some((shape_id, count)) => (Some(shape_id), num_trips - count),
+        None => (None, 0),
     }
 }
 
@@ -532,6 +2737,653 @@ impl Network {
 mod tests {
     use super::*;
 
+    // A network over stops named "Stop i" with ID i, at the given points. Each route lists its stops and the ID and first stop
+    // departure time of each trip, and trips take two minutes between stops with no dwell time. Every stop has the given transfer
+    // time.
+    fn timetable_network(stop_points: &[NetworkPoint], routes: Vec<(&str, &[StopIndex], Vec<(String, Timestamp)>)>, transfer_time: Timestamp) -> Network {
+        let names = (0..stop_points.len()).map(|i| (format!("Stop {i}"), i.to_string())).collect::<Vec<_>>();
+        let stops = names.iter().zip(stop_points).map(|((name, id), &point)| (name.as_str(), id.as_str(), point)).collect::<Vec<_>>();
+        let routes = routes.into_iter().map(|(line, stops, trips)| TimetableRoute {
+            line: line.to_string(),
+            colour: RGB8::default(),
+            stops: stops.to_vec(),
+            trips: trips.into_iter().map(|(trip_id, start)| {
+                let stop_times = (0..stops.len() as Timestamp).map(|stop_order| {
+                    let time = start + stop_order * 120;
+                    StopTime { arrival_time: time, departure_time: time }
+                });
+                (trip_id, stop_times.collect())
+            }).collect(),
+        }).collect();
+        Network::from_timetable(&stops, routes, NaiveDate::default(), transfer_time)
+    }
+
+    // A network with a single route over the given stop points, with the given shape. Trips leave the first stop at each of
+    // trip_starts (which must be sorted), and take two minutes between stops with no dwell time.
+    fn single_route_network(stop_points: Vec<NetworkPoint>, shape: Vec<NetworkPoint>, trip_starts: &[Timestamp]) -> Network {
+        let stops = (0..stop_points.len() as StopIndex).collect::<Vec<_>>();
+        let trips = trip_starts.iter().enumerate().map(|(i, &start)| (i.to_string(), start)).collect();
+        let mut network = timetable_network(&stop_points, vec![("Test", &stops[..], trips)], 0);
+        network.routes[0].shape = shape.into_boxed_slice();
+        network.has_shapes = true;
+        network
+    }
+
+    // A network of named routes over stops 0..num_stops. Each route lists its stops and the times its trips leave the first stop
+    // (which must be sorted), and trips take two minutes between stops. Every stop has the given transfer time.
+    fn multi_route_network(num_stops: usize, routes: &[(&str, &[StopIndex], &[Timestamp])], transfer_time: Timestamp) -> Network {
+        let routes = routes.iter().map(|&(line, stops, trip_starts)| {
+            (line, stops, trip_starts.iter().enumerate().map(|(i, &start)| (format!("{line} {i}"), start)).collect())
+        }).collect();
+        timetable_network(&vec![point(-37.80, 145.00); num_stops], routes, transfer_time)
+    }
+
+    fn point(latitude: CoordType, longitude: CoordType) -> NetworkPoint {
+        NetworkPoint { latitude, longitude }
+    }
+
+    fn assert_same_points(a: &[NetworkPoint], b: &[NetworkPoint]) {
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert_eq!((a.latitude, a.longitude), (b.latitude, b.longitude));
+        }
+    }
+
+    #[test]
+    fn headways() {
+        // Ten minute headways, with an extra peak trip inserted at 08:05.
+        let mut trip_starts = (0..6).map(|i| 8 * 3600 + i * 600).collect::<Vec<_>>();
+        trip_starts.push(8 * 3600 + 300);
+        trip_starts.sort_unstable();
+        let stop_points = vec![point(-37.80, 145.00), point(-37.80, 145.01), point(-37.80, 145.02)];
+        let network = single_route_network(stop_points, Vec::new(), &trip_starts);
+
+        let all_day = 0..24 * 3600;
+        let headways = network.route_headways(0, 0, all_day.clone());
+        assert_eq!(headways, [300, 300, 600, 600, 600, 600]);
+        assert_eq!(HeadwaySummary::new(&headways), Some(HeadwaySummary { min: 300, median: 600, max: 600 }));
+
+        // Later stops have the same headways, shifted by the travel time.
+        assert_eq!(network.route_headways(0, 1, all_day.clone()), headways);
+
+        // Restricting the window to after the peak.
+        assert_eq!(network.route_headways(0, 0, 8 * 3600 + 600..9 * 3600), [600, 600, 600, 600]);
+        assert!(network.route_headways(0, 0, 7 * 3600..8 * 3600).is_empty());
+        assert_eq!(HeadwaySummary::new(&[]), None);
+
+        // The final stop has no departures.
+        assert_eq!(network.stop_service_frequency(0, all_day.clone()), 7);
+        assert_eq!(network.stop_service_frequency(0, 8 * 3600..8 * 3600 + 600), 2);
+        assert_eq!(network.stop_service_frequency(2, all_day), 0);
+    }
+
+    #[test]
+    fn adjacency_graph() {
+        let stop_points = vec![point(-37.80, 145.00), point(-37.80, 145.01), point(-37.80, 145.02)];
+        let network = single_route_network(stop_points, Vec::new(), &[8 * 3600, 8 * 3600 + 600, 8 * 3600 + 1200]);
+        let graph = network.adjacency_graph();
+
+        assert_eq!(graph.num_stops, 3);
+        assert_eq!(graph.edges, [
+            StopEdge { from: 0, to: 1, min_travel_time: 120, num_trips: 3, routes: vec![0] },
+            StopEdge { from: 1, to: 2, min_travel_time: 120, num_trips: 3, routes: vec![0] },
+        ]);
+        assert_eq!(graph.out_edges(1).len(), 1);
+        assert!(graph.out_edges(2).is_empty());
+
+        let mut csv = Vec::new();
+        graph.write_csv(&network, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.lines().nth(1).unwrap().starts_with(r#""0","Stop 0","1","Stop 1",120,3,"Test""#));
+
+        let mut dot = Vec::new();
+        graph.write_dot(&network, &mut dot).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("digraph"));
+        assert!(dot.contains("0 -> 1"));
+        assert!(dot.contains("1 -> 2"));
+    }
+
+    #[test]
+    fn route_graph_dot() {
+        let mut network = multi_route_network(4, &[("Red", &[0, 1, 2], &[8 * 3600, 9 * 3600]), ("Blue", &[3, 2], &[8 * 3600])], 60);
+        network.routes[0].colour = RGB8::new(255, 0, 0);
+        let write_dot = |options: &DotOptions| {
+            let mut dot = Vec::new();
+            network.write_dot(&mut dot, options).unwrap();
+            String::from_utf8(dot).unwrap()
+        };
+
+        let dot = write_dot(&DotOptions::default());
+        assert!(dot.starts_with("digraph routes {"));
+        assert!(dot.contains(r#"    0 [label="Stop 0"];"#));
+        assert!(dot.contains(r##"    0 -> 1 [label="Red (2 trips)", color="#ff0000"];"##));
+        assert!(dot.contains(r#"    1 -> 2 [label="Red (2 trips)""#));
+        assert!(dot.contains(r#"    3 -> 2 [label="Blue (1 trips)""#));
+
+        let dot = write_dot(&DotOptions { lines: Some(vec!["Blue".to_string()]), ..Default::default() });
+        assert!(dot.contains("3 -> 2") && !dot.contains("0 -> 1"));
+        assert!(!dot.contains("    0 [label"));
+
+        // Stop 0 is only on the red line.
+        let dot = write_dot(&DotOptions { around_stop: Some(0), ..Default::default() });
+        assert!(dot.contains("0 -> 1") && !dot.contains("3 -> 2"));
+        assert!(dot.ends_with("}\n"));
+
+        // Names are escaped the same way as in StopGraph::write_dot.
+        network.stop_names = ["Stop \"0\"", "Stop 1", "Stop 2", "Stop 3"].into_iter().collect();
+        let mut dot = Vec::new();
+        network.write_dot(&mut dot, &DotOptions::default()).unwrap();
+        assert!(String::from_utf8(dot).unwrap().contains(r#"    0 [label="Stop \"0\""];"#));
+    }
+
+    #[test]
+    fn stop_times_are_split_by_field() {
+        let network = multi_route_network(3, &[("Red", &[0, 1, 2], &[8 * 3600, 9 * 3600])], 60);
+        assert_eq!(network.stop_times.len(), 6);
+        assert_eq!(network.stop_times.arrival_times.len(), network.stop_times.departure_times.len());
+
+        let trip = network.routes[0].get_trip(1, &network.stop_times);
+        assert_eq!(trip.len(), 3);
+        assert_eq!(trip.departure_times, &[9 * 3600, 9 * 3600 + 120, 9 * 3600 + 240]);
+        assert_eq!(trip.last().unwrap().arrival_time, 9 * 3600 + 240);
+        assert_eq!(trip.iter().map(|stop_time| stop_time.departure_time).collect::<Vec<_>>(), trip.departure_times);
+        assert_eq!(network.stop_times.get(network.routes[0].get_stop_times_index(1, 2)).arrival_time, trip.get(2).arrival_time);
+    }
+
+    #[test]
+    fn connection_buckets_match_binary_search() {
+        let mut network = multi_route_network(4, &[("Red", &[0, 1, 2], &[8 * 3600, 8 * 3600 + 45, 9 * 3600]), ("Blue", &[3, 2], &[8 * 3600 + 30])], 60);
+        network.build_connections();
+        let check = |network: &Network| {
+            for time in (7 * 3600..10 * 3600).step_by(15) {
+                assert_eq!(network.first_connection_at_or_after(time), network.connections.partition_point(|connection| connection.departure_time < time), "{time}");
+            }
+        };
+        check(&network);
+        assert!(network.connections_departing_between(8 * 3600..8 * 3600 + 60).iter().all(|connection| connection.departure_time < 8 * 3600 + 60));
+        assert_eq!(network.connections_departing_between(8 * 3600..8 * 3600 + 60).len(), 3);
+
+        network.add_trip(0, &[(10 * 3600, 10 * 3600), (10 * 3600 + 120, 10 * 3600 + 120), (10 * 3600 + 240, 10 * 3600 + 240)], "late").unwrap();
+        check(&network);
+        assert_eq!(network.first_connection_at_or_after(10 * 3600), network.connections.len() - 2);
+        network.apply_delay(GlobalTripIndex::new(RouteIdx(0), TripIdx(0)), 1, 90).unwrap();
+        check(&network);
+        network.cancel_trip(GlobalTripIndex::new(RouteIdx(1), TripIdx(0))).unwrap();
+        check(&network);
+        assert_eq!(network.first_connection_at_or_after(11 * 3600), network.connections.len());
+    }
+
+    #[test]
+    fn prune_removes_serviceless_stops() {
+        let stop_points = vec![point(-37.80, 145.00), point(-37.80, 145.01), point(-37.80, 145.02)];
+        let mut network = single_route_network(stop_points, Vec::new(), &[8 * 3600, 8 * 3600 + 600]);
+
+        // Add a stop without service before the route's stops.
+        network.stops.insert(0, Stop::default());
+        network.stop_names.insert(0, "Closed");
+        network.stop_ids.insert(0, "closed");
+        for stop in network.route_stops.iter_mut() {
+            *stop += 1;
+        }
+        for stop in network.stop_index.values_mut() {
+            *stop += 1;
+        }
+        network.stop_index.insert("closed".to_string(), 0);
+        network.stop_points.insert(0, point(-37.70, 145.00));
+        network.transfer_times = vec![0, 60, 120, 180];
+        network.pairwise_transfer_times.insert((0, 1), 30);
+        network.pairwise_transfer_times.insert((2, 2), 90);
+        network.build_connections();
+        let connections_before = network.connections.clone();
+
+        let mapping = network.prune();
+        assert_eq!(mapping.num_removed(), 1);
+        assert_eq!(mapping.to_new(0), None);
+        for old_stop in 1..4 {
+            let new_stop = mapping.to_new(old_stop).unwrap();
+            assert_eq!(new_stop, old_stop - 1);
+            assert_eq!(mapping.to_old(new_stop), old_stop);
+        }
+
+        assert_eq!(network.stops.len(), 3);
+        assert_eq!(network.stop_names.iter().collect::<Vec<_>>(), ["Stop 0", "Stop 1", "Stop 2"]);
+        assert!(network.stops.iter().all(|stop| stop.num_routes == 1));
+        assert_eq!(network.route_stops, [0, 1, 2]);
+        assert_eq!(network.try_get_stop_idx("closed"), None);
+        assert_eq!(network.get_stop_idx("1"), 1);
+        assert_eq!(network.stop_points[0].latitude, -37.80);
+        assert_eq!(network.transfer_times, [60, 120, 180]);
+        assert_eq!(network.pairwise_transfer_times, HashMap::from([((1, 1), 90)]));
+        assert!(network.connections.iter().zip(connections_before.iter()).all(|(after, before)| {
+            after.departure_idx + 1 == before.departure_idx && after.arrival_idx + 1 == before.arrival_idx && after.departure_time == before.departure_time
+        }));
+        assert_eq!(network.nearest_stops(point(-37.70, 145.00), 1)[0].0, 0);
+
+        // Pruning again changes nothing.
+        assert_eq!(network.prune().num_removed(), 0);
+    }
+
+    #[test]
+    fn stop_route_info_at_termini() {
+        let stop_points = vec![point(-37.80, 145.00), point(-37.80, 145.01), point(-37.80, 145.02)];
+        let network = single_route_network(stop_points, Vec::new(), &[8 * 3600]);
+
+        let info = network.stop_route_info(0);
+        assert_eq!(info.len(), 1);
+        assert_eq!((info[0].stop_order, info[0].next_stop, info[0].destination), (0, Some(1), 2));
+        assert!(info[0].is_first && !info[0].is_last);
+
+        let info = network.stop_route_info(1);
+        assert_eq!((info[0].stop_order, info[0].next_stop), (1, Some(2)));
+        assert!(!info[0].is_first && !info[0].is_last);
+
+        let info = network.stop_route_info(2);
+        assert_eq!((info[0].stop_order, info[0].next_stop, info[0].destination), (2, None, 2));
+        assert!(!info[0].is_first && info[0].is_last);
+        assert_eq!(&*info[0].line, "Test");
+    }
+
+    #[test]
+    fn service_span() {
+        let stop_points = vec![point(-37.80, 145.00), point(-37.80, 145.01), point(-37.80, 145.02)];
+        let mut network = single_route_network(stop_points, Vec::new(), &[12 * 3600, 6 * 3600, 23 * 3600]);
+        network.stops.push(Stop::default());
+        network.stop_names.push("Closed");
+        network.stop_ids.push("closed");
+
+        assert_eq!(network.service_span(0), Some((6 * 3600, 23 * 3600)));
+        assert_eq!(network.service_span(2), Some((6 * 3600 + 240, 23 * 3600 + 240)));
+        assert_eq!(network.service_span(3), None);
+
+        assert!(network.has_service_between(1, 22 * 3600, 24 * 3600));
+        assert!(network.has_service_between(1, 12 * 3600 + 120, 12 * 3600 + 121));
+        assert!(!network.has_service_between(1, 12 * 3600 + 121, 23 * 3600 + 120));
+        assert!(!network.has_service_between(1, 0, 6 * 3600 + 120));
+        assert!(!network.has_service_between(3, 0, Timestamp::MAX));
+    }
+
+    #[test]
+    fn station_groups() {
+        let stop_points = vec![point(-37.8240, 144.9900), point(-37.8244, 144.9902), point(-37.9000, 145.1000), point(-37.8270, 145.0080)];
+        let mut network = single_route_network(stop_points, Vec::new(), &[8 * 3600]);
+        network.stop_names = ["Richmond Railway Station (Platform 1/2)", "Richmond Railway Station (Platform 3/4)", "Richmond", "Burnley"].into_iter().collect();
+
+        let groups = network.station_groups();
+        assert_eq!(groups.iter().map(|group| (&*group.name, group.stops.clone())).collect::<Vec<_>>(), [
+            ("Richmond", vec![0, 1]),
+            ("Richmond", vec![2]),
+            ("Burnley", vec![3]),
+        ]);
+        assert_eq!(network.stops_in_station("richmond"), [0, 1]);
+        assert_eq!(network.stops_in_station("Richmond Railway Station (Platform 3/4)"), [0, 1]);
+        assert_eq!(network.stops_in_station("Burnley"), [3]);
+        assert!(network.stops_in_station("Not A Station").is_empty());
+    }
+
+    #[test]
+    fn route_transfer_times() {
+        // A bus (from stop 0) and a feeder train (from stop 1) both reach the interchange (stop 2) at 08:10, where trains to
+        // stop 3 leave at 08:13 and 08:30.
+        let mut network = multi_route_network(4, &[
+            ("Bus", &[0, 2], &[8 * 3600 + 480]),
+            ("Feeder", &[1, 2], &[8 * 3600 + 480]),
+            ("Train", &[2, 3], &[8 * 3600 + 780, 8 * 3600 + 1800]),
+        ], 60);
+        assert_eq!(network.set_route_transfer_time("2", "bus", "Train", 6 * 60), 1);
+        assert_eq!(network.try_set_route_transfer_time("2", "Not A Line", "Train", 0), Ok(0));
+        assert!(network.try_set_route_transfer_time("not a stop", "Bus", "Train", 0).is_err());
+        assert_eq!(network.get_route_transfer_time(2, Some(0), 2), 6 * 60);
+        assert_eq!(network.get_route_transfer_time(2, Some(1), 2), 60);
+        assert_eq!(network.get_route_transfer_time(2, None, 2), 60);
+        network.build_connections();
+
+        let query_time = 8 * 3600;
+        for (start, expected_arrival) in [(0, 8 * 3600 + 1920), (1, 8 * 3600 + 900)] {
+            let raptor_journey = crate::raptor_query(&network, StopIdx(start), query_time, StopIdx(3)).unwrap();
+            let csa_journey = crate::csa_query(&network, StopIdx(start), query_time, StopIdx(3)).unwrap();
+            assert_eq!(raptor_journey.arrival_time(), Some(expected_arrival), "{raptor_journey:?}");
+            assert_eq!(csa_journey.arrival_time(), Some(expected_arrival), "{csa_journey:?}");
+        }
+
+        // The bus transfer leg reports the overridden transfer time.
+        let journey = crate::raptor_query(&network, StopIdx(0), query_time, StopIdx(3)).unwrap();
+        assert!(journey.legs.iter().any(|leg| matches!(leg, crate::Leg::Transfer { at_stop: 2, transfer_time: 360, wait_time: 840 })));
+    }
+
+    #[test]
+    #[cfg(feature = "gtfs")]
+    fn most_common_shape_id_per_route() {
+        // The full-length pattern, and a short-working pattern with its own shorter shape (and one trip tagged with the full one).
+        let full = [Some("full"), Some("full"), Some("full")];
+        let short = [Some("short"), Some("full"), Some("short")];
+        assert_eq!(most_common_shape_id(full.into_iter()), (Some("full"), 0));
+        assert_eq!(most_common_shape_id(short.into_iter()), (Some("short"), 1));
+
+        // Trips without shapes count as mismatches, and ties pick the smallest ID.
+        assert_eq!(most_common_shape_id([None, Some("b"), Some("a")].into_iter()), (Some("a"), 2));
+        assert_eq!(most_common_shape_id([None, None].into_iter()), (None, 0));
+        assert_eq!(most_common_shape_id(std::iter::empty()), (None, 0));
+    }
+
+    #[test]
+    fn stops_between() {
+        let network = multi_route_network(6, &[("Line", &[0, 1, 2, 3], &[8 * 3600]), ("Loop", &[4, 1, 2, 4, 5], &[8 * 3600])], 0);
+
+        assert_eq!(network.stops_between(0, 0, 3), Some(&[0, 1, 2, 3][..]));
+        assert_eq!(network.stops_between(0, 1, 2), Some(&[1, 2][..]));
+        assert_eq!(network.stops_between(0, 2, 2), Some(&[2][..]));
+
+        // Wrong direction and missing stops.
+        assert_eq!(network.stops_between(0, 3, 0), None);
+        assert_eq!(network.stops_between(0, 0, 4), None);
+        assert_eq!(network.stops_between(0, 5, 1), None);
+
+        // Loops take the first visit at or after the start.
+        assert_eq!(network.stops_between(1, 4, 4), Some(&[4][..]));
+        assert_eq!(network.stops_between(1, 1, 4), Some(&[1, 2, 4][..]));
+        assert_eq!(network.stops_between(1, 2, 5), Some(&[2, 4, 5][..]));
+    }
+
+    #[test]
+    fn twelve_hour_output() {
+        let network = multi_route_network(3, &[("Day", &[0, 1], &[11 * 3600 + 50 * 60]), ("Night", &[1, 2], &[23 * 3600 + 59 * 60])], 60);
+
+        let journey = crate::raptor_query(&network, StopIdx(0), 11 * 3600 + 45 * 60, StopIdx(2)).unwrap();
+        assert_eq!(
+            journey.format(crate::ItineraryStyle::Compact, TimeFormat::H12),
+            "11:50 am Stop 0 → 11:52 am Stop 1 (Day)\n\
+             11:59 pm Stop 1 → 12:01 am (+1 day) Stop 2 (Night)\n"
+        );
+        assert_eq!(
+            journey.format(crate::ItineraryStyle::Compact, TimeFormat::H24NoSeconds),
+            "11:50 Stop 0 → 11:52 Stop 1 (Day)\n\
+             23:59 Stop 1 → 00:01 (+1 day) Stop 2 (Night)\n"
+        );
+
+        assert_eq!(
+            network.format_departures(1, 23 * 3600, 5, TimeFormat::H12),
+            "11:59 pm  Night to Stop 2 (in 59m)\n"
+        );
+        assert_eq!(network.format_departures(2, 0, 5, TimeFormat::H12), "No departures.\n");
+    }
+
+    #[test]
+    fn typed_accessors() {
+        let network = multi_route_network(4, &[("Line", &[0, 1, 2], &[8 * 3600, 9 * 3600]), ("Branch", &[3, 1], &[8 * 3600])], 60);
+
+        assert_eq!(network.stops_of(RouteIdx(1)).collect::<Vec<_>>(), vec![StopIdx(3), StopIdx(1)]);
+        assert_eq!(network.routes_at(StopIdx(1)).collect::<Vec<_>>(), vec![RouteIdx(0), RouteIdx(1)]);
+        assert_eq!(network.routes_at(StopIdx(2)).collect::<Vec<_>>(), vec![RouteIdx(0)]);
+        assert_eq!(network.stop_id(StopIdx(3).idx()), "3");
+        assert_eq!(network.transfer_time(StopIdx(3)), 60);
+
+        let trip = GlobalTripIndex::new(RouteIdx(0), TripIdx(1));
+        assert_eq!(trip, GlobalTripIndex::new(RouteIdx(0), TripIdx(1)));
+        assert_eq!((trip.route(), trip.trip()), (RouteIdx(0), TripIdx(1)));
+        let departures = network.trip(trip).iter().map(|stop_time| stop_time.departure_time).collect::<Vec<_>>();
+        assert_eq!(departures, vec![9 * 3600, 9 * 3600 + 120, 9 * 3600 + 240]);
+    }
+
+    #[test]
+    fn from_timetable() {
+        let stop_time = |time: Timestamp| StopTime { arrival_time: time, departure_time: time };
+        let stops = [("A", "a", point(-37.80, 145.00)), ("B", "b", point(-37.80, 145.01)), ("C", "c", point(-37.80, 145.02))];
+        let network = Network::from_timetable(&stops, vec![
+            TimetableRoute {
+                line: String::from("Up"),
+                colour: RGB8::new(255, 0, 0),
+                stops: vec![0, 1, 2],
+                // Out of order, to check trips are sorted.
+                trips: vec![
+                    (String::from("late"), vec![stop_time(9 * 3600), stop_time(9 * 3600 + 60), stop_time(9 * 3600 + 120)]),
+                    (String::from("early"), vec![stop_time(8 * 3600), stop_time(8 * 3600 + 60), stop_time(8 * 3600 + 120)]),
+                ],
+            },
+            TimetableRoute { line: String::from("Down"), colour: RGB8::new(0, 0, 255), stops: vec![2, 0], trips: vec![(String::from("down"), vec![stop_time(8 * 3600), stop_time(8 * 3600 + 300)])] },
+        ], NaiveDate::default(), 60);
+
+        assert_eq!(network.num_trips, 3);
+        assert_eq!(network.get_stop_idx("c"), 2);
+        assert_eq!(&network.routes[0].trip_ids[0], "early");
+        assert_eq!(network.get_stop_in_route(1, 0), 2);
+        assert_eq!(network.stops_of(RouteIdx(1)).collect::<Vec<_>>(), vec![StopIdx(2), StopIdx(0)]);
+        assert_eq!(network.routes_at(StopIdx(1)).collect::<Vec<_>>(), vec![RouteIdx(0)]);
+        assert_eq!(network.routes_at(StopIdx(2)).collect::<Vec<_>>(), vec![RouteIdx(0), RouteIdx(1)]);
+        assert_eq!(network.transfer_times, vec![60; 3]);
+        assert!(network.routes[0].shape_height != network.routes[1].shape_height);
+
+        let journey = crate::raptor_query(&network, StopIdx(0), 8 * 3600 + 30, StopIdx(2)).unwrap();
+        assert_eq!(journey.arrival_time(), Some(9 * 3600 + 120));
+    }
+
+    #[test]
+    fn stop_order_in_route() {
+        let network = multi_route_network(6, &[("Line", &[0, 1, 2, 3], &[8 * 3600]), ("Loop", &[4, 1, 2, 4, 5], &[8 * 3600])], 0);
+
+        assert_eq!(network.stop_order_in_route(0, 2, 0), Some(2));
+        assert_eq!(network.stop_order_in_route(0, 2, 2), Some(2));
+        assert_eq!(network.stop_order_in_route(0, 2, 3), None);
+        assert_eq!(network.stop_order_in_route(0, 3, 10), None);
+
+        // Stop 4 is visited twice by the loop.
+        assert_eq!(network.stop_order_in_route(1, 4, 0), Some(0));
+        assert_eq!(network.stop_order_in_route(1, 4, 1), Some(3));
+        assert_eq!(network.stop_order_in_route(1, 4, 4), None);
+        assert_eq!(network.stop_order_in_route(1, 5, 0), Some(4));
+
+        // Stops not on the route.
+        assert_eq!(network.stop_order_in_route(0, 4, 0), None);
+        assert_eq!(network.stop_order_in_route(1, 0, 0), None);
+
+        // The stop visit table agrees with scanning the route's stops.
+        for route_idx in 0..network.routes.len() {
+            let stops = network.routes[route_idx].get_stops(&network.route_stops);
+            for stop in 0..network.stops.len() as StopIndex {
+                for min_order in 0..6 {
+                    let scanned = stops.iter().enumerate().skip(min_order).find(|&(_, &visited)| visited == stop).map(|(stop_order, _)| stop_order);
+                    assert_eq!(network.stop_order_in_route(route_idx, stop, min_order), scanned);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn add_trip() {
+        let mut network = multi_route_network(4, &[
+            ("Early", &[3, 2], &[7 * 3600]),
+            ("Line", &[0, 1, 2], &[8 * 3600, 8 * 3600 + 1800]),
+            ("Late", &[2, 3], &[9 * 3600]),
+        ], 0);
+        network.build_connections();
+
+        assert_eq!(network.add_trip(5, &[], "Extra"), Err(AddTripError::UnknownRoute(5)));
+        assert_eq!(network.add_trip(1, &[(0, 0)], "Extra"), Err(AddTripError::WrongLength { expected: 3, actual: 1 }));
+        assert_eq!(network.add_trip(1, &[(8 * 3600, 8 * 3600), (8 * 3600 + 120, 8 * 3600 + 60), (8 * 3600 + 240, 8 * 3600 + 240)], "Extra"), Err(AddTripError::NotMonotonic(1)));
+        assert_eq!(network.num_trips, 4);
+
+        // A trip between the two existing services.
+        let extra_start = 8 * 3600 + 900;
+        let stop_times = [(extra_start, extra_start), (extra_start + 120, extra_start + 180), (extra_start + 300, extra_start + 300)];
+        let trip = network.add_trip(1, &stop_times, "Extra").unwrap();
+        assert_eq!(trip, GlobalTripIndex::new(RouteIdx(1), TripIdx(1)));
+        assert_eq!(network.num_trips, 5);
+        let route = &network.routes[1];
+        assert_eq!((route.num_trips, &route.trip_ids[1], route.cancelled_trips.len()), (3, "Extra", 3));
+        assert_eq!(route.get_trip(1, &network.stop_times).departure_times[1], extra_start + 180);
+        assert_eq!(route.get_trip(2, &network.stop_times).departure_times[0], 8 * 3600 + 1800);
+        assert_eq!(network.routes[2].get_trip(0, &network.stop_times).departure_times[0], 9 * 3600);
+
+        // Connections updated in place match rebuilt connections.
+        let updated = network.connections.clone();
+        network.build_connections();
+        assert_eq!(updated, network.connections);
+
+        for journey in [crate::raptor_query(&network, StopIdx(0), 8 * 3600 + 600, StopIdx(2)).unwrap(), crate::csa_query(&network, StopIdx(0), 8 * 3600 + 600, StopIdx(2)).unwrap()] {
+            assert_eq!(journey.arrival_time(), Some(extra_start + 300));
+            assert_eq!(journey.legs_vehicle().next().unwrap().trip, trip);
+        }
+    }
+
+    #[test]
+    fn add_trip_to_route_without_trips() {
+        // The empty route's stop times start where the next route's do, so only the route index tells which come after it.
+        let mut network = multi_route_network(3, &[("Empty", &[0, 1], &[]), ("Line", &[1, 2], &[8 * 3600])], 0);
+        assert_eq!(network.routes[0].stop_times_idx, network.routes[1].stop_times_idx);
+
+        let trip = network.add_trip(0, &[(7 * 3600, 7 * 3600), (7 * 3600 + 120, 7 * 3600 + 120)], "Extra").unwrap();
+        assert_eq!(trip, GlobalTripIndex::new(RouteIdx(0), TripIdx(0)));
+        assert_eq!(network.routes[1].stop_times_idx, 2);
+        assert_eq!(network.routes[0].get_trip(0, &network.stop_times).departure_times[1], 7 * 3600 + 120);
+        assert_eq!(network.routes[1].get_trip(0, &network.stop_times).departure_times[0], 8 * 3600);
+    }
+
+    #[test]
+    fn apply_delay() {
+        let mut network = multi_route_network(3, &[("Line", &[0, 1, 2], &[8 * 3600, 8 * 3600 + 900])], 0);
+        network.build_connections();
+        let trip = GlobalTripIndex::new(RouteIdx(0), TripIdx(0));
+        let query_time = 7 * 3600 + 3300;
+        assert_eq!(crate::raptor_query(&network, StopIdx(0), query_time, StopIdx(2)).unwrap().arrival_time(), Some(8 * 3600 + 240));
+
+        assert_eq!(network.apply_delay(GlobalTripIndex::new(RouteIdx(0), TripIdx(2)), 0, 60), Err(DelayError::UnknownTrip(GlobalTripIndex::new(RouteIdx(0), TripIdx(2)))));
+        assert_eq!(network.apply_delay(trip, 3, 60), Err(DelayError::UnknownStopOrder(3)));
+
+        // Delaying from a later stop doesn't reorder trips. Early running is limited by the previous stop.
+        assert_eq!(network.apply_delay(trip, 1, 300), Ok((trip, 300)));
+        assert_eq!(network.get_arrival_time(0, 0, 0), 8 * 3600);
+        assert_eq!(network.get_arrival_time(0, 0, 1), 8 * 3600 + 420);
+        assert_eq!(network.get_departure_time(0, 0, 2), 8 * 3600 + 540);
+        assert_eq!(network.apply_delay(trip, 1, -10_000), Ok((trip, -420)));
+        assert_eq!(network.get_arrival_time(0, 0, 1), 8 * 3600);
+        assert_eq!(network.get_arrival_time(0, 0, 2), 8 * 3600 + 120);
+        let patched = network.connections.clone();
+        network.build_connections();
+        assert_eq!(patched, network.connections);
+
+        // Delaying the first trip past the second swaps them.
+        let (delayed, _) = network.apply_delay(trip, 0, 1200).unwrap();
+        assert_eq!(delayed, GlobalTripIndex::new(RouteIdx(0), TripIdx(1)));
+        assert_eq!(&network.routes[0].trip_ids[1], "Line 0");
+        assert_eq!(network.get_departure_time(0, 1, 0), 8 * 3600 + 1200);
+        let rebuilt = network.connections.clone();
+        network.build_connections();
+        assert_eq!(rebuilt, network.connections);
+
+        // Both algorithms take the now-earlier service.
+        for journey in [crate::raptor_query(&network, StopIdx(0), query_time, StopIdx(2)).unwrap(), crate::csa_query(&network, StopIdx(0), query_time, StopIdx(2)).unwrap()] {
+            assert_eq!(journey.arrival_time(), Some(8 * 3600 + 1140));
+            assert_eq!(journey.legs_vehicle().next().unwrap().trip, GlobalTripIndex::new(RouteIdx(0), TripIdx(0)));
+        }
+    }
+
+    #[test]
+    fn skip_stop() {
+        let mut network = multi_route_network(3, &[("Line", &[0, 1, 2], &[8 * 3600, 8 * 3600 + 900])], 0);
+        network.build_connections();
+        let trip = GlobalTripIndex::new(RouteIdx(0), TripIdx(0));
+        assert_eq!(network.skip_stop(trip, 3), Err(DelayError::UnknownStopOrder(3)));
+        assert_eq!(network.skip_stop(trip, 1), Ok(()));
+
+        // The skipping trip can't be left or boarded at stop 1, but can still be ridden through it.
+        let query_time = 7 * 3600 + 3300;
+        for (start, start_time, end, arrival_time) in [(0, query_time, 1, 8 * 3600 + 1020), (1, 8 * 3600 + 60, 2, 8 * 3600 + 1140), (0, query_time, 2, 8 * 3600 + 240)] {
+            assert_eq!(crate::raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap().arrival_time(), Some(arrival_time));
+            assert_eq!(crate::csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap().arrival_time(), Some(arrival_time));
+            assert_eq!(crate::reference::dijkstra_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap(), arrival_time);
+            let mc_journey = crate::mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &CountBoardings, &Default::default()).remove(0).unwrap();
+            assert_eq!(mc_journey.arrival_time(), Some(arrival_time));
+        }
+
+        // Departure boards leave out the stop the trip skips.
+        assert_eq!(network.departures(1, 0, usize::MAX).iter().map(|departure| departure.trip.trip()).collect::<Vec<_>>(), [TripIdx(1)]);
+        assert_eq!(network.departures(0, 0, usize::MAX).len(), 2);
+
+        // The skip moves with the trip when a delay reorders it.
+        let (delayed, _) = network.apply_delay(trip, 0, 1200).unwrap();
+        assert!(network.routes[0].is_skipped(delayed.trip().idx(), 1));
+        assert!(!network.routes[0].is_skipped(0, 1));
+    }
+
+    #[test]
+    fn skipped_stops_are_ridden_through() {
+        // The feeder reaches stop 1 before the line passes through it without stopping, so the line must have been boarded at
+        // stop 0, not at stop 1 after the feeder.
+        let mut network = multi_route_network(3, &[("Line", &[0, 1, 2], &[8 * 3600]), ("Feeder", &[0, 1], &[7 * 3600 + 3360])], 0);
+        network.skip_stop(GlobalTripIndex::new(RouteIdx(0), TripIdx(0)), 1).unwrap();
+        network.build_connections();
+
+        for journey in [crate::raptor_query(&network, StopIdx(0), 7 * 3600 + 3300, StopIdx(2)).unwrap(), crate::csa_query(&network, StopIdx(0), 7 * 3600 + 3300, StopIdx(2)).unwrap()] {
+            let legs = journey.legs_vehicle().collect::<Vec<_>>();
+            assert_eq!(legs.len(), 1);
+            assert_eq!((legs[0].trip.route(), legs[0].boarded_stop, legs[0].boarded_time), (RouteIdx(0), 0, 8 * 3600));
+            assert_eq!(journey.arrival_time(), Some(8 * 3600 + 240));
+        }
+    }
+
+    #[test]
+    fn transfer_opportunity_report() {
+        // A route arrives at stop 0 at 08:10, and another departs from stop 1 (about 100 m away) at 08:12.
+        let mut network = multi_route_network(4, &[("A", &[2, 0], &[8 * 3600 + 480]), ("B", &[1, 3], &[8 * 3600 + 720])], 0);
+        network.stop_points = vec![point(-37.8000, 145.0000), point(-37.8009, 145.0000), point(-37.9000, 145.0000), point(-37.7000, 145.0000)];
+
+        let report = network.transfer_opportunity_report(0.2, 180);
+        assert_eq!(report.len(), 1, "{report:?}");
+        assert_eq!((report[0].from_stop, report[0].to_stop, report[0].num_near_misses, report[0].tightest_gap), (0, 1, 1, 120));
+        assert!(report[0].distance_km < 0.2);
+
+        // Too far apart, too long a gap, or already related by a transfer.
+        assert!(network.transfer_opportunity_report(0.05, 180).is_empty());
+        assert!(network.transfer_opportunity_report(0.2, 60).is_empty());
+        network.pairwise_transfer_times.insert((0, 1), 300);
+        assert!(network.transfer_opportunity_report(0.2, 180).is_empty());
+    }
+
+    #[test]
+    fn datetime_for() {
+        let mut network = single_route_network(vec![point(-37.80, 145.00)], Vec::new(), &[]);
+        network.date = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+        let datetime = |y, mo, d, h, mi, s| NaiveDate::from_ymd_opt(y, mo, d).unwrap().and_hms_opt(h, mi, s).unwrap();
+        assert_eq!(network.datetime_for(8 * 3600 + 30 * 60), datetime(2024, 12, 31, 8, 30, 0));
+        assert_eq!(network.datetime_for(86399), datetime(2024, 12, 31, 23, 59, 59));
+        assert_eq!(network.datetime_for(86400), datetime(2025, 1, 1, 0, 0, 0));
+        assert_eq!(network.datetime_for(25 * 3600 + 10 * 60), datetime(2025, 1, 1, 1, 10, 0));
+    }
+
+    #[test]
+    fn shape_between_l_shape() {
+        // An L-shaped shape that runs east then turns south at a corner, with stops at the start, partway east and at the end.
+        let shape = vec![point(-37.80, 145.00), point(-37.80, 145.01), point(-37.80, 145.02), point(-37.81, 145.02), point(-37.82, 145.02)];
+        let corner = shape[2];
+        let network = single_route_network(vec![shape[0], shape[1], shape[4]], shape.clone(), &[]);
+        let route = &network.routes[0];
+
+        assert_same_points(&route.shape_between(0, 1, &network), &shape[0..=1]);
+        // The middle segment goes around the corner.
+        let middle = route.shape_between(1, 2, &network);
+        assert_same_points(&middle, &shape[1..=4]);
+        assert!(middle.iter().any(|&p| p.latitude == corner.latitude && p.longitude == corner.longitude));
+
+        // The same shape drawn for a route running in the opposite direction is traversed backwards.
+        let network = single_route_network(vec![shape[4], shape[1], shape[0]], shape.clone(), &[]);
+        let reversed = network.routes[0].shape_between(0, 1, &network);
+        assert_same_points(&reversed, &shape[1..=4].iter().rev().copied().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn shape_between_falls_back_to_stops() {
+        let stop_points = vec![point(-37.80, 145.00), point(-37.80, 145.01), point(-37.81, 145.02)];
+
+        // No shape.
+        let network = single_route_network(stop_points.clone(), Vec::new(), &[]);
+        assert_same_points(&network.routes[0].shape_between(0, 2, &network), &stop_points);
+
+        // A shape that's nowhere near the stops.
+        let network = single_route_network(stop_points.clone(), vec![point(-38.5, 144.0), point(-38.6, 144.1)], &[]);
+        assert_same_points(&network.routes[0].shape_between(1, 2, &network), &stop_points[1..=2]);
+    }
+
     #[test]
     fn west_north_richmond() {
         let west_richmond = NetworkPoint {
@@ -0,0 +1,213 @@
+// Journeys in the shape of OpenTripPlanner's plan API itineraries, so frontends that render OTP responses can use this router.
+// Only the fields the router knows are filled in: fares, realtime information and agencies are left out, and vehicle legs have
+// the OTP mode of their GTFS route type, or TRANSIT if it isn't known. Serialize an OtpItinerary with e.g. serde_json.
+use chrono::{NaiveDate, TimeZone};
+
+use crate::journey::Leg;
+use crate::network::{NetworkPoint, StopIndex, Timestamp};
+use crate::{utils, Journey};
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OtpItinerary {
+    // Seconds.
+    pub duration: Timestamp,
+    // Milliseconds since the Unix epoch.
+    pub start_time: i64,
+    pub end_time: i64,
+    // Seconds walking, on vehicles and waiting (including transfers).
+    pub walk_time: Timestamp,
+    pub transit_time: Timestamp,
+    pub waiting_time: Timestamp,
+    // Metres.
+    pub walk_distance: f64,
+    pub transfers: usize,
+    pub legs: Vec<OtpLeg>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OtpLeg {
+    // WALK, the mode of the route (e.g. RAIL or BUS), or TRANSIT if the route type isn't known.
+    pub mode: String,
+    pub transit_leg: bool,
+    pub start_time: i64,
+    pub end_time: i64,
+    // Seconds.
+    pub duration: Timestamp,
+    // Metres, along the leg geometry.
+    pub distance: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route_short_name: Option<String>,
+    // Hex without a leading #, as in GTFS.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trip_id: Option<String>,
+    pub from: OtpPlace,
+    pub to: OtpPlace,
+    pub leg_geometry: OtpGeometry,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OtpPlace {
+    pub name: String,
+    pub stop_id: String,
+    pub lat: f64,
+    pub lon: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arrival: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub departure: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct OtpGeometry {
+    // Encoded with utils::encode_polyline.
+    pub points: String,
+    // The number of points.
+    pub length: usize,
+}
+
+// The OTP mode of a GTFS route_type, including the extended route types.
+fn otp_mode(route_type: Option<i16>) -> &'static str {
+    match route_type {
+        Some(0 | 900..=999) => "TRAM",
+        Some(1 | 400..=499) => "SUBWAY",
+        Some(2 | 100..=199) => "RAIL",
+        Some(3 | 700..=799) => "BUS",
+        Some(200..=299) => "COACH",
+        Some(4 | 1000..=1099 | 1200..=1299) => "FERRY",
+        Some(5) => "CABLE_CAR",
+        Some(6 | 1300..=1399) => "GONDOLA",
+        Some(7 | 1400..=1499) => "FUNICULAR",
+        Some(11 | 800..=899) => "TROLLEYBUS",
+        Some(12) => "MONORAIL",
+        Some(1100..=1199) => "AIRPLANE",
+        Some(1500..=1599) => "TAXI",
+        _ => "TRANSIT",
+    }
+}
+
+// Milliseconds since the Unix epoch at the start of the service day in the timezone. GTFS measures times from noon minus 12 hours,
+// which is midnight except on days when daylight saving starts or ends. Unknown timezones are read as UTC.
+fn service_day_start_millis(network_date: NaiveDate, timezone: Option<&str>) -> i64 {
+    let noon = network_date.and_hms_opt(12, 0, 0).unwrap();
+    let noon_secs = match timezone.map(|timezone| (timezone, timezone.parse::<chrono_tz::Tz>())) {
+        Some((_, Ok(tz))) => tz.from_local_datetime(&noon).earliest().map_or(noon.and_utc().timestamp(), |noon| noon.timestamp()),
+        Some((timezone, Err(_))) => {
+            log::warn!("Unknown timezone {timezone:?}, reading times as UTC.");
+            noon.and_utc().timestamp()
+        }
+        None => noon.and_utc().timestamp(),
+    };
+    (noon_secs - 12 * 3600) * 1000
+}
+
+impl Journey<'_> {
+    // Converts the journey to an OTP itinerary, reading its times as local to the network's timezone on network_date (usually the
+    // network's date). Networks without a known timezone are read as UTC.
+    pub fn to_otp_itinerary(&self, network_date: NaiveDate) -> OtpItinerary {
+        self.to_otp_itinerary_from(service_day_start_millis(network_date, self.network.timezone.as_deref()))
+    }
+
+    // Like to_otp_itinerary, for a timetable that is utc_offset_secs ahead of UTC (e.g. 36000 for AEST) whatever the network's
+    // timezone.
+    pub fn to_otp_itinerary_with_utc_offset(&self, network_date: NaiveDate, utc_offset_secs: i32) -> OtpItinerary {
+        self.to_otp_itinerary_from((network_date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() - utc_offset_secs as i64) * 1000)
+    }
+
+    // Transfers aren't legs in OTP, so they only count towards waiting_time.
+    fn to_otp_itinerary_from(&self, service_day_start_millis: i64) -> OtpItinerary {
+        let network = self.network;
+        let to_millis = |time: Timestamp| service_day_start_millis + time as i64 * 1000;
+        let place = |stop: StopIndex, arrival: Option<Timestamp>, departure: Option<Timestamp>| {
+            let point = network.stop_points[stop as usize];
+            OtpPlace {
+                name: network.stop_name(stop as usize).to_string(),
+                stop_id: network.stop_id(stop as usize).to_string(),
+                lat: point.latitude as f64,
+                lon: point.longitude as f64,
+                arrival: arrival.map(to_millis),
+                departure: departure.map(to_millis),
+            }
+        };
+        let geometry = |points: &[NetworkPoint]| OtpGeometry { points: utils::encode_polyline(points), length: points.len() };
+        let length_metres = |points: &[NetworkPoint]| points.windows(2).map(|pair| pair[0].distance(pair[1]) as f64 * 1000.).sum::<f64>();
+
+        let mut legs = Vec::new();
+        let (mut walk_time, mut transit_time, mut walk_distance) = (0, 0, 0.);
+        // Walks and transfers follow on from the previous leg, and walks before the first trip are taken just in time to board it.
+        let leading_walks = self.legs.iter().take_while(|leg| leg.as_vehicle().is_none()).map(Leg::duration).sum::<Timestamp>();
+        let mut time = self.departure_time().map_or(self.start_time, |departure_time| departure_time.saturating_sub(leading_walks));
+        for leg in self.legs.iter() {
+            match leg {
+                Leg::Vehicle(leg) => {
+                    let route = &network.routes[leg.trip.route().idx()];
+                    let points = route.shape_between(leg.boarded_stop_order as usize, leg.arrival_stop_order as usize, network);
+                    let line = leg.line_name(network).to_string();
+                    legs.push(OtpLeg {
+                        mode: otp_mode(route.route_type).to_string(),
+                        transit_leg: true,
+                        start_time: to_millis(leg.boarded_time),
+                        end_time: to_millis(leg.arrival_time),
+                        duration: leg.duration(),
+                        distance: length_metres(&points),
+                        route: Some(line.clone()),
+                        route_short_name: Some(line),
+                        route_color: Some(utils::get_colour_hex(leg.route_colour(network))[1..].to_string()),
+                        trip_id: Some(leg.trip_id(network).to_string()),
+                        from: place(leg.boarded_stop, None, Some(leg.boarded_time)),
+                        to: place(leg.arrival_stop, Some(leg.arrival_time), None),
+                        leg_geometry: geometry(&points),
+                    });
+                    transit_time += leg.duration();
+                    time = leg.arrival_time;
+                }
+                Leg::Transfer { .. } => time += leg.duration(),
+                Leg::Walk { from, to, duration, distance } => {
+                    let points = [network.stop_points[*from as usize], network.stop_points[*to as usize]];
+                    legs.push(OtpLeg {
+                        mode: "WALK".to_string(),
+                        transit_leg: false,
+                        start_time: to_millis(time),
+                        end_time: to_millis(time + duration),
+                        duration: *duration,
+                        distance: *distance as f64 * 1000.,
+                        route: None,
+                        route_short_name: None,
+                        route_color: None,
+                        trip_id: None,
+                        from: place(*from, None, Some(time)),
+                        to: place(*to, Some(time + duration), None),
+                        leg_geometry: geometry(&points),
+                    });
+                    walk_time += duration;
+                    walk_distance += *distance as f64 * 1000.;
+                    time += duration;
+                }
+            }
+        }
+
+        let start_time = legs.first().map_or(to_millis(self.start_time), |leg| leg.start_time);
+        let end_time = legs.last().map_or(start_time, |leg| leg.end_time);
+        let duration = ((end_time - start_time) / 1000) as Timestamp;
+        // Transfers, and waits between a walk and the next trip.
+        let waiting_time = duration.saturating_sub(walk_time + transit_time);
+
+        OtpItinerary {
+            duration,
+            start_time,
+            end_time,
+            walk_time,
+            transit_time,
+            waiting_time,
+            walk_distance,
+            transfers: self.legs_vehicle().count().saturating_sub(1),
+            legs,
+        }
+    }
+}
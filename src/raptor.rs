@@ -1,125 +1,290 @@
-use crate::journey::{Boarding, JourneyError, JourneyPreferences, JourneyResult, TauEntry};
-use crate::multicriteria::{Bag, Label};
-use crate::network::{GlobalTripIndex, Network, PathfindingCost, Route, RouteIndex, StopIndex, Timestamp, TripOrder};
-use crate::utils::{self, OptionExt};
+use crate::journey::{Boarding, JourneyError, JourneyPreferences, JourneyResult, Leg, TauEntry};
+use crate::multicriteria::{BoardingIdx, GenericBag, Label, LabelStorage, McRaptorStats, StatsRecorder};
+use arrayvec::ArrayVec;
+use crate::network::{CostProvider, GlobalTripIndex, Network, PathfindingCost, Route, RouteIndex, StopIndex, Timestamp};
+use crate::index::{RouteIdx, StopIdx, TripIdx};
+use crate::time::{Seconds, Time};
+use crate::utils::{BitSet, OptionExt};
 use crate::Journey;
+use std::collections::HashSet;
 
 // Number of rounds to run RAPTOR for.
 const K: usize = 8;
 
+// RAPTOR's labels, from which journeys are reconstructed round by round. τ* alone isn't enough, as an arrival that a trip was
+// boarded from can later be improved by one that needs a longer transfer, e.g. by a vehicle rather than on foot.
+struct Rounds {
+    // τ[p][i] = earliest known arrival at stop p with up to i trips, and how it was reached.
+    tau: Vec<[TauEntry; K]>,
+    // τ*[p] = earliest known arrival time at stop p, and the round it was reached in.
+    tau_star: Vec<(Time, usize)>,
+}
+
+impl Rounds {
+    fn new(num_stops: usize, start: usize, start_time: Time) -> Self {
+        let mut rounds = Self { tau: vec![std::array::from_fn(|_| TauEntry::default()); num_stops], tau_star: vec![(Time::MAX, 0); num_stops] };
+        rounds.improve(start, 0, TauEntry { time: start_time, boarding: None, walked_from: None });
+        rounds
+    }
+
+    fn arrival_time(&self, stop: usize) -> Time {
+        self.tau_star[stop].0
+    }
+
+    // Records an arrival at stop in round k, which must be earlier than τ*[stop].
+    fn improve(&mut self, stop: usize, k: usize, entry: TauEntry) {
+        self.tau_star[stop] = (entry.time, k);
+        self.tau[stop][k] = entry;
+    }
+
+    // Reconstructs the journey to end, boarding each trip from the arrival in the previous round that it was boarded from.
+    fn journey_into<'a>(&self, network: &'a Network, start: usize, end: usize, legs_buf: &mut Vec<Leg>) -> JourneyResult<'a> {
+        let start_time = self.tau[start][0].time;
+        let end_round = self.tau_star[end].1;
+        let end_entry = &self.tau[end][end_round];
+        // No journey found.
+        if end_entry.boarding.is_none() && end_entry.walked_from.is_none() {
+            return Err(JourneyError::no_journey_found(network, start, end, start_time.into(), self.tau_star.iter().map(|&(time, _)| time.into())));
+        }
+        Journey::from_rounds_into(|stop, round| &self.tau[stop][round], end_round, network, start, end, legs_buf)
+    }
+}
+
 struct MarkedStops<'a> {
-    marked_stops: Vec<bool>,
+    marked_stops: BitSet,
+    // The earliest marked stop order of each route, and the routes that have one. These are reused across rounds, so collecting
+    // the marked routes only clears the routes it touched.
+    earliest_stop_for_route: Vec<Option<usize>>,
+    touched_routes: Vec<usize>,
     network: &'a Network
 }
 
 impl<'a> MarkedStops<'a> {
     pub fn new(network: &'a Network) -> Self {
         Self {
-            marked_stops: vec![false; network.stops.len()],
+            marked_stops: BitSet::new(network.stops.len()),
+            earliest_stop_for_route: vec![None; network.routes.len()],
+            touched_routes: Vec::new(),
             network,
         }
     }
 
     pub fn mark_stop(&mut self, stop_idx: usize) {
-        self.marked_stops[stop_idx] = true;
+        self.marked_stops.insert(stop_idx);
     }
 
-    // Calculates the equivalent of the set Q in the paper, and iterates over (route_idx, earliest_stop_order) pairs.
-    pub fn iter_marked_routes(&mut self) -> impl Iterator<Item=(usize, usize)> {
-        let mut earliest_stop_for_route = vec![None; self.network.routes.len()];
-        for marked_stop in
-            self.marked_stops
-                .iter()
-                .enumerate()
-                .filter_map(|(i, &touched)| if touched { Some(i) } else { None })
-        {
+    // Calculates the equivalent of the set Q in the paper, as (route_idx, earliest_stop_order) pairs in route order.
+    // marked_routes is cleared first, so callers can reuse it across rounds.
+    pub fn collect_marked_routes(&mut self, marked_routes: &mut Vec<(usize, usize)>) {
+        marked_routes.clear();
+        for marked_stop in self.marked_stops.iter() {
             for &route_idx in self.network.stops[marked_stop].get_routes(&self.network.stop_routes) {
                 let route_idx = route_idx as usize;
-                let route = &self.network.routes[route_idx];
-                let earliest_stop_in_route_order =
-                    earliest_stop_for_route[route_idx].unwrap_or(route.num_stops as usize);
-
-                // TODO: profile to test if stop orders in a route for stop indices should be cached.
-                for (stop_order, &route_stop) in
-                    route.get_stops(&self.network.route_stops).iter().enumerate()
-                {
-                    if stop_order >= earliest_stop_in_route_order {
-                        break;
-                    }
-                    if route_stop == (marked_stop as StopIndex) {
-                        // Update the earliest touched stop for route.
-                        earliest_stop_for_route[route_idx] = Some(stop_order);
-                        break;
+                if let Some(stop_order) = self.network.stop_order_in_route(route_idx, marked_stop as StopIndex, 0) {
+                    match &mut self.earliest_stop_for_route[route_idx] {
+                        Some(earliest_stop_order) => *earliest_stop_order = stop_order.min(*earliest_stop_order),
+                        earliest_stop_order => {
+                            *earliest_stop_order = Some(stop_order);
+                            self.touched_routes.push(route_idx);
+                        }
                     }
                 }
                 // Should always have an earliest stop for route.
-                debug_assert!(earliest_stop_for_route[route_idx].is_some());
+                debug_assert!(self.earliest_stop_for_route[route_idx].is_some());
             }
         }
-        self.marked_stops.fill(false);
+        self.marked_stops.clear();
 
-        earliest_stop_for_route.into_iter()
-                               .enumerate()
-                               .filter_map(|(i, stop)| stop.map(|s| (i, s)))
+        // Routes are scanned in index order, as which of two equally good journeys is found depends on it.
+        self.touched_routes.sort_unstable();
+        let earliest_stop_for_route = &mut self.earliest_stop_for_route;
+        marked_routes.extend(self.touched_routes.drain(..).map(|route_idx| (route_idx, earliest_stop_for_route[route_idx].take().unwrap())));
     }
 
     pub fn is_empty(&self) -> bool {
-        utils::is_zero(&self.marked_stops)
+        self.marked_stops.is_empty()
+    }
+
+    pub fn iter_marked_stops(&self) -> impl Iterator<Item=usize> + '_ {
+        self.marked_stops.iter()
+    }
+}
+
+// Walks the footpaths from each of from_stops, which were reached in round k, updating the stops they reach in round k.
+// Walks aren't chained, so only stops reached by a vehicle (or the start) are walked from.
+fn relax_footpaths(network: &Network, from_stops: &[usize], k: usize, end: Option<usize>, rounds: &mut Rounds, marked_stops: &mut MarkedStops) {
+    for &from in from_stops {
+        for footpath in network.footpaths_from(from as StopIndex) {
+            let to = footpath.to as usize;
+            let arrival_time = rounds.tau[from][k].time.saturating_add(Seconds(network.get_footpath_time(footpath)));
+            let target_time = end.map_or(Time::MAX, |end| rounds.arrival_time(end));
+            if arrival_time < rounds.arrival_time(to).min(target_time) {
+                rounds.improve(to, k, TauEntry { time: arrival_time, boarding: None, walked_from: Some(from as StopIndex) });
+                marked_stops.mark_stop(to);
+            }
+        }
     }
 }
 
 // Compute et(r, p).
 // Returns the earliest trip boardable from the given stop on the given route before the given time as well as its departure time at the given stop.
-fn earliest_trip(network: &Network, route: &Route, stop_order: usize, time: Timestamp, boarding: Option<&Boarding>) -> Option<(usize, Timestamp)> {
+// Cancelled trips, trips that skip this stop, and trips where can_board(trip, stop_times_index) is false at this stop, are skipped.
+fn earliest_trip(network: &Network, route: &Route, route_idx: usize, stop_order: usize, time: Time, boarding: Option<&Boarding>, can_board: &impl Fn(GlobalTripIndex, usize) -> bool) -> Option<(usize, Time)> {
     // This is the trip we are currently on.
     // An exclusive range is used below, so we don't scan the current trip and to scan all trips we use num_trips as the default.
     let current_trip_order = match boarding {
-        Some(boarding) => boarding.trip.trip_order,
-        None => route.num_trips,
-    } as usize;
+        Some(boarding) => boarding.trip.trip().idx(),
+        None => route.num_trips as usize,
+    };
 
     // Because the trip index can only ever decrease, we start from the next earliest trip and work our way back.
     // Thus, all trips are accessed at most once each round.
-    let found_trip_order = (0..current_trip_order)
-        .rev()
-        .map(|trip_order| {
-            // We want to save the departure time of the trip we select.
-            (
-                trip_order,
-                network.stop_times[route.get_stop_times_index(trip_order, stop_order)].departure_time,
-            )
+    let departure_times = &network.departure_column(route_idx, stop_order)[..current_trip_order];
+    let first_catchable = first_catchable_trip(departure_times, time.into());
+
+    // Of the trips that can be caught, take the earliest that can be boarded.
+    (first_catchable..current_trip_order)
+        .find(|&trip_order| {
+            !route.is_cancelled(trip_order) && !route.is_skipped(trip_order, stop_order)
+                && can_board(GlobalTripIndex::new(RouteIdx::new(route_idx), TripIdx::new(trip_order)), route.get_stop_times_index(trip_order, stop_order))
         })
-        .take_while(|(_, departure_time)| {
-            time <= *departure_time
+        .map(|trip_order| (trip_order, Time(departure_times[trip_order])))
+}
+
+// Returns the earliest trip after after_trip_order that can be boarded at boarded_stop_order no earlier than ready_time and
+// doesn't skip stop_order, as well as its departure time at boarded_stop_order. This is for alighting at a stop that the trip
+// being ridden skips, where a later trip may still serve it.
+#[allow(clippy::too_many_arguments)]
+fn earliest_trip_serving(network: &Network, route: &Route, route_idx: usize, boarded_stop_order: usize, ready_time: Time, after_trip_order: usize, stop_order: usize, can_board: &impl Fn(GlobalTripIndex, usize) -> bool) -> Option<(usize, Time)> {
+    let departure_times = network.departure_column(route_idx, boarded_stop_order);
+    (after_trip_order + 1..route.num_trips as usize)
+        .find(|&trip_order| {
+            ready_time <= Time(departure_times[trip_order]) && !route.is_cancelled(trip_order)
+                && !route.is_skipped(trip_order, boarded_stop_order) && !route.is_skipped(trip_order, stop_order)
+                && can_board(GlobalTripIndex::new(RouteIdx::new(route_idx), TripIdx::new(trip_order)), route.get_stop_times_index(trip_order, boarded_stop_order))
         })
-        .last();
+        .map(|trip_order| (trip_order, Time(departure_times[trip_order])))
+}
 
-    found_trip_order
+// The cost of riding a trip from boarding it at from_stop_order to alighting at to_stop_order, as McRAPTOR accumulates it.
+fn ride_cost(network: &Network, route: &Route, trip: GlobalTripIndex, from_stop_order: usize, to_stop_order: usize, costs: &(impl CostProvider + ?Sized)) -> PathfindingCost {
+    let index = |stop_order| route.get_stop_times_index(trip.trip().idx(), stop_order);
+    costs.boarding_cost(network, trip, index(from_stop_order))
+        + (from_stop_order + 1..=to_stop_order).map(|stop_order| costs.cost(network, trip, index(stop_order))).sum::<PathfindingCost>()
 }
 
-pub fn raptor_query(network: &Network, start: StopIndex, start_time: Timestamp, end: StopIndex) -> Result<Journey, JourneyError> {
-    let start = start as usize;
-    let end = end as usize;
-    let num_stops = network.stops.len();
+// Number of departures compared at once when scanning back for the earliest catchable trip.
+const SCAN_CHUNK: usize = 16;
+
+// Returns the start of the run of departures at the end of departure_times that all leave at or after time. Whole chunks are
+// checked without branching, so the comparisons vectorise, and only the chunk that ends the run is searched.
+fn first_catchable_trip(departure_times: &[Timestamp], time: Timestamp) -> usize {
+    let mut first_catchable = departure_times.len();
+    for chunk in departure_times.rchunks(SCAN_CHUNK) {
+        if chunk.iter().fold(true, |catchable, &departure_time| catchable & (time <= departure_time)) {
+            first_catchable -= chunk.len();
+        } else {
+            let last_missed = chunk.iter().rposition(|&departure_time| departure_time < time).unwrap();
+            first_catchable -= chunk.len() - last_missed - 1;
+            break;
+        }
+    }
+    first_catchable
+}
 
-    // τ[p][i] = earliest known arrival time at stop p with up to i trips.
-    let mut tau = vec![[Timestamp::MAX; K]; num_stops];
-    // τ*[p] = earliest known arrival time at stop p.
-    let mut tau_star = vec![TauEntry::default(); num_stops];
+pub fn raptor_query(network: &Network, start: StopIdx, start_time: Timestamp, end: StopIdx) -> Result<Journey, JourneyError> {
+    raptor_query_impl(network, start.0, start_time, end.0, |_, _| false, |_, _| true)
+}
+
+// Runs a RAPTOR query that refuses boardings where can_board(trip, stop_times_index) is false (e.g. because the trip is full at that stop).
+// Staying on a trip that has already been boarded is always allowed.
+pub fn raptor_query_with_capacity(network: &Network, start: StopIdx, start_time: Timestamp, end: StopIdx, can_board: impl Fn(GlobalTripIndex, usize) -> bool) -> Result<Journey, JourneyError> {
+    raptor_query_impl(network, start.0, start_time, end.0, |_, _| false, can_board)
+}
+
+// Runs a RAPTOR query where trips of the given route can't be boarded at the given stop when is_banned(route_idx, stop_idx) is true,
+// and individual trips can't be boarded when can_board(trip, stop_times_index) is false.
+fn raptor_query_impl(network: &Network,
+                     start: StopIndex,
+                     start_time: Timestamp,
+                     end: StopIndex,
+                     is_banned: impl Fn(usize, usize) -> bool,
+                     can_board: impl Fn(GlobalTripIndex, usize) -> bool) -> Result<Journey, JourneyError> {
+    let rounds = raptor_rounds(network, start as usize, start_time, Some(end as usize), is_banned, can_board);
+    rounds.journey_into(network, start as usize, end as usize, &mut Vec::new())
+}
+
+// Returns the earliest arrival time at every stop when leaving start at start_time, or None if the stop can't be reached within K rounds.
+// The start stop's arrival time is start_time.
+pub fn raptor_one_to_all(network: &Network, start: StopIdx, start_time: Timestamp) -> Vec<Option<Timestamp>> {
+    let one_to_all = raptor_one_to_all_journeys(network, start, start_time);
+    (0..network.stops.len()).map(|stop| one_to_all.arrival_time(StopIdx::new(stop))).collect()
+}
+
+// Runs a one-to-all RAPTOR query, from which the journey to any stop can be reconstructed. Reconstructing many journeys from one
+// query this way is much cheaper than a query per destination.
+pub fn raptor_one_to_all_journeys(network: &Network, start: StopIdx, start_time: Timestamp) -> OneToAll<'_> {
+    OneToAll { network, start, rounds: raptor_rounds(network, start.idx(), start_time, None, |_, _| false, |_, _| true) }
+}
+
+// The result of a one-to-all RAPTOR query (see raptor_one_to_all_journeys).
+pub struct OneToAll<'a> {
+    network: &'a Network,
+    start: StopIdx,
+    rounds: Rounds,
+}
+
+impl<'a> OneToAll<'a> {
+    pub fn start(&self) -> StopIdx {
+        self.start
+    }
+
+    pub fn start_time(&self) -> Timestamp {
+        self.rounds.tau[self.start.idx()][0].time.into()
+    }
+
+    // The earliest arrival time at stop, or None if it can't be reached within K rounds.
+    pub fn arrival_time(&self, stop: StopIdx) -> Option<Timestamp> {
+        Some(self.rounds.arrival_time(stop.idx())).filter(|&time| time != Time::MAX).map(Timestamp::from)
+    }
 
+    // Reconstructs the journey to end.
+    pub fn journey(&self, end: StopIdx) -> JourneyResult<'a> {
+        self.journey_into(end, &mut Vec::new())
+    }
+
+    // The same as journey, but the legs are collected in legs_buf (which is cleared first) rather than a new Vec, so that
+    // reconstructing many journeys only allocates each journey's own legs.
+    pub fn journey_into(&self, end: StopIdx, legs_buf: &mut Vec<Leg>) -> JourneyResult<'a> {
+        self.rounds.journey_into(self.network, self.start.idx(), end.idx(), legs_buf)
+    }
+}
+
+// Runs the RAPTOR rounds and returns the labels of every stop. If there is a target stop, arrivals later than the best known arrival there are pruned.
+fn raptor_rounds(network: &Network,
+                 start: usize,
+                 start_time: Timestamp,
+                 end: Option<usize>,
+                 is_banned: impl Fn(usize, usize) -> bool,
+                 can_board: impl Fn(GlobalTripIndex, usize) -> bool) -> Rounds {
     // Set initial departure time from start station.
-    tau[start][0] = start_time;
-    tau_star[start] = TauEntry { time: start_time, boarding: None };
+    let mut rounds = Rounds::new(network.stops.len(), start, Time(start_time));
 
     // Array for recording which stops have been marked in the current round.
     let mut marked_stops = MarkedStops::new(network);
     marked_stops.mark_stop(start);
+    relax_footpaths(network, &[start], 0, end, &mut rounds, &mut marked_stops);
+    let mut marked_routes = Vec::new();
+    // The stop orders on the route being scanned where a trip could be boarded, and the time a passenger is ready to board there.
+    let mut boarding_stop_orders: Vec<(usize, Time)> = Vec::new();
 
     // RAPTOR
     for k in 1..K {
         // Traverse each marked route.
-        for (route_idx, earliest_stop_order) in marked_stops.iter_marked_routes() {
+        marked_stops.collect_marked_routes(&mut marked_routes);
+        for &(route_idx, earliest_stop_order) in marked_routes.iter() {
             let route = &network.routes[route_idx];
+            boarding_stop_orders.clear();
 
             // This keeps track of when and where we got on the current trip.
             let mut boarding: Option<Boarding> = None;
@@ -127,14 +292,34 @@ pub fn raptor_query(network: &Network, start: StopIndex, start_time: Timestamp,
             {
                 // Can the arrival time at this stop be improved in this round?
                 let mut current_departure_time = None;
-                if let Some(boarding) = &boarding {
-                    let trip = route.get_trip(boarding.trip.trip_order as usize, &network.stop_times);
-                    let arrival_time = trip[stop_order].arrival_time;
-                    current_departure_time = Some(trip[stop_order].departure_time);
-                    if arrival_time < tau_star[stop_idx].time.min(tau_star[end].time) {
-                        tau[stop_idx][k] = arrival_time;
-                        tau_star[stop_idx] = TauEntry { time: arrival_time, boarding: Some(boarding.clone()) };
-                        marked_stops.mark_stop(stop_idx);
+                if let Some(current_boarding) = &boarding {
+                    let trip_order = current_boarding.trip.trip().idx();
+                    current_departure_time = Some(Time(route.get_trip(trip_order, &network.stop_times).departure_times[stop_order]));
+                    // Passengers can't alight where the trip skips the stop, but a later trip boarded at an earlier stop may
+                    // still serve it. The current trip is kept for the rest of the route, as it's the earliest.
+                    let alighting = if route.is_skipped(trip_order, stop_order) {
+                        boarding_stop_orders.iter()
+                            .filter_map(|&(boarded_stop_order, ready_time)| {
+                                earliest_trip_serving(network, route, route_idx, boarded_stop_order, ready_time, trip_order, stop_order, &can_board)
+                                    .map(|(trip_order, boarded_time)| (boarded_stop_order, trip_order, boarded_time))
+                            })
+                            .min_by_key(|&(_, trip_order, _)| network.stop_times.arrival_times[route.get_stop_times_index(trip_order, stop_order)])
+                            .map(|(boarded_stop_order, trip_order, boarded_time)| Boarding {
+                                boarded_stop: route.get_stops(&network.route_stops)[boarded_stop_order],
+                                boarded_stop_order: boarded_stop_order as StopIndex,
+                                boarded_time,
+                                trip: GlobalTripIndex::new(RouteIdx::new(route_idx), TripIdx::new(trip_order)),
+                            })
+                    } else {
+                        Some(current_boarding.clone())
+                    };
+                    if let Some(alighting) = alighting {
+                        let arrival_time = Time(network.stop_times.arrival_times[route.get_stop_times_index(alighting.trip.trip().idx(), stop_order)]);
+                        let target_time = end.map_or(Time::MAX, |end| rounds.arrival_time(end));
+                        if arrival_time < rounds.arrival_time(stop_idx).min(target_time) {
+                            rounds.improve(stop_idx, k, TauEntry { time: arrival_time, boarding: Some(alighting), walked_from: None });
+                            marked_stops.mark_stop(stop_idx);
+                        }
                     }
                 }
 
@@ -143,28 +328,28 @@ pub fn raptor_query(network: &Network, start: StopIndex, start_time: Timestamp,
                 // 1. This is the first stop in the trip. The stop was therefore set by the previous round.
                 // 2. This is a subsequent stop in the trip, where another route has reached it faster. Similarly, it has already been updated to the fastest time.
 
-                // Ignore transfer time for first round.
-                let transfer_time = if k > 1 {
-                    network.transfer_times[stop_idx]
-                } else {
-                    0
+                // Ignore transfer time at the start, and after walking, as the footpath's duration includes it.
+                let reached = &rounds.tau[stop_idx][k - 1];
+                let transfer_time = match &reached.boarding {
+                    Some(boarding) => network.get_route_transfer_time(stop_idx as StopIndex, Some(boarding.trip.route_idx), route_idx as RouteIndex),
+                    None => 0,
                 };
 
                 // Can we catch an earlier trip at this stop?
-                let current_tau = tau[stop_idx][k - 1].saturating_add(transfer_time);
-                if OptionExt::is_none_or(current_departure_time, |departure_time| current_tau <= departure_time) {
+                let current_tau = reached.time.saturating_add(Seconds(transfer_time));
+                if !is_banned(route_idx, stop_idx) && current_tau != Time::MAX {
+                    boarding_stop_orders.push((stop_order, current_tau));
+                }
+                if !is_banned(route_idx, stop_idx) && OptionExt::is_none_or(current_departure_time, |departure_time| current_tau <= departure_time) {
                     // If no new trip was found, we continue with the current trip.
                     // If a new trip was found, we update the trip and the stop we boarded it.
-                    if let Some((found_trip_order, departure_time)) = earliest_trip(network, route, stop_order, current_tau, boarding.as_ref()) {
+                    if let Some((found_trip_order, departure_time)) = earliest_trip(network, route, route_idx, stop_order, current_tau, boarding.as_ref(), &can_board) {
                         boarding = Some(
                             Boarding {
                                 boarded_stop: stop_idx as StopIndex,
                                 boarded_stop_order: stop_order as StopIndex,
                                 boarded_time: departure_time,
-                                trip: GlobalTripIndex {
-                                    route_idx: route_idx as RouteIndex,
-                                    trip_order: found_trip_order as TripOrder,
-                                },
+                                trip: GlobalTripIndex::new(RouteIdx::new(route_idx), TripIdx::new(found_trip_order)),
                             },
                         )
                     }
@@ -172,85 +357,244 @@ pub fn raptor_query(network: &Network, start: StopIndex, start_time: Timestamp,
             }
         }
 
+        if !network.footpaths.is_empty() {
+            let reached_by_vehicle = marked_stops.iter_marked_stops().collect::<Vec<_>>();
+            relax_footpaths(network, &reached_by_vehicle, k, end, &mut rounds, &mut marked_stops);
+        }
+
         if marked_stops.is_empty() {
             break;
         }
     }
 
-    Journey::from_tau(&tau_star, network, start, end)
+    rounds
+}
+
+// Journeys slower than this factor times the fastest journey's travel time are not returned as alternatives.
+pub const DEFAULT_ALTERNATIVE_SLOWDOWN_FACTOR: f32 = 1.5;
+
+// Finds up to k meaningfully different journeys, sorted by arrival time.
+// See raptor_query_alternatives_with_factor for the diversity heuristic.
+pub fn raptor_query_alternatives(network: &Network, start: StopIdx, start_time: Timestamp, end: StopIdx, k: usize) -> Vec<Journey> {
+    raptor_query_alternatives_with_factor(network, start, start_time, end, k, DEFAULT_ALTERNATIVE_SLOWDOWN_FACTOR)
+}
+
+// Finds up to k meaningfully different journeys, sorted by arrival time.
+// The query is re-run repeatedly, each time banning boarding a route at any stop where a previously found journey boarded it.
+// Journeys using the same sequence of routes as a previously found journey are skipped, as are journeys whose travel time
+// (from start_time) is more than max_slowdown times that of the fastest journey. The search is deterministic for a given network.
+pub fn raptor_query_alternatives_with_factor(network: &Network, start: StopIdx, start_time: Timestamp, end: StopIdx, k: usize, max_slowdown: f32) -> Vec<Journey> {
+    // Bound the number of re-runs, because each one may only find a duplicate of an existing journey.
+    const MAX_ATTEMPTS_PER_JOURNEY: usize = 4;
+
+    let mut journeys: Vec<Journey> = Vec::with_capacity(k);
+    let mut banned = HashSet::<(usize, usize)>::new();
+    let mut best_travel_time = None;
+
+    for _ in 0..k.saturating_mul(MAX_ATTEMPTS_PER_JOURNEY) {
+        if journeys.len() >= k {
+            break;
+        }
+        let journey = match raptor_query_impl(network, start.0, start_time, end.0, |route_idx, stop_idx| banned.contains(&(route_idx, stop_idx)), |_, _| true) {
+            Ok(journey) if !journey.legs.is_empty() => journey,
+            _ => break,
+        };
+
+        let travel_time = journey.arrival_time().unwrap().saturating_sub(start_time);
+        let best_travel_time = *best_travel_time.get_or_insert(travel_time);
+        if travel_time as f32 > best_travel_time as f32 * max_slowdown {
+            // Banning more boardings can only make journeys slower.
+            break;
+        }
+
+        banned.extend(journey.legs_vehicle().map(|leg| (leg.trip.route().idx(), leg.boarded_stop as usize)));
+
+        let is_duplicate = journeys.iter().any(|existing| {
+            existing.legs.len() == journey.legs.len()
+                && existing.legs_vehicle().zip(journey.legs_vehicle()).all(|(a, b)| a.trip.route_idx == b.trip.route_idx)
+        });
+        if !is_duplicate {
+            journeys.push(journey);
+        }
+    }
+
+    journeys.sort_by_key(|journey| journey.arrival_time().unwrap_or(Timestamp::MAX));
+    journeys
 }
 
+// Runs a multicriteria RAPTOR query from start to every stop in ends, returning one journey result per end in input order.
+// Bags hold at most N labels, so the Pareto sets found are heuristic.
 pub fn mc_raptor_query<'a, const N: usize>(network: &'a Network,
-                                           start: StopIndex,
+                                           start: StopIdx,
                                            start_time: Timestamp,
-                                           ends: &[StopIndex],
-                                           costs: &[PathfindingCost],
+                                           ends: &[StopIdx],
+                                           costs: &(impl CostProvider + ?Sized),
                                            path_preferences: &JourneyPreferences) -> Vec<JourneyResult<'a>> {
-    let end = if ends.len() == 1 {
-        if start == ends[0] {
-            return Vec::new();
+    mc_raptor_query_impl::<ArrayVec<Label, N>>(network, start, start_time, ends, costs, path_preferences, &mut ())
+}
+
+// Runs mc_raptor_query, also collecting statistics about how labels were created and pruned.
+pub fn mc_raptor_query_with_stats<'a, const N: usize>(network: &'a Network,
+                                                      start: StopIdx,
+                                                      start_time: Timestamp,
+                                                      ends: &[StopIdx],
+                                                      costs: &(impl CostProvider + ?Sized),
+                                                      path_preferences: &JourneyPreferences) -> (Vec<JourneyResult<'a>>, McRaptorStats) {
+    let mut stats = McRaptorStats::default();
+    let journeys = mc_raptor_query_impl::<ArrayVec<Label, N>>(network, start, start_time, ends, costs, path_preferences, &mut stats);
+    (journeys, stats)
+}
+
+// Runs a multicriteria RAPTOR query with unbounded bags, so the exact Pareto sets are found. This is slower than mc_raptor_query.
+pub fn mc_raptor_query_exact<'a>(network: &'a Network,
+                                 start: StopIdx,
+                                 start_time: Timestamp,
+                                 ends: &[StopIdx],
+                                 costs: &(impl CostProvider + ?Sized),
+                                 path_preferences: &JourneyPreferences) -> Vec<JourneyResult<'a>> {
+    mc_raptor_query_impl::<Vec<Label>>(network, start, start_time, ends, costs, path_preferences, &mut ())
+}
+
+fn mc_raptor_query_impl<'a, S: LabelStorage>(network: &'a Network,
+                                             start: StopIdx,
+                                             start_time: Timestamp,
+                                             ends: &[StopIdx],
+                                             costs: &(impl CostProvider + ?Sized),
+                                             path_preferences: &JourneyPreferences,
+                                             stats: &mut impl StatsRecorder) -> Vec<JourneyResult<'a>> {
+    // Target pruning: a label can only be discarded if it is dominated at every target, as it may still lead to any of them.
+    // Targets at the start stop are excluded, because the start label dominates every other label.
+    let targets = ends.iter().filter(|&&end| end != start).map(|&end| end.idx()).collect::<Vec<_>>();
+    if targets.is_empty() {
+        return ends.iter().map(|&end| Ok(Journey::empty_at(network, end.0, start_time))).collect();
+    }
+
+    let (tau_star, boardings) = mc_raptor_rounds::<S>(network, start.idx(), start_time, &targets, costs, path_preferences, stats);
+
+    let mut legs_buf = Vec::new();
+    ends.iter().map(|&end| {
+        if end == start {
+            Ok(Journey::empty_at(network, end.0, start_time))
+        } else {
+            Journey::from_tau_bag_into(&tau_star, &boardings, network, start.idx(), end.idx(), costs, path_preferences, &mut legs_buf)
         }
-        Some(ends[0] as usize)
-    } else {
-        None
-    };
+    }).collect::<Vec<_>>()
+}
 
-    let start = start as usize;
+// Runs the McRAPTOR rounds from start towards targets, returning τ* and the boardings its labels refer to.
+fn mc_raptor_rounds<S: LabelStorage>(network: &Network,
+                                     start: usize,
+                                     start_time: Timestamp,
+                                     targets: &[usize],
+                                     costs: &(impl CostProvider + ?Sized),
+                                     path_preferences: &JourneyPreferences,
+                                     stats: &mut impl StatsRecorder) -> (Vec<GenericBag<S>>, Vec<Boarding>) {
     let num_stops = network.stops.len();
 
     // τ[p][i] = earliest known arrival time at stop p with up to i trips.
-    let mut tau = vec![[const { Bag::<N>::new() }; K]; num_stops];
+    let mut tau = vec![std::array::from_fn::<_, K, _>(|_| GenericBag::<S>::default()); num_stops];
     // τ*[p] = earliest known arrival time at stop p.
-    let mut tau_star = vec![Bag::<N>::new(); num_stops];
+    let mut tau_star = vec![GenericBag::<S>::default(); num_stops];
 
     // Set initial departure time from start station.
     let start_label = Label::new(start_time, 0.);
-    tau[start][0].add(start_label.clone());
+    tau[start][0].add(start_label);
     tau_star[start].add(start_label);
 
+    // Every trip boarded by a label. Labels refer to their boarding by index, so that copying a label doesn't copy it.
+    let mut boardings = Vec::new();
+
     // Array for recording which stops have been marked in the current round.
     let mut marked_stops = MarkedStops::new(network);
     marked_stops.mark_stop(start);
+    let mut marked_routes = Vec::new();
+
+    // B_r, and scratch bags reused for every route and stop rather than allocated for each. Step 1 updates B_r into
+    // next_route_bag and then swaps them.
+    let mut route_bag = GenericBag::<S>::default();
+    let mut next_route_bag = GenericBag::<S>::default();
+    let mut improvements = GenericBag::<S>::default();
+    mc_relax_footpaths(network, &[start], 0, targets, &mut tau, &mut tau_star, &boardings, path_preferences, &mut marked_stops, &mut improvements, stats);
 
     // RAPTOR
     for k in 1..K {
         // Traverse each marked route.
-        for (route_idx, earliest_stop_order) in marked_stops.iter_marked_routes()
+        marked_stops.collect_marked_routes(&mut marked_routes);
+        for &(route_idx, earliest_stop_order) in marked_routes.iter()
         {
             let route = &network.routes[route_idx];
-
-            // B_r
-            let mut route_bag = Bag::<N>::new();
+            route_bag.clear();
 
             // This keeps track of when and where we got on the current trip.
             for (stop_order, stop_idx) in route.iter_stops(earliest_stop_order, &network.route_stops)
             {
                 // Multicriteria step 1: Update arrival time of every label in B_r according to each labels' trip.
                 {
-                    let mut new_bag = Bag::<N>::new();
-                    for label in route_bag.consume_iter() {
-                        let boarding = label.boarding.as_ref().unwrap();
-                        assert_eq!(boarding.trip.route_idx, route_idx as RouteIndex);
-                        let index = route.get_stop_times_index(boarding.trip.trip_order as usize, stop_order);
-                        new_bag.add(Label {
-                            arrival_time: network.stop_times[index].arrival_time,
-                            cost: label.cost + costs[index],
-                            boarding: label.boarding,
-                        });
+                    next_route_bag.clear();
+                    for label in route_bag.iter() {
+                        let trip = label.boarding(&boardings).unwrap().trip;
+                        assert_eq!(trip.route(), RouteIdx::new(route_idx));
+                        let index = route.get_stop_times_index(trip.trip().idx(), stop_order);
+                        let new_label = Label {
+                            arrival_time: network.stop_times.arrival_times[index],
+                            cost: label.cost + costs.cost(network, trip, index),
+                            ..*label
+                        };
+                        stats.record_label(next_route_bag.insert(new_label, path_preferences.tie_break, &path_preferences.overflow, &boardings));
                     }
-                    route_bag.set(new_bag);
+                    std::mem::swap(&mut route_bag, &mut next_route_bag);
                 }
 
                 // Multicriteria step 2: Merge B_r into B_k.
-                let mut updated = false;
+                // Labels on a trip that skips this stop can't alight here. B_r is already a Pareto set, so the labels that
+                // aren't filtered out are copied as they are rather than added again.
+                let epsilon = path_preferences.epsilon;
+                improvements.set_filtered(&route_bag, |label| {
+                    !route.is_skipped(label.boarding(&boardings).unwrap().trip.trip().idx(), stop_order)
+                        && !tau_star[stop_idx].dominates_with_epsilon(label, epsilon)
+                        && !targets.iter().all(|&end| tau_star[end].dominates_with_epsilon(label, epsilon))
+                });
+                // A label whose trip skips this stop could instead have boarded the earliest later trip that serves it.
                 for label in route_bag.iter() {
-                    if !tau_star[stop_idx].dominates(label) && OptionExt::is_none_or(end, |end| !tau_star[end].dominates(label)) {
-                        updated |= tau[stop_idx][k].add(label.clone());
-                        updated |= tau_star[stop_idx].add(label.clone());
+                    let boarding = label.boarding(&boardings).unwrap().clone();
+                    let trip_order = boarding.trip.trip().idx();
+                    let boarded_stop_order = boarding.boarded_stop_order as usize;
+                    if !route.is_skipped(trip_order, stop_order) {
+                        continue;
                     }
+                    let Some((later_trip_order, boarded_time)) = earliest_trip_serving(network, route, route_idx, boarded_stop_order, boarding.boarded_time, trip_order, stop_order, &|_, _| true) else {
+                        continue;
+                    };
+                    let later_trip = GlobalTripIndex::new(RouteIdx::new(route_idx), TripIdx::new(later_trip_order));
+                    let later_boarding = Boarding { boarded_time, trip: later_trip, ..boarding };
+                    let new_label = Label::boarded(
+                        network.stop_times.arrival_times[route.get_stop_times_index(later_trip_order, stop_order)],
+                        label.cost - ride_cost(network, route, boarding.trip, boarded_stop_order, stop_order, costs)
+                            + ride_cost(network, route, later_trip, boarded_stop_order, stop_order, costs),
+                        boardings.len() as BoardingIdx,
+                        &later_boarding,
+                    );
+                    if tau_star[stop_idx].dominates_with_epsilon(&new_label, epsilon)
+                        || targets.iter().all(|&end| tau_star[end].dominates_with_epsilon(&new_label, epsilon)) {
+                        continue;
+                    }
+                    boardings.push(later_boarding);
+                    let outcome = improvements.insert(new_label, path_preferences.tie_break, &path_preferences.overflow, &boardings);
+                    // Nothing refers to the boarding of a label that wasn't added.
+                    if !outcome.is_added() {
+                        boardings.pop();
+                    }
+                    stats.record_label(outcome);
                 }
-                if updated {
-                    marked_stops.mark_stop(stop_idx);
+                if !improvements.is_empty() {
+                    let mut updated = tau[stop_idx][k].merge_with_boardings(&improvements, path_preferences.tie_break, &path_preferences.overflow, &boardings);
+                    updated |= tau_star[stop_idx].merge_with_boardings(&improvements, path_preferences.tie_break, &path_preferences.overflow, &boardings);
+                    stats.record_merge();
+                    stats.record_merge();
+                    stats.record_occupancy(k, tau[stop_idx][k].len());
+                    if updated {
+                        marked_stops.mark_stop(stop_idx);
+                    }
                 }
 
                 // Multicriteria step 3: Merge B_{k-1} into B_r and assign trips.
@@ -260,46 +604,221 @@ pub fn mc_raptor_query<'a, const N: usize>(network: &'a Network,
                     // 1. This is the first stop in the trip. The stop was therefore set by the previous round.
                     // 2. This is a subsequent stop in the trip, where another route has reached it faster. Similarly, it has already been updated to the fastest time.
 
-                    // Ignore transfer time for first round.
-                    let transfer_time = if k > 1 {
-                        network.transfer_times[stop_idx]
+                    // Ignore transfer time for first round, and after walking, as the footpath's duration includes it.
+                    let transfer_time = if k > 1 && label.walked_from.is_none() {
+                        let from_route = label.boarding(&boardings).map(|boarding| boarding.trip.route_idx);
+                        network.get_route_transfer_time(stop_idx as StopIndex, from_route, route_idx as RouteIndex)
                     } else {
                         0
                     };
 
                     // Can we catch an earlier trip at this stop?
-                    let current_tau = label.arrival_time.saturating_add(transfer_time);
+                    let current_tau = Time(label.arrival_time).saturating_add(Seconds(transfer_time));
                     let boarding = None;
                     // TODO: check this has the equivalent effect of the original code (boarding = none).
                     //let boarding = label.boarding.as_ref().filter(|label_boarding| label_boarding.trip.route_idx == route_idx as RouteIndex);
 
-                    if let Some((found_trip_order, departure_time)) = earliest_trip(network, route, stop_order, current_tau, boarding) {
-                        let new_label = Label {
-                            arrival_time: label.arrival_time,
-                            cost: label.cost,
-                            boarding: Some(
-                                Boarding {
-                                    boarded_stop: stop_idx as StopIndex,
-                                    boarded_stop_order: stop_order as StopIndex,
-                                    boarded_time: departure_time,
-                                    trip: GlobalTripIndex {
-                                        route_idx: route_idx as RouteIndex,
-                                        trip_order: found_trip_order as TripOrder,
-                                    },
-                                },
-                            ),
+                    if let Some((found_trip_order, departure_time)) = earliest_trip(network, route, route_idx, stop_order, current_tau, boarding, &|_, _| true) {
+                        let trip = GlobalTripIndex::new(RouteIdx::new(route_idx), TripIdx::new(found_trip_order));
+                        let index = route.get_stop_times_index(found_trip_order, stop_order);
+                        let boarding = Boarding {
+                            boarded_stop: stop_idx as StopIndex,
+                            boarded_stop_order: stop_order as StopIndex,
+                            boarded_time: departure_time,
+                            trip,
                         };
-
-                        route_bag.add(new_label);
+                        let new_label = Label::boarded(label.arrival_time, label.cost + costs.boarding_cost(network, trip, index), boardings.len() as BoardingIdx, &boarding);
+                        boardings.push(boarding);
+
+                        let outcome = route_bag.insert(new_label, path_preferences.tie_break, &path_preferences.overflow, &boardings);
+                        // Nothing refers to the boarding of a label that wasn't added.
+                        if !outcome.is_added() {
+                            boardings.pop();
+                        }
+                        stats.record_label(outcome);
                     }
                 }
             }
         }
 
+        if !network.footpaths.is_empty() {
+            let reached_by_vehicle = marked_stops.iter_marked_stops().collect::<Vec<_>>();
+            mc_relax_footpaths(network, &reached_by_vehicle, k, targets, &mut tau, &mut tau_star, &boardings, path_preferences, &mut marked_stops, &mut improvements, stats);
+        }
+
         if marked_stops.is_empty() {
             break;
         }
     }
 
-    ends.iter().map(|&end| Journey::from_tau_bag::<N>(&tau_star, network, start, end as usize, path_preferences)).collect::<Vec<_>>()
-}
\ No newline at end of file
+    (tau_star, boardings)
+}
+
+// Walks the footpaths from each of from_stops, which were reached in round k, merging the walked labels into the stops they
+// reach in round k. As in relax_footpaths, walks aren't chained, so labels that were themselves walked aren't walked on.
+// improvements is scratch space.
+#[allow(clippy::too_many_arguments)]
+fn mc_relax_footpaths<S: LabelStorage>(network: &Network,
+                                       from_stops: &[usize],
+                                       k: usize,
+                                       targets: &[usize],
+                                       tau: &mut [[GenericBag<S>; K]],
+                                       tau_star: &mut [GenericBag<S>],
+                                       boardings: &[Boarding],
+                                       path_preferences: &JourneyPreferences,
+                                       marked_stops: &mut MarkedStops,
+                                       improvements: &mut GenericBag<S>,
+                                       stats: &mut impl StatsRecorder) {
+    let epsilon = path_preferences.epsilon;
+    for &from in from_stops {
+        for footpath in network.footpaths_from(from as StopIndex) {
+            let to = footpath.to as usize;
+            let duration = network.get_footpath_time(footpath);
+            improvements.clear();
+            for label in tau[from][k].iter().filter(|label| label.walked_from.is_none()) {
+                let new_label = label.walked(from as StopIndex, duration);
+                if tau_star[to].dominates_with_epsilon(&new_label, epsilon)
+                    || targets.iter().all(|&end| tau_star[end].dominates_with_epsilon(&new_label, epsilon)) {
+                    continue;
+                }
+                stats.record_label(improvements.insert(new_label, path_preferences.tie_break, &path_preferences.overflow, boardings));
+            }
+            if !improvements.is_empty() {
+                let mut updated = tau[to][k].merge_with_boardings(improvements, path_preferences.tie_break, &path_preferences.overflow, boardings);
+                updated |= tau_star[to].merge_with_boardings(improvements, path_preferences.tie_break, &path_preferences.overflow, boardings);
+                stats.record_merge();
+                stats.record_merge();
+                stats.record_occupancy(k, tau[to][k].len());
+                if updated {
+                    marked_stops.mark_stop(to);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{NetworkPoint, StopTime, TimetableRoute};
+    use chrono::NaiveDate;
+
+    // The strided scan earliest_trip used before departure columns, as a reference.
+    fn earliest_trip_strided(network: &Network, route: &Route, route_idx: usize, stop_order: usize, time: Time, current_trip_order: usize, can_board: &impl Fn(GlobalTripIndex, usize) -> bool) -> Option<(usize, Time)> {
+        (0..current_trip_order)
+            .rev()
+            .map(|trip_order| {
+                let index = route.get_stop_times_index(trip_order, stop_order);
+                (trip_order, index, Time(network.stop_times.departure_times[index]))
+            })
+            .take_while(|(_, _, departure_time)| time <= *departure_time)
+            .filter(|&(trip_order, index, _)| {
+                !route.is_cancelled(trip_order) && !route.is_skipped(trip_order, stop_order) && can_board(GlobalTripIndex::new(RouteIdx::new(route_idx), TripIdx::new(trip_order)), index)
+            })
+            .last()
+            .map(|(trip_order, _, departure_time)| (trip_order, departure_time))
+    }
+
+    // Irregular headways and dwell times, so some trips overtake others and the catchable trips aren't always a suffix.
+    fn irregular_network() -> Network {
+        let stops = (0..6).map(|stop| (format!("Stop {stop}"), stop.to_string())).collect::<Vec<_>>();
+        let stops = stops.iter().map(|(name, id)| (name.as_str(), id.as_str(), NetworkPoint { latitude: 0., longitude: 0. })).collect::<Vec<_>>();
+        let routes = [(0..6).collect::<Vec<StopIndex>>(), vec![5, 3, 1]].into_iter().enumerate().map(|(route, route_stops)| {
+            let trips = (0..40).map(|trip| {
+                let mut time = 6 * 3600 + fastrand::u32(0..4 * 3600);
+                let stop_times = route_stops.iter().map(|_| {
+                    let arrival_time = time;
+                    time += fastrand::u32(0..300);
+                    let stop_time = StopTime { arrival_time, departure_time: time };
+                    time += fastrand::u32(60..600);
+                    stop_time
+                }).collect();
+                (format!("{route}.{trip}"), stop_times)
+            }).collect();
+            TimetableRoute { line: format!("Line {route}"), colour: Default::default(), stops: route_stops, trips }
+        }).collect();
+        Network::from_timetable(&stops, routes, NaiveDate::default(), 60)
+    }
+
+    #[test]
+    fn earliest_trip_matches_strided_scan() {
+        fastrand::seed(21);
+        let mut network = irregular_network();
+        for _ in 0..6 {
+            let route_idx = fastrand::usize(0..network.routes.len());
+            let trip = GlobalTripIndex::new(RouteIdx::new(route_idx), TripIdx(fastrand::u32(0..network.routes[route_idx].num_trips)));
+            let _ = network.cancel_trip(trip);
+            network.skip_stop(GlobalTripIndex::new(trip.route(), TripIdx(fastrand::u32(0..network.routes[route_idx].num_trips))), fastrand::usize(0..network.routes[route_idx].num_stops as usize)).unwrap();
+        }
+        let can_board = |trip: GlobalTripIndex, stop_times_index: usize| (trip.trip().idx() + stop_times_index) % 7 != 0;
+
+        for (route_idx, route) in network.routes.iter().enumerate() {
+            for stop_order in 0..route.num_stops as usize {
+                for current_trip_order in 0..=route.num_trips as usize {
+                    let boarding = (current_trip_order < route.num_trips as usize).then(|| Boarding {
+                        boarded_stop: 0,
+                        boarded_stop_order: 0,
+                        boarded_time: Time::MIDNIGHT,
+                        trip: GlobalTripIndex::new(RouteIdx::new(route_idx), TripIdx::new(current_trip_order)),
+                    });
+                    for time in (5 * 3600..12 * 3600).step_by(97).map(Time) {
+                        assert_eq!(
+                            earliest_trip(&network, route, route_idx, stop_order, time, boarding.as_ref(), &can_board),
+                            earliest_trip_strided(&network, route, route_idx, stop_order, time, current_trip_order, &can_board),
+                            "route {route_idx}, stop order {stop_order}, current trip {current_trip_order}, time {time}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    // A fare for boarding the first trip on the route from A to C.
+    struct FirstTripFare;
+
+    impl CostProvider for FirstTripFare {
+        fn cost(&self, _network: &Network, _trip: GlobalTripIndex, _stop_times_index: usize) -> PathfindingCost {
+            0.
+        }
+
+        fn boarding_cost(&self, _network: &Network, trip: GlobalTripIndex, _stop_times_index: usize) -> PathfindingCost {
+            if trip == GlobalTripIndex::new(RouteIdx(2), TripIdx(0)) { 5. } else { 0. }
+        }
+    }
+
+    #[test]
+    fn later_trips_that_are_dominated_leave_no_boardings() {
+        let time = |minutes: Timestamp| StopTime { arrival_time: 8 * 3600 + minutes * 60, departure_time: 8 * 3600 + minutes * 60 };
+        let stops = ["S", "A", "B", "C"].map(|stop| (stop, stop, NetworkPoint { latitude: 0., longitude: 0. }));
+        let mut network = Network::from_timetable(&stops, vec![
+            TimetableRoute { line: "To A".to_string(), colour: Default::default(), stops: vec![0, 1], trips: vec![("sa".to_string(), vec![time(0), time(5)])] },
+            TimetableRoute { line: "To B".to_string(), colour: Default::default(), stops: vec![0, 2], trips: vec![("sb".to_string(), vec![time(0), time(12)])] },
+            TimetableRoute { line: "A to C".to_string(), colour: Default::default(), stops: vec![1, 2, 3], trips: vec![
+                ("express".to_string(), vec![time(6), time(10), time(15)]),
+                ("all stops".to_string(), vec![time(8), time(14), time(20)]),
+            ] },
+        ], NaiveDate::default(), 60);
+        let express = GlobalTripIndex::new(RouteIdx(2), TripIdx(0));
+        network.skip_stop(express, 1).unwrap();
+        network.skip_stop(express, 2).unwrap();
+
+        // From A, the express is boarded and its later trip reaches C at the same time as the all stops trip boarded at B, but
+        // with the express's fare, so it isn't kept.
+        let (tau_star, boardings) = mc_raptor_rounds::<ArrayVec<Label, 4>>(&network, 0, 8 * 3600, &[3], &FirstTripFare, &JourneyPreferences::default(), &mut ());
+        let labels_at_c = tau_star[3].iter().map(|label| (label.arrival_time, label.cost)).collect::<Vec<_>>();
+        assert_eq!(labels_at_c, vec![(8 * 3600 + 1200, 0.)]);
+        // One boarding for each trip boarded by a kept label: to A, to B, the express at A and the all stops trip at B.
+        assert_eq!(boardings.len(), 4);
+    }
+
+    #[test]
+    fn departure_columns_follow_delays() {
+        fastrand::seed(22);
+        let mut network = irregular_network();
+        let route = &network.routes[0];
+        assert_eq!(network.departure_column(0, 1), (0..route.num_trips as usize).map(|trip_order| network.stop_times.departure_times[route.get_stop_times_index(trip_order, 1)]).collect::<Vec<_>>());
+        let (trip, _) = network.apply_delay(GlobalTripIndex::new(RouteIdx(0), TripIdx(0)), 0, 3600).unwrap();
+        let route = &network.routes[0];
+        assert_eq!(network.departure_column(0, 1)[trip.trip().idx()], network.stop_times.departure_times[route.get_stop_times_index(trip.trip().idx(), 1)]);
+    }
+}
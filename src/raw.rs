@@ -0,0 +1,91 @@
+use crate::journey::{JourneyError, JourneyPreferences, JourneyResult};
+use crate::network::{CostProvider, GlobalTripIndex, StopIndex, Timestamp};
+use crate::raptor::OneToAll;
+use crate::{Journey, Network, StopIdx};
+
+// The queries with their old signatures, which take raw stop indices. These are kept for one release so callers can migrate
+// to the typed ones (see crate::index); each forwards to the query of the same name at the crate root.
+
+fn stop_idxs(stops: &[StopIndex]) -> Vec<StopIdx> {
+    stops.iter().map(|&stop| StopIdx(stop)).collect()
+}
+
+#[deprecated(note = "Use crate::raptor_query, which takes StopIdx.")]
+pub fn raptor_query(network: &Network, start: StopIndex, start_time: Timestamp, end: StopIndex) -> Result<Journey, JourneyError> {
+    crate::raptor_query(network, StopIdx(start), start_time, StopIdx(end))
+}
+
+#[deprecated(note = "Use crate::raptor_query_with_capacity, which takes StopIdx.")]
+pub fn raptor_query_with_capacity(network: &Network, start: StopIndex, start_time: Timestamp, end: StopIndex, can_board: impl Fn(GlobalTripIndex, usize) -> bool) -> Result<Journey, JourneyError> {
+    crate::raptor_query_with_capacity(network, StopIdx(start), start_time, StopIdx(end), can_board)
+}
+
+#[deprecated(note = "Use crate::raptor_one_to_all, which takes StopIdx.")]
+pub fn raptor_one_to_all(network: &Network, start: StopIndex, start_time: Timestamp) -> Vec<Option<Timestamp>> {
+    crate::raptor_one_to_all(network, StopIdx(start), start_time)
+}
+
+#[deprecated(note = "Use crate::raptor_one_to_all_journeys, which takes StopIdx.")]
+pub fn raptor_one_to_all_journeys(network: &Network, start: StopIndex, start_time: Timestamp) -> OneToAll<'_> {
+    crate::raptor_one_to_all_journeys(network, StopIdx(start), start_time)
+}
+
+#[deprecated(note = "Use crate::raptor_query_alternatives, which takes StopIdx.")]
+pub fn raptor_query_alternatives(network: &Network, start: StopIndex, start_time: Timestamp, end: StopIndex, k: usize) -> Vec<Journey> {
+    crate::raptor_query_alternatives(network, StopIdx(start), start_time, StopIdx(end), k)
+}
+
+#[deprecated(note = "Use crate::raptor::raptor_query_alternatives_with_factor, which takes StopIdx.")]
+pub fn raptor_query_alternatives_with_factor(network: &Network, start: StopIndex, start_time: Timestamp, end: StopIndex, k: usize, max_slowdown: f32) -> Vec<Journey> {
+    crate::raptor::raptor_query_alternatives_with_factor(network, StopIdx(start), start_time, StopIdx(end), k, max_slowdown)
+}
+
+#[deprecated(note = "Use crate::mc_raptor_query, which takes StopIdx.")]
+pub fn mc_raptor_query<'a, const N: usize>(network: &'a Network,
+                                           start: StopIndex,
+                                           start_time: Timestamp,
+                                           ends: &[StopIndex],
+                                           costs: &(impl CostProvider + ?Sized),
+                                           path_preferences: &JourneyPreferences) -> Vec<JourneyResult<'a>> {
+    crate::mc_raptor_query::<N>(network, StopIdx(start), start_time, &stop_idxs(ends), costs, path_preferences)
+}
+
+#[deprecated(note = "Use crate::mc_raptor_query_with_stats, which takes StopIdx.")]
+pub fn mc_raptor_query_with_stats<'a, const N: usize>(network: &'a Network,
+                                                      start: StopIndex,
+                                                      start_time: Timestamp,
+                                                      ends: &[StopIndex],
+                                                      costs: &(impl CostProvider + ?Sized),
+                                                      path_preferences: &JourneyPreferences) -> (Vec<JourneyResult<'a>>, crate::McRaptorStats) {
+    crate::mc_raptor_query_with_stats::<N>(network, StopIdx(start), start_time, &stop_idxs(ends), costs, path_preferences)
+}
+
+#[deprecated(note = "Use crate::mc_raptor_query_exact, which takes StopIdx.")]
+pub fn mc_raptor_query_exact<'a>(network: &'a Network,
+                                 start: StopIndex,
+                                 start_time: Timestamp,
+                                 ends: &[StopIndex],
+                                 costs: &(impl CostProvider + ?Sized),
+                                 path_preferences: &JourneyPreferences) -> Vec<JourneyResult<'a>> {
+    crate::mc_raptor_query_exact(network, StopIdx(start), start_time, &stop_idxs(ends), costs, path_preferences)
+}
+
+#[deprecated(note = "Use crate::csa_query, which takes StopIdx.")]
+pub fn csa_query(network: &Network, start: StopIndex, start_time: Timestamp, end: StopIndex) -> JourneyResult {
+    crate::csa_query(network, StopIdx(start), start_time, StopIdx(end))
+}
+
+#[deprecated(note = "Use crate::csa_query_with_capacity, which takes StopIdx.")]
+pub fn csa_query_with_capacity(network: &Network, start: StopIndex, start_time: Timestamp, end: StopIndex, can_board: impl Fn(GlobalTripIndex, usize) -> bool) -> JourneyResult {
+    crate::csa_query_with_capacity(network, StopIdx(start), start_time, StopIdx(end), can_board)
+}
+
+#[deprecated(note = "Use crate::mc_csa_query, which takes StopIdx.")]
+pub fn mc_csa_query<'a>(network: &'a Network, start: StopIndex, start_time: Timestamp, end: StopIndex, costs: &(impl CostProvider + ?Sized), path_preferences: &JourneyPreferences) -> JourneyResult<'a> {
+    crate::mc_csa_query(network, StopIdx(start), start_time, StopIdx(end), costs, path_preferences)
+}
+
+#[deprecated(note = "Use crate::reference::dijkstra_query, which takes StopIdx.")]
+pub fn dijkstra_query(network: &Network, start: StopIndex, start_time: Timestamp, end: StopIndex) -> Result<Timestamp, JourneyError> {
+    crate::reference::dijkstra_query(network, StopIdx(start), start_time, StopIdx(end))
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use gtfs_rt::trip_descriptor::ScheduleRelationship as TripRelationship;
+use gtfs_rt::trip_update::stop_time_update::ScheduleRelationship as StopRelationship;
+use gtfs_rt::trip_update::StopTimeUpdate;
+use gtfs_rt::FeedMessage;
+
+use crate::index::{RouteIdx, TripIdx};
+use crate::network::{AddTripError, GlobalTripIndex, RouteIndex, StopIndex, Timestamp};
+use crate::Network;
+
+// Why a TripUpdate was not applied.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum RejectedUpdate {
+    #[error("The trip is already cancelled.")]
+    AlreadyCancelled,
+    #[error("An added trip has the ID of an existing trip.")]
+    DuplicateTripId,
+    #[error("An added trip needs a stop_id and a time at every stop, which update {0} is missing.")]
+    IncompleteStopTime(usize),
+    #[error("An added trip's time at stop {0} is before the network's date.")]
+    TimeOutOfRange(String),
+    #[error("Update {0} has no stop_id. Stops can't be matched by stop_sequence, as the network doesn't keep GTFS stop_sequence values.")]
+    MissingStopId(usize),
+    #[error("No route serves the added trip's stops {0:?} in that order.")]
+    NoMatchingRoute(Vec<String>),
+    #[error(transparent)]
+    InvalidTrip(#[from] AddTripError),
+}
+
+// What Network::apply_trip_updates did with a feed.
+#[derive(Debug, Default, PartialEq)]
+pub struct UpdateReport {
+    // Trips with at least one delay or skipped stop applied.
+    pub num_trips_updated: usize,
+    pub num_delays_applied: usize,
+    pub num_stops_skipped: usize,
+    // Trip IDs of cancelled trips, and of added trips that were inserted into the network.
+    pub cancelled_trips: Vec<String>,
+    pub added_trips: Vec<String>,
+    // Trip IDs of TripUpdates that matched a trip (or were added trips) but weren't applied, and why.
+    pub rejected_trips: Vec<(String, RejectedUpdate)>,
+    // Trip IDs of TripUpdates that don't match a trip in the network (e.g. trips on another day). Updates
+    // without a trip ID are listed by their entity ID.
+    pub unmatched_trips: Vec<String>,
+    // (Trip ID, stop ID) for StopTimeUpdates whose stop isn't on the trip (or is before the previous update's).
+    pub out_of_range_stops: Vec<(String, String)>,
+}
+
+impl Network {
+    // Applies the TripUpdates in a GTFS-Realtime feed. Delays propagate to later stops until the next StopTimeUpdate, as in the
+    // GTFS-Realtime spec, and are applied with apply_delay, so trips may be reordered. Skipped stops are applied with skip_stop,
+    // and cancelled trips with cancel_trip. Added trips are inserted with add_trip into the route with exactly their stops.
+    // Stops are matched by stop_id. The network doesn't keep GTFS stop_sequence values, so trips with an update that only has a
+    // stop_sequence are rejected rather than guessing which stop it means.
+    // Existing trips only use delays, and added trips use absolute times, which are read as UTC (see apply_trip_updates_with_utc_offset).
+    // NOTE: Delays are relative to the timetable, so apply each feed to a network that hasn't had earlier delays applied.
+    pub fn apply_trip_updates(&mut self, feed: &FeedMessage) -> UpdateReport {
+        self.apply_trip_updates_with_utc_offset(feed, 0)
+    }
+
+    // Applies the TripUpdates in a GTFS-Realtime feed whose timetable is utc_offset_secs ahead of UTC (e.g. 36000 for AEST).
+    pub fn apply_trip_updates_with_utc_offset(&mut self, feed: &FeedMessage, utc_offset_secs: i32) -> UpdateReport {
+        // Trips move within their route when delayed, but never between routes.
+        let mut trip_routes = self.routes.iter().enumerate()
+            .flat_map(|(route_idx, route)| route.trip_ids.iter().map(move |trip_id| (trip_id.to_string(), route_idx as RouteIndex)))
+            .collect::<HashMap<_, _>>();
+
+        let mut report = UpdateReport::default();
+        for entity in feed.entity.iter() {
+            let Some(trip_update) = &entity.trip_update else {
+                continue;
+            };
+            let Some(trip_id) = trip_update.trip.trip_id.as_deref() else {
+                report.unmatched_trips.push(entity.id.clone());
+                continue;
+            };
+            if trip_update.trip.schedule_relationship() == TripRelationship::Added {
+                let added = if trip_routes.contains_key(trip_id) {
+                    Err(RejectedUpdate::DuplicateTripId)
+                } else {
+                    self.add_realtime_trip(trip_id, &trip_update.stop_time_update, utc_offset_secs)
+                };
+                match added {
+                    Ok(trip) => {
+                        trip_routes.insert(trip_id.to_string(), trip.route_idx);
+                        report.added_trips.push(trip_id.to_string());
+                    }
+                    Err(reason) => report.rejected_trips.push((trip_id.to_string(), reason)),
+                }
+                continue;
+            }
+
+            let Some(&route_idx) = trip_routes.get(trip_id) else {
+                report.unmatched_trips.push(trip_id.to_string());
+                continue;
+            };
+            let trip_order = self.routes[route_idx as usize].trip_ids.position(trip_id).unwrap();
+            let trip = GlobalTripIndex::new(RouteIdx(route_idx), TripIdx::new(trip_order));
+
+            if trip_update.trip.schedule_relationship() == TripRelationship::Canceled {
+                match self.cancel_trip(trip) {
+                    Ok(()) => report.cancelled_trips.push(trip_id.to_string()),
+                    Err(_) => report.rejected_trips.push((trip_id.to_string(), RejectedUpdate::AlreadyCancelled)),
+                }
+                continue;
+            }
+
+            if let Some(i) = trip_update.stop_time_update.iter().position(|update| update.stop_id.is_none()) {
+                report.rejected_trips.push((trip_id.to_string(), RejectedUpdate::MissingStopId(i)));
+                continue;
+            }
+            if self.apply_stop_time_updates(trip, trip_id, trip_update.delay, &trip_update.stop_time_update, &mut report) {
+                report.num_trips_updated += 1;
+            }
+        }
+        report
+    }
+
+    // Applies one trip's StopTimeUpdates, which must all have a stop_id, or its trip-level delay if it has none. Returns whether
+    // anything changed.
+    fn apply_stop_time_updates(&mut self, mut trip: GlobalTripIndex, trip_id: &str, trip_delay: Option<i32>, updates: &[StopTimeUpdate], report: &mut UpdateReport) -> bool {
+        if updates.is_empty() {
+            return match trip_delay.filter(|&delay| delay != 0) {
+                Some(delay) => {
+                    self.apply_delay(trip, 0, delay).expect("trip was found by ID");
+                    report.num_delays_applied += 1;
+                    true
+                }
+                None => false,
+            };
+        }
+
+        let mut updated = false;
+        // The delay currently applied from the previous update onwards, which apply_delay may have limited, and the first stop
+        // order the next update can match.
+        let mut applied_delay = 0;
+        let mut next_stop_order = 0;
+        for update in updates {
+            let stop_id = update.stop_id.as_deref().expect("updates without a stop_id are rejected");
+            let Some(stop_order) = self.find_update_stop_order(trip, stop_id, next_stop_order) else {
+                report.out_of_range_stops.push((trip_id.to_string(), stop_id.to_string()));
+                continue;
+            };
+            next_stop_order = stop_order;
+
+            // Without data, later stops go back to the timetable.
+            let delay = match update.schedule_relationship() {
+                StopRelationship::Skipped => {
+                    self.skip_stop(trip, stop_order).expect("stop order is on the trip");
+                    report.num_stops_skipped += 1;
+                    updated = true;
+                    continue;
+                }
+                StopRelationship::NoData => Some(0),
+                _ => update.arrival.as_ref().and_then(|event| event.delay).or(update.departure.as_ref().and_then(|event| event.delay)),
+            };
+            if let Some(delay) = delay.filter(|&delay| delay != applied_delay) {
+                let (delayed, shift) = self.apply_delay(trip, stop_order, delay - applied_delay).expect("stop order is on the trip");
+                trip = delayed;
+                applied_delay += shift;
+                report.num_delays_applied += 1;
+                updated = true;
+            }
+        }
+        updated
+    }
+
+    // Inserts an added trip into the route with exactly its stops. Every StopTimeUpdate needs a stop_id and an absolute time.
+    fn add_realtime_trip(&mut self, trip_id: &str, updates: &[StopTimeUpdate], utc_offset_secs: i32) -> Result<GlobalTripIndex, RejectedUpdate> {
+        // The POSIX time of local midnight on the network's date.
+        let local_midnight = self.date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() - utc_offset_secs as i64;
+
+        let mut stops = Vec::with_capacity(updates.len());
+        let mut stop_times = Vec::with_capacity(updates.len());
+        for (i, update) in updates.iter().enumerate() {
+            let arrival = update.arrival.as_ref().and_then(|event| event.time);
+            let departure = update.departure.as_ref().and_then(|event| event.time);
+            let (Some(stop_id), Some(arrival), Some(departure)) = (&update.stop_id, arrival.or(departure), departure.or(arrival)) else {
+                return Err(RejectedUpdate::IncompleteStopTime(i));
+            };
+            let to_timestamp = |time: i64| Timestamp::try_from(time - local_midnight).map_err(|_| RejectedUpdate::TimeOutOfRange(stop_id.clone()));
+            stops.push(stop_id.clone());
+            stop_times.push((to_timestamp(arrival)?, to_timestamp(departure)?));
+        }
+
+        let stop_idxs = stops.iter().map(|stop_id| self.stop_index.get(stop_id).copied()).collect::<Option<Vec<StopIndex>>>();
+        let route_idx = stop_idxs.and_then(|stop_idxs| self.routes.iter().position(|route| route.get_stops(&self.route_stops) == stop_idxs.as_slice()))
+            .ok_or(RejectedUpdate::NoMatchingRoute(stops))?;
+        Ok(self.add_trip(route_idx as RouteIndex, &stop_times, trip_id)?)
+    }
+
+    // Returns the stop order of the stop on the trip, at or after min_stop_order.
+    fn find_update_stop_order(&self, trip: GlobalTripIndex, stop_id: &str, min_stop_order: usize) -> Option<usize> {
+        let stop_idx = *self.stop_index.get(stop_id)?;
+        let route = &self.routes[trip.route().idx()];
+        route.get_stops(&self.route_stops)[min_stop_order..].iter().position(|&stop| stop == stop_idx).map(|offset| min_stop_order + offset)
+    }
+}
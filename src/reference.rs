@@ -0,0 +1,201 @@
+use crate::index::StopIdx;
+use crate::journey::JourneyError;
+use crate::network::{StopIndex, Timestamp};
+use crate::Network;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// A slow but simple reference router, used to validate the results of the RAPTOR and CSA implementations.
+
+#[derive(Clone, Copy, PartialEq)]
+enum EventKind {
+    // Arriving at a stop on a trip.
+    Arrival,
+    // Passing through a stop the trip skips, without being able to alight.
+    PassThrough,
+    // Departing a stop on a trip.
+    Departure,
+    // Waiting at a stop, able to board any departure from this time on.
+    Wait,
+    // Arriving at a stop on foot, after alighting at another stop.
+    Walked,
+}
+
+struct Event {
+    stop: StopIndex,
+    time: Timestamp,
+    kind: EventKind,
+}
+
+// A time-expanded graph, where each node is an event at a stop and edges go forwards in time.
+pub struct TimeExpandedGraph {
+    events: Vec<Event>,
+    // Outgoing edges for each event, weighted by the time between the two events.
+    edges: Vec<Vec<(usize, Timestamp)>>,
+    // Wait events for each stop, sorted by time.
+    stop_waits: Vec<Vec<usize>>,
+    // The stops each stop has a footpath to, and the walk's duration, for walking from the start.
+    footpaths: Vec<Vec<(StopIndex, Timestamp)>>,
+    // For reporting queries that find no journey.
+    stop_names: Vec<String>,
+}
+
+impl TimeExpandedGraph {
+    pub fn new(network: &Network) -> Self {
+        let mut events = Vec::new();
+        let mut edges = Vec::new();
+        fn add_event(events: &mut Vec<Event>, edges: &mut Vec<Vec<(usize, Timestamp)>>, stop: StopIndex, time: Timestamp, kind: EventKind) -> usize {
+            events.push(Event { stop, time, kind });
+            edges.push(Vec::new());
+            events.len() - 1
+        }
+
+        // Arrival and departure events for every stop time, connected by trip edges and stay-seated edges.
+        // A wait event is created for every departure, so that boarding is only possible from waiting at the stop.
+        let mut stop_waits = vec![Vec::new(); network.stops.len()];
+        let mut arrivals = Vec::new();
+        for route in network.routes.iter() {
+            let stops = route.get_stops(&network.route_stops);
+            for trip_order in (0..route.num_trips as usize).filter(|&trip_order| !route.is_cancelled(trip_order)) {
+                let trip = route.get_trip(trip_order, &network.stop_times);
+                let mut previous_departure: Option<usize> = None;
+                for (stop_order, stop_time) in trip.iter().enumerate() {
+                    let stop = stops[stop_order];
+                    let skipped = route.is_skipped(trip_order, stop_order);
+                    let arrival_kind = if skipped { EventKind::PassThrough } else { EventKind::Arrival };
+                    let arrival = add_event(&mut events, &mut edges, stop, stop_time.arrival_time, arrival_kind);
+                    let departure = add_event(&mut events, &mut edges, stop, stop_time.departure_time, EventKind::Departure);
+                    let wait = add_event(&mut events, &mut edges, stop, stop_time.departure_time, EventKind::Wait);
+
+                    if let Some(previous_departure) = previous_departure {
+                        let weight = stop_time.arrival_time.saturating_sub(events[previous_departure].time);
+                        edges[previous_departure].push((arrival, weight));
+                    }
+                    edges[arrival].push((departure, stop_time.departure_time.saturating_sub(stop_time.arrival_time)));
+                    previous_departure = Some(departure);
+
+                    // Where the trip skips the stop, passengers can only stay seated.
+                    if skipped {
+                        continue;
+                    }
+                    edges[wait].push((departure, 0));
+                    stop_waits[stop as usize].push(wait);
+                    arrivals.push(arrival);
+                }
+            }
+        }
+
+        // Waiting edges between consecutive wait events at each stop.
+        for waits in stop_waits.iter_mut() {
+            waits.sort_by_key(|&wait| events[wait].time);
+            for pair in waits.windows(2) {
+                edges[pair[0]].push((pair[1], events[pair[1]].time - events[pair[0]].time));
+            }
+        }
+
+        let stop_names = (0..network.num_stops()).map(|stop| network.stop_name(stop).to_string()).collect();
+        let footpaths = (0..network.num_stops())
+            .map(|stop| network.footpaths_from(stop as StopIndex).iter().map(|footpath| (footpath.to, network.get_footpath_time(footpath))).collect())
+            .collect();
+        let mut graph = Self { events, edges, stop_waits, footpaths, stop_names };
+
+        // Transfer edges from each arrival to the first wait event at the stop after the transfer time.
+        for &arrival in &arrivals {
+            let Event { stop, time, .. } = graph.events[arrival];
+            let ready_time = time.saturating_add(network.get_effective_transfer_time(stop, stop));
+            if let Some(wait) = graph.first_wait(stop, ready_time) {
+                let weight = graph.events[wait].time - time;
+                graph.edges[arrival].push((wait, weight));
+            }
+        }
+
+        // Walks from each arrival along the stop's footpaths, to the first wait event at the stop walked to. Walks aren't chained,
+        // and there is no transfer time after walking, as the footpath's duration includes it. The routers only walk from arrivals
+        // that improve on the stop's earliest arrival, which finds the same arrival times if the footpaths are transitively closed.
+        for arrival in arrivals {
+            let Event { stop, time, .. } = graph.events[arrival];
+            for footpath_idx in 0..graph.footpaths[stop as usize].len() {
+                let (to, duration) = graph.footpaths[stop as usize][footpath_idx];
+                let walked = add_event(&mut graph.events, &mut graph.edges, to, time.saturating_add(duration), EventKind::Walked);
+                graph.edges[arrival].push((walked, duration));
+                if let Some(wait) = graph.first_wait(to, graph.events[walked].time) {
+                    let weight = graph.events[wait].time - graph.events[walked].time;
+                    graph.edges[walked].push((wait, weight));
+                }
+            }
+        }
+
+        graph
+    }
+
+    fn no_journey_found(&self, start: StopIndex, end: StopIndex, start_time: Timestamp, arrival_times: impl Iterator<Item=Timestamp>) -> JourneyError {
+        let (start, end) = (start as usize, end as usize);
+        JourneyError::no_journey_found_between(start, end, &self.stop_names[start], &self.stop_names[end], start_time, arrival_times)
+    }
+
+    // Returns the first wait event at the given stop at or after the given time.
+    fn first_wait(&self, stop: StopIndex, time: Timestamp) -> Option<usize> {
+        let waits = &self.stop_waits[stop as usize];
+        let i = waits.partition_point(|&wait| self.events[wait].time < time);
+        waits.get(i).copied()
+    }
+
+    // Returns the earliest arrival time at end when leaving start at start_time.
+    pub fn query(&self, start: StopIndex, start_time: Timestamp, end: StopIndex) -> Result<Timestamp, JourneyError> {
+        if start == end {
+            return Ok(start_time);
+        }
+
+        // The journey may start by walking, straight to end or to the first trip. No transfer time is required to board the first
+        // trip.
+        let walks = self.footpaths[start as usize].iter().map(|&(to, duration)| (to, start_time.saturating_add(duration)));
+        let walk_to_end = walks.clone().filter(|&(to, _)| to == end).map(|(_, time)| time).min();
+        let sources = std::iter::once((start, start_time)).chain(walks).filter_map(|(stop, time)| self.first_wait(stop, time));
+
+        let mut dist = vec![Timestamp::MAX; self.events.len()];
+        let mut heap = BinaryHeap::new();
+        for source in sources {
+            dist[source] = self.events[source].time;
+            heap.push(Reverse((dist[source], source)));
+        }
+
+        while let Some(Reverse((time, event))) = heap.pop() {
+            if time > dist[event] {
+                continue;
+            }
+            if let Some(walk_to_end) = walk_to_end.filter(|&walk_to_end| walk_to_end <= time) {
+                return Ok(walk_to_end);
+            }
+            let Event { stop, kind, .. } = self.events[event];
+            if stop == end && matches!(kind, EventKind::Arrival | EventKind::Walked) {
+                return Ok(time);
+            }
+            for &(next, weight) in self.edges[event].iter() {
+                let next_time = time + weight;
+                if next_time < dist[next] {
+                    dist[next] = next_time;
+                    heap.push(Reverse((next_time, next)));
+                }
+            }
+        }
+        if let Some(walk_to_end) = walk_to_end {
+            return Ok(walk_to_end);
+        }
+
+        // The earliest arrival at each stop, for reporting how far the search got.
+        let mut arrival_times = vec![Timestamp::MAX; self.stop_waits.len()];
+        for (event, &time) in self.events.iter().zip(dist.iter()) {
+            if matches!(event.kind, EventKind::Arrival | EventKind::Walked) {
+                let arrival_time = &mut arrival_times[event.stop as usize];
+                *arrival_time = (*arrival_time).min(time);
+            }
+        }
+        Err(self.no_journey_found(start, end, start_time, arrival_times.into_iter()))
+    }
+}
+
+// Builds a time-expanded graph of the network and runs Dijkstra's algorithm on it to find the earliest arrival time at end.
+// This is much slower than the other queries, and is intended as ground truth for testing.
+pub fn dijkstra_query(network: &Network, start: StopIdx, start_time: Timestamp, end: StopIdx) -> Result<Timestamp, JourneyError> {
+    TimeExpandedGraph::new(network).query(start.0, start_time, end.0)
+}
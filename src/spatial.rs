@@ -0,0 +1,162 @@
+use crate::network::{CoordType, NetworkPoint, StopIndex};
+
+// Width and height of a grid cell in degrees (roughly 1 km).
+const CELL_DEGREES: CoordType = 0.01;
+
+// A fixed-cell grid over stop points, for nearest-stop queries. Stops without valid coordinates are left out.
+pub(crate) struct StopGrid {
+    min_latitude: CoordType,
+    min_longitude: CoordType,
+    num_lat_cells: usize,
+    num_lon_cells: usize,
+    // Stops in each cell, indexed by lat_cell * num_lon_cells + lon_cell.
+    cells: Vec<Vec<StopIndex>>,
+    // Largest absolute latitude of any stop, which determines the narrowest cell width.
+    max_abs_latitude: CoordType,
+}
+
+impl StopGrid {
+    pub(crate) fn new(stop_points: &[NetworkPoint]) -> Self {
+        let valid_points = || stop_points.iter().enumerate().filter(|(_, point)| point.is_valid());
+
+        let mut min_latitude = CoordType::INFINITY;
+        let mut min_longitude = CoordType::INFINITY;
+        let mut max_latitude = CoordType::NEG_INFINITY;
+        let mut max_longitude = CoordType::NEG_INFINITY;
+        for (_, point) in valid_points() {
+            min_latitude = min_latitude.min(point.latitude);
+            min_longitude = min_longitude.min(point.longitude);
+            max_latitude = max_latitude.max(point.latitude);
+            max_longitude = max_longitude.max(point.longitude);
+        }
+
+        let mut grid = StopGrid {
+            min_latitude,
+            min_longitude,
+            num_lat_cells: 0,
+            num_lon_cells: 0,
+            cells: Vec::new(),
+            max_abs_latitude: min_latitude.abs().max(max_latitude.abs()),
+        };
+        if min_latitude > max_latitude {
+            // No valid stops.
+            return grid;
+        }
+
+        grid.num_lat_cells = ((max_latitude - min_latitude) / CELL_DEGREES) as usize + 1;
+        grid.num_lon_cells = ((max_longitude - min_longitude) / CELL_DEGREES) as usize + 1;
+        grid.cells = vec![Vec::new(); grid.num_lat_cells * grid.num_lon_cells];
+        for (stop_idx, &point) in valid_points() {
+            let (lat_cell, lon_cell) = grid.cell(point);
+            let index = grid.cell_index(lat_cell, lon_cell).unwrap();
+            grid.cells[index].push(stop_idx as StopIndex);
+        }
+        grid
+    }
+
+    // The cell containing the point. Points outside the grid are clamped to the nearest edge cell, which only brings them closer
+    // to the cells, so ring distances remain lower bounds.
+    fn cell(&self, point: NetworkPoint) -> (isize, isize) {
+        let lat_cell = ((point.latitude - self.min_latitude) / CELL_DEGREES).floor() as isize;
+        let lon_cell = ((point.longitude - self.min_longitude) / CELL_DEGREES).floor() as isize;
+        (lat_cell.clamp(0, self.num_lat_cells as isize - 1), lon_cell.clamp(0, self.num_lon_cells as isize - 1))
+    }
+
+    fn cell_index(&self, lat_cell: isize, lon_cell: isize) -> Option<usize> {
+        if lat_cell < 0 || lon_cell < 0 || lat_cell >= self.num_lat_cells as isize || lon_cell >= self.num_lon_cells as isize {
+            return None;
+        }
+        Some(lat_cell as usize * self.num_lon_cells + lon_cell as usize)
+    }
+
+    // The narrowest width of a cell in km, for distances from the point. A stop in a cell `ring` cells away (in Chebyshev
+    // distance) is at least ring - 1 of these away. Cells are narrowest at the largest absolute latitude, and distances use the
+    // cosine of the mean latitude, so this bounds the distance.
+    fn min_cell_km(&self, point: NetworkPoint) -> CoordType {
+        let max_abs_latitude = self.max_abs_latitude.max(point.latitude.abs());
+        CELL_DEGREES.to_radians() * NetworkPoint::EARTH_RADIUS * max_abs_latitude.to_radians().cos()
+    }
+
+    // The number of rings around the cell needed to cover the whole grid.
+    fn max_ring(&self, (lat_cell, lon_cell): (isize, isize)) -> isize {
+        [lat_cell, self.num_lat_cells as isize - 1 - lat_cell, lon_cell, self.num_lon_cells as isize - 1 - lon_cell]
+            .into_iter()
+            .max()
+            .unwrap()
+    }
+
+    // Calls f with the stops in each cell on the border of the ring around the cell.
+    fn visit_ring(&self, (lat_cell, lon_cell): (isize, isize), ring: isize, mut f: impl FnMut(&[StopIndex])) {
+        let mut visit_cell = |lat: isize, lon: isize| {
+            if let Some(index) = self.cell_index(lat, lon) {
+                f(&self.cells[index]);
+            }
+        };
+        for lat in (lat_cell - ring)..=(lat_cell + ring) {
+            if (lat - lat_cell).abs() == ring {
+                for lon in (lon_cell - ring)..=(lon_cell + ring) {
+                    visit_cell(lat, lon);
+                }
+            } else {
+                visit_cell(lat, lon_cell - ring);
+                visit_cell(lat, lon_cell + ring);
+            }
+        }
+    }
+
+    // Returns the k stops closest to the point, sorted by distance (in km) and then by stop index.
+    pub(crate) fn nearest(&self, stop_points: &[NetworkPoint], point: NetworkPoint, k: usize) -> Vec<(StopIndex, CoordType)> {
+        if k == 0 || self.cells.is_empty() || !point.is_valid() {
+            return Vec::new();
+        }
+
+        let cell = self.cell(point);
+        let min_cell_km = self.min_cell_km(point);
+        let mut candidates = Vec::new();
+        for ring in 0..=self.max_ring(cell) {
+            self.visit_ring(cell, ring, |stops| {
+                candidates.extend(stops.iter().map(|&stop| (stop, point.distance(stop_points[stop as usize]))));
+            });
+
+            // Stop once the k-th closest candidate is closer than any stop in the remaining rings.
+            if candidates.len() >= k {
+                let (_, &mut (_, kth_distance), _) = candidates.select_nth_unstable_by(k - 1, |a, b| a.1.total_cmp(&b.1));
+                if kth_distance <= ring as CoordType * min_cell_km {
+                    break;
+                }
+            }
+        }
+
+        candidates.sort_unstable_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        candidates.truncate(k);
+        candidates
+    }
+
+    // Returns the stops within radius_km of the point, sorted by distance (in km) and then by stop index.
+    pub(crate) fn within(&self, stop_points: &[NetworkPoint], point: NetworkPoint, radius_km: CoordType) -> Vec<(StopIndex, CoordType)> {
+        if self.cells.is_empty() || !point.is_valid() || radius_km.is_nan() || radius_km < 0. {
+            return Vec::new();
+        }
+
+        let cell = self.cell(point);
+        let min_cell_km = self.min_cell_km(point);
+        let mut stops_within = Vec::new();
+        for ring in 0..=self.max_ring(cell) {
+            // Every stop in this ring or further is at least (ring - 1) cell widths away.
+            if (ring - 1) as CoordType * min_cell_km > radius_km {
+                break;
+            }
+            self.visit_ring(cell, ring, |stops| {
+                for &stop in stops {
+                    let distance = point.distance(stop_points[stop as usize]);
+                    if distance <= radius_km {
+                        stops_within.push((stop, distance));
+                    }
+                }
+            });
+        }
+
+        stops_within.sort_unstable_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        stops_within
+    }
+}
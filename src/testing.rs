@@ -0,0 +1,113 @@
+use crate::index::StopIdx;
+use crate::journey::{JourneyError, JourneyResult};
+use crate::network::{StopIndex, Timestamp};
+use crate::reference::dijkstra_query;
+use crate::{csa_query, raptor_query, utils, Network};
+use std::fmt::Write;
+
+// Helpers for validating the routers, e.g. on your own GTFS feed. Enabled with the testing feature.
+
+// Runs raptor_query and csa_query for each (start, start time, end) query, and panics if their earliest arrival times differ,
+// including when only one of them finds a journey. The panic message lists every disagreement with both journeys, and the
+// arrival time from reference::dijkstra_query to show which is right.
+// Connections must be built first (see Network::build_connections).
+pub fn assert_algorithms_agree(network: &Network, queries: &[(StopIndex, Timestamp, StopIndex)]) {
+    assert!(network.num_trips == 0 || !network.connections.is_empty(), "Call build_connections before assert_algorithms_agree.");
+
+    let mut report = String::new();
+    let mut num_disagreements = 0;
+    for &(start, start_time, end) in queries {
+        let raptor_result = raptor_query(network, StopIdx(start), start_time, StopIdx(end));
+        let csa_result = csa_query(network, StopIdx(start), start_time, StopIdx(end));
+        if arrival(&raptor_result) == arrival(&csa_result) {
+            continue;
+        }
+
+        num_disagreements += 1;
+        let stop_name = |stop: StopIndex| network.stop_name(stop as usize);
+        // Writing to a String can't fail.
+        writeln!(report, "From {} ({start}) at {} to {} ({end}):", stop_name(start), utils::get_time_str(start_time), stop_name(end)).unwrap();
+        writeln!(report, "RAPTOR: {}", describe(&raptor_result)).unwrap();
+        writeln!(report, "CSA: {}", describe(&csa_result)).unwrap();
+        match dijkstra_query(network, StopIdx(start), start_time, StopIdx(end)) {
+            Ok(arrival_time) => writeln!(report, "Reference: arrives at {}.", utils::get_time_str(arrival_time)).unwrap(),
+            Err(e) => writeln!(report, "Reference: {e}").unwrap(),
+        }
+        writeln!(report).unwrap();
+    }
+    assert!(num_disagreements == 0, "RAPTOR and CSA disagree on {num_disagreements} of {} queries.\n\n{report}", queries.len());
+}
+
+// The arrival time, None if no journey was found, or any other error.
+fn arrival(result: &JourneyResult) -> Result<Option<Timestamp>, String> {
+    match result {
+        Ok(journey) => Ok(journey.arrival_time()),
+        Err(JourneyError::NoJourneyFound { .. }) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn describe(result: &JourneyResult) -> String {
+    match result {
+        Ok(journey) => match journey.arrival_time() {
+            Some(arrival_time) => format!("arrives at {}.\n{journey:#}", utils::get_time_str(arrival_time)),
+            None => format!("empty journey.\n{journey:#}"),
+        },
+        Err(e) => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::{NetworkPoint, StopTime, TimetableRoute};
+    use chrono::NaiveDate;
+
+    // Two lines from stop 0 to stop 2, one direct and one changing at stop 1, every 10 minutes from 08:00.
+    fn network() -> Network {
+        let stop_times = |times: &[Timestamp]| times.iter().map(|&time| StopTime { arrival_time: time, departure_time: time }).collect::<Vec<_>>();
+        let route = |line: &str, stops: Vec<StopIndex>, offsets: &[Timestamp]| TimetableRoute {
+            line: line.to_string(),
+            colour: Default::default(),
+            stops,
+            trips: (0..6).map(|trip| {
+                let start = 8 * 3600 + trip * 600;
+                (format!("{line} {trip}"), stop_times(&offsets.iter().map(|offset| start + offset).collect::<Vec<_>>()))
+            }).collect(),
+        };
+        let point = NetworkPoint { latitude: -37.8, longitude: 145. };
+        Network::from_timetable(&[("A", "a", point), ("B", "b", point), ("C", "c", point)], vec![
+            route("Direct", vec![0, 2], &[0, 1800]),
+            route("First", vec![0, 1], &[300, 600]),
+            route("Second", vec![1, 2], &[720, 1200]),
+        ], NaiveDate::default(), 60)
+    }
+
+    #[test]
+    fn agreeing_queries_pass() {
+        let mut network = network();
+        network.build_connections();
+        let queries = (0..3).flat_map(|start| (0..3).map(move |end| (start, end)))
+            .filter(|(start, end)| start != end)
+            .flat_map(|(start, end)| (7 * 3600..10 * 3600).step_by(317).map(move |time| (start, time, end)))
+            .collect::<Vec<_>>();
+        assert_algorithms_agree(&network, &queries);
+    }
+
+    #[test]
+    #[should_panic(expected = "build_connections")]
+    fn requires_connections() {
+        assert_algorithms_agree(&network(), &[(0, 8 * 3600, 2)]);
+    }
+
+    #[test]
+    fn arrivals_distinguish_missing_journeys() {
+        let mut network = network();
+        network.build_connections();
+        let found = raptor_query(&network, StopIdx(0), 8 * 3600, StopIdx(2));
+        let not_found = raptor_query(&network, StopIdx(2), 8 * 3600, StopIdx(0));
+        assert!(matches!(arrival(&found), Ok(Some(_))));
+        assert_eq!(arrival(&not_found), Ok(None));
+        assert_eq!(describe(&not_found), "No journey found from C to A.");
+    }
+}
@@ -0,0 +1,192 @@
+use crate::network::Timestamp;
+use crate::utils;
+use std::fmt::{Display, Formatter};
+use std::ops::{Add, AddAssign};
+
+// A time of day in seconds since midnight on the network's date, which can be past 24:00:00. Unlike a bare Timestamp, times
+// can only be offset by Seconds, and the difference between two times is checked, so they can't be added together or
+// subtracted in the wrong order.
+// Timestamp converts to and from Time, so code can move over incrementally.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
+pub struct Time(pub Timestamp);
+
+// A duration in seconds.
+#[repr(transparent)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug)]
+pub struct Seconds(pub Timestamp);
+
+impl Time {
+    pub const MIDNIGHT: Time = Time(0);
+    // Used as "never reached" by the routers.
+    pub const MAX: Time = Time(Timestamp::MAX);
+
+    pub const fn from_hms(hours: Timestamp, minutes: Timestamp, seconds: Timestamp) -> Time {
+        Time(hours * 3600 + minutes * 60 + seconds)
+    }
+
+    pub fn checked_add(self, duration: Seconds) -> Option<Time> {
+        self.0.checked_add(duration.0).map(Time)
+    }
+
+    // Saturates at Time::MAX, so unreached times stay unreached.
+    pub fn saturating_add(self, duration: Seconds) -> Time {
+        Time(self.0.saturating_add(duration.0))
+    }
+
+    pub fn checked_sub(self, duration: Seconds) -> Option<Time> {
+        self.0.checked_sub(duration.0).map(Time)
+    }
+
+    // The time from earlier to self, or None if earlier is later than self.
+    pub fn checked_duration_since(self, earlier: Time) -> Option<Seconds> {
+        self.0.checked_sub(earlier.0).map(Seconds)
+    }
+
+    // The time from earlier to self, or zero if earlier is later than self.
+    pub fn saturating_duration_since(self, earlier: Time) -> Seconds {
+        Seconds(self.0.saturating_sub(earlier.0))
+    }
+}
+
+impl Seconds {
+    pub const ZERO: Seconds = Seconds(0);
+
+    pub const fn from_minutes(minutes: Timestamp) -> Seconds {
+        Seconds(minutes * 60)
+    }
+
+    pub fn checked_add(self, other: Seconds) -> Option<Seconds> {
+        self.0.checked_add(other.0).map(Seconds)
+    }
+
+    pub fn saturating_add(self, other: Seconds) -> Seconds {
+        Seconds(self.0.saturating_add(other.0))
+    }
+
+    pub fn checked_sub(self, other: Seconds) -> Option<Seconds> {
+        self.0.checked_sub(other.0).map(Seconds)
+    }
+}
+
+// Like integer addition, this panics on overflow in debug builds. Use checked_add or saturating_add near Time::MAX.
+impl Add<Seconds> for Time {
+    type Output = Time;
+
+    fn add(self, duration: Seconds) -> Time {
+        Time(self.0 + duration.0)
+    }
+}
+
+impl AddAssign<Seconds> for Time {
+    fn add_assign(&mut self, duration: Seconds) {
+        self.0 += duration.0;
+    }
+}
+
+impl Add for Seconds {
+    type Output = Seconds;
+
+    fn add(self, other: Seconds) -> Seconds {
+        Seconds(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Seconds {
+    fn add_assign(&mut self, other: Seconds) {
+        self.0 += other.0;
+    }
+}
+
+impl From<Timestamp> for Time {
+    fn from(time: Timestamp) -> Self {
+        Time(time)
+    }
+}
+
+impl From<Time> for Timestamp {
+    fn from(time: Time) -> Self {
+        time.0
+    }
+}
+
+impl From<Timestamp> for Seconds {
+    fn from(duration: Timestamp) -> Self {
+        Seconds(duration)
+    }
+}
+
+impl From<Seconds> for Timestamp {
+    fn from(duration: Seconds) -> Self {
+        duration.0
+    }
+}
+
+// HH:MM:SS, with hours past 24 for times after midnight, like utils::get_time_str.
+impl Display for Time {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", utils::get_time_str(self.0))
+    }
+}
+
+// Hours and minutes, like utils::format_duration in the default style.
+impl Display for Seconds {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", utils::format_duration(self.0, utils::DurationStyle::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checked_arithmetic() {
+        let time = Time::from_hms(8, 30, 0);
+        assert_eq!(time + Seconds::from_minutes(15), Time::from_hms(8, 45, 0));
+        assert_eq!(time.checked_add(Seconds(1)), Some(Time(time.0 + 1)));
+        assert_eq!(Time::MAX.checked_add(Seconds(1)), None);
+        assert_eq!(Time::MAX.checked_add(Seconds::ZERO), Some(Time::MAX));
+        assert_eq!(Time::MAX.saturating_add(Seconds(60)), Time::MAX);
+        assert_eq!(Time(Timestamp::MAX - 1).saturating_add(Seconds(60)), Time::MAX);
+
+        assert_eq!(time.checked_sub(Seconds::from_minutes(30)), Some(Time::from_hms(8, 0, 0)));
+        assert_eq!(Time::MIDNIGHT.checked_sub(Seconds(1)), None);
+        assert_eq!(Time::MIDNIGHT.checked_sub(Seconds::ZERO), Some(Time::MIDNIGHT));
+
+        // Durations between times are only defined in the right order.
+        let later = Time::from_hms(9, 0, 0);
+        assert_eq!(later.checked_duration_since(time), Some(Seconds::from_minutes(30)));
+        assert_eq!(time.checked_duration_since(later), None);
+        assert_eq!(time.checked_duration_since(time), Some(Seconds::ZERO));
+        assert_eq!(time.saturating_duration_since(later), Seconds::ZERO);
+        assert_eq!(Time::MAX.checked_duration_since(Time::MIDNIGHT), Some(Seconds(Timestamp::MAX)));
+
+        assert_eq!(Seconds(Timestamp::MAX).checked_add(Seconds(1)), None);
+        assert_eq!(Seconds(Timestamp::MAX).saturating_add(Seconds(1)), Seconds(Timestamp::MAX));
+        assert_eq!(Seconds(10).checked_sub(Seconds(11)), None);
+        assert_eq!(Seconds(10) + Seconds(5), Seconds(15));
+
+        let mut time = time;
+        time += Seconds(30);
+        assert_eq!(time, Time::from_hms(8, 30, 30));
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn add_overflow_panics_in_debug() {
+        let _ = Time::MAX + Seconds(1);
+    }
+
+    #[test]
+    fn ordering_conversions_and_display() {
+        assert!(Time::from_hms(8, 0, 0) < Time::from_hms(25, 0, 0));
+        assert!(Time::from_hms(25, 0, 0) < Time::MAX);
+        assert_eq!(Timestamp::from(Time::from(1234)), 1234);
+        assert_eq!(Timestamp::from(Seconds::from(60)), 60);
+        assert_eq!(Time::from_hms(8, 5, 9).to_string(), "08:05:09");
+        assert_eq!(Time::from_hms(25, 0, 0).to_string(), "25:00:00");
+        assert_eq!(Seconds(3600 + 23 * 60).to_string(), "1 h 23 min");
+    }
+}
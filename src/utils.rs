@@ -1,7 +1,9 @@
-use chrono::NaiveDate;
-use gtfs_structures::{Gtfs, RouteType, Trip};
+use chrono::{Days, NaiveDate, NaiveTime, Timelike};
+#[cfg(feature = "gtfs")]
+use gtfs_structures::{Exception, Gtfs, RouteType, Trip};
+use rgb::RGB8;
 
-use crate::network::Timestamp;
+use crate::network::{CoordType, NetworkPoint, Timestamp};
 
 // NOTE: This will be in the standard library in 1.82.
 pub trait OptionExt<T> {
@@ -18,13 +20,150 @@ pub const fn const_unwrap<T: Copy>(x: Option<T>) -> T {
     if let Some(x) = x { x } else { panic!("Failed to const unwrap.") }
 }
 
-// A fast way to check a buffer is all zeros (https://stackoverflow.com/questions/65367552/how-to-efficiently-check-a-vecu8-to-see-if-its-all-zeros).
-pub fn is_zero(buf: &[bool]) -> bool {
-    let (prefix, aligned, suffix) = unsafe { buf.align_to::<u128>() };
+// A fixed-size set of indices below capacity, stored as one bit per index in u64 words.
+#[derive(Clone, Debug, Default)]
+pub struct BitSet {
+    words: Vec<u64>,
+    capacity: usize,
+}
+
+impl BitSet {
+    pub fn new(capacity: usize) -> Self {
+        Self { words: vec![0; capacity.div_ceil(64)], capacity }
+    }
+
+    // The number of indices the set can hold, not the number it does.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn insert(&mut self, index: usize) {
+        debug_assert!(index < self.capacity, "Index {index} out of range for a bit set of capacity {}.", self.capacity);
+        self.words[index / 64] |= 1 << (index % 64);
+    }
+
+    pub fn contains(&self, index: usize) -> bool {
+        index < self.capacity && self.words[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.words.fill(0);
+    }
+
+    // Iterates over the indices in the set in increasing order, skipping empty words.
+    pub fn iter(&self) -> impl Iterator<Item=usize> + '_ {
+        self.words.iter().enumerate().filter(|(_, &word)| word != 0).flat_map(|(word_idx, &word)| {
+            // Each step clears the lowest set bit.
+            std::iter::successors(Some(word), |&word| Some(word & (word - 1)).filter(|&word| word != 0))
+                .map(move |word| word_idx * 64 + word.trailing_zeros() as usize)
+        })
+    }
+}
+
+// Strings stored back to back in one buffer, so many small strings (stop names, trip IDs) cost two allocations in total rather
+// than one each. Indexing returns a &str. Inserting and removing before the end moves the later strings, so are O(n).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StringArena {
+    text: String,
+    // The end of each string in text.
+    ends: Vec<usize>,
+}
+
+impl StringArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ends.is_empty()
+    }
+
+    fn range(&self, index: usize) -> std::ops::Range<usize> {
+        let start = if index == 0 { 0 } else { self.ends[index - 1] };
+        start..self.ends[index]
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        (index < self.len()).then(|| &self.text[self.range(index)])
+    }
+
+    pub fn push(&mut self, string: &str) {
+        self.text.push_str(string);
+        self.ends.push(self.text.len());
+    }
+
+    pub fn insert(&mut self, index: usize, string: &str) {
+        let start = if index == 0 { 0 } else { self.ends[index - 1] };
+        self.text.insert_str(start, string);
+        self.ends.insert(index, start);
+        self.ends[index..].iter_mut().for_each(|end| *end += string.len());
+    }
+
+    pub fn remove(&mut self, index: usize) -> String {
+        let range = self.range(index);
+        let removed = self.text.drain(range.clone()).collect();
+        self.ends.remove(index);
+        self.ends[index..].iter_mut().for_each(|end| *end -= range.len());
+        removed
+    }
+
+    // Moves the string at from to index to, shifting the strings between them by one, like a rotate of that range.
+    pub fn move_to(&mut self, from: usize, to: usize) {
+        let string = self.remove(from);
+        self.insert(to, &string);
+    }
+
+    pub fn iter(&self) -> impl ExactSizeIterator<Item=&str> + '_ {
+        (0..self.len()).map(|index| &self.text[self.range(index)])
+    }
+
+    pub fn position(&self, string: &str) -> Option<usize> {
+        self.iter().position(|other| other == string)
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.text.len() + self.ends.len() * std::mem::size_of::<usize>()
+    }
 
-    prefix.iter().all(|&x| x == false)
-        && aligned.iter().all(|&x| x == 0)
-        && suffix.iter().all(|&x| x == false)
+    pub fn capacity_bytes(&self) -> usize {
+        self.text.capacity() + self.ends.capacity() * std::mem::size_of::<usize>()
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.text.shrink_to_fit();
+        self.ends.shrink_to_fit();
+    }
+}
+
+impl std::ops::Index<usize> for StringArena {
+    type Output = str;
+
+    fn index(&self, index: usize) -> &str {
+        &self.text[self.range(index)]
+    }
+}
+
+impl<S: AsRef<str>> Extend<S> for StringArena {
+    fn extend<I: IntoIterator<Item=S>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|string| self.push(string.as_ref()));
+    }
+}
+
+impl<S: AsRef<str>> FromIterator<S> for StringArena {
+    fn from_iter<I: IntoIterator<Item=S>>(iter: I) -> Self {
+        let mut arena = Self::new();
+        arena.extend(iter);
+        arena
+    }
 }
 
 pub const fn get_size_bits<T>() -> usize {
@@ -32,55 +171,151 @@ pub const fn get_size_bits<T>() -> usize {
     std::mem::size_of::<T>() * 8
 }
 
+// Levenshtein distance between two strings, counting characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    // Distances from the prefix of a processed so far to every prefix of b.
+    let mut distances = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut previous_diagonal = distances[0];
+        distances[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = previous_diagonal + (a_char != b_char) as usize;
+            previous_diagonal = distances[j + 1];
+            distances[j + 1] = substitution.min(distances[j] + 1).min(distances[j + 1] + 1);
+        }
+    }
+    distances[b.len()]
+}
+
 pub fn get_short_stop_name(stop: &str) -> &str {
     // Convert "Laburnum Railway Station (Blackburn)" to "Laburnum", and "Noble Park Railway Station (Noble Park)" to "Noble Park", etc.
     stop.split(" Railway Station").next().unwrap()
 }
 
+#[cfg(feature = "gtfs")]
 pub fn does_trip_run(gtfs: &Gtfs, mode_filter: Option<RouteType>, trip: &Trip, date: NaiveDate) -> bool {
     if let Some(mode_filter) = mode_filter {
         if gtfs.routes.get(trip.route_id.as_str()).map(|route| route.route_type).unwrap() != mode_filter {
             return false;
         }
     }
-    if let Some(calender) = gtfs.calendar.get(trip.service_id.as_str()) {
+    // Exceptions in calendar_dates override the regular calendar, both to add and to remove service.
+    let calender_dates = gtfs.calendar_dates.get(trip.service_id.as_str());
+    if let Some(exception) = calender_dates.and_then(|calender_dates| calender_dates.iter().find(|calender_date| calender_date.date == date)) {
+        exception.exception_type == Exception::Added
+    } else if let Some(calender) = gtfs.calendar.get(trip.service_id.as_str()) {
         calender.valid_weekday(date) && calender.start_date <= date && date <= calender.end_date
-    } else if let Some(calender_dates) = gtfs.calendar_dates.get(trip.service_id.as_str()) {
-        calender_dates.iter().any(|calender_date| calender_date.date == date)
+    } else if calender_dates.is_some() {
+        // Service is only defined by exceptions, and there are none on this date.
+        false
     } else {
         assert!(false, "Trip {} does not have a valid service_id", trip.id);
         false
     }
 }
 
-// Copied from gtfs_structures::serde_helpers, which are private :(
-fn parse_time_impl(h: &str, m: &str, s: &str) -> Result<Timestamp, std::num::ParseIntError> {
-    let hours: u32 = h.parse()?;
-    let minutes: u32 = m.parse()?;
-    let seconds: u32 = s.parse()?;
-    Ok(hours * 3600 + minutes * 60 + seconds)
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum TimeParseError {
+    #[error("Invalid time {0:?}, expected H:MM, HH:MM, H:MM:SS or HH:MM:SS.")]
+    MalformedTime(String),
+    #[error("Minutes or seconds out of range in time {0:?}.")]
+    TimeOutOfRange(String),
 }
 
-pub fn parse_time(s: &str) -> Result<Timestamp, gtfs_structures::Error> {
-    if s.len() < 7 {
-        Err(gtfs_structures::Error::InvalidTime(s.to_owned()))
-    } else {
-        let parts: Vec<&str> = s.split(':').collect();
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum DateParseError {
+    #[error("Invalid date {0:?}, expected DD/MM, DD/MM/YYYY, YYYY-MM-DD, YYYYMMDD, today or tomorrow.")]
+    MalformedDate(String),
+    #[error("Ambiguous date {0:?}, use DD/MM/YYYY or YYYY-MM-DD.")]
+    AmbiguousDate(String),
+    #[error("Date {0:?} has no year, use DD/MM/YYYY.")]
+    MissingYear(String),
+    #[error("Date {0:?} doesn't exist.")]
+    InvalidDate(String),
+}
 
-        if parts.len() != 3 {
-            return Err(gtfs_structures::Error::InvalidTime(s.to_owned()));
+// Parses a time of day as seconds since midnight. Accepts H:MM, HH:MM, H:MM:SS and HH:MM:SS, where hours can be 24 or more
+// (up to 99) for GTFS trips that run past midnight, and minutes and seconds are below 60.
+pub fn parse_time(s: &str) -> Result<Timestamp, TimeParseError> {
+    let malformed = || TimeParseError::MalformedTime(s.to_owned());
+    let parse_part = |part: &str, max_len: usize| -> Option<Timestamp> {
+        if part.is_empty() || part.len() > max_len || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
         }
+        part.parse().ok()
+    };
 
-        let sec = parts[2];
-        let min = parts[1];
-        let hour = parts[0];
+    let mut parts = s.split(':');
+    let hours = parts.next().and_then(|hours| parse_part(hours, 2)).ok_or_else(malformed)?;
+    let minutes = parts.next().filter(|minutes| minutes.len() == 2).and_then(|minutes| parse_part(minutes, 2)).ok_or_else(malformed)?;
+    let seconds = match parts.next() {
+        Some(seconds) if seconds.len() == 2 => parse_part(seconds, 2).ok_or_else(malformed)?,
+        Some(_) => return Err(malformed()),
+        None => 0,
+    };
+    if parts.next().is_some() {
+        return Err(malformed());
+    }
+    if minutes >= 60 || seconds >= 60 {
+        return Err(TimeParseError::TimeOutOfRange(s.to_owned()));
+    }
+    Ok(hours * 3600 + minutes * 60 + seconds)
+}
 
-        if min.len() != 2 || sec.len() != 2 {
-            return Err(gtfs_structures::Error::InvalidTime(s.to_owned()));
-        }
+// Parses a date as DD/MM/YYYY, YYYY-MM-DD or YYYYMMDD, or DD/MM in default_year. "today" and "tomorrow" are relative to the
+// current UTC date. Two-digit years and dashed dates without a year are rejected as ambiguous rather than guessed.
+// NOTE: wasm32-unknown-unknown has no clock, so use parse_date_relative_to there.
+pub fn parse_date(s: &str, default_year: Option<i32>) -> Result<NaiveDate, DateParseError> {
+    let days_since_epoch = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs() / 86400;
+    let today = NaiveDate::default() + Days::new(days_since_epoch);
+    parse_date_relative_to(s, default_year, today)
+}
 
-        parse_time_impl(hour, min, sec).map_err(|_| gtfs_structures::Error::InvalidTime(s.to_owned()))
+// Like parse_date, with "today" and "tomorrow" relative to the given date.
+pub fn parse_date_relative_to(s: &str, default_year: Option<i32>, today: NaiveDate) -> Result<NaiveDate, DateParseError> {
+    let malformed = || DateParseError::MalformedDate(s.to_owned());
+    let invalid = || DateParseError::InvalidDate(s.to_owned());
+    match s.to_lowercase().as_str() {
+        "today" => return Ok(today),
+        "tomorrow" => return today.succ_opt().ok_or_else(invalid),
+        _ => {}
     }
+
+    let is_number = |part: &str, max_len: usize| (1..=max_len).contains(&part.len()) && part.bytes().all(|b| b.is_ascii_digit());
+    let slash_parts = s.split('/').collect::<Vec<_>>();
+    let dash_parts = s.split('-').collect::<Vec<_>>();
+    let (year, month, day) = match (&slash_parts[..], &dash_parts[..]) {
+        (&[day, month], _) if is_number(day, 2) && is_number(month, 2) => {
+            (default_year.ok_or_else(|| DateParseError::MissingYear(s.to_owned()))?, month, day)
+        }
+        (&[day, month, year], _) if is_number(day, 2) && is_number(month, 2) && year.len() == 4 && is_number(year, 4) => {
+            (year.parse().unwrap(), month, day)
+        }
+        (&[day, month, year], _) if is_number(day, 2) && is_number(month, 2) && is_number(year, 2) => {
+            return Err(DateParseError::AmbiguousDate(s.to_owned()));
+        }
+        (_, &[year, month, day]) if year.len() == 4 && is_number(year, 4) && month.len() == 2 && is_number(month, 2) && day.len() == 2 && is_number(day, 2) => {
+            (year.parse().unwrap(), month, day)
+        }
+        (_, &[first, second]) if is_number(first, 2) && is_number(second, 2) => {
+            return Err(DateParseError::AmbiguousDate(s.to_owned()));
+        }
+        (&[compact], &[_]) if compact.len() == 8 && is_number(compact, 8) => (compact[..4].parse().unwrap(), &compact[4..6], &compact[6..]),
+        _ => return Err(malformed()),
+    };
+    NaiveDate::from_ymd_opt(year, month.parse().unwrap(), day.parse().unwrap()).ok_or_else(invalid)
+}
+
+// Converts a time of day to seconds since midnight. Fractional seconds (and leap seconds) are dropped.
+pub fn timestamp_from_naive_time(time: NaiveTime) -> Timestamp {
+    time.num_seconds_from_midnight()
+}
+
+// Converts seconds since midnight to a time of day and the number of days past the first, e.g. 25:00:00 is 01:00:00 on day 1.
+pub fn timestamp_to_naive_time(time: Timestamp) -> (NaiveTime, u32) {
+    let naive_time = NaiveTime::from_num_seconds_from_midnight_opt(time % 86400, 0).unwrap();
+    (naive_time, time / 86400)
 }
 
 pub fn get_time_str(time: Timestamp) -> String {
@@ -89,3 +324,422 @@ pub fn get_time_str(time: Timestamp) -> String {
     let seconds = time % 60;
     format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
 }
+
+// Formats a time as HH:MM:SS within its day, with a day offset for times past midnight, e.g. "01:00:00 (+1 day)" for 25:00:00.
+pub fn get_time_str_wrapped(time: Timestamp) -> String {
+    format_time(time, TimeFormat::H24)
+}
+
+// How durations are shown in user-facing output. Durations of a minute or more drop their seconds, except in Clock style.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DurationStyle {
+    // "1 h 5 min", "45 s".
+    #[default]
+    Short,
+    // "1h05", "5m", "45s".
+    Compact,
+    // "1 hour 5 minutes", "45 seconds".
+    Words,
+    // "01:05:00", with hours past 24 for long durations.
+    Clock,
+}
+
+// Formats a duration in the given style, e.g. "1 h 23 min" in Short style, or in seconds if it's under a minute.
+pub fn format_duration(secs: Timestamp, style: DurationStyle) -> String {
+    let hours = secs / 3600;
+    let minutes = (secs % 3600) / 60;
+    let plural = |n: Timestamp, unit: &str| if n == 1 { format!("1 {unit}") } else { format!("{n} {unit}s") };
+    match style {
+        DurationStyle::Short => match (hours, minutes) {
+            (0, 0) => format!("{secs} s"),
+            (0, _) => format!("{minutes} min"),
+            (_, 0) => format!("{hours} h"),
+            _ => format!("{hours} h {minutes} min"),
+        },
+        DurationStyle::Compact => match (hours, minutes) {
+            (0, 0) => format!("{secs}s"),
+            (0, _) => format!("{minutes}m"),
+            _ => format!("{hours}h{minutes:02}"),
+        },
+        DurationStyle::Words => match (hours, minutes) {
+            (0, 0) => plural(secs, "second"),
+            (0, _) => plural(minutes, "minute"),
+            (_, 0) => plural(hours, "hour"),
+            _ => format!("{} {}", plural(hours, "hour"), plural(minutes, "minute")),
+        },
+        DurationStyle::Clock => get_time_str(secs),
+    }
+}
+
+// Formats a time in 12-hour format without seconds, e.g. "8:32 am". Times past midnight wrap around.
+pub fn get_time_str_12h(time: Timestamp) -> String {
+    let hours = (time / 3600) % 24;
+    let minutes = (time % 3600) / 60;
+    let (hours_12, suffix) = match hours {
+        0 => (12, "am"),
+        1..=11 => (hours, "am"),
+        12 => (12, "pm"),
+        _ => (hours - 12, "pm"),
+    };
+    format!("{}:{:02} {}", hours_12, minutes, suffix)
+}
+
+// How times are shown in user-facing output.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimeFormat {
+    // "08:42:00".
+    #[default]
+    H24,
+    // "8:42 am".
+    H12,
+    // "08:42".
+    H24NoSeconds,
+}
+
+// Formats a time within its day in the given format, with a day offset for times past midnight, e.g. "1:00 am (+1 day)" for
+// 25:00:00 in 12-hour format.
+pub fn format_time(time: Timestamp, format: TimeFormat) -> String {
+    let days = time / 86400;
+    let time_of_day = time % 86400;
+    let time_str = match format {
+        TimeFormat::H24 => get_time_str(time_of_day),
+        TimeFormat::H12 => get_time_str_12h(time_of_day),
+        TimeFormat::H24NoSeconds => format!("{:02}:{:02}", time_of_day / 3600, (time_of_day % 3600) / 60),
+    };
+    match days {
+        0 => time_str,
+        1 => format!("{time_str} (+1 day)"),
+        _ => format!("{time_str} (+{days} days)"),
+    }
+}
+
+// Escapes a string for inclusion in a JSON string literal (without the surrounding quotes).
+pub fn escape_json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Encodes points with Google's encoded polyline algorithm at 5 decimal places, as used by OpenTripPlanner and many map libraries
+// (https://developers.google.com/maps/documentation/utilities/polylinealgorithm).
+pub fn encode_polyline(points: &[NetworkPoint]) -> String {
+    let mut encoded = String::new();
+    let mut encode_value = |value: i64| {
+        let mut value = if value < 0 { !(value << 1) } else { value << 1 };
+        while value >= 0x20 {
+            encoded.push(char::from((0x20 | (value & 0x1f)) as u8 + 63));
+            value >>= 5;
+        }
+        encoded.push(char::from(value as u8 + 63));
+    };
+
+    let (mut previous_latitude, mut previous_longitude) = (0, 0);
+    for point in points {
+        let latitude = (point.latitude as f64 * 1e5).round() as i64;
+        let longitude = (point.longitude as f64 * 1e5).round() as i64;
+        encode_value(latitude - previous_latitude);
+        encode_value(longitude - previous_longitude);
+        (previous_latitude, previous_longitude) = (latitude, longitude);
+    }
+    encoded
+}
+
+// Decodes a polyline from encode_polyline. Returns None if it is malformed.
+pub fn decode_polyline(encoded: &str) -> Option<Vec<NetworkPoint>> {
+    fn decode_value(bytes: &mut std::iter::Peekable<std::str::Bytes>) -> Option<i64> {
+        let (mut value, mut shift) = (0i64, 0);
+        loop {
+            let chunk = bytes.next()?.checked_sub(63)? as i64;
+            if shift > 60 {
+                return None;
+            }
+            value |= (chunk & 0x1f) << shift;
+            shift += 5;
+            if chunk < 0x20 {
+                return Some(if value & 1 == 1 { !(value >> 1) } else { value >> 1 });
+            }
+        }
+    }
+
+    let mut bytes = encoded.bytes().peekable();
+    let mut points = Vec::new();
+    let (mut latitude, mut longitude) = (0, 0);
+    while bytes.peek().is_some() {
+        latitude += decode_value(&mut bytes)?;
+        longitude += decode_value(&mut bytes)?;
+        points.push(NetworkPoint { latitude: (latitude as f64 / 1e5) as CoordType, longitude: (longitude as f64 / 1e5) as CoordType });
+    }
+    Some(points)
+}
+
+pub fn get_colour_hex(colour: RGB8) -> String {
+    format!("#{:02x}{:02x}{:02x}", colour.r, colour.g, colour.b)
+}
+
+// Serializes a map as a list of (key, value) pairs, for use with #[serde(with)] on maps whose keys JSON can't represent.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_pairs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+    use std::hash::Hash;
+
+    pub fn serialize<K: Serialize, V: Serialize, S: Serializer>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, K: Deserialize<'de> + Eq + Hash, V: Deserialize<'de>, D: Deserializer<'de>>(deserializer: D) -> Result<HashMap<K, V>, D::Error> {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+// Serializes an RGB8 as a "#rrggbb" string, for use with #[serde(with)]. rgb's own serde impls write a struct instead.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_colour {
+    use rgb::RGB8;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(colour: &RGB8, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::get_colour_hex(*colour))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RGB8, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        let malformed = || D::Error::custom(format!("Expected a #rrggbb colour, got {hex:?}."));
+        let digits = hex.strip_prefix('#').filter(|digits| digits.len() == 6 && digits.is_ascii()).ok_or_else(malformed)?;
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| malformed());
+        Ok(RGB8::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_set() {
+        let mut set = BitSet::new(130);
+        assert!(set.is_empty());
+        for index in [129, 0, 64, 63, 5] {
+            set.insert(index);
+        }
+        set.insert(5);
+        assert!(!set.is_empty());
+        assert!(set.contains(63) && !set.contains(62) && !set.contains(200));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![0, 5, 63, 64, 129]);
+        set.clear();
+        assert!(set.is_empty());
+        assert_eq!(set.iter().next(), None);
+        assert_eq!(set.capacity(), 130);
+    }
+
+    #[test]
+    fn string_arena() {
+        let mut arena = ["Flinders Street", "", "Richmond"].into_iter().collect::<StringArena>();
+        assert_eq!((arena.len(), &arena[0], &arena[1], &arena[2]), (3, "Flinders Street", "", "Richmond"));
+        arena.insert(1, "Parliament");
+        arena.push("Caulfield");
+        assert_eq!(arena.iter().collect::<Vec<_>>(), ["Flinders Street", "Parliament", "", "Richmond", "Caulfield"]);
+        assert_eq!(arena.remove(0), "Flinders Street");
+        arena.move_to(0, 3);
+        assert_eq!(arena.iter().collect::<Vec<_>>(), ["", "Richmond", "Caulfield", "Parliament"]);
+        arena.move_to(3, 1);
+        assert_eq!(arena.iter().collect::<Vec<_>>(), ["", "Parliament", "Richmond", "Caulfield"]);
+        assert_eq!((arena.position("Richmond"), arena.get(4)), (Some(2), None));
+    }
+
+    #[test]
+    fn polyline_round_trip() {
+        // The example from Google's documentation.
+        let points = [(38.5, -120.2), (40.7, -120.95), (43.252, -126.453)].map(|(latitude, longitude)| NetworkPoint { latitude, longitude });
+        let encoded = encode_polyline(&points);
+        assert_eq!(encoded, "_p~iF~ps|U_ulLnnqC_mqNvxq`@");
+
+        let decoded = decode_polyline(&encoded).unwrap();
+        assert_eq!(decoded.len(), points.len());
+        for (a, b) in decoded.iter().zip(points.iter()) {
+            assert!((a.latitude - b.latitude).abs() < 1e-4 && (a.longitude - b.longitude).abs() < 1e-4);
+        }
+        assert_eq!(decode_polyline("").unwrap().len(), 0);
+        assert!(decode_polyline("_p~iF").is_none());
+    }
+
+    #[test]
+    fn parse_time_accepts() {
+        for (time, expected) in [
+            ("08:30:00", 8 * 3600 + 30 * 60),
+            ("8:30:00", 8 * 3600 + 30 * 60),
+            ("08:30", 8 * 3600 + 30 * 60),
+            ("8:30", 8 * 3600 + 30 * 60),
+            ("00:00:00", 0),
+            ("0:00", 0),
+            ("23:59:59", 23 * 3600 + 59 * 60 + 59),
+            ("24:00:00", 24 * 3600),
+            ("25:10:00", 25 * 3600 + 10 * 60),
+            ("47:59:59", 47 * 3600 + 59 * 60 + 59),
+            ("99:00", 99 * 3600),
+        ] {
+            assert_eq!(parse_time(time), Ok(expected), "{time:?}");
+        }
+    }
+
+    #[test]
+    fn parse_time_rejects() {
+        for time in [
+            "", "8", "08", "830", "08:3", "08:300", "08:30:0", "08:30:000", "100:00", "08:30:00:00",
+            ":30", "08:", "08:30:", "-1:30", "+8:30", " 8:30", "8:30 ", "08h30", "08:3a", "٨:30", "08:30:00.5",
+        ] {
+            assert_eq!(parse_time(time), Err(TimeParseError::MalformedTime(time.to_owned())), "{time:?}");
+        }
+        for time in ["08:60", "08:30:60", "8:99"] {
+            assert_eq!(parse_time(time), Err(TimeParseError::TimeOutOfRange(time.to_owned())), "{time:?}");
+        }
+    }
+
+    #[test]
+    fn parse_dates() {
+        let date = NaiveDate::from_ymd_opt(2024, 5, 10).unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 12, 31).unwrap();
+        let parse = |s: &str, default_year: Option<i32>| parse_date_relative_to(s, default_year, today);
+        assert_eq!(parse("2024-05-10", None), Ok(date));
+        assert_eq!(parse("20240510", None), Ok(date));
+        assert_eq!(parse("10/05/2024", None), Ok(date));
+        assert_eq!(parse("10/5/2024", Some(2023)), Ok(date));
+        assert_eq!(parse("10/05", Some(2024)), Ok(date));
+        assert_eq!(parse("10/5", Some(2024)), Ok(date));
+        assert_eq!(parse("today", None), Ok(today));
+        assert_eq!(parse("Tomorrow", None), Ok(NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()));
+
+        assert_eq!(parse("10/05", None), Err(DateParseError::MissingYear("10/05".to_owned())));
+        for ambiguous in ["10/05/24", "10-05", "05-10"] {
+            assert_eq!(parse(ambiguous, Some(2024)), Err(DateParseError::AmbiguousDate(ambiguous.to_owned())), "{ambiguous:?}");
+        }
+        for invalid in ["2024-13-01", "2024-02-30", "31/04/2024", "00/05"] {
+            assert_eq!(parse(invalid, Some(2024)), Err(DateParseError::InvalidDate(invalid.to_owned())), "{invalid:?}");
+        }
+        for malformed in ["", "2024-05-10 ", "May 10", "2024-5-10", "10/05/2024/1", "10.05.2024", "2024051", "+10/05"] {
+            assert_eq!(parse(malformed, Some(2024)), Err(DateParseError::MalformedDate(malformed.to_owned())), "{malformed:?}");
+        }
+    }
+
+    #[test]
+    fn parse_feb_29() {
+        let leap_day = NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        let today = NaiveDate::from_ymd_opt(2023, 6, 1).unwrap();
+        assert_eq!(parse_date_relative_to("29/02", Some(2024), today), Ok(leap_day));
+        assert_eq!(parse_date_relative_to("29/02/2024", Some(2023), today), Ok(leap_day));
+        assert_eq!(parse_date_relative_to("2024-02-29", None, today), Ok(leap_day));
+        assert_eq!(parse_date_relative_to("29/02", Some(2023), today), Err(DateParseError::InvalidDate("29/02".to_owned())));
+        assert_eq!(parse_date_relative_to("29/02/2023", Some(2024), today), Err(DateParseError::InvalidDate("29/02/2023".to_owned())));
+        assert_eq!(parse_date_relative_to("2100-02-29", None, today), Err(DateParseError::InvalidDate("2100-02-29".to_owned())));
+    }
+
+    #[test]
+    fn naive_time_conversions() {
+        let time = |h, m, s| NaiveTime::from_hms_opt(h, m, s).unwrap();
+        assert_eq!(timestamp_from_naive_time(time(0, 0, 0)), 0);
+        assert_eq!(timestamp_from_naive_time(time(8, 30, 15)), 8 * 3600 + 30 * 60 + 15);
+        assert_eq!(timestamp_from_naive_time(NaiveTime::from_hms_milli_opt(23, 59, 59, 999).unwrap()), 86399);
+
+        assert_eq!(timestamp_to_naive_time(86399), (time(23, 59, 59), 0));
+        assert_eq!(timestamp_to_naive_time(86400), (time(0, 0, 0), 1));
+        assert_eq!(timestamp_to_naive_time(90000), (time(1, 0, 0), 1));
+        assert_eq!(timestamp_to_naive_time(172800), (time(0, 0, 0), 2));
+
+        // Round trips, including past midnight.
+        for timestamp in (0..3 * 86400).step_by(997) {
+            let (naive_time, days) = timestamp_to_naive_time(timestamp);
+            assert_eq!(timestamp_from_naive_time(naive_time) + days * 86400, timestamp);
+        }
+    }
+
+    #[test]
+    fn time_str_wrapped() {
+        assert_eq!(get_time_str_wrapped(0), "00:00:00");
+        assert_eq!(get_time_str_wrapped(86399), "23:59:59");
+        assert_eq!(get_time_str_wrapped(86400), "00:00:00 (+1 day)");
+        assert_eq!(get_time_str_wrapped(90000), "01:00:00 (+1 day)");
+        assert_eq!(get_time_str_wrapped(172799), "23:59:59 (+1 day)");
+        assert_eq!(get_time_str_wrapped(172800), "00:00:00 (+2 days)");
+        // The timetable form is unchanged.
+        assert_eq!(get_time_str(90000), "25:00:00");
+    }
+
+    #[test]
+    fn format_time_edges() {
+        for (time, h24, h12, h24_no_seconds) in [
+            (0, "00:00:00", "12:00 am", "00:00"),
+            (59, "00:00:59", "12:00 am", "00:00"),
+            (42 * 60, "00:42:00", "12:42 am", "00:42"),
+            (3600, "01:00:00", "1:00 am", "01:00"),
+            (8 * 3600 + 42 * 60 + 30, "08:42:30", "8:42 am", "08:42"),
+            (11 * 3600 + 59 * 60 + 59, "11:59:59", "11:59 am", "11:59"),
+            (12 * 3600, "12:00:00", "12:00 pm", "12:00"),
+            (12 * 3600 + 30 * 60, "12:30:00", "12:30 pm", "12:30"),
+            (13 * 3600, "13:00:00", "1:00 pm", "13:00"),
+            (23 * 3600 + 59 * 60 + 59, "23:59:59", "11:59 pm", "23:59"),
+            (24 * 3600, "00:00:00 (+1 day)", "12:00 am (+1 day)", "00:00 (+1 day)"),
+            (24 * 3600 + 12 * 60, "00:12:00 (+1 day)", "12:12 am (+1 day)", "00:12 (+1 day)"),
+            (36 * 3600 + 5 * 60, "12:05:00 (+1 day)", "12:05 pm (+1 day)", "12:05 (+1 day)"),
+            (49 * 3600, "01:00:00 (+2 days)", "1:00 am (+2 days)", "01:00 (+2 days)"),
+        ] {
+            assert_eq!(format_time(time, TimeFormat::H24), h24);
+            assert_eq!(format_time(time, TimeFormat::H12), h12);
+            assert_eq!(format_time(time, TimeFormat::H24NoSeconds), h24_no_seconds);
+        }
+    }
+
+    #[test]
+    fn format_durations() {
+        assert_eq!(format_duration(0, DurationStyle::Short), "0 s");
+        assert_eq!(format_duration(1, DurationStyle::Short), "1 s");
+        assert_eq!(format_duration(59, DurationStyle::Short), "59 s");
+        assert_eq!(format_duration(60, DurationStyle::Short), "1 min");
+        assert_eq!(format_duration(119, DurationStyle::Short), "1 min");
+        assert_eq!(format_duration(3599, DurationStyle::Short), "59 min");
+        assert_eq!(format_duration(3600, DurationStyle::Short), "1 h");
+        assert_eq!(format_duration(3600 + 23 * 60 + 5, DurationStyle::Short), "1 h 23 min");
+        assert_eq!(format_duration(86400, DurationStyle::Short), "24 h");
+    }
+
+    #[test]
+    fn duration_styles() {
+        for (secs, compact, words, clock) in [
+            (0, "0s", "0 seconds", "00:00:00"),
+            (1, "1s", "1 second", "00:00:01"),
+            (59, "59s", "59 seconds", "00:00:59"),
+            (60, "1m", "1 minute", "00:01:00"),
+            (61, "1m", "1 minute", "00:01:01"),
+            (3599, "59m", "59 minutes", "00:59:59"),
+            (3600, "1h00", "1 hour", "01:00:00"),
+            (3661, "1h01", "1 hour 1 minute", "01:01:01"),
+            (3600 + 5 * 60, "1h05", "1 hour 5 minutes", "01:05:00"),
+            (2 * 3600 + 2 * 60, "2h02", "2 hours 2 minutes", "02:02:00"),
+            (25 * 3600, "25h00", "25 hours", "25:00:00"),
+        ] {
+            assert_eq!(format_duration(secs, DurationStyle::Compact), compact);
+            assert_eq!(format_duration(secs, DurationStyle::Words), words);
+            assert_eq!(format_duration(secs, DurationStyle::Clock), clock);
+        }
+        assert_eq!(format_duration(3661, DurationStyle::default()), "1 h 1 min");
+    }
+
+    #[test]
+    fn parse_time_round_trips() {
+        for time in (0..48 * 3600).step_by(37) {
+            assert_eq!(parse_time(&get_time_str(time)), Ok(time));
+        }
+    }
+}
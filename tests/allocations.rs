@@ -0,0 +1,73 @@
+use chrono::NaiveDate;
+use raptor::network::{NetworkPoint, StopIndex, StopTime, Timestamp, TimetableRoute};
+use raptor::{raptor_query, Network, StopIdx};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+// Counts allocations of at least LARGE_ALLOCATION_BYTES while COUNTING is set. This is the only test in the binary, so nothing
+// else allocates concurrently.
+struct CountingAllocator;
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static LARGE_ALLOCATION_BYTES: AtomicUsize = AtomicUsize::new(usize::MAX);
+static NUM_LARGE_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.load(Ordering::Relaxed) && layout.size() >= LARGE_ALLOCATION_BYTES.load(Ordering::Relaxed) {
+            NUM_LARGE_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Five stops in a line, with many single-trip routes between each neighbouring pair, so there are far more routes than stops
+// and getting from one end to the other takes four rounds.
+fn many_routes_network() -> Network {
+    const ROUTES_PER_HOP: usize = 500;
+    let stops = [
+        ("Stop 0", "0", NetworkPoint { latitude: -37.80, longitude: 145.0 }),
+        ("Stop 1", "1", NetworkPoint { latitude: -37.81, longitude: 145.0 }),
+        ("Stop 2", "2", NetworkPoint { latitude: -37.82, longitude: 145.0 }),
+        ("Stop 3", "3", NetworkPoint { latitude: -37.83, longitude: 145.0 }),
+        ("Stop 4", "4", NetworkPoint { latitude: -37.84, longitude: 145.0 }),
+    ];
+    let routes = (0..4 as StopIndex).flat_map(|hop| (0..ROUTES_PER_HOP as Timestamp).map(move |i| {
+        let departure_time = 8 * 3600 + hop * 1800 + i * 60;
+        TimetableRoute {
+            line: format!("{hop}.{i}"),
+            colour: Default::default(),
+            stops: vec![hop, hop + 1],
+            trips: vec![(format!("{hop}.{i}"), vec![
+                StopTime { arrival_time: departure_time, departure_time },
+                StopTime { arrival_time: departure_time + 600, departure_time: departure_time + 600 },
+            ])],
+        }
+    })).collect();
+    Network::from_timetable(&stops, routes, NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(), 60)
+}
+
+#[test]
+fn raptor_rounds_dont_allocate_per_route_state() {
+    let network = many_routes_network();
+    // Build the network's lazily cached data before counting.
+    let journey = raptor_query(&network, StopIdx(0), 8 * 3600, StopIdx(4)).unwrap();
+    assert_eq!(journey.legs_vehicle().count(), 4);
+
+    // Anything this large is sized by the number of routes (the per-stop state is tiny, and no round touches half the routes).
+    LARGE_ALLOCATION_BYTES.store(network.routes.len() * std::mem::size_of::<Option<usize>>(), Ordering::Relaxed);
+    COUNTING.store(true, Ordering::Relaxed);
+    let result = raptor_query(&network, StopIdx(0), 8 * 3600, StopIdx(4));
+    COUNTING.store(false, Ordering::Relaxed);
+    assert!(result.is_ok());
+
+    // Only the earliest marked stop of each route, allocated once per query rather than once per round.
+    assert_eq!(NUM_LARGE_ALLOCATIONS.load(Ordering::Relaxed), 1);
+}
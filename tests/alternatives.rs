@@ -0,0 +1,31 @@
+use dev_utils::get_example_scenario;
+use raptor::{raptor_query_alternatives, StopIdx};
+
+#[test]
+fn alternatives_are_distinct_and_sorted() {
+    let (network, start, start_time, end) = get_example_scenario();
+
+    let journeys = raptor_query_alternatives(&network, StopIdx(start), start_time, StopIdx(end), 3);
+    assert!(journeys.len() >= 2, "Expected at least two alternatives, found {}.", journeys.len());
+
+    let route_sequences = journeys.iter().map(|journey| journey.legs_vehicle().map(|leg| leg.trip.route_idx).collect::<Vec<_>>()).collect::<Vec<_>>();
+    for (i, a) in route_sequences.iter().enumerate() {
+        for b in route_sequences.iter().skip(i + 1) {
+            assert_ne!(a, b, "Alternatives should use different routes.");
+        }
+    }
+
+    for pair in journeys.windows(2) {
+        assert!(pair[0].legs_vehicle().last().unwrap().arrival_time <= pair[1].legs_vehicle().last().unwrap().arrival_time);
+    }
+
+    for journey in journeys.iter() {
+        let legs = journey.legs_vehicle().collect::<Vec<_>>();
+        assert_eq!(legs.first().unwrap().boarded_stop, start);
+        assert_eq!(legs.last().unwrap().arrival_stop, end);
+        for pair in legs.windows(2) {
+            assert_eq!(pair[0].arrival_stop, pair[1].boarded_stop);
+            assert!(pair[0].arrival_time <= pair[1].boarded_time);
+        }
+    }
+}
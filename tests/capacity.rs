@@ -0,0 +1,62 @@
+use dev_utils::get_example_scenario;
+use raptor::journey::JourneyError;
+use raptor::{csa_query, csa_query_with_capacity, raptor_query, raptor_query_with_capacity, Journey, StopIdx};
+
+// Returns the stop times index where the first trip of the journey was boarded.
+fn first_boarding_index(journey: &Journey) -> usize {
+    let first_leg = journey.legs_vehicle().next().unwrap();
+    let route = &journey.network.routes[first_leg.trip.route().idx()];
+    route.get_stop_times_index(first_leg.trip.trip().idx(), first_leg.boarded_stop_order as usize)
+}
+
+#[test]
+fn raptor_full_trip_is_not_boarded() {
+    let (network, start, start_time, end) = get_example_scenario();
+
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let full_index = first_boarding_index(&journey);
+
+    // Mark the first trip as full at the origin only.
+    let new_journey = raptor_query_with_capacity(&network, StopIdx(start), start_time, StopIdx(end), |_, index| index != full_index).unwrap();
+    assert_ne!(first_boarding_index(&new_journey), full_index);
+    assert!(new_journey.legs_vehicle().next().unwrap().boarded_time >= journey.legs_vehicle().next().unwrap().boarded_time);
+
+    // Every trip is full.
+    let result = raptor_query_with_capacity(&network, StopIdx(start), start_time, StopIdx(end), |_, _| false);
+    assert!(matches!(result, Err(JourneyError::NoJourneyFound { .. })));
+}
+
+#[test]
+fn csa_full_trip_is_not_boarded() {
+    let (mut network, start, start_time, end) = get_example_scenario();
+    network.build_connections();
+
+    let journey = csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let full_index = first_boarding_index(&journey);
+
+    // Mark the first trip as full at the origin only.
+    let new_journey = csa_query_with_capacity(&network, StopIdx(start), start_time, StopIdx(end), |_, index| index != full_index).unwrap();
+    assert_ne!(first_boarding_index(&new_journey), full_index);
+    assert!(new_journey.legs_vehicle().next().unwrap().boarded_time >= journey.legs_vehicle().next().unwrap().boarded_time);
+
+    // Every trip is full.
+    let result = csa_query_with_capacity(&network, StopIdx(start), start_time, StopIdx(end), |_, _| false);
+    assert!(matches!(result, Err(JourneyError::NoJourneyFound { .. })));
+}
+
+#[test]
+fn no_journey_found_reports_how_far_the_search_got() {
+    let (network, start, start_time, end) = get_example_scenario();
+
+    // Every route serving end is full, but the rest of the network can still be reached from start.
+    let serves_end = |route_idx: usize| network.routes[route_idx].get_stops(&network.route_stops).contains(&end);
+    let result = raptor_query_with_capacity(&network, StopIdx(start), start_time, StopIdx(end), |trip, _| !serves_end(trip.route().idx()));
+    match result {
+        Err(JourneyError::NoJourneyFound { start: from, end: to, start_time: time, last_reachable_time, stops_reached, .. }) => {
+            assert_eq!((from, to, time), (start, end, start_time));
+            assert!(stops_reached > 0);
+            assert!(last_reachable_time.is_some_and(|last| last > start_time));
+        }
+        other => panic!("Expected NoJourneyFound, got {:?}", other.map(|journey| journey.duration)),
+    }
+}
@@ -0,0 +1,70 @@
+use dev_utils::{get_example_scenario, get_synthetic_scenario, QuerySampler};
+use raptor::reference::dijkstra_query;
+use raptor::{csa_query, raptor_query, StopIdx};
+
+#[test]
+fn raptor_and_csa_agree() {
+    let (mut network, start, start_time, end) = get_example_scenario();
+    network.build_connections();
+
+    let raptor_journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let csa_journey = csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    // Both algorithms find the earliest arrival, but may choose different paths with the same arrival time.
+    assert_eq!(raptor_journey.arrival_time(), csa_journey.arrival_time(), "\n{raptor_journey:?}\n{csa_journey:?}");
+    // The reference router is independent of both, so a bug shared by RAPTOR and CSA can't hide behind their agreement.
+    assert_eq!(raptor_journey.arrival_time(), dijkstra_query(&network, StopIdx(start), start_time, StopIdx(end)).ok());
+    // When the paths differ, each one must still be valid against the timetable.
+    if !raptor_journey.same_path(&csa_journey) {
+        raptor_journey.validate().unwrap();
+        csa_journey.validate().unwrap();
+    }
+}
+
+#[test]
+fn raptor_and_csa_agree_on_synthetic_network() {
+    let (mut network, start, start_time, end) = get_synthetic_scenario();
+    network.build_connections();
+
+    let raptor_journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    assert_eq!(raptor_journey.arrival_time(), csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap().arrival_time());
+
+    // Every pair of stops, at times across the day.
+    let num_stops = network.stops.len() as u32;
+    for start in 0..num_stops {
+        for end in (0..num_stops).filter(|&end| end != start) {
+            for start_time in (5 * 3600..24 * 3600).step_by(1733) {
+                let raptor_arrival = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).ok().and_then(|journey| journey.arrival_time());
+                let csa_arrival = csa_query(&network, StopIdx(start), start_time, StopIdx(end)).ok().and_then(|journey| journey.arrival_time());
+                assert_eq!(raptor_arrival, csa_arrival, "{start} -> {end} at {start_time}");
+            }
+        }
+    }
+}
+
+#[test]
+fn sampled_queries_terminate() {
+    let (mut network, ..) = get_example_scenario();
+    network.build_connections();
+
+    let queries = QuerySampler::new(&network, 42).sample(50);
+    assert_eq!(queries, QuerySampler::new(&network, 42).sample(50), "Sampling should be deterministic under the seed.");
+    for (start, start_time, end) in queries {
+        assert_ne!(start, end);
+        assert!(network.get_stop(start as usize).num_routes > 0 && network.get_stop(end as usize).num_routes > 0);
+        // Queries may find no journey, but shouldn't panic.
+        let _ = raptor_query(&network, StopIdx(start), start_time, StopIdx(end));
+        let _ = csa_query(&network, StopIdx(start), start_time, StopIdx(end));
+    }
+}
+
+#[test]
+fn sampled_queries_agree() {
+    let (mut network, ..) = get_example_scenario();
+    network.build_connections();
+    raptor::testing::assert_algorithms_agree(&network, &QuerySampler::new(&network, 905).sample(50));
+
+    let (mut network, ..) = get_synthetic_scenario();
+    network.build_connections();
+    raptor::testing::assert_algorithms_agree(&network, &QuerySampler::new(&network, 905).sample(200));
+}
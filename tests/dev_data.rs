@@ -0,0 +1,29 @@
+use dev_utils::{find_dev_data_folder, load_example_gtfs};
+use std::path::Path;
+
+// The current directory and environment variables are process-wide, so these run in one test.
+#[test]
+fn dev_data_resolves_from_anywhere() {
+    let workspace_root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let expected = workspace_root.join("dev_utils").canonicalize().unwrap();
+    let temp_dir = std::env::temp_dir();
+    for dir in [workspace_root.to_path_buf(), workspace_root.join("dev_utils"), workspace_root.join("src"), temp_dir] {
+        std::env::set_current_dir(&dir).unwrap();
+        let found = find_dev_data_folder().unwrap_or_else(|e| panic!("From {}: {e}", dir.display()));
+        assert_eq!(found.canonicalize().unwrap(), expected, "From {}", dir.display());
+    }
+    std::env::set_current_dir(workspace_root).unwrap();
+
+    // An override that doesn't exist is an error naming it, not a silent fallback.
+    let missing = workspace_root.join("no_such_dev_data");
+    std::env::set_var("RAPTOR_DEV_DATA", &missing);
+    let error = find_dev_data_folder().unwrap_err();
+    assert!(error.to_string().contains("no_such_dev_data"), "{error}");
+
+    // An override without the feed gives an error with the missing file's path.
+    std::env::set_var("RAPTOR_DEV_DATA", workspace_root.join("src"));
+    // gtfs_structures' Display hides the io::Error, but Debug includes it.
+    let error = format!("{:?}", load_example_gtfs().unwrap_err());
+    assert!(error.contains("melbourne.zip"), "{error}");
+    std::env::remove_var("RAPTOR_DEV_DATA");
+}
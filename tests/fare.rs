@@ -0,0 +1,112 @@
+use chrono::NaiveDate;
+use dev_utils::{GtfsFixture, SyntheticNetwork};
+use gtfs_structures::GtfsReader;
+use raptor::{raptor_query, Fare, FareTable, Network, StopIdx};
+use std::collections::HashMap;
+
+// Alpha, Beta and Delta are in zone 1 and Gamma is in zone 2. The red line runs from Alpha to Beta, where the blue line continues
+// to Gamma and the green line to Delta. Zone 1 fares are $4.50 and zone 1+2 fares are $7.00, valid for two hours.
+fn two_zone_network() -> Network {
+    let mut network = SyntheticNetwork::new()
+        .transfer_time(60)
+        .stop("Alpha", -37.80, 145.0)
+        .stop("Beta", -37.81, 145.0)
+        .stop("Gamma", -37.82, 145.0)
+        .stop("Delta", -37.83, 145.0)
+        .line("Red", &[("Alpha", "08:05", "08:05"), ("Beta", "08:20", "08:20")])
+        .line("Blue", &[("Beta", "08:30", "08:30"), ("Gamma", "08:45", "08:45")])
+        .line("Green", &[("Beta", "08:30", "08:30"), ("Delta", "08:40", "08:40")])
+        .build();
+    network.fares = Some(FareTable {
+        stop_zones: vec![1, 1, 2, 1],
+        zone_fares: vec![450, 700],
+        line_fares: HashMap::new(),
+        transfer_window: Some(2 * 3600),
+    });
+    network
+}
+
+fn fare(network: &Network, start: &str, end: &str) -> Option<Fare> {
+    raptor_query(network, StopIdx(network.get_stop_idx(start)), 8 * 3600, StopIdx(network.get_stop_idx(end))).unwrap().fare()
+}
+
+#[test]
+fn zone_one_journey_costs_zone_one_fare() {
+    let network = two_zone_network();
+    assert_eq!(fare(&network, "Alpha", "Beta"), Some(Fare { leg_fares: vec![450], total: 450 }));
+}
+
+#[test]
+fn zone_crossing_journey_costs_highest_zone_fare() {
+    let network = two_zone_network();
+    assert_eq!(fare(&network, "Alpha", "Gamma"), Some(Fare { leg_fares: vec![450, 700], total: 700 }));
+}
+
+#[test]
+fn legs_within_transfer_window_are_capped() {
+    let mut network = two_zone_network();
+    // Each leg costs a zone 1 fare on its own, but both are covered by one.
+    assert_eq!(fare(&network, "Alpha", "Delta"), Some(Fare { leg_fares: vec![450, 450], total: 450 }));
+
+    // The green line leaves 25 minutes after the red line, so a 10 minute window doesn't cover it.
+    network.fares.as_mut().unwrap().transfer_window = Some(10 * 60);
+    assert_eq!(fare(&network, "Alpha", "Delta"), Some(Fare { leg_fares: vec![450, 450], total: 900 }));
+
+    // Line fares are paid on top of zone fares.
+    network.fares.as_mut().unwrap().line_fares.insert("Green".to_string(), 200);
+    assert_eq!(fare(&network, "Alpha", "Delta"), Some(Fare { leg_fares: vec![450, 200], total: 650 }));
+
+    // Journeys can't be priced without the zone of every stop touched.
+    network.fares.as_mut().unwrap().stop_zones[0] = 0;
+    assert_eq!(fare(&network, "Alpha", "Delta"), None);
+    network.fares = None;
+    assert_eq!(fare(&network, "Beta", "Delta"), None);
+}
+
+#[test]
+fn fares_are_read_from_gtfs() {
+    let fixture = GtfsFixture::new()
+        .stop_in_zone("alpha", "Alpha", -37.80, 145.00, "1")
+        .stop_in_zone("beta", "Beta", -37.81, 145.00, "1")
+        .stop_in_zone("gamma", "Gamma", -37.82, 145.00, "2")
+        .stop("delta", "Delta", -37.83, 145.00)
+        .route("red", "Red", 3)
+        .route("blue", "Blue", 3)
+        .route("shuttle", "Shuttle", 3)
+        .trip("red", "red", "daily").stop_times("red", &[("alpha", "08:05:00", "08:05:00"), ("beta", "08:20:00", "08:20:00")])
+        .trip("blue", "blue", "daily").stop_times("blue", &[("beta", "08:30:00", "08:30:00"), ("gamma", "08:45:00", "08:45:00")])
+        .trip("shuttle", "shuttle", "daily").stop_times("shuttle", &[("beta", "08:30:00", "08:30:00"), ("delta", "08:40:00", "08:40:00")])
+        .calendar("daily", "1111111", "20240101", "20241231")
+        .row("fare_attributes.txt", "fare_id,price,currency_type,payment_method,transfers,transfer_duration", "zone1,4.50,AUD,0,,7200")
+        .row("fare_attributes.txt", "fare_id,price,currency_type,payment_method,transfers,transfer_duration", "zone2,7.00,AUD,0,,7200")
+        .row("fare_attributes.txt", "fare_id,price,currency_type,payment_method,transfers,transfer_duration", "shuttle,2.00,AUD,0,0,")
+        .row("fare_rules.txt", "fare_id,route_id,origin_id,destination_id,contains_id", "zone1,,,,1")
+        .row("fare_rules.txt", "fare_id,route_id,origin_id,destination_id,contains_id", "zone2,,,,2")
+        .row("fare_rules.txt", "fare_id,route_id,origin_id,destination_id,contains_id", "shuttle,shuttle,,,");
+    let fixture_dir = fixture.write().unwrap();
+    let gtfs = GtfsReader::default().read_shapes(false).read_from_path(fixture_dir.path().to_str().unwrap()).unwrap();
+    let network = Network::new(&gtfs, None, NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(), 60);
+
+    let fares = network.fares.as_ref().unwrap();
+    let zone = |stop_id| fares.stop_zones[network.get_stop_idx(stop_id) as usize];
+    assert_eq!((zone("alpha"), zone("beta"), zone("gamma"), zone("delta")), (1, 1, 2, 0));
+    assert_eq!(fares.zone_fares, [450, 700]);
+    assert_eq!(fares.line_fares, HashMap::from([("Shuttle".to_string(), 200)]));
+    assert_eq!(fares.transfer_window, Some(2 * 3600));
+
+    assert_eq!(fare(&network, "alpha", "gamma"), Some(Fare { leg_fares: vec![450, 700], total: 700 }));
+    assert_eq!(fare(&network, "alpha", "delta"), Some(Fare { leg_fares: vec![450, 200], total: 650 }));
+}
+
+#[test]
+fn feeds_without_fares_have_no_fare_table() {
+    let fixture = GtfsFixture::new()
+        .stop("alpha", "Alpha", -37.80, 145.00)
+        .stop("beta", "Beta", -37.81, 145.00)
+        .route("red", "Red", 3)
+        .trip("red", "red", "daily").stop_times("red", &[("alpha", "08:05:00", "08:05:00"), ("beta", "08:20:00", "08:20:00")])
+        .calendar("daily", "1111111", "20240101", "20241231");
+    let fixture_dir = fixture.write().unwrap();
+    let gtfs = GtfsReader::default().read_shapes(false).read_from_path(fixture_dir.path().to_str().unwrap()).unwrap();
+    assert_eq!(Network::new(&gtfs, None, NaiveDate::from_ymd_opt(2024, 5, 10).unwrap(), 60).fares, None);
+}
@@ -0,0 +1,148 @@
+use dev_utils::{get_synthetic_scenario, SyntheticNetwork};
+use raptor::ffi::*;
+use raptor::network::StopIndex;
+use raptor::{raptor_query, Journey, Leg, Network, StopIdx};
+use std::ffi::{c_char, CString};
+use std::path::Path;
+use std::ptr::{null, null_mut};
+
+#[test]
+fn header_is_up_to_date() {
+    let crate_dir = env!("CARGO_MANIFEST_DIR");
+    let config = cbindgen::Config::from_file(Path::new(crate_dir).join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new().with_crate(crate_dir).with_config(config).generate().unwrap().write(&mut generated);
+    let generated = String::from_utf8(generated).unwrap();
+
+    let header_path = Path::new(crate_dir).join("include").join("raptor.h");
+    if std::env::var_os("RAPTOR_UPDATE_HEADER").is_some_and(|update| update == "1") {
+        std::fs::write(&header_path, generated).unwrap();
+        return;
+    }
+    let existing = std::fs::read_to_string(&header_path).unwrap();
+    assert!(existing == generated, "{} is out of date. Regenerate it with RAPTOR_UPDATE_HEADER=1 cargo test --features ffi --test ffi.", header_path.display());
+}
+
+// Writes the network to a temporary file named after the test, so tests running in parallel don't share it, and loads it
+// through the C API.
+fn load_through_ffi(network: &Network, test_name: &str) -> *mut Network {
+    let path = std::env::temp_dir().join(format!("raptor-ffi-{}-{test_name}.json", std::process::id()));
+    serde_json::to_writer(std::fs::File::create(&path).unwrap(), network).unwrap();
+    let c_path = CString::new(path.to_str().unwrap()).unwrap();
+    let mut loaded = null_mut();
+    let status = unsafe { raptor_network_load(c_path.as_ptr(), &mut loaded) };
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(status, RaptorStatus::Ok);
+    loaded
+}
+
+fn stop_id(network: *const Network, stop: StopIndex) -> String {
+    let mut len = 0;
+    assert_eq!(unsafe { raptor_stop_id(network, stop, null_mut(), 0, &mut len) }, RaptorStatus::Ok);
+    let mut buffer = vec![0u8; len + 1];
+    assert_eq!(unsafe { raptor_stop_id(network, stop, buffer.as_mut_ptr().cast::<c_char>(), buffer.len(), null_mut()) }, RaptorStatus::Ok);
+    assert_eq!(buffer.pop(), Some(0));
+    String::from_utf8(buffer).unwrap()
+}
+
+// Checks the C legs against the journey's legs, with each leg starting when the previous one ends.
+fn assert_legs_match(legs: &[RaptorLeg], expected: &Journey) {
+    assert_eq!(legs.len(), expected.legs.len());
+    for (leg, expected_leg) in legs.iter().zip(expected.legs.iter()) {
+        assert_eq!(leg.arrival_time - leg.departure_time, expected_leg.duration());
+        match expected_leg {
+            Leg::Vehicle(vehicle_leg) => {
+                assert_eq!(leg.kind, RaptorLegKind::Vehicle);
+                assert_eq!((leg.from_stop, leg.to_stop, leg.departure_time, leg.arrival_time), (vehicle_leg.boarded_stop, vehicle_leg.arrival_stop, vehicle_leg.boarded_time, vehicle_leg.arrival_time));
+                assert_eq!((leg.route_idx, leg.trip_order), (vehicle_leg.trip.route_idx, vehicle_leg.trip.trip_order));
+            }
+            Leg::Transfer { at_stop, .. } => assert_eq!((leg.kind, leg.from_stop, leg.to_stop), (RaptorLegKind::Transfer, *at_stop, *at_stop)),
+            Leg::Walk { from, to, .. } => assert_eq!((leg.kind, leg.from_stop, leg.to_stop), (RaptorLegKind::Walk, *from, *to)),
+        }
+    }
+    for pair in legs.windows(2) {
+        assert_eq!(pair[0].arrival_time, pair[1].departure_time);
+    }
+}
+
+#[test]
+fn query_round_trips_through_the_c_abi() {
+    let (network, start, start_time, end) = get_synthetic_scenario();
+    let expected = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let loaded = load_through_ffi(&network, "query_round_trips_through_the_c_abi");
+
+    let start_id = CString::new(network.stop_id(start as usize)).unwrap();
+    let end_id = CString::new(network.stop_id(end as usize)).unwrap();
+    let mut journey = RaptorJourney { legs: null_mut(), num_legs: 0, departure_time: 0, arrival_time: 0 };
+    assert_eq!(unsafe { raptor_query_c(loaded, start_id.as_ptr(), start_time, end_id.as_ptr(), &mut journey) }, RaptorStatus::Ok);
+
+    let legs = unsafe { std::slice::from_raw_parts(journey.legs, journey.num_legs) };
+    assert_legs_match(legs, &expected);
+    assert_eq!(Some(journey.arrival_time), expected.arrival_time());
+
+    // Stop indices resolve back to IDs.
+    let mut stop = 0;
+    assert_eq!(unsafe { raptor_stop_index(loaded, end_id.as_ptr(), &mut stop) }, RaptorStatus::Ok);
+    assert_eq!(stop, end);
+    assert_eq!(stop_id(loaded, legs[0].from_stop), start_id.to_str().unwrap());
+
+    unsafe {
+        raptor_journey_free(&mut journey);
+        assert!(journey.legs.is_null());
+        raptor_journey_free(&mut journey);
+        raptor_network_free(loaded);
+    }
+}
+
+#[test]
+fn leading_walk_ends_at_the_first_boarding() {
+    let network = SyntheticNetwork::new()
+        .stop("Home", -37.800, 145.000)
+        .stop("Station", -37.803, 145.000)
+        .stop("Airport", -37.700, 144.850)
+        .line("Express", &[("Station", "08:10", "08:10"), ("Airport", "08:30", "08:30")])
+        .footpath("Home", "Station", 300)
+        .build();
+    let expected = raptor_query(&network, StopIdx(0), 8 * 3600, StopIdx(2)).unwrap();
+    assert!(matches!(expected.legs.as_slice(), [Leg::Walk { .. }, Leg::Vehicle(_)]));
+    let loaded = load_through_ffi(&network, "leading_walk_ends_at_the_first_boarding");
+
+    let (home, airport) = (CString::new("Home").unwrap(), CString::new("Airport").unwrap());
+    let mut journey = RaptorJourney { legs: null_mut(), num_legs: 0, departure_time: 0, arrival_time: 0 };
+    assert_eq!(unsafe { raptor_query_c(loaded, home.as_ptr(), 8 * 3600, airport.as_ptr(), &mut journey) }, RaptorStatus::Ok);
+
+    // The five minute walk is taken just in time for the 08:10 departure, rather than at the query's start time.
+    let legs = unsafe { std::slice::from_raw_parts(journey.legs, journey.num_legs) };
+    assert_legs_match(legs, &expected);
+    assert_eq!((legs[0].departure_time, legs[0].arrival_time), (8 * 3600 + 300, 8 * 3600 + 600));
+    assert_eq!((journey.departure_time, journey.arrival_time), (8 * 3600 + 300, 8 * 3600 + 1800));
+
+    unsafe {
+        raptor_journey_free(&mut journey);
+        raptor_network_free(loaded);
+    }
+}
+
+#[test]
+fn errors_are_status_codes() {
+    let (network, start, start_time, _) = get_synthetic_scenario();
+    let loaded = load_through_ffi(&network, "errors_are_status_codes");
+    let start_id = CString::new(network.stop_id(start as usize)).unwrap();
+    let unknown_id = CString::new("not a stop").unwrap();
+    let mut journey = RaptorJourney { legs: null_mut(), num_legs: 0, departure_time: 0, arrival_time: 0 };
+
+    unsafe {
+        assert_eq!(raptor_query_c(loaded, start_id.as_ptr(), start_time, unknown_id.as_ptr(), &mut journey), RaptorStatus::UnknownStop);
+        assert_eq!(raptor_query_c(loaded, start_id.as_ptr(), start_time, null(), &mut journey), RaptorStatus::NullArgument);
+        assert_eq!(raptor_query_c(null(), start_id.as_ptr(), start_time, start_id.as_ptr(), &mut journey), RaptorStatus::NullArgument);
+        assert!(journey.legs.is_null());
+        assert_eq!(raptor_stop_id(loaded, StopIndex::MAX, null_mut(), 0, null_mut()), RaptorStatus::UnknownStop);
+
+        let missing = CString::new("/nonexistent/network.json").unwrap();
+        let mut not_loaded = null_mut();
+        assert_eq!(raptor_network_load(missing.as_ptr(), &mut not_loaded), RaptorStatus::Io);
+        assert!(not_loaded.is_null());
+
+        raptor_network_free(loaded);
+    }
+}
@@ -0,0 +1,185 @@
+use dev_utils::SyntheticNetwork;
+use raptor::journey::{JourneyError, JourneyPreferences};
+use raptor::network::{FootpathError, GlobalTripIndex, UnknownStop};
+use raptor::reference::dijkstra_query;
+use raptor::{csa_query, mc_raptor_query_exact, raptor_query, Leg, Network, StopIdx};
+
+// Two lines whose nearest stops, Riverside and Wharf, are on opposite banks of a river. They're far enough apart in a straight
+// line that a radius-based footpath generator wouldn't connect them, but a nearby bridge makes the walk five minutes.
+fn river_network() -> Network {
+    SyntheticNetwork::new()
+        .transfer_time(60)
+        .stop("North", -37.800, 145.000)
+        .stop("Riverside", -37.810, 145.000)
+        .stop("Wharf", -37.825, 145.000)
+        .stop("South", -37.840, 145.000)
+        .line("North line", &[("North", "08:00", "08:00"), ("Riverside", "08:05", "08:05")])
+        .line("South line", &[("Wharf", "08:15", "08:15"), ("South", "08:20", "08:20")])
+        .build()
+}
+
+#[test]
+fn query_uses_imported_walk() {
+    let mut network = river_network();
+    let (north, riverside, wharf, south) = (0, 1, 2, 3);
+    assert!(network.stop_points[riverside as usize].distance(network.stop_points[wharf as usize]) > 1.);
+    // Without the walk, the north line is ridden but south can't be reached.
+    match raptor_query(&network, StopIdx(north), 8 * 3600, StopIdx(south)) {
+        Err(JourneyError::NoJourneyFound { start, end, start_name, end_name, start_time, last_reachable_time, stops_reached }) => {
+            assert_eq!((start, end, start_time), (north, south, 8 * 3600));
+            assert_eq!((start_name.as_str(), end_name.as_str()), ("North", "South"));
+            assert_eq!(stops_reached, 1);
+            assert_eq!(last_reachable_time, Some(8 * 3600 + 300));
+        }
+        result => panic!("Expected no journey, got {result:?}"),
+    }
+
+    let csv = "from_stop_id,to_stop_id,duration_secs\n\
+               Riverside,Wharf,300\n\
+               Riverside,nowhere,60\n\
+               \"Wharf\",\"Riverside\",0\n\
+               Riverside,Wharf\n";
+    let rejected = network.load_footpaths_csv(csv.as_bytes()).unwrap();
+    assert_eq!(rejected, vec![
+        (3, FootpathError::UnknownStop(UnknownStop("nowhere".to_string()))),
+        (4, FootpathError::ZeroDuration),
+        (5, FootpathError::Malformed("Riverside,Wharf".to_string())),
+    ]);
+    assert_eq!(network.footpath_duration(riverside, wharf), Some(300));
+    assert_eq!(network.footpath_duration(wharf, riverside), None);
+
+    let journey = raptor_query(&network, StopIdx(north), 8 * 3600, StopIdx(south)).unwrap();
+    assert!(matches!(journey.legs.as_slice(), [Leg::Vehicle(_), Leg::Walk { from: 1, to: 2, duration: 300, .. }, Leg::Vehicle(_)]));
+    assert_eq!(journey.arrival_time(), Some(8 * 3600 + 1200));
+    // The walk ends five minutes before the south line departs.
+    let stats = journey.stats();
+    assert_eq!((stats.in_vehicle_secs, stats.walking_secs, stats.waiting_secs, stats.num_transfers), (600, 300, 300, 1));
+
+    // Walking to the destination adds the walk to the arrival time.
+    let journey = raptor_query(&network, StopIdx(north), 8 * 3600, StopIdx(wharf)).unwrap();
+    assert_eq!(journey.arrival_time(), Some(8 * 3600 + 600));
+    assert_eq!(journey.total_duration(), 600);
+}
+
+#[test]
+fn leading_walk_ends_at_the_first_boarding() {
+    let mut network = river_network();
+    let (riverside, wharf, south) = (1, 2, 3);
+    network.set_footpaths(&[("Riverside", "Wharf", 300)]);
+
+    let journey = raptor_query(&network, StopIdx(riverside), 8 * 3600, StopIdx(south)).unwrap();
+    assert!(matches!(journey.legs.as_slice(), [Leg::Walk { from: 1, to: 2, duration: 300, .. }, Leg::Vehicle(_)]));
+    // The walk sets off at 08:10 to catch the 08:15 south line, so the wait comes before it.
+    let events = journey.events().map(|event| (event.stop, event.arrival, event.departure)).collect::<Vec<_>>();
+    assert_eq!(events, vec![
+        (riverside, None, Some(8 * 3600 + 600)),
+        (wharf, Some(8 * 3600 + 900), None),
+        (wharf, None, Some(8 * 3600 + 900)),
+        (south, Some(8 * 3600 + 1200), None),
+    ]);
+    let stats = journey.stats();
+    assert_eq!((stats.in_vehicle_secs, stats.walking_secs, stats.waiting_secs, stats.num_transfers), (300, 300, 600, 0));
+    assert_eq!(stats.in_vehicle_secs + stats.walking_secs + stats.waiting_secs, journey.total_duration());
+}
+
+#[test]
+fn set_footpaths_keeps_the_shortest_duplicate() {
+    let mut network = river_network();
+    let rejected = network.set_footpaths(&[("Riverside", "Wharf", 600), ("Riverside", "Wharf", 300), ("North", "North", 60)]);
+    assert_eq!(rejected, vec![(2, FootpathError::SameStop("North".to_string()))]);
+    assert_eq!(network.footpaths.len(), 1);
+    assert_eq!(network.footpath_duration(1, 2), Some(300));
+}
+
+#[test]
+fn pairwise_transfer_time_overrides_walk() {
+    let mut network = river_network();
+    let (north, riverside, wharf, south) = (0, 1, 2, 3);
+    network.set_footpaths(&[("Riverside", "Wharf", 300)]);
+
+    // The bridge is closed, so the detour takes twenty minutes and misses the south line.
+    network.set_transfer_times(&[("Riverside", "Wharf", 20 * 60)]);
+    assert_eq!(network.get_effective_transfer_time(riverside, wharf), 20 * 60);
+    assert_eq!(network.footpath_duration(riverside, wharf), Some(20 * 60));
+    assert!(raptor_query(&network, StopIdx(north), 8 * 3600, StopIdx(south)).is_err());
+    let journey = raptor_query(&network, StopIdx(north), 8 * 3600, StopIdx(wharf)).unwrap();
+    assert!(matches!(journey.legs.as_slice(), [Leg::Vehicle(_), Leg::Walk { from: 1, to: 2, duration: 1200, .. }]));
+    assert_eq!(journey.arrival_time(), Some(8 * 3600 + 300 + 20 * 60));
+    journey.validate().unwrap();
+
+    // Overrides only apply in their direction, and a shorter one makes the connection again.
+    assert_eq!(network.footpath_duration(wharf, riverside), None);
+    network.set_transfer_times(&[("Riverside", "Wharf", 4 * 60)]);
+    let journey = raptor_query(&network, StopIdx(north), 8 * 3600, StopIdx(south)).unwrap();
+    assert!(matches!(journey.legs.as_slice(), [Leg::Vehicle(_), Leg::Walk { duration: 240, .. }, Leg::Vehicle(_)]));
+    assert_eq!(journey.arrival_time(), Some(8 * 3600 + 1200));
+}
+
+#[test]
+fn journey_changes_trips_where_the_transfer_time_allows() {
+    let network = SyntheticNetwork::new()
+        .transfer_time(180)
+        .stop("Home", -37.800, 145.000)
+        .stop("Station", -37.805, 145.000)
+        .stop("Airport", -37.700, 144.850)
+        .line("Shuttle", &[("Home", "08:00", "08:00"), ("Station", "08:04", "08:04")])
+        .line("Express", &[("Station", "08:06", "08:06"), ("Airport", "08:30", "08:30")])
+        .footpath("Home", "Station", 300)
+        .build();
+
+    // The shuttle reaches the station sooner than walking, but leaves too little time to change onto the express, so the
+    // express is boarded after the walk.
+    let journey = raptor_query(&network, StopIdx(0), 8 * 3600, StopIdx(2)).unwrap();
+    assert!(matches!(journey.legs.as_slice(), [Leg::Walk { from: 0, to: 1, duration: 300, .. }, Leg::Vehicle(leg)] if leg.boarded_time == 8 * 3600 + 360));
+    assert_eq!(journey.arrival_time(), Some(8 * 3600 + 1800));
+    journey.validate().unwrap();
+}
+
+#[test]
+fn routers_agree_when_walking() {
+    // Two lines that cross without sharing a stop, linked by walks, and a third line whose ends are a walk apart. The
+    // footpaths between Airport, Bay, Dock and Elm are transitively closed, and none is longer than walking via another stop.
+    let mut network = SyntheticNetwork::new()
+        .transfer_time(120)
+        .stop("Airport", -37.800, 145.000)
+        .stop("Bay", -37.804, 145.002)
+        .stop("Cove", -37.830, 145.010)
+        .stop("Dock", -37.802, 145.003)
+        .stop("Elm", -37.805, 145.005)
+        .stop("Ferry", -37.834, 145.015)
+        .line("Red", &[("Airport", "08:00", "08:00"), ("Bay", "08:06", "08:06"), ("Cove", "08:12", "08:12")]).every(10, 12)
+        .line("Blue", &[("Dock", "08:03", "08:03"), ("Elm", "08:10", "08:10"), ("Ferry", "08:20", "08:20")]).every(15, 8)
+        .line("Green", &[("Cove", "08:05", "08:05"), ("Ferry", "08:25", "08:25")]).every(20, 6)
+        .footpath("Airport", "Dock", 300).footpath("Dock", "Airport", 300)
+        .footpath("Airport", "Bay", 500).footpath("Bay", "Airport", 500)
+        .footpath("Airport", "Elm", 550).footpath("Elm", "Airport", 550)
+        .footpath("Bay", "Dock", 240).footpath("Dock", "Bay", 240)
+        .footpath("Bay", "Elm", 420).footpath("Elm", "Bay", 420)
+        .footpath("Dock", "Elm", 300).footpath("Elm", "Dock", 300)
+        .footpath("Cove", "Ferry", 600).footpath("Ferry", "Cove", 600)
+        .build();
+    network.build_connections();
+
+    let free = |_: &Network, _: GlobalTripIndex, _: usize| 0.;
+    let preferences = JourneyPreferences::default();
+    let mut num_walks = 0;
+    for start in 0..network.num_stops() as u32 {
+        for end in (0..network.num_stops() as u32).filter(|&end| end != start) {
+            for start_time in (7 * 3600 + 1800..10 * 3600).step_by(211) {
+                let expected = dijkstra_query(&network, StopIdx(start), start_time, StopIdx(end)).ok();
+                let raptor_journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end));
+                let csa_journey = csa_query(&network, StopIdx(start), start_time, StopIdx(end));
+                let mc_journey = mc_raptor_query_exact(&network, StopIdx(start), start_time, &[StopIdx(end)], &free, &preferences).remove(0);
+                for (router, journey) in [("RAPTOR", raptor_journey), ("CSA", csa_journey), ("McRAPTOR", mc_journey)] {
+                    let arrival_time = journey.as_ref().ok().and_then(|journey| journey.arrival_time());
+                    assert_eq!(arrival_time, expected, "{router} from {start} at {start_time} to {end}: {journey:?}");
+                    if let Ok(journey) = journey {
+                        journey.validate().unwrap();
+                        num_walks += journey.legs.iter().filter(|leg| matches!(leg, Leg::Walk { .. })).count();
+                    }
+                }
+            }
+        }
+    }
+    assert!(num_walks > 0);
+}
@@ -0,0 +1,90 @@
+use dev_utils::{get_example_date, GtfsFixture};
+use gtfs_structures::{Gtfs, GtfsReader};
+use raptor::network::NetworkError;
+use raptor::{raptor_query, Network, StopIdx};
+
+// Two stops, with a train from A to B for each of the given (trip ID, service ID) pairs, an hour apart from 08:00.
+fn two_stop_fixture(trips: &[(&str, &str)]) -> GtfsFixture {
+    let mut fixture = GtfsFixture::new()
+        .stop("A", "A Railway Station", -37.80, 145.00)
+        .stop("B", "B Railway Station", -37.80, 145.01)
+        .route("R", "Line", 2);
+    for (i, &(trip_id, service_id)) in trips.iter().enumerate() {
+        let departure = format!("{:02}:00:00", 8 + i);
+        let arrival = format!("{:02}:10:00", 8 + i);
+        fixture = fixture.trip(trip_id, "R", service_id).stop_times(trip_id, &[("A", &departure, &departure), ("B", &arrival, &arrival)]);
+    }
+    fixture
+}
+
+fn read_gtfs(fixture: &GtfsFixture) -> Gtfs {
+    let fixture_dir = fixture.write().unwrap();
+    GtfsReader::default().read_shapes(false).read_from_path(fixture_dir.path().to_str().unwrap()).unwrap()
+}
+
+fn trip_ids(network: &Network) -> Vec<&str> {
+    let mut trip_ids = network.routes.iter().flat_map(|route| route.trip_ids.iter()).collect::<Vec<_>>();
+    trip_ids.sort_unstable();
+    trip_ids
+}
+
+#[test]
+fn fixture_builds_a_network() {
+    let gtfs = read_gtfs(&two_stop_fixture(&[("T1", "Weekdays")]).calendar("Weekdays", "1111100", "20240101", "20241231"));
+    let network = Network::new(&gtfs, None, get_example_date(), 0);
+
+    assert_eq!(network.stops.len(), 2);
+    assert_eq!(trip_ids(&network), ["T1"]);
+    let start = network.get_stop_idx_from_name("A").unwrap();
+    let end = network.get_stop_idx_from_name("B").unwrap();
+    let journey = raptor_query(&network, StopIdx(start), 7 * 3600, StopIdx(end)).unwrap();
+    assert_eq!(journey.arrival_time(), Some(8 * 3600 + 600));
+
+    // The example date is a Friday, so the service doesn't run the next day.
+    let saturday = get_example_date().succ_opt().unwrap();
+    assert_eq!(Network::new(&gtfs, None, saturday, 0).num_trips, 0);
+}
+
+#[test]
+fn unbuildable_feeds_are_errors() {
+    let untimed = GtfsFixture::new()
+        .stop("A", "A Railway Station", -37.80, 145.00)
+        .stop("B", "B Railway Station", -37.80, 145.01)
+        .route("R", "Line", 2)
+        .calendar("Weekdays", "1111100", "20240101", "20241231")
+        .trip("T1", "R", "Weekdays")
+        .stop_times("T1", &[("A", "08:00:00", "08:00:00"), ("B", "", "")]);
+    let gtfs = read_gtfs(&untimed);
+    match Network::try_new(&gtfs, None, get_example_date(), 0) {
+        Err(NetworkError::MissingStopTime { trip_id, stop_id }) => assert_eq!((trip_id.as_str(), stop_id.as_str()), ("T1", "B")),
+        other => panic!("Expected a missing stop time, got {:?}.", other.map(|network| network.num_trips)),
+    }
+
+    let missing = std::env::temp_dir().join("raptor-no-such-gtfs");
+    assert!(matches!(Network::load(missing.to_str().unwrap(), None, get_example_date(), 0), Err(NetworkError::Gtfs(_))));
+}
+
+#[test]
+fn calendar_dates_remove_service() {
+    let fixture = two_stop_fixture(&[("Regular", "Weekdays"), ("Cancelled", "Weekdays except today")])
+        .calendar("Weekdays", "1111100", "20240101", "20241231")
+        .calendar("Weekdays except today", "1111100", "20240101", "20241231")
+        .calendar_date("Weekdays except today", "20240510", 2);
+    let gtfs = read_gtfs(&fixture);
+
+    assert_eq!(trip_ids(&Network::new(&gtfs, None, get_example_date(), 0)), ["Regular"]);
+    // The removal only applies on its date.
+    let next_monday = get_example_date() + chrono::Days::new(3);
+    assert_eq!(trip_ids(&Network::new(&gtfs, None, next_monday, 0)), ["Cancelled", "Regular"]);
+}
+
+#[test]
+fn calendar_dates_without_calendar() {
+    let fixture = two_stop_fixture(&[("Added", "Special"), ("Removed", "Never")])
+        .calendar_date("Special", "20240510", 1)
+        .calendar_date("Never", "20240510", 2);
+    let gtfs = read_gtfs(&fixture);
+
+    assert_eq!(trip_ids(&Network::new(&gtfs, None, get_example_date(), 0)), ["Added"]);
+    assert!(trip_ids(&Network::new(&gtfs, None, get_example_date().succ_opt().unwrap(), 0)).is_empty());
+}
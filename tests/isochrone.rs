@@ -0,0 +1,52 @@
+use dev_utils::{get_example_scenario, SyntheticNetwork};
+use raptor::isochrone::DEFAULT_ISOCHRONE_BANDS;
+use raptor::{raptor_one_to_all, raptor_query, Isochrone, StopIdx};
+
+#[test]
+fn stops_are_bucketed_by_travel_time() {
+    // A line from A through B, C and D, reaching them after 10, 25 and 50 minutes.
+    let network = SyntheticNetwork::new()
+        .transfer_time(60)
+        .stop("A", -37.8, 145.0)
+        .stop("B", -37.8, 145.1)
+        .stop("C", -37.8, 145.2)
+        .stop("D", -37.8, 145.3)
+        .line("Line", &[("A", "08:00", "08:00"), ("B", "08:10", "08:10"), ("C", "08:25", "08:25"), ("D", "08:50", "08:50")])
+        .build();
+    let isochrone = Isochrone::new(&network, 0, 8 * 3600, &DEFAULT_ISOCHRONE_BANDS);
+    assert_eq!(isochrone.bands, vec![vec![(0, 0), (1, 600)], vec![(2, 1500)], vec![], vec![(3, 3000)]]);
+
+    // Missing the trip leaves only the start stop.
+    let isochrone = Isochrone::new(&network, 0, 8 * 3600 + 1, &DEFAULT_ISOCHRONE_BANDS);
+    assert_eq!(isochrone.bands, vec![vec![(0, 0)], vec![], vec![], vec![]]);
+}
+
+#[test]
+fn every_band_is_reached() {
+    let (network, start, start_time, _) = get_example_scenario();
+    let isochrone = Isochrone::new(&network, start, start_time, &DEFAULT_ISOCHRONE_BANDS);
+
+    assert!(isochrone.bands[0].contains(&(start, 0)));
+    for (limit, band) in isochrone.band_limits.iter().zip(&isochrone.bands) {
+        assert!(!band.is_empty(), "Nothing reachable within {} minutes.", limit / 60);
+    }
+
+    let geojson: serde_json::Value = serde_json::from_str(&isochrone.to_geojson(&network, true)).unwrap();
+    let features = geojson["features"].as_array().unwrap();
+    let hulls = features.iter().filter(|feature| feature["properties"]["kind"] == "hull").count();
+    assert_eq!(hulls, DEFAULT_ISOCHRONE_BANDS.len());
+}
+
+#[test]
+fn one_to_all_matches_point_queries() {
+    let (network, start, start_time, _) = get_example_scenario();
+    let arrivals = raptor_one_to_all(&network, StopIdx(start), start_time);
+    assert_eq!(arrivals.len(), network.stops.len());
+    assert_eq!(arrivals[start as usize], Some(start_time));
+
+    for end in (0..network.stops.len()).step_by(37).filter(|&end| end != start as usize) {
+        let journey_arrival = raptor_query(&network, StopIdx(start), start_time, StopIdx(end as u32)).ok()
+            .and_then(|journey| journey.legs_vehicle().last().map(|leg| leg.arrival_time));
+        assert_eq!(arrivals[end], journey_arrival, "Arrival at {}", network.stop_name(end));
+    }
+}
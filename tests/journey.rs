@@ -0,0 +1,371 @@
+use dev_utils::{get_example_scenario, get_synthetic_scenario, SyntheticNetwork};
+use raptor::journey::JourneyValidationError;
+use raptor::network::StopIndex;
+use raptor::{raptor_one_to_all_journeys, raptor_query, utils, ItineraryStyle, Journey, JourneyStats, Leg, Network, StopIdx, TimeFormat};
+
+#[test]
+fn legs_alternate_between_vehicles_and_transfers() {
+    let (network, start, start_time, end) = get_synthetic_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    assert!(!journey.legs.is_empty());
+    for (i, leg) in journey.legs.iter().enumerate() {
+        // Journeys start and end on a vehicle, with a transfer between each pair of vehicle legs.
+        match leg {
+            Leg::Vehicle(_) => assert_eq!(i % 2, 0),
+            Leg::Transfer { .. } => assert_eq!(i % 2, 1),
+            Leg::Walk { .. } => panic!("The synthetic network has no footpaths."),
+        }
+    }
+    assert!(matches!(journey.legs.last(), Some(Leg::Vehicle(_))));
+
+    let total_duration: u32 = journey.legs.iter().map(Leg::duration).sum();
+    assert_eq!(total_duration, journey.duration);
+}
+
+#[test]
+fn intermediate_stops_are_between_boarding_and_arrival() {
+    let (network, start, start_time, end) = get_synthetic_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    for leg in journey.legs_vehicle() {
+        let intermediate = leg.intermediate_stops(&network).collect::<Vec<_>>();
+        assert_eq!(intermediate.len(), leg.num_stops() - 1);
+        let mut previous_time = leg.boarded_time;
+        for &(stop, time) in intermediate.iter() {
+            assert!(stop != leg.boarded_stop && stop != leg.arrival_stop);
+            assert!(previous_time <= time && time <= leg.arrival_time);
+            previous_time = time;
+        }
+    }
+}
+
+#[test]
+fn total_duration_includes_initial_wait() {
+    let (network, start, start_time, end) = get_synthetic_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    let first_boarding = journey.legs_vehicle().next().unwrap().boarded_time;
+    let last_arrival = journey.legs_vehicle().last().unwrap().arrival_time;
+    assert_eq!(journey.start_time, start_time);
+    assert_eq!(journey.in_motion_duration(), last_arrival - first_boarding);
+    assert_eq!(journey.total_duration(), last_arrival - start_time);
+    assert_eq!(journey.total_duration(), journey.in_motion_duration() + journey.initial_wait());
+}
+
+#[test]
+fn stats_add_up_to_total_duration() {
+    let (network, start, start_time, end) = get_example_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    let stats = journey.stats();
+    assert_eq!(stats.in_vehicle_secs + stats.waiting_secs + stats.walking_secs, journey.total_duration());
+    assert!(stats.waiting_secs >= journey.initial_wait());
+    assert_eq!(stats.walking_secs, 0);
+    assert_eq!(stats.num_transfers, journey.legs_vehicle().count() - 1);
+    assert_eq!(stats.num_stops_passed, journey.legs_vehicle().map(|leg| leg.intermediate_stops(&network).count()).sum::<usize>());
+
+    assert_eq!(Journey::empty(&network).stats(), JourneyStats::default());
+}
+
+#[test]
+fn total_distance_is_plausible() {
+    let (network, start, start_time, end) = get_example_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    // Cheltenham to Greensborough is about 30 km as the crow flies, and further by rail through the city.
+    let distance = journey.total_distance_km();
+    assert!((20. ..60.).contains(&distance), "{distance} km");
+    assert!(journey.legs_vehicle().all(|leg| leg.distance_km(&network) > 0.));
+    assert_eq!(journey.legs.iter().map(|leg| leg.distance_km(&network)).sum::<f32>(), distance);
+
+    // Zero-length legs, which shouldn't happen, cover no distance.
+    let mut leg = journey.legs_vehicle().next().unwrap().clone();
+    leg.arrival_stop = leg.boarded_stop;
+    leg.arrival_stop_order = leg.boarded_stop_order;
+    assert_eq!(leg.distance_km(&network), 0.);
+}
+
+#[test]
+fn csv_rows_parse_back() {
+    let (network, start, start_time, end) = get_example_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let mut output = Vec::new();
+    journey.write_csv(&mut output).unwrap();
+    let mut reader = csv::Reader::from_reader(output.as_slice());
+    assert_eq!(reader.headers().unwrap().iter().collect::<Vec<_>>(), ["stop_id", "stop_name", "arrival_time", "departure_time", "trip_id", "line", "wait_secs"]);
+    let rows = reader.records().collect::<Result<Vec<_>, _>>().unwrap();
+
+    // Each trip's stops from boarding to alighting, and a row for each transfer.
+    let num_stops_on_board = journey.legs_vehicle().map(|leg| leg.num_stops() + 1).sum::<usize>();
+    let num_transfers = journey.legs.iter().filter(|leg| matches!(leg, Leg::Transfer { .. })).count();
+    assert_eq!(rows.len(), num_stops_on_board + num_transfers);
+    assert!(rows.iter().all(|row| row.len() == 7));
+
+    for (row, expected) in rows.iter().zip(journey.to_rows()) {
+        assert_eq!(&row[0], expected.stop_id);
+        assert_eq!(row[2], expected.arrival_time.map(utils::get_time_str).unwrap_or_default());
+        assert_eq!(&row[4], expected.trip_id.unwrap_or_default());
+    }
+    let transfer_rows = rows.iter().filter(|row| row[4].is_empty()).collect::<Vec<_>>();
+    assert_eq!(transfer_rows.len(), num_transfers);
+    for (row, leg) in transfer_rows.iter().zip(journey.legs.iter().filter(|leg| matches!(leg, Leg::Transfer { .. }))) {
+        assert_eq!(row[6], leg.duration().to_string());
+    }
+}
+
+#[test]
+fn geojson_is_valid_and_uses_route_colours() {
+    let (network, start, start_time, end) = get_example_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    let geojson: serde_json::Value = serde_json::from_str(&journey.to_geojson()).unwrap();
+    assert_eq!(geojson["type"], "FeatureCollection");
+
+    let features = geojson["features"].as_array().unwrap();
+    let legs = features.iter().filter(|feature| feature["properties"]["kind"] == "leg").collect::<Vec<_>>();
+    assert_eq!(legs.len(), journey.legs_vehicle().count());
+    for (feature, leg) in legs.iter().zip(journey.legs_vehicle()) {
+        assert_eq!(feature["geometry"]["type"], "LineString");
+        assert!(feature["geometry"]["coordinates"].as_array().unwrap().len() >= 2);
+        let colour = network.routes[leg.trip.route().idx()].colour;
+        assert_eq!(feature["properties"]["colour"], raptor::utils::get_colour_hex(colour));
+    }
+}
+
+#[test]
+fn events_are_monotonic() {
+    let (network, start, start_time, end) = get_synthetic_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    let events = journey.events().collect::<Vec<_>>();
+    let mut previous_time = 0;
+    for event in events.iter() {
+        for time in [event.arrival, event.departure].into_iter().flatten() {
+            assert!(previous_time <= time, "Events are not in time order: {events:?}");
+            previous_time = time;
+        }
+    }
+
+    let first = events.first().unwrap();
+    let last = events.last().unwrap();
+    assert_eq!(first.stop, start);
+    assert_eq!(first.departure, journey.departure_time());
+    assert_eq!(last.stop, end);
+    assert_eq!(last.arrival, journey.arrival_time());
+}
+
+#[test]
+fn itinerary_styles() {
+    let (network, start, start_time, end) = get_example_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let num_vehicle_legs = journey.legs_vehicle().count();
+
+    let compact = journey.format(ItineraryStyle::Compact, TimeFormat::H24);
+    assert_eq!(compact.lines().count(), num_vehicle_legs);
+    let first_leg = journey.legs_vehicle().next().unwrap();
+    assert!(compact.starts_with(&format!("{} Cheltenham →", utils::get_time_str(first_leg.boarded_time))));
+    assert!(compact.lines().last().unwrap().contains("→ ") && compact.contains("Greensborough"));
+
+    let compact_12h = journey.format(ItineraryStyle::Compact, TimeFormat::H12);
+    assert!(compact_12h.starts_with(&utils::get_time_str_12h(first_leg.boarded_time)));
+
+    let markdown = journey.format(ItineraryStyle::Markdown, TimeFormat::H24);
+    let mut lines = markdown.lines();
+    assert_eq!(lines.next(), Some("| Departs | From | Arrives | To | Line | Stops |"));
+    assert_eq!(lines.next(), Some("|---|---|---|---|---|---|"));
+    assert_eq!(markdown.lines().filter(|line| line.starts_with("| ")).count(), num_vehicle_legs + 1);
+
+    // Display is the detailed style without intermediate stop counts.
+    assert_eq!(journey.format(ItineraryStyle::Detailed, TimeFormat::H24), format!("{journey:#}"));
+    assert_eq!(format!("{journey:#}").lines().filter(|line| line.starts_with("Pass ")).count(), num_vehicle_legs);
+    assert!(!journey.to_string().contains("Pass "));
+}
+
+#[test]
+fn transfer_splits_interchange_and_wait() {
+    let (mut network, start, start_time, end) = get_synthetic_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    let check_transfers = |journey: &raptor::Journey, network: &raptor::Network| {
+        let mut num_transfers = 0;
+        for (i, leg) in journey.legs.iter().enumerate() {
+            if let Leg::Transfer { at_stop, transfer_time, wait_time } = *leg {
+                let previous = journey.legs[i - 1].as_vehicle().unwrap();
+                let next = journey.legs[i + 1].as_vehicle().unwrap();
+                assert_eq!(transfer_time, network.transfer_times[at_stop as usize]);
+                assert_eq!(transfer_time + wait_time, next.boarded_time - previous.arrival_time);
+                num_transfers += 1;
+            }
+        }
+        num_transfers
+    };
+    assert!(check_transfers(&journey, &network) > 0, "The example journey should have a transfer.");
+
+    // A long interchange at the first transfer stop should be reported as transfer time, not waiting time.
+    let transfer_stop = journey.legs.iter().find_map(|leg| match leg {
+        Leg::Transfer { at_stop, .. } => Some(*at_stop),
+        _ => None,
+    }).unwrap();
+    let transfer_stop_id = network.stop_id(transfer_stop as usize).to_string();
+    let arrival_time = journey.arrival_time();
+    network.set_transfer_time_for_stop(&transfer_stop_id, 15 * 60);
+    let long_journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    check_transfers(&long_journey, &network);
+    // A longer interchange can't make the journey arrive earlier.
+    assert!(long_journey.arrival_time() >= arrival_time);
+    // If the journey still changes at that stop, the whole interchange counts as transfer time and only what's left over
+    // as waiting.
+    for (i, leg) in long_journey.legs.iter().enumerate() {
+        if let Leg::Transfer { at_stop, transfer_time, wait_time } = *leg {
+            if at_stop == transfer_stop {
+                assert_eq!(transfer_time, 15 * 60);
+                let previous = long_journey.legs[i - 1].as_vehicle().unwrap();
+                let next = long_journey.legs[i + 1].as_vehicle().unwrap();
+                assert_eq!(wait_time, next.boarded_time - previous.arrival_time - 15 * 60);
+            }
+        }
+    }
+}
+
+#[test]
+fn reconstructing_into_a_shared_buffer_matches_a_new_buffer() {
+    let (network, start, start_time, end) = get_synthetic_scenario();
+    let one_to_all = raptor_one_to_all_journeys(&network, StopIdx(start), start_time);
+    assert_eq!((one_to_all.start(), one_to_all.start_time()), (StopIdx(start), start_time));
+
+    let mut legs_buf = Vec::new();
+    let mut num_journeys = 0;
+    for stop in (0..network.stops.len() as StopIndex).filter(|&stop| stop != start).map(StopIdx) {
+        let journey = one_to_all.journey(stop);
+        let buffered = one_to_all.journey_into(stop, &mut legs_buf);
+        match (journey, buffered) {
+            (Ok(journey), Ok(buffered)) => {
+                assert_eq!(journey.legs, buffered.legs);
+                assert_eq!((journey.duration, journey.start_time), (buffered.duration, buffered.start_time));
+                assert_eq!(journey.arrival_time(), one_to_all.arrival_time(stop));
+                num_journeys += 1;
+            }
+            (journey, buffered) => assert_eq!(journey.is_err(), buffered.is_err()),
+        }
+    }
+    assert!(num_journeys > 1);
+
+    // Without a target to prune on, the journey to the query's end is the same as the point-to-point query finds.
+    let journey = one_to_all.journey_into(StopIdx(end), &mut legs_buf).unwrap();
+    assert_eq!(journey.arrival_time(), raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap().arrival_time());
+}
+
+// A copy of the journey with its legs changed by corrupt.
+fn corrupted<'a>(journey: &Journey<'a>, corrupt: impl FnOnce(&mut Vec<Leg>)) -> Journey<'a> {
+    let mut legs = journey.legs.clone();
+    corrupt(&mut legs);
+    Journey { legs, duration: journey.duration, start_time: journey.start_time, cost: journey.cost, origin: journey.origin, destination: journey.destination, network: journey.network }
+}
+
+fn vehicle_leg(legs: &mut [Leg], i: usize) -> &mut raptor::VehicleLeg {
+    match &mut legs[i] {
+        Leg::Vehicle(leg) => leg,
+        _ => panic!("Leg {i} isn't a vehicle leg."),
+    }
+}
+
+#[test]
+fn validate_detects_corrupted_journeys() {
+    let (mut network, start, start_time, end) = get_synthetic_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    assert_eq!(journey.validate(), Ok(()));
+    // The synthetic journey is a vehicle leg, a transfer and another vehicle leg (see legs_alternate_between_vehicles_and_transfers).
+    assert!(journey.legs.len() >= 3);
+
+    let boarded_stop = journey.legs_vehicle().next().unwrap().boarded_stop;
+    let invalid = corrupted(&journey, |legs| vehicle_leg(legs, 0).arrival_stop = boarded_stop);
+    assert!(matches!(invalid.validate(), Err(JourneyValidationError::WrongStopOrder { leg: 0, .. })));
+
+    let invalid = corrupted(&journey, |legs| vehicle_leg(legs, 0).arrival_time += 60);
+    assert!(matches!(invalid.validate(), Err(JourneyValidationError::WrongTime { leg: 0, .. })));
+
+    let invalid = corrupted(&journey, |legs| vehicle_leg(legs, 0).trip.trip_order = u32::MAX);
+    assert!(matches!(invalid.validate(), Err(JourneyValidationError::UnknownTrip { leg: 0, .. })));
+
+    let invalid = corrupted(&journey, |legs| {
+        if let Leg::Transfer { at_stop, .. } = &mut legs[1] {
+            *at_stop = boarded_stop;
+        }
+    });
+    assert!(matches!(invalid.validate(), Err(JourneyValidationError::Disconnected { leg: 1 })));
+
+    let invalid = corrupted(&journey, |legs| {
+        if let Leg::Transfer { wait_time, .. } = &mut legs[1] {
+            *wait_time += 60;
+        }
+    });
+    assert!(matches!(invalid.validate(), Err(JourneyValidationError::WrongTransferDuration { leg: 1 })));
+
+    let mut invalid = corrupted(&journey, |_| {});
+    invalid.start_time = journey.departure_time().unwrap() + 1;
+    assert!(matches!(invalid.validate(), Err(JourneyValidationError::NotTimeOrdered { leg: 0 })));
+
+    // Requiring a longer transfer than the journey leaves invalidates it without changing the journey.
+    let legs = journey.legs.clone();
+    let (duration, cost, origin, destination) = (journey.duration, journey.cost, journey.origin, journey.destination);
+    let Leg::Transfer { at_stop, .. } = legs[1] else { panic!("Leg 1 isn't a transfer.") };
+    let transfer_stop_id = network.stop_id(at_stop as usize).to_string();
+    network.set_transfer_time_for_stop(&transfer_stop_id, 24 * 3600);
+    let invalid = Journey { legs, duration, start_time, cost, origin, destination, network: &network };
+    assert!(matches!(invalid.validate(), Err(JourneyValidationError::TransferTooShort { leg: 2, .. })));
+}
+
+// Alpha to Beta on the red line, then Beta to Gamma on the blue line.
+fn two_line_network() -> Network {
+    SyntheticNetwork::new()
+        .transfer_time(60)
+        .stop("Alpha", -37.80, 145.0)
+        .stop("Beta", -37.81, 145.0)
+        .stop("Gamma", -37.82, 145.0)
+        .line("Red", &[("Alpha", "08:05", "08:05"), ("Beta", "08:20", "08:20")])
+        .line("Blue", &[("Beta", "08:30", "08:30"), ("Gamma", "08:45", "08:45")])
+        .build()
+}
+
+#[test]
+fn display_one_leg_journey() {
+    let network = two_line_network();
+    let journey = raptor_query(&network, StopIdx(0), 8 * 3600 + 300, StopIdx(1)).unwrap();
+    assert_eq!(journey.to_string(), "\
+-----------------------------------------------
+Board at Alpha at 08:05:00 (Red line).
+Arrive at Beta at 08:20:00.
+
+0 transfers.
+Travel time: 15 min.
+Door-to-door time from 08:05:00: 15 min.
+-----------------------------------------------
+");
+}
+
+#[test]
+fn display_two_leg_journey_shows_waits_and_transfers() {
+    let network = two_line_network();
+    let journey = raptor_query(&network, StopIdx(0), 8 * 3600, StopIdx(2)).unwrap();
+    assert_eq!(journey.to_string(), "\
+-----------------------------------------------
+Wait 5 min at Alpha.
+Board at Alpha at 08:05:00 (Red line).
+Arrive at Beta at 08:20:00.
+
+Transfer at Beta (1 min interchange), then wait 9 min.
+
+Board at Beta at 08:30:00 (Blue line).
+Arrive at Gamma at 08:45:00.
+
+1 transfer.
+Travel time: 40 min.
+Door-to-door time from 08:00:00: 45 min (including 5 min waiting for the first service).
+-----------------------------------------------
+");
+
+    let already_there = Journey::empty_at(&network, 1, 8 * 3600);
+    assert_eq!(already_there.to_string(), "-----------------------------------------------\nAlready at Beta, no travel needed.\n-----------------------------------------------\n");
+    assert!(Journey::empty(&network).to_string().contains("No journey found."));
+}
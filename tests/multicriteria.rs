@@ -0,0 +1,321 @@
+use std::iter::repeat_with;
+
+use dev_utils::{get_example_scenario, SyntheticNetwork};
+use raptor::journey::JourneyPreferences;
+use raptor::network::{CostProvider, CountBoardings, GlobalTripIndex, PathfindingCost, Timestamp};
+use raptor::{mc_raptor_query, mc_raptor_query_exact, mc_raptor_query_with_stats, raptor_query, utils, Epsilon, Journey, Network, OverflowPolicy, StopIdx, TieBreak};
+
+fn assert_same_journey(a: &Journey, b: &Journey) {
+    assert!(a.same_path(b), "Journeys differ:\n{a:?}\n{b:?}");
+    assert_eq!(a.legs, b.legs);
+    assert_eq!(a.cost, b.cost);
+}
+
+#[test]
+fn multi_target_matches_single_target() {
+    let (network, start, start_time, end) = get_example_scenario();
+    fastrand::seed(7);
+    let costs: Vec<_> = repeat_with(|| fastrand::f32() as PathfindingCost).take(network.stop_times.len()).collect();
+    let preferences = JourneyPreferences::default();
+
+    let ends = [
+        end,
+        network.get_stop_idx_from_name("Flinders Street").unwrap(),
+        network.get_stop_idx_from_name("Box Hill").unwrap(),
+        start,
+    ];
+    let journeys = mc_raptor_query::<5>(&network, StopIdx(start), start_time, &ends.map(StopIdx), &costs, &preferences);
+    assert_eq!(journeys.len(), ends.len());
+
+    for (&end, journey) in ends.iter().zip(journeys.iter()) {
+        let single = mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &costs, &preferences);
+        assert_eq!(single.len(), 1);
+        match (journey, &single[0]) {
+            (Ok(a), Ok(b)) => assert_same_journey(a, b),
+            (Err(_), Err(_)) => {}
+            _ => panic!("Multi-target and single-target queries disagree for stop {end}."),
+        }
+    }
+}
+
+#[test]
+fn closure_costs_match_slice_costs() {
+    let (network, start, start_time, end) = get_example_scenario();
+    fastrand::seed(7);
+    let costs: Vec<_> = repeat_with(|| fastrand::f32() as PathfindingCost).take(network.stop_times.len()).collect();
+    let preferences = JourneyPreferences::default();
+
+    let slice_journeys = mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], costs.as_slice(), &preferences);
+    let closure_costs = |_: &Network, _: GlobalTripIndex, stop_times_index: usize| costs[stop_times_index];
+    let closure_journeys = mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &closure_costs, &preferences);
+
+    match (&slice_journeys[0], &closure_journeys[0]) {
+        (Ok(a), Ok(b)) => assert_same_journey(a, b),
+        _ => panic!("Expected a journey from both cost providers."),
+    }
+}
+
+// A flat fare charged every time a trip is boarded.
+struct FlatFare(PathfindingCost);
+
+impl CostProvider for FlatFare {
+    fn cost(&self, _network: &Network, _trip: GlobalTripIndex, _stop_times_index: usize) -> PathfindingCost {
+        0.
+    }
+
+    fn boarding_cost(&self, _network: &Network, _trip: GlobalTripIndex, _stop_times_index: usize) -> PathfindingCost {
+        self.0
+    }
+}
+
+#[test]
+fn boarding_cost_prefers_fewer_trips() {
+    let network = express_or_direct_network();
+    let (start, end) = (StopIdx(network.get_stop_idx("Alpha")), StopIdx(network.get_stop_idx("Delta")));
+    let query = |fare| {
+        // Value each dollar of fare at 10 minutes of travel time.
+        let fare_weighted = JourneyPreferences {
+            utility_function: Box::new(|label, start_time| (label.arrival_time - start_time) as PathfindingCost + label.cost * 600.),
+            ..Default::default()
+        };
+        mc_raptor_query::<5>(&network, start, 8 * 3600, &[end], &FlatFare(fare), &fare_weighted).remove(0).unwrap()
+    };
+
+    // Without fares, changing onto the shuttle saves half an hour. A $5 fare for each trip boarded outweighs that.
+    let free = query(0.);
+    assert_eq!((free.legs_vehicle().count(), free.arrival_time()), (2, Some(8 * 3600 + 1800)));
+    let fared = query(5.);
+    assert_eq!((fared.legs_vehicle().count(), fared.arrival_time(), fared.cost), (1, Some(9 * 3600), 5.));
+}
+
+#[test]
+fn leg_costs_sum_to_journey_cost() {
+    let (network, start, start_time, end) = get_example_scenario();
+    fastrand::seed(7);
+    let costs: Vec<_> = repeat_with(|| fastrand::f32() as PathfindingCost).take(network.stop_times.len()).collect();
+
+    let journey = mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &costs, &JourneyPreferences::default()).remove(0).unwrap();
+    let leg_costs: PathfindingCost = journey.legs_vehicle().map(|leg| leg.cost).sum();
+    assert!((leg_costs - journey.cost).abs() < 1e-3 * journey.cost.max(1.), "Leg costs {leg_costs} don't sum to journey cost {}.", journey.cost);
+}
+
+#[test]
+fn boarding_tie_breaks_reconstruct_consistent_journeys() {
+    let (network, start, start_time, end) = get_example_scenario();
+    let ends = [end, network.get_stop_idx_from_name("Flinders Street").unwrap(), network.get_stop_idx_from_name("Box Hill").unwrap()];
+
+    // Counting boardings makes exact ties common, so the tie-breaks that look up labels' boardings decide the journeys.
+    for tie_break in [TieBreak::KeepFirst, TieBreak::EarlierBoarding, TieBreak::LowerRouteIndex] {
+        let preferences = JourneyPreferences { tie_break, ..Default::default() };
+        let journeys = mc_raptor_query::<5>(&network, StopIdx(start), start_time, &ends.map(StopIdx), &CountBoardings, &preferences);
+        assert!(journeys[0].is_ok());
+        for journey in journeys.iter().flatten() {
+            assert_eq!(journey.cost, journey.legs_vehicle().count() as PathfindingCost);
+            assert_eq!(journey.legs_vehicle().next().unwrap().boarded_stop, start);
+            for (leg, next_leg) in journey.legs_vehicle().zip(journey.legs_vehicle().skip(1)) {
+                assert!(leg.boarded_time <= leg.arrival_time && leg.arrival_time <= next_leg.boarded_time);
+                assert_eq!(leg.arrival_stop, next_leg.boarded_stop);
+            }
+        }
+    }
+}
+
+#[test]
+fn epsilon_dominance_still_finds_journeys() {
+    // Four lines reach Junction within 30 seconds of each other, the later ones a cent cheaper, and are scanned cheapest first.
+    // Every label kept at Junction is then boarded onto the onward line.
+    let network = SyntheticNetwork::new()
+        .line("A", &[("Alpha", "08:00", "08:00"), ("Junction", "08:20:30", "08:20:30")])
+        .line("B", &[("Alpha", "08:00", "08:00"), ("Junction", "08:20:20", "08:20:20")])
+        .line("C", &[("Alpha", "08:00", "08:00"), ("Junction", "08:20:10", "08:20:10")])
+        .line("D", &[("Alpha", "08:00", "08:00"), ("Junction", "08:20:00", "08:20:00")])
+        .line("Onward", &[("Junction", "08:25", "08:25"), ("Omega", "08:35", "08:35")])
+        .build();
+    let fares = LineFares(&[("A", 0.97), ("B", 0.98), ("C", 0.99), ("D", 1.), ("Onward", 1.)]);
+    let (start, end) = (StopIdx(network.get_stop_idx("Alpha")), StopIdx(network.get_stop_idx("Junction")));
+    let query = |epsilon| {
+        let preferences = JourneyPreferences { epsilon, ..Default::default() };
+        let (mut journeys, stats) = mc_raptor_query_with_stats::<5>(&network, start, 8 * 3600, &[end], &fares, &preferences);
+        (journeys.remove(0).unwrap(), stats)
+    };
+
+    let (exact, exact_stats) = query(Epsilon::default());
+    let (zero, zero_stats) = query(Epsilon { time: 0, cost: 0. });
+    assert_same_journey(&exact, &zero);
+    assert_eq!(zero_stats.labels_added, exact_stats.labels_added);
+    assert_eq!(exact.arrival_time(), Some(8 * 3600 + 1200));
+
+    // Each of the earlier arrivals is within a minute of the cheaper one scanned before it, so only that one is kept and
+    // continued from, and the fastest journey found is up to a minute late.
+    let (pruned, pruned_stats) = query(Epsilon { time: 60, cost: 0. });
+    assert!(pruned_stats.labels_added < exact_stats.labels_added, "{pruned_stats:?}\n{exact_stats:?}");
+    assert_eq!(pruned.arrival_time(), Some(8 * 3600 + 1230));
+    assert!(pruned.arrival_time().unwrap() <= exact.arrival_time().unwrap() + 60);
+}
+
+// A fare for boarding each line, by name.
+struct LineFares(&'static [(&'static str, PathfindingCost)]);
+
+impl CostProvider for LineFares {
+    fn cost(&self, _network: &Network, _trip: GlobalTripIndex, _stop_times_index: usize) -> PathfindingCost {
+        0.
+    }
+
+    fn boarding_cost(&self, network: &Network, trip: GlobalTripIndex, _stop_times_index: usize) -> PathfindingCost {
+        let line = &*network.routes[trip.route().idx()].line;
+        self.0.iter().find(|&&(name, _)| name == line).map(|&(_, fare)| fare).unwrap()
+    }
+}
+
+#[test]
+fn drop_worst_cost_keeps_cheaper_journeys() {
+    // Three direct lines from Alpha to Omega, where the later a line arrives the cheaper it is.
+    let network = SyntheticNetwork::new()
+        .line("Fast", &[("Alpha", "08:00", "08:00"), ("Omega", "08:20", "08:20")])
+        .line("Medium", &[("Alpha", "08:05", "08:05"), ("Omega", "08:40", "08:40")])
+        .line("Slow", &[("Alpha", "08:10", "08:10"), ("Omega", "09:00", "09:00")])
+        .build();
+    let fares = LineFares(&[("Fast", 3.), ("Medium", 2.), ("Slow", 1.)]);
+    let (start, end) = (StopIdx(network.get_stop_idx("Alpha")), StopIdx(network.get_stop_idx("Omega")));
+    let query = |overflow| {
+        let preferences = JourneyPreferences { overflow, ..JourneyPreferences::least_cost() };
+        mc_raptor_query::<2>(&network, start, 8 * 3600, &[end], &fares, &preferences).remove(0).unwrap()
+    };
+
+    // A bag of two can't hold all three journeys. The default policy evicts the latest arrival and so loses the cheapest journey,
+    // while evicting the highest cost keeps it.
+    let late_evicted = query(OverflowPolicy::default());
+    assert_eq!((late_evicted.legs_vehicle().count(), late_evicted.arrival_time(), late_evicted.cost), (1, Some(8 * 3600 + 2400), 2.));
+    let expensive_evicted = query(OverflowPolicy::DropWorstCost);
+    assert_eq!((expensive_evicted.legs_vehicle().count(), expensive_evicted.arrival_time(), expensive_evicted.cost), (1, Some(9 * 3600), 1.));
+}
+
+#[test]
+fn exact_query_is_at_least_as_good_as_bounded_query() {
+    let (network, start, start_time, end) = get_example_scenario();
+    let fare = FlatFare(5.);
+    let utility = |journey: &Journey| (journey.arrival_time().unwrap() - start_time) as PathfindingCost + journey.cost * 600.;
+    let preferences = || JourneyPreferences {
+        utility_function: Box::new(|label, start_time| (label.arrival_time - start_time) as PathfindingCost + label.cost * 600.),
+        ..Default::default()
+    };
+
+    // The exact Pareto set contains or dominates every journey a bounded bag keeps, so its best journey is never worse.
+    let exact = mc_raptor_query_exact(&network, StopIdx(start), start_time, &[StopIdx(end)], &fare, &preferences()).remove(0).unwrap();
+    exact.validate().unwrap();
+    assert!(exact.arrival_time() >= raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap().arrival_time());
+    for bounded in [
+        mc_raptor_query::<2>(&network, StopIdx(start), start_time, &[StopIdx(end)], &fare, &preferences()).remove(0).unwrap(),
+        mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &fare, &preferences()).remove(0).unwrap(),
+    ] {
+        assert!(utility(&exact) <= utility(&bounded), "Exact:\n{exact:?}\nBounded:\n{bounded:?}");
+        assert!(!(bounded.arrival_time() < exact.arrival_time() && bounded.cost < exact.cost));
+    }
+}
+
+#[test]
+fn exact_query_keeps_every_pareto_optimal_journey() {
+    // Eight direct lines from Alpha to Omega, each arriving five minutes later and a dollar cheaper than the one before, so all
+    // eight arrivals at Omega are Pareto-optimal.
+    let arrivals = ["08:10", "08:15", "08:20", "08:25", "08:30", "08:35", "08:40", "08:45"];
+    let network = arrivals.iter().enumerate().fold(SyntheticNetwork::new(), |network, (line, &arrival)| {
+        network.line(&format!("Line {line}"), &[("Alpha", "08:00", "08:00"), ("Omega", arrival, arrival)])
+    }).build();
+    let arrival_times = arrivals.map(|arrival| utils::parse_time(arrival).unwrap());
+    let fares = |_: &Network, trip: GlobalTripIndex, _: usize| (8 - trip.route().idx()) as PathfindingCost;
+    let (start, end) = (StopIdx(network.get_stop_idx("Alpha")), StopIdx(network.get_stop_idx("Omega")));
+    // Prefers the journey arriving closest to the given time, so each of the Pareto-optimal journeys is preferred in turn.
+    let arriving_at = |arrival_time: Timestamp| JourneyPreferences {
+        utility_function: Box::new(move |label, _| label.arrival_time.abs_diff(arrival_time) as PathfindingCost),
+        ..Default::default()
+    };
+
+    // A DynBag keeps every one of them, while a Bag<4> keeps only the four earliest.
+    for (line, &arrival_time) in arrival_times.iter().enumerate() {
+        let exact = mc_raptor_query_exact(&network, start, 8 * 3600, &[end], &fares, &arriving_at(arrival_time)).remove(0).unwrap();
+        assert_eq!((exact.arrival_time(), exact.cost), (Some(arrival_time), (8 - line) as PathfindingCost));
+        let bounded = mc_raptor_query::<4>(&network, start, 8 * 3600, &[end], &fares, &arriving_at(arrival_time)).remove(0).unwrap();
+        assert_eq!(bounded.arrival_time(), Some(arrival_time.min(arrival_times[3])));
+    }
+}
+
+#[test]
+fn stats_are_self_consistent() {
+    let (network, start, start_time, end) = get_example_scenario();
+    fastrand::seed(7);
+    let costs: Vec<_> = repeat_with(|| fastrand::f32() as PathfindingCost).take(network.stop_times.len()).collect();
+    let preferences = JourneyPreferences::default();
+
+    let (journeys, stats) = mc_raptor_query_with_stats::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &costs, &preferences);
+    let plain = mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &costs, &preferences);
+    assert_same_journey(journeys[0].as_ref().unwrap(), plain[0].as_ref().unwrap());
+
+    assert!(stats.labels_created > 0);
+    assert!(stats.labels_added > 0);
+    assert!(stats.labels_rejected > 0);
+    assert!(stats.bag_merges > 0);
+    assert_eq!(stats.labels_created, stats.labels_added + stats.labels_rejected + stats.labels_evicted);
+    assert!(stats.labels_displaced <= stats.labels_added);
+    assert!(stats.max_bag_occupancy.iter().all(|&occupancy| occupancy <= 5));
+    assert!(stats.max_bag_occupancy.iter().any(|&occupancy| occupancy > 0));
+}
+
+// From Alpha to Delta, the express needs a change at Beta and arrives at 08:30, while the all-stops train is direct but arrives
+// at 09:00.
+fn express_or_direct_network() -> Network {
+    SyntheticNetwork::new()
+        .transfer_time(60)
+        .stop("Alpha", -37.80, 145.0)
+        .stop("Beta", -37.81, 145.0)
+        .stop("Delta", -37.83, 145.0)
+        .line("Express", &[("Alpha", "08:05", "08:05"), ("Beta", "08:15", "08:15")])
+        .line("Shuttle", &[("Beta", "08:20", "08:20"), ("Delta", "08:30", "08:30")])
+        .line("All Stops", &[("Alpha", "08:10", "08:10"), ("Delta", "09:00", "09:00")])
+        .build()
+}
+
+#[test]
+fn preset_preferences_trade_time_for_cost() {
+    fn query<'a>(network: &'a Network, costs: &impl CostProvider, preferences: &JourneyPreferences) -> Journey<'a> {
+        let (start, end) = (network.get_stop_idx("Alpha"), network.get_stop_idx("Delta"));
+        mc_raptor_query::<5>(network, StopIdx(start), 8 * 3600, &[StopIdx(end)], costs, preferences).remove(0).unwrap()
+    }
+    let network = express_or_direct_network();
+    let fare = FlatFare(5.);
+
+    let fastest = query(&network, &fare, &JourneyPreferences::fastest());
+    let least_cost = query(&network, &fare, &JourneyPreferences::least_cost());
+    assert_eq!(fastest.arrival_time(), Some(8 * 3600 + 1800));
+    assert_eq!((fastest.legs_vehicle().count(), fastest.cost), (2, 10.));
+    assert_eq!(least_cost.arrival_time(), Some(9 * 3600));
+    assert_eq!((least_cost.legs_vehicle().count(), least_cost.cost), (1, 5.));
+    assert!(!fastest.same_path(&least_cost));
+
+    // The change is worth making when a fare is worth five minutes, but not when it's worth five hours.
+    assert!(query(&network, &fare, &JourneyPreferences::balanced(1., 60.)).same_path(&fastest));
+    assert!(query(&network, &fare, &JourneyPreferences::balanced(1., 3600.)).same_path(&least_cost));
+
+    let least_transfers = query(&network, &CountBoardings, &JourneyPreferences::least_transfers());
+    assert_eq!(least_transfers.cost, least_transfers.legs_vehicle().count() as PathfindingCost);
+    assert!(least_transfers.same_path(&least_cost));
+    assert!(query(&network, &CountBoardings, &JourneyPreferences::fastest()).same_path(&fastest));
+}
+
+#[test]
+fn utility_can_avoid_the_last_boarded_route() {
+    let network = express_or_direct_network();
+    let (start, end) = (network.get_stop_idx("Alpha"), network.get_stop_idx("Delta"));
+    let shuttle = network.routes.iter().position(|route| &*route.line == "Shuttle").unwrap();
+
+    // Labels carry their boarded trip and stop, so a utility can rule out arriving on the shuttle.
+    let no_shuttle = JourneyPreferences {
+        utility_function: Box::new(move |label, _| match label.boarded_trip() {
+            Some(trip) if trip.route().idx() == shuttle => PathfindingCost::INFINITY,
+            _ => label.arrival_time as PathfindingCost,
+        }),
+        ..Default::default()
+    };
+    let journey = mc_raptor_query::<5>(&network, StopIdx(start), 8 * 3600, &[StopIdx(end)], &FlatFare(5.), &no_shuttle).remove(0).unwrap();
+    assert_eq!(journey.arrival_time(), Some(9 * 3600));
+    assert_eq!(journey.legs_vehicle().count(), 1);
+}
@@ -0,0 +1,670 @@
+use dev_utils::{build_example_network, get_example_date, get_example_scenario, get_example_transfer_time, load_example_gtfs};
+use std::collections::HashMap;
+use raptor::journey::{Connection, JourneyError};
+use raptor::network::{CancelError, CoordType, GeoJsonOptions, GlobalTripIndex, NetworkPoint, RouteHeights, StopIndex, StopTime, Timestamp, TransferTimeEntry, UnknownStop};
+use std::mem::size_of;
+use raptor::{csa_query, raptor_query, utils, Leg, Network, StopIdx, TripIdx};
+
+#[test]
+fn unknown_stops_are_reported() {
+    let (mut network, start, _, _) = get_example_scenario();
+    let start_id = network.stop_id(start as usize).to_string();
+
+    // Known stops.
+    assert_eq!(network.try_get_stop_idx(&start_id), Some(start));
+    assert!(network.try_get_stop(start as usize).is_some());
+    assert_eq!(network.stop_ids.get(start as usize), Some(start_id.as_str()));
+    assert_eq!(network.try_set_transfer_time_for_stop(&start_id, 5 * 60), Ok(()));
+    assert_eq!(network.transfer_times[start as usize], 5 * 60);
+
+    // Unknown stops.
+    assert_eq!(network.try_get_stop_idx("not a stop"), None);
+    assert!(network.try_get_stop(network.stops.len()).is_none());
+    assert_eq!(network.try_set_transfer_time_for_stop("not a stop", 5 * 60), Err(UnknownStop("not a stop".to_string())));
+    assert_eq!(network.get_stop_idx_from_name("Not A Stop"), None);
+}
+
+#[test]
+#[should_panic]
+fn get_stop_idx_panics_on_unknown_stop() {
+    let (network, ..) = get_example_scenario();
+    network.get_stop_idx("not a stop");
+}
+
+#[test]
+#[should_panic]
+fn set_transfer_time_panics_on_unknown_stop() {
+    let (mut network, ..) = get_example_scenario();
+    network.set_transfer_time_for_stop("not a stop", 5 * 60);
+}
+
+#[test]
+#[should_panic]
+fn get_stop_panics_out_of_bounds() {
+    let (network, ..) = get_example_scenario();
+    network.get_stop(network.stops.len());
+}
+
+fn search_names(network: &Network, query: &str) -> Vec<String> {
+    network.search_stops(query, 5).iter().map(|&(stop, _)| network.stop_name(stop as usize).to_string()).collect()
+}
+
+#[test]
+fn search_stops_tolerates_typos_and_prefixes() {
+    let (network, ..) = get_example_scenario();
+
+    // Exact matches are the top result with a perfect score.
+    let exact = network.search_stops("Cheltenham", 5);
+    assert_eq!(Some(exact[0].0), network.get_stop_idx_from_name("Cheltenham"));
+    assert_eq!(exact[0].1, 1.);
+
+    // Abbreviations, prefixes and typos.
+    for (query, expected) in [("flinders st", "Flinders Street"), ("Flinder Street", "Flinders Street"), ("cheltenam", "Cheltenham"), ("greensbor", "Greensborough")] {
+        let names = search_names(&network, query);
+        assert!(names.first().is_some_and(|name| name.starts_with(expected)), "{query:?} matched {names:?}.");
+    }
+
+    // Scores are in descending order.
+    let matches = network.search_stops("box", 5);
+    assert!(matches.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+    assert!(network.search_stops("zzzzzzzz", 5).is_empty());
+    assert!(network.search_stops("", 5).is_empty());
+}
+
+#[test]
+fn search_stops_returns_ambiguous_candidates() {
+    let (network, ..) = get_example_scenario();
+
+    // Several stations start with "North" (e.g. North Melbourne, North Richmond, North Williamstown).
+    let names = search_names(&network, "north");
+    assert!(names.len() > 1, "Expected several matches, got {names:?}.");
+    assert!(names.iter().all(|name| name.to_lowercase().contains("north")));
+    assert_eq!(network.search_stops("north", 2).len(), 2);
+}
+
+#[test]
+fn edit_distance() {
+    assert_eq!(utils::edit_distance("", ""), 0);
+    assert_eq!(utils::edit_distance("kitten", "sitting"), 3);
+    assert_eq!(utils::edit_distance("cheltenam", "cheltenham"), 1);
+    assert_eq!(utils::edit_distance("abc", ""), 3);
+}
+
+#[test]
+fn nearest_stops_match_linear_scan() {
+    let (network, ..) = get_example_scenario();
+    fastrand::seed(11);
+    for _ in 0..100 {
+        // Random points around greater Melbourne, including some outside the network's extent.
+        let point = NetworkPoint {
+            latitude: -38.5 + fastrand::f32() * 1.2,
+            longitude: 144.4 + fastrand::f32() * 1.4,
+        };
+        let k = fastrand::usize(1..10);
+
+        let mut expected = network.stop_points.iter().enumerate()
+            .filter(|(_, stop_point)| stop_point.is_valid())
+            .map(|(stop, &stop_point)| (stop as StopIndex, point.distance(stop_point)))
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        expected.truncate(k);
+
+        assert_eq!(network.nearest_stops(point, k), expected);
+    }
+}
+
+#[test]
+fn nearest_stops_excludes_invalid_points() {
+    let (mut network, start, ..) = get_example_scenario();
+    let start_point = network.stop_points[start as usize];
+
+    // Stops missing coordinates are stored at (0, 0), and are never returned. The index is built on the first query.
+    network.stop_points[start as usize] = NetworkPoint { latitude: 0., longitude: 0. };
+    let nearest = network.nearest_stops(start_point, 3);
+    assert_eq!(nearest.len(), 3);
+    assert!(nearest.iter().all(|&(stop, distance)| stop != start && distance > 0.));
+
+    // Invalid query points have no nearest stops.
+    assert!(network.nearest_stops(NetworkPoint { latitude: 0., longitude: 0. }, 1).is_empty());
+    assert!(network.nearest_stops(NetworkPoint { latitude: CoordType::NAN, longitude: 145. }, 1).is_empty());
+    assert!(network.nearest_stops(start_point, 0).is_empty());
+}
+
+#[test]
+fn stops_within_match_linear_scan() {
+    let (network, ..) = get_example_scenario();
+    fastrand::seed(12);
+    for _ in 0..100 {
+        let point = NetworkPoint {
+            latitude: -38.5 + fastrand::f32() * 1.2,
+            longitude: 144.4 + fastrand::f32() * 1.4,
+        };
+        let radius_km = 0.5 + fastrand::f32() * 2.5;
+
+        let mut expected = network.stop_points.iter().enumerate()
+            .filter(|(_, stop_point)| stop_point.is_valid())
+            .map(|(stop, &stop_point)| (stop as StopIndex, point.distance(stop_point)))
+            .filter(|&(_, distance)| distance <= radius_km)
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+
+        let within = network.stops_within(point, radius_km);
+        assert_eq!(within, expected.iter().map(|&(stop, _)| stop).collect::<Vec<_>>());
+        // Sorted by distance.
+        assert!(within.windows(2).all(|pair| point.distance(network.stop_points[pair[0] as usize]) <= point.distance(network.stop_points[pair[1] as usize])));
+    }
+}
+
+#[test]
+fn stops_within_edge_cases() {
+    let (network, start, ..) = get_example_scenario();
+    let start_point = network.stop_points[start as usize];
+
+    // A radius larger than the network returns every valid stop.
+    let num_valid = network.stop_points.iter().filter(|stop_point| stop_point.is_valid()).count();
+    assert_eq!(network.stops_within(start_point, 10_000.).len(), num_valid);
+
+    // Points far from the network have no stops nearby.
+    assert!(network.stops_within(NetworkPoint { latitude: 51.5, longitude: -0.1 }, 1.5).is_empty());
+    assert!(network.stops_within(start_point, -1.).is_empty());
+    assert_eq!(network.stops_within(start_point, 0.), [start]);
+}
+
+#[test]
+fn departures_are_ordered_and_boardable() {
+    let (network, start, start_time, _) = get_example_scenario();
+    let departures = network.departures(start, start_time, 10);
+    assert!(!departures.is_empty());
+    assert!(departures.len() <= 10);
+    assert!(departures.windows(2).all(|pair| pair[0].departure_time <= pair[1].departure_time));
+
+    for departure in departures.iter() {
+        assert!(departure.departure_time >= start_time);
+        let route = &network.routes[departure.trip.route().idx()];
+        let stops = route.get_stops(&network.route_stops);
+        let stop_order = departure.stop_order as usize;
+        // The trip stops here, and continues on to the destination.
+        assert_eq!(stops[stop_order], start);
+        assert!(stop_order + 1 < stops.len());
+        assert_eq!(*stops.last().unwrap(), departure.destination);
+        let stop_times_index = route.get_stop_times_index(departure.trip.trip().idx(), stop_order);
+        assert_eq!(network.stop_times.departure_times[stop_times_index], departure.departure_time);
+        assert_eq!(departure.line, route.line);
+    }
+}
+
+#[test]
+fn route_timetable_matches_stop_times_layout() {
+    let (network, ..) = get_example_scenario();
+    for route_idx in 0..network.routes.len() {
+        let route = &network.routes[route_idx];
+        let timetable = network.route_timetable(route_idx);
+        assert_eq!(timetable.route_idx as usize, route_idx);
+        assert_eq!(timetable.trips.len(), route.num_trips as usize);
+
+        for (trip_order, trip) in timetable.trips.iter().enumerate() {
+            assert_eq!(trip.trip.trip().idx(), trip_order);
+            assert_eq!(trip.trip_id, network.get_trip_id(trip.trip));
+            assert_eq!(trip.stop_times.len(), route.num_stops as usize);
+            for (stop_order, &(stop, arrival_time, departure_time)) in trip.stop_times.iter().enumerate() {
+                // Stop times are laid out as [trip_order * num_stops + stop_order] from the route's first stop time.
+                let stop_time = network.stop_times.get(route.stop_times_idx + trip_order * route.num_stops as usize + stop_order);
+                assert_eq!(stop, network.route_stops[route.route_stops_idx + stop_order]);
+                assert_eq!(arrival_time, stop_time.arrival_time);
+                assert_eq!(departure_time, stop_time.departure_time);
+            }
+        }
+
+        // Trips are sorted by first arrival.
+        assert!(timetable.trips.windows(2).all(|pair| pair[0].stop_times[0].1 <= pair[1].stop_times[0].1));
+    }
+}
+
+#[test]
+fn trips_through_stop_are_in_range() {
+    let (network, start, start_time, _) = get_example_scenario();
+    let time_range = start_time..start_time + 60 * 60;
+    let visits = network.trips_through_stop(start, time_range.clone());
+    assert!(!visits.is_empty());
+    assert!(visits.windows(2).all(|pair| pair[0].arrival_time <= pair[1].arrival_time));
+    for visit in visits.iter() {
+        assert!(time_range.contains(&visit.arrival_time));
+        let timetable = network.route_timetable(visit.trip.route().idx());
+        let (stop, arrival_time, departure_time) = timetable.trips[visit.trip.trip().idx()].stop_times[visit.stop_order as usize];
+        assert_eq!((stop, arrival_time, departure_time), (start, visit.arrival_time, visit.departure_time));
+    }
+}
+
+#[test]
+fn stats_are_consistent() {
+    let (mut network, ..) = get_example_scenario();
+    let stats = network.stats();
+    assert_eq!(stats.num_stops, network.stops.len());
+    assert_eq!(stats.num_routes, network.routes.len());
+    assert_eq!(stats.num_trips, network.routes.iter().map(|route| route.num_trips as usize).sum::<usize>());
+    assert_eq!(stats.num_stop_times, network.routes.iter().map(|route| route.num_trips as usize * route.num_stops as usize).sum::<usize>());
+    assert_eq!(stats.num_connections, 0);
+    assert_eq!(stats.num_stops_with_no_service, network.stops.iter().filter(|stop| stop.get_routes(&network.stop_routes).is_empty()).count());
+    assert_eq!(stats.date, network.date);
+    let (first_departure, last_arrival) = stats.service_span.unwrap();
+    assert_eq!(first_departure, network.stop_times.departure_times.iter().copied().min().unwrap());
+    assert!(last_arrival > first_departure);
+    assert!(stats.memory_estimate_bytes >= stats.num_stop_times * size_of::<StopTime>());
+
+    network.build_connections();
+    let with_connections = network.stats();
+    assert_eq!(with_connections.num_connections, network.routes.iter().map(|route| route.num_trips as usize * (route.num_stops as usize - 1)).sum::<usize>());
+    assert!(with_connections.memory_estimate_bytes > stats.memory_estimate_bytes);
+    assert!(with_connections.to_string().contains(&format!("{} stops", stats.num_stops)));
+    assert!(with_connections.to_string().contains(&format!("running from {}", utils::get_time_str(first_departure))));
+}
+
+#[test]
+fn connection_iterators_match_build_connections() {
+    let (mut network, ..) = get_example_scenario();
+    let unsorted = network.iter_connections().collect::<Vec<_>>();
+    let sorted = network.iter_connections_sorted().collect::<Vec<_>>();
+    network.build_connections();
+
+    // The parallel build matches a serial build, including the order of connections with equal departure times.
+    let mut serial = unsorted.clone();
+    serial.sort_by_key(Connection::sort_key);
+    assert_eq!(network.connections, serial);
+    assert_eq!(sorted, serial);
+    assert!(serial.windows(2).all(|pair| pair[0].departure_time <= pair[1].departure_time));
+
+    // Building again gives the same order.
+    network.build_connections();
+    assert_eq!(network.connections, serial);
+}
+
+#[test]
+fn connections_csv_matches_connections() {
+    let (mut network, ..) = get_example_scenario();
+    let mut csv = Vec::new();
+    // Connections aren't built yet.
+    network.write_connections_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("trip_id,line,departure_stop_id,departure_stop_name,departure_time,arrival_stop_id,arrival_stop_name,arrival_time"));
+
+    network.build_connections();
+    let rows = lines.collect::<Vec<_>>();
+    assert_eq!(rows.len(), network.stats().num_connections);
+    for (row, connection) in rows.iter().zip(network.iter_connections()).take(100) {
+        let expected_start = format!("\"{}\",\"{}\",\"{}\",", network.get_trip_id(connection.trip), network.routes[connection.trip.route().idx()].line, network.stop_id(connection.departure_idx as usize));
+        assert!(row.starts_with(&expected_start), "{row}");
+        assert!(row.ends_with(&format!(",{}", utils::get_time_str(connection.arrival_time))), "{row}");
+        assert!(row.contains(&format!(",{},", utils::get_time_str(connection.departure_time))), "{row}");
+    }
+}
+
+#[test]
+fn geojson_of_filtered_routes_parses_back() {
+    let (network, ..) = get_example_scenario();
+    let line = network.routes[0].line.clone();
+    let options = GeoJsonOptions { use_shapes: false, route_filter: Box::new(move |_, route| route.line == line), ..Default::default() };
+    let mut geojson = Vec::new();
+    network.write_geojson(&options, &mut geojson).unwrap();
+    let geojson: serde_json::Value = serde_json::from_slice(&geojson).unwrap();
+    assert_eq!(geojson["type"], "FeatureCollection");
+
+    let features = geojson["features"].as_array().unwrap();
+    let routes = features.iter().filter(|feature| feature["properties"]["kind"] == "route").collect::<Vec<_>>();
+    let expected_routes = network.routes.iter().enumerate().filter(|(_, route)| route.line == network.routes[0].line).collect::<Vec<_>>();
+    assert_eq!(routes.len(), expected_routes.len());
+    assert!(expected_routes.len() < network.routes.len());
+    for (feature, (route_idx, route)) in routes.iter().zip(expected_routes) {
+        assert_eq!(feature["properties"]["route_idx"], route_idx);
+        assert_eq!(feature["properties"]["colour"], utils::get_colour_hex(route.colour));
+        assert_eq!(feature["properties"]["num_trips"], route.num_trips);
+        // Straight segments have a point per stop.
+        assert_eq!(feature["geometry"]["coordinates"].as_array().unwrap().len(), route.num_stops as usize);
+    }
+
+    // Only stops on the line are included.
+    let stops = features.iter().filter(|feature| feature["properties"]["kind"] == "stop").collect::<Vec<_>>();
+    assert!(!stops.is_empty());
+    for feature in stops {
+        let stop_idx = network.get_stop_idx(feature["properties"]["id"].as_str().unwrap());
+        assert!(network.stop_route_info(stop_idx).iter().any(|info| info.line == network.routes[0].line));
+    }
+}
+
+#[test]
+fn memory_report_and_shrink_to_fit() {
+    let (mut network, start, start_time, end) = get_example_scenario();
+    network.build_connections();
+    let report = network.memory_report();
+    assert!(report.total_used_bytes() > 0);
+    assert!(report.total_capacity_bytes() >= report.total_used_bytes());
+    let stop_times = report.components.iter().find(|component| component.name == "stop_times").unwrap();
+    assert_eq!(stop_times.len, network.stop_times.len());
+    let stop_names = report.components.iter().find(|component| component.name == "stop_names").unwrap();
+    assert_eq!(stop_names.len, network.stops.len());
+    let trip_ids = report.components.iter().find(|component| component.name == "trip_ids").unwrap();
+    assert_eq!(trip_ids.len, network.num_trips as usize);
+    assert!(report.to_string().lines().count() > report.components.len());
+
+    let raptor_before = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap().legs;
+    let csa_before = csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap().legs;
+    network.shrink_to_fit();
+    let shrunk = network.memory_report();
+    assert_eq!(shrunk.total_used_bytes(), report.total_used_bytes());
+    assert!(shrunk.total_capacity_bytes() <= report.total_capacity_bytes());
+
+    let raptor_after = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let csa_after = csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    assert_eq!(raptor_before, raptor_after.legs);
+    assert_eq!(csa_before, csa_after.legs);
+}
+
+// The gaps between arriving at and departing from each transfer stop in the journey.
+fn transfer_gaps(journey: &raptor::Journey) -> Vec<(StopIndex, Timestamp)> {
+    journey.legs.windows(3).filter_map(|legs| match legs {
+        [Leg::Vehicle(previous), Leg::Transfer { at_stop, .. }, Leg::Vehicle(next)] => Some((*at_stop, next.boarded_time - previous.arrival_time)),
+        _ => None,
+    }).collect()
+}
+
+#[test]
+fn csa_charges_transfer_time_where_the_next_trip_is_boarded() {
+    let (mut network, start, start_time, end) = get_example_scenario();
+    network.build_connections();
+    let journey = csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    // The stop after the first transfer, which the journey passes through rather than transfers at.
+    let next_leg = journey.legs_vehicle().nth(1).expect("The example journey should have a transfer.");
+    let route = &network.routes[next_leg.trip.route().idx()];
+    let passed_stop = route.get_stops(&network.route_stops)[next_leg.boarded_stop_order as usize + 1];
+    assert!(transfer_gaps(&journey).iter().all(|&(stop, _)| stop != passed_stop));
+
+    // A long transfer time there doesn't stop the trip being boarded at the stop before.
+    let passed_stop_id = network.stop_id(passed_stop as usize).to_string();
+    let arrival_time = journey.arrival_time();
+    network.set_transfer_time_for_stop(&passed_stop_id, 3 * 3600);
+    assert_eq!(csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap().arrival_time(), arrival_time);
+    assert_eq!(raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap().arrival_time(), arrival_time);
+}
+
+#[test]
+fn pairwise_transfer_times_are_honoured() {
+    let (mut network, start, start_time, end) = get_example_scenario();
+    network.build_connections();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let (at_stop, gap) = transfer_gaps(&journey)[0];
+    let stop_id = network.stop_id(at_stop as usize).to_string();
+    let arrival_time = journey.arrival_time();
+
+    // Make the transfer just too slow to catch the same trip.
+    let transfer_time = gap + 1;
+    assert_eq!(network.try_set_transfer_times(&[(&stop_id, &stop_id, transfer_time), ("not a stop", &stop_id, 0)]), Err(UnknownStop("not a stop".to_string())));
+    assert!(network.pairwise_transfer_times.is_empty(), "Nothing is set if any stop is unknown.");
+    network.set_transfer_times(&[(&stop_id, &stop_id, transfer_time)]);
+    assert_eq!(network.get_effective_transfer_time(at_stop, at_stop), transfer_time);
+    assert_eq!(network.get_effective_transfer_time(start, start), network.default_transfer_time);
+    assert_eq!(network.non_default_transfer_times(), [TransferTimeEntry { from: at_stop, to: at_stop, transfer_time, pairwise: true }]);
+
+    let raptor_journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let csa_journey = csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    assert_eq!(raptor_journey.arrival_time(), csa_journey.arrival_time(), "\n{raptor_journey:?}\n{csa_journey:?}");
+    assert!(raptor_journey.arrival_time() >= arrival_time);
+    for journey in [&raptor_journey, &csa_journey] {
+        for (stop, gap) in transfer_gaps(journey) {
+            assert!(gap >= network.get_effective_transfer_time(stop, stop), "{journey:?}");
+        }
+        for leg in journey.legs.iter() {
+            if let Leg::Transfer { at_stop: stop, transfer_time: leg_transfer_time, .. } = *leg {
+                assert!(stop != at_stop || leg_transfer_time == transfer_time);
+            }
+        }
+    }
+
+    // A per-stop transfer time of the same value gives the same journey.
+    let (mut per_stop_network, ..) = get_example_scenario();
+    per_stop_network.set_transfer_time_for_stop(&stop_id, transfer_time);
+    assert_eq!(raptor_query(&per_stop_network, StopIdx(start), start_time, StopIdx(end)).unwrap().legs, raptor_journey.legs);
+    assert_eq!(per_stop_network.non_default_transfer_times(), [TransferTimeEntry { from: at_stop, to: at_stop, transfer_time, pairwise: false }]);
+}
+
+#[test]
+fn prune_keeps_journeys_and_round_trips_indices() {
+    let (mut network, start, start_time, end) = get_example_scenario();
+    network.build_connections();
+    let num_stops = network.stops.len();
+    let stop_ids = network.stop_ids.iter().map(str::to_string).collect::<Vec<_>>();
+    let served = network.stops.iter().map(|stop| stop.num_routes > 0).collect::<Vec<_>>();
+    let events = |journey: &raptor::Journey| journey.events().map(|event| (event.stop, event.arrival, event.departure, event.trip)).collect::<Vec<_>>();
+    let raptor_before = events(&raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap());
+    let csa_before = events(&csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap());
+
+    let mapping = network.prune();
+    assert_eq!(network.stops.len() + mapping.num_removed(), num_stops);
+    assert!(network.stops.iter().all(|stop| stop.num_routes > 0));
+    for old_stop in 0..num_stops as StopIndex {
+        match mapping.to_new(old_stop) {
+            Some(new_stop) => {
+                assert_eq!(mapping.to_old(new_stop), old_stop);
+                assert_eq!(network.stop_id(new_stop as usize), stop_ids[old_stop as usize]);
+                assert_eq!(network.get_stop_idx(&stop_ids[old_stop as usize]), new_stop);
+            }
+            None => {
+                assert!(!served[old_stop as usize]);
+                assert_eq!(network.try_get_stop_idx(&stop_ids[old_stop as usize]), None);
+            }
+        }
+    }
+
+    // Queries give the same journeys, with remapped stops.
+    let (start, end) = (mapping.to_new(start).unwrap(), mapping.to_new(end).unwrap());
+    let remap = |events: Vec<_>| events.into_iter().map(|(stop, arrival, departure, trip)| (mapping.to_new(stop).unwrap(), arrival, departure, trip)).collect::<Vec<_>>();
+    assert_eq!(events(&raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap()), remap(raptor_before));
+    assert_eq!(events(&csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap()), remap(csa_before));
+}
+
+#[test]
+fn routes_by_line() {
+    let (network, ..) = get_example_scenario();
+
+    // Each direction and stopping pattern of a line is a separate route.
+    let frankston = network.get_routes_by_line("Frankston");
+    assert!(frankston.len() > 1, "{frankston:?}");
+    assert!(frankston.iter().all(|&route_idx| &*network.routes[route_idx as usize].line == "Frankston"));
+    assert_eq!(network.get_routes_by_line(" frank STON "), frankston);
+    assert!(network.get_routes_by_line("Not A Line").is_empty());
+
+    let partial = network.get_routes_by_partial_line("frank");
+    assert!(frankston.iter().all(|route_idx| partial.contains(route_idx)));
+    assert!(network.get_routes_by_partial_line("zzzz").is_empty());
+
+    // Lines are distinct and cover every route.
+    let lines = network.lines().collect::<Vec<_>>();
+    assert!(lines.contains(&"Frankston"));
+    assert!(lines.iter().enumerate().all(|(i, line)| !lines[..i].contains(line)));
+    assert!(network.routes.iter().all(|route| lines.contains(&&*route.line)));
+    assert_eq!(lines.iter().map(|line| network.get_routes_by_line(line).len()).sum::<usize>(), network.routes.len());
+}
+
+#[test]
+fn stop_route_info_covers_each_route_once() {
+    let (network, ..) = get_example_scenario();
+    for (stop_idx, stop) in network.stops.iter().enumerate() {
+        let stop_idx = stop_idx as StopIndex;
+        let info = network.stop_route_info(stop_idx);
+        let routes = stop.get_routes(&network.stop_routes);
+        assert_eq!(info.iter().map(|info| info.route_idx).collect::<Vec<_>>(), routes);
+        for info in info {
+            let route = &network.routes[info.route_idx as usize];
+            let stops = route.get_stops(&network.route_stops);
+            assert_eq!(stops[info.stop_order as usize], stop_idx);
+            assert_eq!(info.line, route.line);
+            assert_eq!(info.destination, *stops.last().unwrap());
+            assert_eq!(info.is_first, info.stop_order == 0);
+            assert_eq!(info.is_last, info.next_stop.is_none());
+            if let Some(next_stop) = info.next_stop {
+                assert_eq!(stops[info.stop_order as usize + 1], next_stop);
+            }
+        }
+    }
+}
+
+#[test]
+fn example_start_stop_has_all_day_service() {
+    let (network, start, ..) = get_example_scenario();
+    let (first, last) = network.service_span(start).unwrap();
+    assert!(first <= utils::parse_time("06:00:00").unwrap(), "First service at {}.", utils::get_time_str(first));
+    assert!(last >= utils::parse_time("23:00:00").unwrap(), "Last service at {}.", utils::get_time_str(last));
+    assert!(network.has_service_between(start, utils::parse_time("22:00:00").unwrap(), last + 1));
+}
+
+#[test]
+fn route_heights_are_deterministic_and_configurable() {
+    let gtfs = load_example_gtfs().unwrap();
+    let heights_by_colour = |network: &Network| {
+        (0..network.num_routes()).map(|route_idx| (network.routes[route_idx].colour, network.route_height(route_idx))).collect::<HashMap<_, _>>()
+    };
+
+    // Route order may differ between builds, but each colour gets the same height.
+    let first = build_example_network(&gtfs);
+    let second = build_example_network(&gtfs);
+    let automatic = heights_by_colour(&first);
+    assert_eq!(automatic, heights_by_colour(&second));
+    for (route_idx, route) in first.routes.iter().enumerate() {
+        assert_eq!(first.route_height(route_idx), automatic[&route.colour]);
+    }
+
+    // Provided colours are honoured exactly, and the rest use the automatic heights.
+    let colour = first.routes[0].colour;
+    let network = Network::new_with_route_heights(&gtfs, None, get_example_date(), get_example_transfer_time(), RouteHeights::ByColour(HashMap::from([(colour, 123.5)])));
+    for (route_idx, route) in network.routes.iter().enumerate() {
+        let expected = if route.colour == colour { 123.5 } else { automatic[&route.colour] };
+        assert_eq!(network.route_height(route_idx), expected);
+    }
+
+    let network = Network::new_with_route_heights(&gtfs, None, get_example_date(), get_example_transfer_time(), RouteHeights::ByLine(Box::new(|line| line.len() as CoordType)));
+    for (route_idx, route) in network.routes.iter().enumerate() {
+        assert_eq!(network.route_height(route_idx), route.line.len() as CoordType);
+    }
+}
+
+#[test]
+fn restrict_to_window() {
+    let (mut network, start, start_time, end) = get_example_scenario();
+    network.build_connections();
+    let from = utils::parse_time("07:00:00").unwrap();
+    let to = utils::parse_time("10:00:00").unwrap();
+    let peak = network.restrict_to_window(from, to, true);
+    assert!(peak.num_trips < network.num_trips);
+    assert_eq!(peak.num_trips as usize, peak.routes.iter().map(|route| route.num_trips as usize).sum::<usize>());
+    assert_eq!(peak.stop_times.len(), peak.routes.iter().map(|route| (route.num_trips * route.num_stops) as usize).sum::<usize>());
+    assert!(peak.routes.iter().all(|route| route.num_trips > 0 && route.trip_ids.len() == route.num_trips as usize));
+    assert_eq!(peak.connections.len(), peak.iter_connections().count());
+
+    // Trips are in the window, or still running at its start.
+    let without_in_progress = network.restrict_to_window(from, to, false);
+    assert!(without_in_progress.num_trips <= peak.num_trips);
+    for route in without_in_progress.routes.iter() {
+        for trip_order in 0..route.num_trips as usize {
+            assert!((from..to).contains(&route.get_trip(trip_order, &without_in_progress.stop_times).departure_times[0]));
+        }
+    }
+
+    // A query inside the window finds the same journey.
+    let events = |journey: &raptor::Journey| journey.events().map(|event| (event.stop, event.arrival, event.departure)).collect::<Vec<_>>();
+    assert_eq!(events(&raptor_query(&peak, StopIdx(start), start_time, StopIdx(end)).unwrap()), events(&raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap()));
+    assert_eq!(events(&csa_query(&peak, StopIdx(start), start_time, StopIdx(end)).unwrap()), events(&csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap()));
+
+    // There is no service late in the evening.
+    let late = utils::parse_time("22:00:00").unwrap();
+    assert!(raptor_query(&network, StopIdx(start), late, StopIdx(end)).is_ok());
+    assert!(matches!(raptor_query(&peak, StopIdx(start), late, StopIdx(end)), Err(JourneyError::NoJourneyFound { .. })));
+    assert!(matches!(csa_query(&peak, StopIdx(start), late, StopIdx(end)), Err(JourneyError::NoJourneyFound { .. })));
+}
+
+#[test]
+fn cancelled_trips_are_not_boarded() {
+    let (mut network, start, start_time, end) = get_example_scenario();
+    network.build_connections();
+    let num_connections = network.connections.len();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let first_leg = journey.legs_vehicle().next().unwrap().clone();
+    let (trip, arrival_time) = (first_leg.trip, journey.arrival_time().unwrap());
+
+    assert_eq!(network.cancel_trip(trip), Ok(()));
+    assert_eq!(network.cancel_trip(trip), Err(CancelError::AlreadyCancelled(trip)));
+    let unknown = GlobalTripIndex::new(trip.route(), TripIdx(network.routes[trip.route().idx()].num_trips));
+    assert_eq!(network.cancel_trip(unknown), Err(CancelError::UnknownTrip(unknown)));
+    let num_trip_connections = network.routes[trip.route().idx()].num_stops as usize - 1;
+    assert_eq!(network.connections.len(), num_connections - num_trip_connections);
+    assert!(network.connections.iter().all(|connection| connection.trip != trip));
+
+    // Both algorithms take a later service, and never board the cancelled trip.
+    let raptor_journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let csa_journey = csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    for journey in [&raptor_journey, &csa_journey] {
+        assert!(journey.legs_vehicle().all(|leg| leg.trip != trip), "{journey:?}");
+        assert!(journey.arrival_time().unwrap() >= arrival_time);
+        assert!(journey.legs_vehicle().next().unwrap().boarded_time > first_leg.boarded_time || journey.legs_vehicle().next().unwrap().trip.route_idx != trip.route_idx);
+    }
+    assert_eq!(raptor_journey.arrival_time(), csa_journey.arrival_time());
+    assert!(network.departures(first_leg.boarded_stop, first_leg.boarded_time, usize::MAX).iter().all(|departure| departure.trip != trip));
+
+    // Rebuilding connections keeps the trip cancelled.
+    network.build_connections();
+    assert_eq!(network.connections.len(), num_connections - num_trip_connections);
+}
+
+#[test]
+fn line_summaries() {
+    let (network, ..) = get_example_scenario();
+    let summaries = network.line_summaries();
+
+    // Each line appears exactly once, and together they cover every route and trip.
+    for line in network.routes.iter().map(|route| &route.line) {
+        assert_eq!(summaries.iter().filter(|summary| summary.line == *line).count(), 1);
+    }
+    assert_eq!(summaries.iter().map(|summary| summary.num_routes).sum::<usize>(), network.routes.len());
+    assert_eq!(summaries.iter().map(|summary| summary.num_trips).sum::<usize>(), network.num_trips as usize);
+    for summary in summaries.iter() {
+        assert!(summary.first_departure <= summary.last_departure);
+        assert!(summary.num_stops >= 2 && summary.num_stops <= network.stops.len());
+        assert_eq!(summary.route_length_km.is_some(), network.has_shapes && network.get_routes_by_line(&summary.line).iter().any(|&route_idx| network.routes[route_idx as usize].shape.len() > 1));
+        assert!(summary.to_string().starts_with(&*summary.line));
+    }
+
+    let mut csv = Vec::new();
+    raptor::network::LineSummary::write_csv(&summaries, &mut csv).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap().lines().count(), summaries.len() + 1);
+
+    // A summary built by hand with its times backwards still displays.
+    let backwards = raptor::network::LineSummary { first_departure: 9 * 3600, last_departure: 8 * 3600, ..summaries[0].clone() };
+    assert!(backwards.to_string().contains("(0 s of service)"));
+}
+
+#[test]
+fn route_transfer_times_use_the_route_that_reached_the_stop_in_the_previous_round() {
+    let trip = |trip_id: &str, times: &[Timestamp]| (trip_id.to_string(), times.iter().map(|&time| StopTime { arrival_time: time, departure_time: time }).collect());
+    let point = |latitude| NetworkPoint { latitude, longitude: 145.0 };
+    let route = |line: &str, stops: Vec<StopIndex>, times: &[Timestamp]| raptor::network::TimetableRoute { line: line.to_string(), colour: Default::default(), stops, trips: vec![trip(line, times)] };
+    // The red line reaches Beta in time for the blue line with its quick cross-platform change. Changing from the green line to
+    // the yellow line reaches Beta earlier, but the change from the yellow line is too slow for the blue line. Gamma comes
+    // before Beta, so the yellow line is scanned before the blue line in the second round.
+    let mut network = Network::from_timetable(&[
+        ("Alpha", "alpha", point(-37.80)),
+        ("Gamma", "gamma", point(-37.81)),
+        ("Beta", "beta", point(-37.82)),
+        ("Delta", "delta", point(-37.83)),
+    ], vec![
+        route("Red", vec![0, 2], &[8 * 3600, 8 * 3600 + 600]),
+        route("Blue", vec![2, 3], &[8 * 3600 + 720, 8 * 3600 + 1800]),
+        route("Green", vec![0, 1], &[8 * 3600, 8 * 3600 + 120]),
+        route("Yellow", vec![1, 2], &[8 * 3600 + 180, 8 * 3600 + 300]),
+    ], get_example_date(), 60);
+    network.set_transfer_time_for_stop("beta", 600);
+    assert_eq!(network.set_route_transfer_time("beta", "Red", "Blue", 60), 1);
+
+    // Yellow improves Beta in the second round, but the blue line is boarded from the red line's arrival in the first.
+    let journey = raptor_query(&network, StopIdx(network.get_stop_idx("alpha")), 8 * 3600, StopIdx(network.get_stop_idx("delta"))).unwrap();
+    assert_eq!(journey.arrival_time(), Some(8 * 3600 + 1800));
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "serde")]
+
+use chrono::NaiveDate;
+use dev_utils::{assert_snapshot, get_example_scenario};
+use raptor::network::{NetworkPoint, StopTime, TimetableRoute};
+use raptor::{raptor_query, utils, Network, StopIdx};
+
+#[test]
+fn example_journey_as_otp_itinerary() {
+    let (network, start, start_time, end) = get_example_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let itinerary = journey.to_otp_itinerary(network.date);
+    // Melbourne is 10 hours ahead of UTC in winter.
+    assert_eq!(itinerary, journey.to_otp_itinerary_with_utc_offset(network.date, 10 * 3600));
+    assert_snapshot("otp_itinerary", &serde_json::to_string_pretty(&itinerary).unwrap());
+
+    assert_eq!(itinerary.legs.len(), journey.legs_vehicle().count());
+    assert_eq!(itinerary.transfers, itinerary.legs.len() - 1);
+    assert_eq!(itinerary.duration, itinerary.transit_time + itinerary.waiting_time);
+    for (otp_leg, leg) in itinerary.legs.iter().zip(journey.legs_vehicle()) {
+        // The example feed has route types.
+        assert_ne!(otp_leg.mode, "TRANSIT");
+        assert_eq!(otp_leg.trip_id.as_deref(), Some(leg.trip_id(&network)));
+        assert_eq!(otp_leg.end_time - otp_leg.start_time, leg.duration() as i64 * 1000);
+
+        // The geometry runs from the boarding stop to the arrival stop.
+        let points = utils::decode_polyline(&otp_leg.leg_geometry.points).unwrap();
+        assert_eq!(points.len(), otp_leg.leg_geometry.length);
+        let (first, last) = (points.first().unwrap(), points.last().unwrap());
+        assert!(first.distance(network.stop_points[leg.boarded_stop as usize]) < 0.2);
+        assert!(last.distance(network.stop_points[leg.arrival_stop as usize]) < 0.2);
+    }
+
+    // Fields the router doesn't know are left out rather than made up.
+    let json = serde_json::to_value(&itinerary).unwrap();
+    assert!(json.get("fare").is_none());
+    assert_eq!(json["legs"][0]["transitLeg"], true);
+    assert!(json["legs"][0]["from"].get("arrival").is_none());
+}
+
+#[test]
+fn modes_and_times_follow_the_network() {
+    let point = NetworkPoint { latitude: -37.8, longitude: 145.0 };
+    let stop_times = [8 * 3600, 8 * 3600 + 600].map(|time| StopTime { arrival_time: time, departure_time: time }).to_vec();
+    let route = TimetableRoute { line: "Line".to_string(), colour: Default::default(), stops: vec![0, 1], trips: vec![("Trip".to_string(), stop_times)] };
+    let date = NaiveDate::from_ymd_opt(2024, 1, 15).unwrap();
+    let mut network = Network::from_timetable(&[("A", "a", point), ("B", "b", point)], vec![route], date, 0);
+
+    let mode = |network: &Network| raptor_query(network, StopIdx(0), 7 * 3600, StopIdx(1)).unwrap().to_otp_itinerary(date).legs[0].mode.clone();
+    assert_eq!(mode(&network), "TRANSIT");
+    for (route_type, expected) in [(0, "TRAM"), (2, "RAIL"), (4, "FERRY"), (700, "BUS"), (1700, "TRANSIT")] {
+        network.routes[0].route_type = Some(route_type);
+        assert_eq!(mode(&network), expected);
+    }
+
+    // Without a timezone, times are read as UTC. Melbourne is on daylight saving time, 11 hours ahead of UTC, in January.
+    let utc_start = date.and_hms_opt(8, 0, 0).unwrap().and_utc().timestamp_millis();
+    assert_eq!(raptor_query(&network, StopIdx(0), 7 * 3600, StopIdx(1)).unwrap().to_otp_itinerary(date).start_time, utc_start);
+    network.timezone = Some("Australia/Melbourne".to_string());
+    let journey = raptor_query(&network, StopIdx(0), 7 * 3600, StopIdx(1)).unwrap();
+    let itinerary = journey.to_otp_itinerary(date);
+    assert_eq!(itinerary.start_time, utc_start - 11 * 3600 * 1000);
+    assert_eq!(itinerary, journey.to_otp_itinerary_with_utc_offset(date, 11 * 3600));
+}
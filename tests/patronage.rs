@@ -0,0 +1,28 @@
+use dev_utils::{build_synthetic_network, get_example_scenario, load_example_costs};
+use raptor::{raptor_query, StopIdx};
+
+#[test]
+fn example_costs_align_with_stop_times() {
+    let (network, start, start_time, end) = get_example_scenario();
+    let example_costs = load_example_costs(&network).unwrap();
+    let costs = &example_costs.costs;
+    assert_eq!(costs.len(), network.stop_times.len());
+    assert!(costs.iter().any(|&cost| cost > 0.));
+
+    // The morning peak train from the example start should have people boarding it.
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+    let first_leg = journey.legs_vehicle().next().unwrap();
+    let route = &network.routes[first_leg.trip.route().idx()];
+    let trip_costs = &costs[route.get_trip_range(first_leg.trip.trip().idx())];
+    assert!(trip_costs.iter().any(|&cost| cost > 0.));
+}
+
+#[test]
+fn example_costs_count_unmatched_rows() {
+    // The synthetic network only has a handful of the stations in the patronage data, so rows from the others are skipped.
+    let network = build_synthetic_network();
+    let example_costs = load_example_costs(&network).unwrap();
+    assert_eq!(example_costs.costs.len(), network.stop_times.len());
+    assert!(example_costs.costs.iter().any(|&cost| cost > 0.));
+    assert!(example_costs.num_skipped > 0);
+}
@@ -0,0 +1,158 @@
+use chrono::NaiveDate;
+use gtfs_rt::trip_descriptor::ScheduleRelationship as TripRelationship;
+use gtfs_rt::trip_update::stop_time_update::ScheduleRelationship;
+use gtfs_rt::trip_update::{StopTimeEvent, StopTimeUpdate};
+use gtfs_rt::{FeedEntity, FeedMessage, TripDescriptor, TripUpdate};
+use raptor::network::{NetworkPoint, StopTime, Timestamp, TimetableRoute};
+use raptor::realtime::{RejectedUpdate, UpdateReport};
+use raptor::{csa_query, raptor_query, Network, StopIdx};
+
+// An express from A to C at 08:00 arriving 08:30, and a stopping train via B at 08:05 arriving 08:35.
+fn network() -> Network {
+    let trip = |trip_id: &str, times: &[Timestamp]| (trip_id.to_string(), times.iter().map(|&time| StopTime { arrival_time: 8 * 3600 + time, departure_time: 8 * 3600 + time }).collect());
+    let point = NetworkPoint { latitude: -37.8, longitude: 145. };
+    let mut network = Network::from_timetable(&[("A", "a", point), ("B", "b", point), ("C", "c", point)], vec![
+        TimetableRoute { line: "Express".to_string(), colour: Default::default(), stops: vec![0, 2], trips: vec![trip("express", &[0, 1800])] },
+        TimetableRoute { line: "Stopping".to_string(), colour: Default::default(), stops: vec![0, 1, 2], trips: vec![trip("stopping", &[300, 1200, 2100])] },
+    ], NaiveDate::default(), 60);
+    network.build_connections();
+    network
+}
+
+fn trip_update(trip_id: &str, stop_time_update: Vec<StopTimeUpdate>) -> FeedEntity {
+    FeedEntity {
+        id: format!("{trip_id} update"),
+        trip_update: Some(TripUpdate {
+            trip: TripDescriptor { trip_id: Some(trip_id.to_string()), ..Default::default() },
+            stop_time_update,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn with_relationship(mut entity: FeedEntity, relationship: TripRelationship) -> FeedEntity {
+    entity.trip_update.as_mut().unwrap().trip.schedule_relationship = Some(relationship as i32);
+    entity
+}
+
+// Returns the trip IDs RAPTOR and CSA ride from A to C when leaving at 07:55.
+fn chosen_trips(network: &Network) -> [String; 2] {
+    let trip_id = |journey: raptor::Journey| journey.legs_vehicle().map(|leg| leg.trip_id(network).to_string()).collect::<Vec<_>>().join(", ");
+    [trip_id(raptor_query(network, StopIdx(0), 7 * 3600 + 3300, StopIdx(2)).unwrap()), trip_id(csa_query(network, StopIdx(0), 7 * 3600 + 3300, StopIdx(2)).unwrap())]
+}
+
+#[test]
+fn delay_changes_the_chosen_connection() {
+    let mut network = network();
+    assert_eq!(chosen_trips(&network), ["express", "express"]);
+
+    // Running 10 minutes late, the express arrives after the stopping train.
+    let feed = FeedMessage {
+        entity: vec![trip_update("express", vec![StopTimeUpdate {
+            stop_id: Some("a".to_string()),
+            departure: Some(StopTimeEvent { delay: Some(600), ..Default::default() }),
+            ..Default::default()
+        }])],
+        ..Default::default()
+    };
+    let report = network.apply_trip_updates(&feed);
+    assert_eq!(report, UpdateReport { num_trips_updated: 1, num_delays_applied: 1, ..Default::default() });
+    assert_eq!(network.get_arrival_time(0, 0, 1), 8 * 3600 + 2400);
+    assert_eq!(chosen_trips(&network), ["stopping", "stopping"]);
+}
+
+#[test]
+fn early_running_is_limited_at_each_stop() {
+    let mut network = network();
+    let early = |stop_id: &str, delay| StopTimeUpdate {
+        stop_id: Some(stop_id.to_string()),
+        arrival: Some(StopTimeEvent { delay: Some(delay), ..Default::default() }),
+        ..Default::default()
+    };
+    // 20 minutes early at B would leave A before its departure, so only 15 minutes is applied there. C is still 20 minutes early.
+    let feed = FeedMessage { entity: vec![trip_update("stopping", vec![early("b", -1200), early("c", -1200)])], ..Default::default() };
+    let report = network.apply_trip_updates(&feed);
+    assert_eq!(report, UpdateReport { num_trips_updated: 1, num_delays_applied: 2, ..Default::default() });
+    assert_eq!(network.get_arrival_time(1, 0, 1), 8 * 3600 + 300);
+    assert_eq!(network.get_arrival_time(1, 0, 2), 8 * 3600 + 900);
+}
+
+#[test]
+fn skips_and_unmatched_updates_are_reported() {
+    let mut network = network();
+    let skip = |stop_id: &str| StopTimeUpdate {
+        stop_id: Some(stop_id.to_string()),
+        schedule_relationship: Some(ScheduleRelationship::Skipped as i32),
+        ..Default::default()
+    };
+    let by_stop_sequence = StopTimeUpdate { stop_sequence: Some(2), schedule_relationship: Some(ScheduleRelationship::Skipped as i32), ..Default::default() };
+    let feed = FeedMessage {
+        entity: vec![
+            // The express doesn't stop at C, and doesn't serve B at all.
+            trip_update("express", vec![StopTimeUpdate { stop_id: Some("b".to_string()), ..Default::default() }, skip("c")]),
+            trip_update("ghost", vec![skip("a")]),
+            // Without a stop_id, the stop can't be told apart from others.
+            trip_update("stopping", vec![skip("a"), by_stop_sequence]),
+        ],
+        ..Default::default()
+    };
+    let report = network.apply_trip_updates(&feed);
+    assert_eq!(report, UpdateReport {
+        num_trips_updated: 1,
+        num_stops_skipped: 1,
+        rejected_trips: vec![("stopping".to_string(), RejectedUpdate::MissingStopId(1))],
+        unmatched_trips: vec!["ghost".to_string()],
+        out_of_range_stops: vec![("express".to_string(), "b".to_string())],
+        ..Default::default()
+    });
+    assert_eq!(chosen_trips(&network), ["stopping", "stopping"]);
+    assert!(!network.routes[1].is_skipped(0, 0));
+}
+
+#[test]
+fn cancellation_forces_a_reroute() {
+    let mut network = network();
+    let cancellation = with_relationship(trip_update("express", Vec::new()), TripRelationship::Canceled);
+    let feed = FeedMessage { entity: vec![cancellation.clone(), cancellation], ..Default::default() };
+    let report = network.apply_trip_updates(&feed);
+    assert_eq!(report, UpdateReport {
+        cancelled_trips: vec!["express".to_string()],
+        rejected_trips: vec![("express".to_string(), RejectedUpdate::AlreadyCancelled)],
+        ..Default::default()
+    });
+    assert_eq!(chosen_trips(&network), ["stopping", "stopping"]);
+}
+
+#[test]
+fn added_trip_is_chosen() {
+    let mut network = network();
+    // The feed is in AEST, and the network's date is 1970-01-01.
+    let utc_offset_secs = 10 * 3600;
+    let added = |trip_id: &str, stops: &[(&str, Option<Timestamp>)]| {
+        let event = |time: Option<Timestamp>| Some(StopTimeEvent { time: time.map(|time| (8 * 3600 + time) as i64 - utc_offset_secs as i64), ..Default::default() });
+        let updates = stops.iter().map(|&(stop_id, time)| StopTimeUpdate { stop_id: Some(stop_id.to_string()), arrival: event(time), departure: event(time), ..Default::default() }).collect();
+        with_relationship(trip_update(trip_id, updates), TripRelationship::Added)
+    };
+    let feed = FeedMessage {
+        entity: vec![
+            // A replacement bus on the stopping pattern that gets to C before the express.
+            added("bus", &[("a", Some(60)), ("b", Some(900)), ("c", Some(1500))]),
+            added("backwards", &[("c", Some(60)), ("a", Some(900))]),
+            added("untimed", &[("a", Some(60)), ("b", None), ("c", Some(1500))]),
+            added("stopping", &[("a", Some(60)), ("b", Some(900)), ("c", Some(1500))]),
+        ],
+        ..Default::default()
+    };
+    let report = network.apply_trip_updates_with_utc_offset(&feed, utc_offset_secs);
+    assert_eq!(report, UpdateReport {
+        added_trips: vec!["bus".to_string()],
+        rejected_trips: vec![
+            ("backwards".to_string(), RejectedUpdate::NoMatchingRoute(vec!["c".to_string(), "a".to_string()])),
+            ("untimed".to_string(), RejectedUpdate::IncompleteStopTime(1)),
+            ("stopping".to_string(), RejectedUpdate::DuplicateTripId),
+        ],
+        ..Default::default()
+    });
+    assert_eq!(chosen_trips(&network), ["bus", "bus"]);
+}
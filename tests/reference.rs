@@ -0,0 +1,31 @@
+use dev_utils::get_example_scenario;
+use raptor::reference::TimeExpandedGraph;
+use raptor::network::{StopIndex, Timestamp};
+use raptor::{csa_query, raptor_query, StopIdx};
+
+// Slow: builds a time-expanded graph of the whole example network. Run with `cargo test --release -- --ignored`.
+#[test]
+#[ignore]
+fn raptor_csa_reference_agree() {
+    let (mut network, ..) = get_example_scenario();
+    network.build_connections();
+    let graph = TimeExpandedGraph::new(&network);
+
+    fastrand::seed(42);
+    for _ in 0..200 {
+        let start = fastrand::usize(..network.num_stops()) as StopIndex;
+        let end = fastrand::usize(..network.num_stops()) as StopIndex;
+        let start_time = fastrand::u32(5 * 3600..22 * 3600) as Timestamp;
+        if start == end {
+            continue;
+        }
+
+        let arrival_time = |journey: Result<raptor::Journey, _>| journey.ok().and_then(|journey| journey.arrival_time());
+        let raptor_arrival = arrival_time(raptor_query(&network, StopIdx(start), start_time, StopIdx(end)));
+        let csa_arrival = arrival_time(csa_query(&network, StopIdx(start), start_time, StopIdx(end)));
+        let reference_arrival = graph.query(start, start_time, end).ok();
+
+        assert_eq!(raptor_arrival, reference_arrival, "RAPTOR disagrees with reference for {start} -> {end} at {start_time}.");
+        assert_eq!(csa_arrival, reference_arrival, "CSA disagrees with reference for {start} -> {end} at {start_time}.");
+    }
+}
@@ -0,0 +1,30 @@
+use dev_utils::{get_example_scenario, load_scenario, ScenarioError};
+
+// Environment variables are process-wide, so these run in one test.
+#[test]
+fn load_scenario_reports_bad_variables() {
+    std::env::set_var("RAPTOR_TIME", "8:30pm");
+    let error = load_scenario().err().unwrap();
+    assert!(matches!(error, ScenarioError::MalformedVariable { variable: "RAPTOR_TIME", .. }), "{error:?}");
+    assert!(error.to_string().contains("RAPTOR_TIME"));
+    std::env::remove_var("RAPTOR_TIME");
+
+    std::env::set_var("RAPTOR_DATE", "10/05");
+    let error = load_scenario().err().unwrap();
+    assert!(error.to_string().contains("RAPTOR_DATE"), "{error}");
+    std::env::remove_var("RAPTOR_DATE");
+
+    std::env::set_var("RAPTOR_DEST", "Not A Station");
+    let error = load_scenario().err().unwrap();
+    assert!(matches!(&error, ScenarioError::UnknownStop { variable: "RAPTOR_DEST", name } if name == "Not A Station"), "{error:?}");
+    std::env::remove_var("RAPTOR_DEST");
+
+    // The defaults are the example scenario.
+    let (network, start, start_time, end) = load_scenario().unwrap();
+    let (example_network, example_start, example_start_time, example_end) = get_example_scenario();
+    // Stop indices depend on the GTFS load, so compare names.
+    assert_eq!(network.stop_name(start as usize), example_network.stop_name(example_start as usize));
+    assert_eq!(network.stop_name(end as usize), example_network.stop_name(example_end as usize));
+    assert_eq!(start_time, example_start_time);
+    assert_eq!(network.stats(), example_network.stats());
+}
@@ -0,0 +1,62 @@
+#![cfg(feature = "serde")]
+
+use dev_utils::get_synthetic_scenario;
+use raptor::journey::Connection;
+use raptor::network::{NetworkStats, Route};
+use raptor::{csa_query, raptor_query, Leg, Network, StopIdx};
+
+#[test]
+fn connections_round_trip() {
+    let (mut network, ..) = get_synthetic_scenario();
+    network.build_connections();
+
+    let json = serde_json::to_string(&network.connections).unwrap();
+    let restored: Vec<Connection> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, network.connections);
+}
+
+#[test]
+fn legs_round_trip() {
+    let (network, start, start_time, end) = get_synthetic_scenario();
+    let journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap();
+
+    let json = serde_json::to_string(&journey.legs).unwrap();
+    let restored: Vec<Leg> = serde_json::from_str(&json).unwrap();
+    assert_eq!(restored, journey.legs);
+}
+
+#[test]
+fn route_colours_are_hex_strings() {
+    let (network, ..) = get_synthetic_scenario();
+    let route = &network.routes[0];
+
+    let json = serde_json::to_value(route).unwrap();
+    assert_eq!(json["colour"], raptor::utils::get_colour_hex(route.colour));
+    let restored: Route = serde_json::from_value(json.clone()).unwrap();
+    assert_eq!((restored.colour, &*restored.line, &restored.trip_ids), (route.colour, &*route.line, &route.trip_ids));
+
+    let mut bad_colour = json;
+    bad_colour["colour"] = "#12345".into();
+    assert!(serde_json::from_value::<Route>(bad_colour).err().unwrap().to_string().contains("#rrggbb"));
+}
+
+#[test]
+fn network_round_trips_and_answers_the_same_queries() {
+    let (mut network, start, start_time, end) = get_synthetic_scenario();
+    let (start_id, end_id) = (network.stop_id(start as usize).to_string(), network.stop_id(end as usize).to_string());
+    network.set_transfer_times(&[(&start_id, &end_id, 300)]);
+    network.build_connections();
+
+    let json = serde_json::to_string(&network).unwrap();
+    let restored: Network = serde_json::from_str(&json).unwrap();
+    // Deserializing doesn't keep spare capacity, so only the bytes in use match.
+    let stats = |network: &Network| NetworkStats { memory_estimate_bytes: 0, ..network.stats() };
+    assert_eq!(stats(&restored), stats(&network));
+    assert_eq!(restored.memory_report().total_used_bytes(), network.memory_report().total_used_bytes());
+    assert_eq!(restored.pairwise_transfer_times, network.pairwise_transfer_times);
+    assert_eq!(restored.connections, network.connections);
+
+    let legs = |network: &Network| raptor_query(network, StopIdx(start), start_time, StopIdx(end)).unwrap().legs;
+    assert_eq!(legs(&restored), legs(&network));
+    assert_eq!(csa_query(&restored, StopIdx(start), start_time, StopIdx(end)).unwrap().legs, csa_query(&network, StopIdx(start), start_time, StopIdx(end)).unwrap().legs);
+}
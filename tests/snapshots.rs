@@ -0,0 +1,46 @@
+use dev_utils::{assert_snapshot, build_example_network, journey_snapshot, load_example_gtfs};
+use raptor::journey::{JourneyPreferences, JourneyResult};
+use raptor::network::CountBoardings;
+use raptor::{csa_query, mc_raptor_query, raptor_query, utils, StopIdx};
+
+// Representative journeys on the example network: a cross-city trip with a change, a long trip across the peak, an off-peak
+// trip, a late night trip and a midday trip.
+const QUERIES: [(&str, &str, &str, &str); 5] = [
+    ("cheltenham_greensborough", "Cheltenham", "08:30:00", "Greensborough"),
+    ("frankston_lilydale", "Frankston", "07:15:00", "Lilydale"),
+    ("werribee_sunbury", "Werribee", "17:45:00", "Sunbury"),
+    ("flinders_street_belgrave", "Flinders Street", "23:10:00", "Belgrave"),
+    ("craigieburn_pakenham", "Craigieburn", "12:00:00", "Pakenham"),
+];
+
+// Queries that find no journey are snapshotted as their error.
+fn snapshot(journey: JourneyResult) -> String {
+    match journey {
+        Ok(journey) => journey_snapshot(&journey),
+        Err(e) => format!("{e}\n"),
+    }
+}
+
+// Any change to the trips taken or the times of a journey fails here. If it's intended, regenerate the snapshots with
+// RAPTOR_UPDATE_SNAPSHOTS=1 and review the diff.
+#[test]
+fn journey_snapshots() {
+    let gtfs = load_example_gtfs().unwrap();
+    let mut network = build_example_network(&gtfs);
+    network.build_connections();
+
+    for (name, from, time, to) in QUERIES {
+        let start = network.get_stop_idx_from_name(from).expect(from);
+        let end = network.get_stop_idx_from_name(to).expect(to);
+        let start_time = utils::parse_time(time).unwrap();
+
+        let raptor_journey = raptor_query(&network, StopIdx(start), start_time, StopIdx(end));
+        assert_snapshot(&format!("raptor_{name}"), &snapshot(raptor_journey));
+        let csa_journey = csa_query(&network, StopIdx(start), start_time, StopIdx(end));
+        assert_snapshot(&format!("csa_{name}"), &snapshot(csa_journey));
+
+        // Costs are integer boarding counts, so the chosen journey doesn't depend on float rounding.
+        let mc_journey = mc_raptor_query::<5>(&network, StopIdx(start), start_time, &[StopIdx(end)], &CountBoardings, &JourneyPreferences::least_transfers()).remove(0);
+        assert_snapshot(&format!("mc_raptor_{name}"), &snapshot(mc_journey));
+    }
+}